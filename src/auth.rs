@@ -6,41 +6,42 @@ use axum::{
 };
 use std::sync::Arc;
 use subtle::ConstantTimeEq;
+use tracing::debug;
 use crate::models::{ErrorResponse, ErrorDetail};
 
+/// A caller authenticated by the transport rather than an API key,
+/// e.g. the subject of a verified TLS client certificate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity(pub String);
+
 pub struct AuthConfig {
     pub api_key: Option<String>,
 }
 
-pub async fn auth_middleware(
-    State(auth_config): State<Arc<AuthConfig>>,
-    mut request: Request,
-    next: Next,
-) -> Result<Response, StatusCode> {
-    // If no auth key is configured, allow all requests
-    if auth_config.api_key.is_none() {
-        return Ok(next.run(request).await);
+impl AuthConfig {
+    /// Check an `Authorization` header value against the configured API key.
+    /// Always succeeds when no key is configured.
+    pub fn authorize(&self, authorization: Option<&str>) -> bool {
+        self.authorize_token(authorization.and_then(|h| h.strip_prefix("Bearer ")))
     }
 
-    let auth_header = request
-        .headers()
-        .get(header::AUTHORIZATION)
-        .and_then(|h| h.to_str().ok());
-
-    let provided_key = auth_header.and_then(|h| h.strip_prefix("Bearer "));
+    /// Check a bare API key against the configured one.
+    /// Always succeeds when no key is configured.
+    pub fn authorize_token(&self, token: Option<&str>) -> bool {
+        let Some(expected_key) = self.api_key.as_deref() else {
+            return true;
+        };
 
-    if let Some(provided) = provided_key {
-        let expected_key = auth_config.api_key.as_deref().unwrap_or_default();
-        
-        // Use constant-time comparison to prevent timing attacks
-        if provided.as_bytes().ct_eq(expected_key.as_bytes()).into() {
-            // Clear the authorization header after validation
-            request.headers_mut().remove(header::AUTHORIZATION);
-            return Ok(next.run(request).await);
+        match token {
+            // Use constant-time comparison to prevent timing attacks
+            Some(provided) => provided.as_bytes().ct_eq(expected_key.as_bytes()).into(),
+            None => false,
         }
     }
+}
 
-    // Return proper OpenAI-style error response
+/// The OpenAI-style 401 response for a missing or invalid API key
+pub fn unauthorized() -> Response {
     let error_response = ErrorResponse {
         error: ErrorDetail {
             message: "Invalid API key".to_string(),
@@ -54,8 +55,39 @@ pub async fn auth_middleware(
         header::WWW_AUTHENTICATE,
         HeaderValue::from_static("Bearer"),
     );
-    
-    Ok(response)
+
+    response
+}
+
+pub async fn auth_middleware(
+    State(auth_config): State<Arc<AuthConfig>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    // If no auth key is configured, allow all requests
+    if auth_config.api_key.is_none() {
+        return Ok(next.run(request).await);
+    }
+
+    // Callers identified by a client certificate do not need an API key
+    if let Some(identity) = request.extensions().get::<ClientIdentity>() {
+        debug!("Authenticated client {}", identity.0);
+        return Ok(next.run(request).await);
+    }
+
+    let auth_header = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok());
+
+    if auth_config.authorize(auth_header) {
+        // Clear the authorization header after validation
+        request.headers_mut().remove(header::AUTHORIZATION);
+        return Ok(next.run(request).await);
+    }
+
+    // Return proper OpenAI-style error response
+    Ok(unauthorized())
 }
 
 #[cfg(test)]
@@ -92,6 +124,26 @@ mod tests {
         assert!(key1.as_bytes().ct_eq(key3.as_bytes()).unwrap_u8() == 0);
     }
 
+    #[test]
+    fn test_authorize() {
+        let config = AuthConfig {
+            api_key: Some("test-key".to_string()),
+        };
+
+        assert!(config.authorize(Some("Bearer test-key")));
+        assert!(!config.authorize(Some("Bearer wrong-key")));
+        assert!(!config.authorize(Some("test-key")));
+        assert!(!config.authorize(None));
+
+        assert!(config.authorize_token(Some("test-key")));
+        assert!(!config.authorize_token(Some("wrong-key")));
+        assert!(!config.authorize_token(None));
+
+        let open = AuthConfig { api_key: None };
+        assert!(open.authorize(None));
+        assert!(open.authorize_token(None));
+    }
+
     #[test]
     fn test_error_response_creation() {
         let error_response = ErrorResponse {
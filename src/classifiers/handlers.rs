@@ -0,0 +1,223 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Response,
+    Json,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+use tokio::task;
+use tracing::{debug, info};
+
+use super::head::{evaluate, split, Head, TrainOptions};
+use super::ClassifierError;
+use crate::codec::{self, Payload, ResponseFormat};
+use crate::error::server_error;
+use crate::handlers::AppState;
+use crate::models::{
+    ClassifierList, ErrorResponse, PredictRequest, PredictResponse, Prediction, PredictionScore, TrainClassifierRequest,
+    Usage,
+};
+
+type HandlerError = (StatusCode, Json<ErrorResponse>);
+
+/// Largest accepted number of training passes
+const MAX_EPOCHS: usize = 10_000;
+
+/// Largest accepted number of training examples
+const MAX_EXAMPLES: usize = 10_000;
+
+/// Largest accepted number of epochs times examples, which bounds the training time
+const MAX_TRAINING_STEPS: usize = 3_000_000;
+
+fn validate_training(request: &TrainClassifierRequest) -> Result<(), ClassifierError> {
+    let invalid = |message: &str| Err(ClassifierError::InvalidTraining(message.to_string()));
+
+    if request.examples.iter().any(|example| example.label.trim().is_empty()) {
+        return invalid("Every example needs a non-empty label");
+    }
+    let labels: BTreeSet<&str> = request.examples.iter().map(|example| example.label.as_str()).collect();
+    if labels.len() < 2 {
+        return invalid("Training needs examples of at least two labels");
+    }
+    if !(0.0..0.9).contains(&request.holdout) {
+        return invalid("holdout must be at least 0 and below 0.9");
+    }
+    if request.epochs == 0 || request.epochs > MAX_EPOCHS {
+        return invalid("epochs must be between 1 and 10000");
+    }
+    if request.examples.len() > MAX_EXAMPLES {
+        return invalid("Training accepts at most 10000 examples");
+    }
+    if request.epochs * request.examples.len() > MAX_TRAINING_STEPS {
+        return invalid("epochs times the number of examples must be at most 3000000");
+    }
+    if !(request.learning_rate.is_finite() && request.learning_rate > 0.0) {
+        return invalid("learning_rate must be a positive number");
+    }
+    if !(request.l2.is_finite() && request.l2 >= 0.0) {
+        return invalid("l2 must be a non-negative number");
+    }
+    Ok(())
+}
+
+/// Train a new version of a classifier head on labelled texts, evaluating it on a held-out
+/// share of each label's examples before publishing it
+pub async fn train_classifier(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Path(name): Path<String>,
+    Payload(request): Payload<TrainClassifierRequest>,
+) -> Result<Response, HandlerError> {
+    debug!("Received training request for classifier {} with {} examples", name, request.examples.len());
+    super::validate_name(&name)?;
+    validate_training(&request)?;
+
+    // Examples are bound by MAX_EXAMPLES rather than the batch size limit
+    let texts: Vec<String> = request.examples.iter().map(|example| example.text.clone()).collect();
+    state.validate_lengths(&texts)?;
+
+    let labels: Vec<String> = request
+        .examples
+        .iter()
+        .map(|example| example.label.clone())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let positions: HashMap<&str, usize> = labels.iter().enumerate().map(|(i, label)| (label.as_str(), i)).collect();
+    let targets: Vec<usize> = request.examples.iter().map(|example| positions[example.label.as_str()]).collect();
+
+    // but are embedded in batches of up to max_batch_size texts
+    let mut embeddings = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(state.max_batch_size.max(1)) {
+        embeddings.extend(state.encode_texts(batch.to_vec()).await?.embeddings);
+    }
+
+    let options = TrainOptions {
+        epochs: request.epochs,
+        learning_rate: request.learning_rate,
+        l2: request.l2,
+    };
+    let (kind, holdout, seed) = (request.head, request.holdout, request.seed);
+    let model = state.model_name.clone();
+    let info = task::spawn_blocking(move || {
+        let (train, test) = split(&targets, labels.len(), holdout, seed);
+        let select = |positions: &[usize]| -> (Vec<Vec<f32>>, Vec<usize>) {
+            positions.iter().map(|&i| (embeddings[i].clone(), targets[i])).unzip()
+        };
+        let (train_embeddings, train_targets) = select(&train);
+        let (test_embeddings, test_targets) = select(&test);
+
+        let head = Head::train(kind, labels, &train_embeddings, &train_targets, options);
+        let metrics = (!test.is_empty()).then(|| evaluate(&head, &test_embeddings, &test_targets, train.len()));
+        state.classifiers.add_version(&name, &model, head, metrics)
+    })
+    .await
+    .map_err(|e| server_error(format!("Training task failed: {}", e)))??;
+
+    let latest = info.versions.last().expect("trained version");
+    info!(
+        "Trained classifier {} version {} (held-out accuracy {:?})",
+        info.name,
+        latest.version,
+        latest.metrics.as_ref().map(|metrics| metrics.accuracy)
+    );
+
+    Ok(codec::encode(response_format, &info))
+}
+
+pub async fn list_classifiers(State(state): State<Arc<AppState>>, response_format: ResponseFormat) -> Response {
+    let list = ClassifierList {
+        object: "list".to_string(),
+        data: state.classifiers.list(),
+    };
+    codec::encode(response_format, &list)
+}
+
+pub async fn get_classifier(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Path(name): Path<String>,
+) -> Result<Response, HandlerError> {
+    let info = state.classifiers.get(&name)?;
+    Ok(codec::encode(response_format, &info))
+}
+
+pub async fn delete_classifier(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, HandlerError> {
+    let deleted = name.clone();
+    task::spawn_blocking(move || state.classifiers.delete(&deleted))
+        .await
+        .map_err(|e| server_error(format!("Classifier task failed: {}", e)))??;
+
+    Ok(Json(serde_json::json!({
+        "id": name,
+        "object": "classifier",
+        "deleted": true,
+    })))
+}
+
+/// Label each input with a classifier version, the latest by default
+pub async fn predict(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Path(name): Path<String>,
+    Payload(request): Payload<PredictRequest>,
+) -> Result<Response, HandlerError> {
+    let texts = request.input.into_texts();
+    debug!("Received prediction request for {} texts with classifier {}", texts.len(), name);
+    state.validate_texts(&texts)?;
+    // Fail before embedding when the classifier or version is missing
+    state.classifiers.with_version(&name, request.version, |_| ())?;
+
+    let result = state.encode_texts(texts).await?;
+    let (version, data) = state.classifiers.with_version(&name, request.version, |version| {
+        let head = &version.head;
+        result
+            .embeddings
+            .iter()
+            .enumerate()
+            .map(|(index, embedding)| {
+                let mut scores: Vec<PredictionScore> = head
+                    .scores(embedding)
+                    .into_iter()
+                    .zip(&head.labels)
+                    .map(|(score, label)| PredictionScore {
+                        label: label.clone(),
+                        score,
+                    })
+                    .collect();
+
+                // Highest first; ties keep the label order
+                scores.sort_by(|a, b| b.score.total_cmp(&a.score));
+                let label = scores[0].label.clone();
+                scores.truncate(request.top_k.unwrap_or(scores.len()));
+                Prediction {
+                    object: "prediction".to_string(),
+                    index,
+                    label,
+                    scores,
+                }
+            })
+            .collect()
+    })?;
+
+    let total_tokens = result.token_counts.iter().sum();
+    let response = PredictResponse {
+        object: "list".to_string(),
+        data,
+        classifier: name,
+        version,
+        model: request.model.unwrap_or_else(|| state.model_name.clone()),
+        usage: Usage {
+            prompt_tokens: total_tokens,
+            total_tokens,
+        },
+    };
+
+    Ok(codec::encode(response_format, &response))
+}
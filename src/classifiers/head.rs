@@ -0,0 +1,296 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::{ClassifierMetrics, HeadKind, LabelMetrics};
+use crate::similarity::norm;
+
+/// Settings for gradient descent over the whole training set
+#[derive(Debug, Clone, Copy)]
+pub struct TrainOptions {
+    pub epochs: usize,
+    pub learning_rate: f32,
+    pub l2: f32,
+}
+
+/// A linear layer over standardized embeddings: one weight row and bias per label
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Head {
+    pub kind: HeadKind,
+    pub labels: Vec<String>,
+    pub weights: Vec<Vec<f32>>,
+    pub bias: Vec<f32>,
+    /// Per-dimension mean and scale of the training embeddings
+    pub mean: Vec<f32>,
+    pub scale: Vec<f32>,
+}
+
+impl Head {
+    /// Fit the head to embeddings and their label positions. Logistic heads minimize softmax
+    /// cross-entropy; linear heads fit each label's ±1 indicator by least squares.
+    pub fn train(kind: HeadKind, labels: Vec<String>, embeddings: &[Vec<f32>], targets: &[usize], options: TrainOptions) -> Self {
+        let dimensions = embeddings.first().map_or(0, Vec::len);
+        let (mean, scale) = standardization(embeddings, dimensions);
+        let mut head = Self {
+            kind,
+            weights: vec![vec![0.0; dimensions]; labels.len()],
+            bias: vec![0.0; labels.len()],
+            labels,
+            mean,
+            scale,
+        };
+
+        let features: Vec<Vec<f32>> = embeddings.iter().map(|embedding| head.features(embedding)).collect();
+        let count = features.len().max(1) as f32;
+        for _ in 0..options.epochs {
+            let mut weight_gradients = vec![vec![0.0; dimensions]; head.labels.len()];
+            let mut bias_gradients = vec![0.0; head.labels.len()];
+
+            for (x, &target) in features.iter().zip(targets) {
+                let outputs = head.outputs(x);
+                for (label, output) in outputs.into_iter().enumerate() {
+                    let error = match kind {
+                        HeadKind::Logistic => output - if label == target { 1.0 } else { 0.0 },
+                        HeadKind::Linear => output - if label == target { 1.0 } else { -1.0 },
+                    };
+                    for (gradient, value) in weight_gradients[label].iter_mut().zip(x) {
+                        *gradient += error * value;
+                    }
+                    bias_gradients[label] += error;
+                }
+            }
+
+            for (label, gradients) in weight_gradients.iter().enumerate() {
+                for (weight, gradient) in head.weights[label].iter_mut().zip(gradients) {
+                    *weight -= options.learning_rate * (gradient / count + options.l2 * *weight);
+                }
+                head.bias[label] -= options.learning_rate * bias_gradients[label] / count;
+            }
+        }
+
+        head
+    }
+
+    /// Standardize a unit-length copy of the embedding. The extra `sqrt(dimensions)` keeps the
+    /// feature vectors near unit length, so one learning rate suits any model size.
+    fn features(&self, embedding: &[f32]) -> Vec<f32> {
+        let length = norm(embedding);
+        let length = if length > 0.0 { length } else { 1.0 };
+        embedding
+            .iter()
+            .zip(self.mean.iter().zip(&self.scale))
+            .map(|(value, (mean, scale))| (value / length - mean) / scale)
+            .collect()
+    }
+
+    /// Softmax probabilities for logistic heads, raw decision values for linear heads
+    fn outputs(&self, features: &[f32]) -> Vec<f32> {
+        let logits: Vec<f32> = self
+            .weights
+            .iter()
+            .zip(&self.bias)
+            .map(|(weights, bias)| weights.iter().zip(features).map(|(w, x)| w * x).sum::<f32>() + bias)
+            .collect();
+
+        match self.kind {
+            HeadKind::Logistic => {
+                let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                let exponentials: Vec<f32> = logits.iter().map(|logit| (logit - max).exp()).collect();
+                let total: f32 = exponentials.iter().sum();
+                exponentials.iter().map(|value| value / total).collect()
+            }
+            HeadKind::Linear => logits,
+        }
+    }
+
+    /// Score of every label for an embedding, in label order
+    pub fn scores(&self, embedding: &[f32]) -> Vec<f32> {
+        self.outputs(&self.features(embedding))
+    }
+
+    pub fn predict(&self, embedding: &[f32]) -> usize {
+        argmax(&self.scores(embedding))
+    }
+}
+
+fn argmax(values: &[f32]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .fold(0, |best, (index, value)| if *value > values[best] { index } else { best })
+}
+
+fn standardization(embeddings: &[Vec<f32>], dimensions: usize) -> (Vec<f32>, Vec<f32>) {
+    let count = embeddings.len().max(1) as f32;
+    let units: Vec<Vec<f32>> = embeddings
+        .iter()
+        .map(|embedding| {
+            let length = norm(embedding);
+            let length = if length > 0.0 { length } else { 1.0 };
+            embedding.iter().map(|value| value / length).collect()
+        })
+        .collect();
+
+    let mut mean = vec![0.0; dimensions];
+    for unit in &units {
+        for (total, value) in mean.iter_mut().zip(unit) {
+            *total += value / count;
+        }
+    }
+    let mut variance = vec![0.0; dimensions];
+    for unit in &units {
+        for ((total, value), mean) in variance.iter_mut().zip(unit).zip(&mean) {
+            *total += (value - mean) * (value - mean) / count;
+        }
+    }
+
+    let spread = (dimensions.max(1) as f32).sqrt();
+    let scale = variance
+        .into_iter()
+        .map(|variance: f32| if variance > 1e-12 { variance.sqrt() * spread } else { spread })
+        .collect();
+    (mean, scale)
+}
+
+/// Split example positions into training and held-out sets, holding out about `holdout` of
+/// each label's examples while leaving every label at least one training example
+pub fn split(targets: &[usize], labels: usize, holdout: f32, seed: u64) -> (Vec<usize>, Vec<usize>) {
+    let mut state = seed;
+    let mut next = || {
+        // splitmix64
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+
+    let (mut train, mut test) = (Vec::new(), Vec::new());
+    for label in 0..labels {
+        let mut positions: Vec<usize> = (0..targets.len()).filter(|&i| targets[i] == label).collect();
+        // Fisher-Yates shuffle
+        for i in (1..positions.len()).rev() {
+            positions.swap(i, (next() % (i as u64 + 1)) as usize);
+        }
+
+        let held = ((positions.len() as f32 * holdout).round() as usize).min(positions.len().saturating_sub(1));
+        test.extend_from_slice(&positions[..held]);
+        train.extend_from_slice(&positions[held..]);
+    }
+    train.sort_unstable();
+    test.sort_unstable();
+    (train, test)
+}
+
+/// Accuracy and per-label precision, recall and F1 of a head on labelled embeddings. Macro F1
+/// averages the labels that occur in the targets or the predictions.
+pub fn evaluate(head: &Head, embeddings: &[Vec<f32>], targets: &[usize], train_examples: usize) -> ClassifierMetrics {
+    let predictions: Vec<usize> = embeddings.iter().map(|embedding| head.predict(embedding)).collect();
+    let correct = predictions.iter().zip(targets).filter(|(p, t)| p == t).count();
+
+    let mut per_label = Vec::new();
+    let mut f1_total = 0.0;
+    let mut f1_count = 0;
+    for (label, name) in head.labels.iter().enumerate() {
+        let true_positives = predictions.iter().zip(targets).filter(|(p, t)| **p == label && **t == label).count();
+        let predicted = predictions.iter().filter(|p| **p == label).count();
+        let support = targets.iter().filter(|t| **t == label).count();
+
+        let ratio = |count: usize, total: usize| if total > 0 { count as f32 / total as f32 } else { 0.0 };
+        let (precision, recall) = (ratio(true_positives, predicted), ratio(true_positives, support));
+        let f1 = if precision + recall > 0.0 { 2.0 * precision * recall / (precision + recall) } else { 0.0 };
+        if predicted > 0 || support > 0 {
+            f1_total += f1;
+            f1_count += 1;
+        }
+
+        per_label.push(LabelMetrics {
+            label: name.clone(),
+            precision,
+            recall,
+            f1,
+            support,
+        });
+    }
+
+    ClassifierMetrics {
+        accuracy: correct as f32 / targets.len().max(1) as f32,
+        macro_f1: if f1_count > 0 { f1_total / f1_count as f32 } else { 0.0 },
+        train_examples,
+        test_examples: targets.len(),
+        per_label,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two well separated clusters around different directions, with a shared offset
+    fn clusters() -> (Vec<Vec<f32>>, Vec<usize>) {
+        let mut embeddings = Vec::new();
+        let mut targets = Vec::new();
+        for i in 0..20 {
+            let noise = (i as f32 * 0.37).sin() * 0.1;
+            embeddings.push(vec![1.0 + noise, 0.2, 1.0 - noise]);
+            targets.push(0);
+            embeddings.push(vec![0.2, 1.0 + noise, 1.0 + noise]);
+            targets.push(1);
+        }
+        (embeddings, targets)
+    }
+
+    #[test]
+    fn test_train_heads() {
+        let (embeddings, targets) = clusters();
+        let labels = vec!["a".to_string(), "b".to_string()];
+        let options = TrainOptions {
+            epochs: 200,
+            learning_rate: 1.0,
+            l2: 1e-4,
+        };
+
+        for kind in [HeadKind::Logistic, HeadKind::Linear] {
+            let head = Head::train(kind, labels.clone(), &embeddings, &targets, options);
+            let metrics = evaluate(&head, &embeddings, &targets, embeddings.len());
+            assert_eq!(metrics.accuracy, 1.0);
+            assert_eq!(metrics.macro_f1, 1.0);
+            assert_eq!(metrics.per_label[1].support, 20);
+        }
+
+        let head = Head::train(HeadKind::Logistic, labels, &embeddings, &targets, options);
+        let scores = head.scores(&[1.0, 0.2, 1.0]);
+        assert!((scores.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!(scores[0] > 0.9);
+    }
+
+    #[test]
+    fn test_split() {
+        let targets = [0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 2];
+        let (train, test) = split(&targets, 3, 0.4, 7);
+        assert_eq!(test.iter().filter(|&&i| targets[i] == 0).count(), 2);
+        assert_eq!(test.iter().filter(|&&i| targets[i] == 1).count(), 2);
+        // A label with one example keeps it for training
+        assert!(train.contains(&10));
+        assert_eq!(train.len() + test.len(), targets.len());
+        assert_eq!(split(&targets, 3, 0.4, 7), (train, test));
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let head = Head {
+            kind: HeadKind::Linear,
+            labels: vec!["a".to_string(), "b".to_string()],
+            weights: vec![vec![1.0, 0.0], vec![0.0, 1.0]],
+            bias: vec![0.0, 0.0],
+            mean: vec![0.0, 0.0],
+            scale: vec![1.0, 1.0],
+        };
+        let embeddings = [vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 0.1], vec![0.9, 0.0]];
+        let metrics = evaluate(&head, &embeddings, &[0, 1, 1, 0], 10);
+        assert_eq!(metrics.accuracy, 0.75);
+        // Label a: precision 2/3, recall 1; label b: precision 1, recall 1/2
+        assert!((metrics.per_label[0].f1 - 0.8).abs() < 1e-6);
+        assert!((metrics.per_label[1].f1 - 2.0 / 3.0).abs() < 1e-6);
+        assert!((metrics.macro_f1 - (0.8 + 2.0 / 3.0) / 2.0).abs() < 1e-6);
+        assert_eq!((metrics.train_examples, metrics.test_examples), (10, 4));
+    }
+}
@@ -0,0 +1,264 @@
+use axum::{http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{PoisonError, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+
+use crate::collections::is_valid_name;
+use crate::models::{ClassifierInfo, ClassifierMetrics, ClassifierVersionInfo, ErrorDetail, ErrorResponse};
+use head::Head;
+
+pub mod handlers;
+pub mod head;
+
+/// Longest accepted classifier name
+const MAX_NAME_LENGTH: usize = 64;
+
+/// Versions kept per classifier; training past this drops the oldest
+const MAX_VERSIONS: usize = 10;
+
+/// Directory of `--data-dir` holding one JSON file per classifier
+const CLASSIFIERS_DIR: &str = "classifiers";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClassifierError {
+    #[error("Classifier '{0}' not found")]
+    NotFound(String),
+    #[error("Classifier '{name}' has no version {version}")]
+    VersionNotFound { name: String, version: u32 },
+    #[error("Classifier names must be 1 to {MAX_NAME_LENGTH} letters, digits, '-' or '_'")]
+    InvalidName,
+    #[error("{0}")]
+    InvalidTraining(String),
+    #[error("Storage error: {0}")]
+    Storage(#[from] io::Error),
+}
+
+impl ClassifierError {
+    fn code(&self) -> &'static str {
+        match self {
+            ClassifierError::NotFound(_) => "classifier_not_found",
+            ClassifierError::VersionNotFound { .. } => "version_not_found",
+            ClassifierError::InvalidName => "invalid_classifier_name",
+            ClassifierError::InvalidTraining(_) => "invalid_training_data",
+            ClassifierError::Storage(_) => "storage_error",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            ClassifierError::NotFound(_) | ClassifierError::VersionNotFound { .. } => StatusCode::NOT_FOUND,
+            ClassifierError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl From<ClassifierError> for (StatusCode, Json<ErrorResponse>) {
+    fn from(err: ClassifierError) -> Self {
+        let error_type = match err {
+            ClassifierError::Storage(_) => "server_error",
+            _ => "invalid_request_error",
+        };
+        (
+            err.status(),
+            Json(ErrorResponse {
+                error: ErrorDetail {
+                    message: err.to_string(),
+                    error_type: error_type.to_string(),
+                    code: Some(err.code().to_string()),
+                },
+            }),
+        )
+    }
+}
+
+/// A trained head with the evaluation it was published with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Version {
+    pub version: u32,
+    pub created_at: u64,
+    pub head: Head,
+    #[serde(default)]
+    pub metrics: Option<ClassifierMetrics>,
+}
+
+/// All kept versions of a named classifier, as stored on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Classifier {
+    name: String,
+    model: String,
+    /// Fingerprint of the model whose embeddings the heads were trained on
+    fingerprint: String,
+    versions: Vec<Version>,
+}
+
+impl Classifier {
+    fn info(&self) -> ClassifierInfo {
+        ClassifierInfo {
+            name: self.name.clone(),
+            model: self.model.clone(),
+            latest: self.versions.last().map_or(0, |version| version.version),
+            versions: self
+                .versions
+                .iter()
+                .map(|version| ClassifierVersionInfo {
+                    version: version.version,
+                    head: version.head.kind,
+                    labels: version.head.labels.clone(),
+                    created_at: version.created_at,
+                    metrics: version.metrics.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The trained classifier heads of the service, saved in `--data-dir` when it is set
+#[derive(Debug, Default)]
+pub struct Classifiers {
+    classifiers: RwLock<BTreeMap<String, Classifier>>,
+    dir: Option<PathBuf>,
+    fingerprint: String,
+}
+
+impl Classifiers {
+    /// Load the classifiers saved in a data directory. Classifiers trained on another model are
+    /// left on disk but not loaded, since their heads do not fit this model's embeddings.
+    pub fn open(data_dir: &Path, fingerprint: String) -> anyhow::Result<Self> {
+        let dir = data_dir.join(CLASSIFIERS_DIR);
+        fs::create_dir_all(&dir)?;
+
+        let mut classifiers = BTreeMap::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            let classifier: Classifier = serde_json::from_slice(&fs::read(&path)?)
+                .map_err(|e| anyhow::anyhow!("Failed to load classifier {}: {}", path.display(), e))?;
+            if classifier.fingerprint != fingerprint {
+                warn!("Skipping classifier '{}', trained with model '{}'", classifier.name, classifier.model);
+                continue;
+            }
+            classifiers.insert(classifier.name.clone(), classifier);
+        }
+        if !classifiers.is_empty() {
+            info!("Loaded {} classifiers from {}", classifiers.len(), dir.display());
+        }
+
+        Ok(Self {
+            classifiers: RwLock::new(classifiers),
+            dir: Some(dir),
+            fingerprint,
+        })
+    }
+
+    pub fn list(&self) -> Vec<ClassifierInfo> {
+        let classifiers = self.classifiers.read().unwrap_or_else(PoisonError::into_inner);
+        classifiers.values().map(Classifier::info).collect()
+    }
+
+    pub fn get(&self, name: &str) -> Result<ClassifierInfo, ClassifierError> {
+        let classifiers = self.classifiers.read().unwrap_or_else(PoisonError::into_inner);
+        classifiers
+            .get(name)
+            .map(Classifier::info)
+            .ok_or_else(|| ClassifierError::NotFound(name.to_string()))
+    }
+
+    /// Publish a newly trained head as the classifier's next version
+    pub fn add_version(
+        &self,
+        name: &str,
+        model: &str,
+        head: Head,
+        metrics: Option<ClassifierMetrics>,
+    ) -> Result<ClassifierInfo, ClassifierError> {
+        validate_name(name)?;
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+
+        let mut classifiers = self.classifiers.write().unwrap_or_else(PoisonError::into_inner);
+        let mut classifier = classifiers.get(name).cloned().unwrap_or_else(|| Classifier {
+            name: name.to_string(),
+            model: model.to_string(),
+            fingerprint: self.fingerprint.clone(),
+            versions: Vec::new(),
+        });
+        let version = classifier.versions.last().map_or(1, |latest| latest.version + 1);
+        classifier.versions.push(Version {
+            version,
+            created_at,
+            head,
+            metrics,
+        });
+        if classifier.versions.len() > MAX_VERSIONS {
+            classifier.versions.remove(0);
+        }
+
+        self.save(&classifier)?;
+        let info = classifier.info();
+        classifiers.insert(name.to_string(), classifier);
+        Ok(info)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), ClassifierError> {
+        let mut classifiers = self.classifiers.write().unwrap_or_else(PoisonError::into_inner);
+        if !classifiers.contains_key(name) {
+            return Err(ClassifierError::NotFound(name.to_string()));
+        }
+        if let Some(dir) = &self.dir {
+            fs::remove_file(dir.join(format!("{}.json", name)))?;
+        }
+        classifiers.remove(name);
+        Ok(())
+    }
+
+    /// Run a version of a classifier, the latest by default, returning the version used
+    pub fn with_version<T>(
+        &self,
+        name: &str,
+        version: Option<u32>,
+        f: impl FnOnce(&Version) -> T,
+    ) -> Result<(u32, T), ClassifierError> {
+        let classifiers = self.classifiers.read().unwrap_or_else(PoisonError::into_inner);
+        let classifier = classifiers
+            .get(name)
+            .ok_or_else(|| ClassifierError::NotFound(name.to_string()))?;
+        let found = match version {
+            Some(version) => classifier.versions.iter().find(|v| v.version == version),
+            None => classifier.versions.last(),
+        };
+        let found = found.ok_or_else(|| ClassifierError::VersionNotFound {
+            name: name.to_string(),
+            version: version.unwrap_or_default(),
+        })?;
+        Ok((found.version, f(found)))
+    }
+
+    fn save(&self, classifier: &Classifier) -> io::Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let path = dir.join(format!("{}.json", classifier.name));
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, serde_json::to_vec(classifier)?)?;
+        fs::rename(&temporary, &path)
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), ClassifierError> {
+    if is_valid_name(name, MAX_NAME_LENGTH) {
+        Ok(())
+    } else {
+        Err(ClassifierError::InvalidName)
+    }
+}
@@ -0,0 +1,394 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Response,
+    Json,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError, RwLock},
+};
+use tokio::task;
+use tracing::{debug, info};
+
+use crate::codec::{self, Payload, ResponseFormat};
+use crate::collections::is_valid_name;
+use crate::config::Config;
+use crate::error::{server_error, InputError};
+use crate::handlers::AppState;
+use crate::models::{
+    Classification, ClassifyRequest, ClassifyResponse, ErrorDetail, ErrorResponse, Label, LabelInput, LabelScore,
+    LabelSet, LabelSetList, LabelSetRequest, Usage,
+};
+use crate::similarity::{cosine, norm};
+
+/// Where named label sets are saved in `--data-dir`
+const LABEL_SETS_FILE: &str = "label_sets.json";
+
+/// Label texts whose embeddings are kept; the cache starts over once it is full
+const LABEL_CACHE_SIZE: usize = 10_000;
+
+const MAX_NAME_LENGTH: usize = 128;
+
+type HandlerError = (StatusCode, Json<ErrorResponse>);
+
+/// Stored label sets and the embeddings of label texts seen so far
+#[derive(Debug, Default)]
+pub struct Labels {
+    sets: RwLock<BTreeMap<String, Vec<Label>>>,
+    path: Option<PathBuf>,
+    cache: Mutex<HashMap<String, Vec<f32>>>,
+}
+
+impl Labels {
+    /// Load the label sets saved in `--data-dir`, if any
+    pub fn load(config: &Config) -> anyhow::Result<Self> {
+        let path = config.data_dir.as_ref().map(|dir| std::path::Path::new(dir).join(LABEL_SETS_FILE));
+
+        let mut sets = BTreeMap::new();
+        if let Some(path) = path.as_ref().filter(|path| path.exists()) {
+            let stored: Vec<LabelSet> = serde_json::from_str(&fs::read_to_string(path)?)
+                .map_err(|e| anyhow::anyhow!("Failed to load label sets {}: {}", path.display(), e))?;
+            info!("Loaded {} label sets from {}", stored.len(), path.display());
+            sets.extend(stored.into_iter().map(|set| (set.name, set.labels)));
+        }
+
+        Ok(Self {
+            sets: RwLock::new(sets),
+            path,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn list(&self) -> Vec<LabelSet> {
+        let sets = self.sets.read().unwrap_or_else(PoisonError::into_inner);
+        sets.iter()
+            .map(|(name, labels)| LabelSet {
+                name: name.clone(),
+                labels: labels.clone(),
+            })
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Vec<Label>> {
+        self.sets.read().unwrap_or_else(PoisonError::into_inner).get(name).cloned()
+    }
+
+    /// Store or replace a set, saving all sets first when there is a data directory
+    pub fn insert(&self, name: String, labels: Vec<Label>) -> anyhow::Result<()> {
+        let mut sets = self.sets.write().unwrap_or_else(PoisonError::into_inner);
+        let mut updated = sets.clone();
+        updated.insert(name, labels);
+        self.save(&updated)?;
+        *sets = updated;
+        Ok(())
+    }
+
+    pub fn remove(&self, name: &str) -> anyhow::Result<bool> {
+        let mut sets = self.sets.write().unwrap_or_else(PoisonError::into_inner);
+        if !sets.contains_key(name) {
+            return Ok(false);
+        }
+        let mut updated = sets.clone();
+        updated.remove(name);
+        self.save(&updated)?;
+        *sets = updated;
+        Ok(true)
+    }
+
+    fn save(&self, sets: &BTreeMap<String, Vec<Label>>) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let stored: Vec<LabelSet> = sets
+            .iter()
+            .map(|(name, labels)| LabelSet {
+                name: name.clone(),
+                labels: labels.clone(),
+            })
+            .collect();
+
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, serde_json::to_vec(&stored)?)?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+
+    fn cached(&self, texts: &[String]) -> Vec<Option<Vec<f32>>> {
+        let cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        texts.iter().map(|text| cache.get(text).cloned()).collect()
+    }
+
+    fn remember(&self, texts: Vec<String>, embeddings: &[Vec<f32>]) {
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        if cache.len() + texts.len() > LABEL_CACHE_SIZE {
+            cache.clear();
+        }
+        cache.extend(texts.into_iter().zip(embeddings.iter().cloned()));
+    }
+}
+
+/// The texts embedded for a label: its description or name, then its examples
+fn label_texts(label: &Label) -> Vec<String> {
+    let mut texts = vec![label.description.clone().unwrap_or_else(|| label.name.clone())];
+    texts.extend(label.examples.iter().cloned());
+    texts
+}
+
+/// Mean of unit-length vectors, so no single text dominates a label
+fn centroid(vectors: &[Vec<f32>]) -> Vec<f32> {
+    let mut sum = vec![0.0; vectors.first().map_or(0, Vec::len)];
+    for vector in vectors {
+        let length = norm(vector);
+        if length > 0.0 {
+            for (total, value) in sum.iter_mut().zip(vector) {
+                *total += value / length;
+            }
+        }
+    }
+    sum.iter().map(|value| value / vectors.len() as f32).collect()
+}
+
+/// Softmax of `scores / temperature`, shifted by the maximum for stability
+pub fn softmax(scores: &[f32], temperature: f32) -> Vec<f32> {
+    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exponentials: Vec<f32> = scores.iter().map(|score| ((score - max) / temperature).exp()).collect();
+    let total: f32 = exponentials.iter().sum();
+    exponentials.iter().map(|value| value / total).collect()
+}
+
+fn validate_labels(state: &AppState, labels: &[Label]) -> Result<(), InputError> {
+    if labels.is_empty() {
+        return Err(InputError::new("At least one label is required", "invalid_labels"));
+    }
+    if labels.len() > state.max_batch_size {
+        return Err(InputError::new(
+            format!("Too many labels: {} (max: {})", labels.len(), state.max_batch_size),
+            "invalid_labels",
+        ));
+    }
+
+    let mut names = HashSet::new();
+    for label in labels {
+        if label.name.trim().is_empty() {
+            return Err(InputError::new("Label names cannot be empty", "invalid_labels"));
+        }
+        if !names.insert(label.name.as_str()) {
+            return Err(InputError::new(format!("Duplicate label '{}'", label.name), "invalid_labels"));
+        }
+        state.validate_lengths(&label_texts(label))?;
+    }
+    Ok(())
+}
+
+/// One vector per label, embedding only texts missing from the cache. Returns the vectors
+/// and the tokens spent embedding.
+async fn label_vectors(state: &AppState, labels: &[Label]) -> Result<(Vec<Vec<f32>>, usize), HandlerError> {
+    let texts: Vec<Vec<String>> = labels.iter().map(label_texts).collect();
+    let flat: Vec<String> = texts.iter().flatten().cloned().collect();
+    let mut embeddings = state.labels.cached(&flat);
+
+    let missing: Vec<String> = flat
+        .iter()
+        .zip(&embeddings)
+        .filter(|(_, embedding)| embedding.is_none())
+        .map(|(text, _)| text.clone())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+
+    let mut tokens = 0;
+    if !missing.is_empty() {
+        let result = state.encode_texts(missing.clone()).await?;
+        tokens = result.token_counts.iter().sum();
+        let fresh: HashMap<&String, &Vec<f32>> = missing.iter().zip(&result.embeddings).collect();
+        for (text, embedding) in flat.iter().zip(embeddings.iter_mut()) {
+            if embedding.is_none() {
+                *embedding = fresh.get(text).map(|vector| (*vector).clone());
+            }
+        }
+        state.labels.remember(missing, &result.embeddings);
+    }
+
+    let mut embeddings = embeddings.into_iter().map(|embedding| embedding.expect("label embedding"));
+    let vectors = texts
+        .iter()
+        .map(|texts| centroid(&embeddings.by_ref().take(texts.len()).collect::<Vec<_>>()))
+        .collect();
+    Ok((vectors, tokens))
+}
+
+fn label_set_not_found(name: &str) -> HandlerError {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: ErrorDetail {
+                message: format!("Label set '{}' not found", name),
+                error_type: "invalid_request_error".to_string(),
+                code: Some("label_set_not_found".to_string()),
+            },
+        }),
+    )
+}
+
+/// Score each input against candidate labels, as a softmax distribution over label similarity
+pub async fn classify(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Payload(request): Payload<ClassifyRequest>,
+) -> Result<Response, HandlerError> {
+    let texts = request.input.into_texts();
+    debug!("Received classify request for {} texts", texts.len());
+    state.validate_texts(&texts)?;
+
+    if !(request.temperature.is_finite() && request.temperature > 0.0) {
+        return Err(InputError::new("temperature must be a positive number", "invalid_temperature").into());
+    }
+    let labels: Vec<Label> = match (request.labels.is_empty(), &request.label_set) {
+        (false, None) => request.labels.into_iter().map(LabelInput::into_label).collect(),
+        (true, Some(name)) => state.labels.get(name).ok_or_else(|| label_set_not_found(name))?,
+        _ => {
+            return Err(InputError::new("Give either labels or label_set", "invalid_labels").into());
+        }
+    };
+    validate_labels(&state, &labels)?;
+
+    let (label_vectors, label_tokens) = label_vectors(&state, &labels).await?;
+    let result = state.encode_texts(texts).await?;
+
+    let data = result
+        .embeddings
+        .iter()
+        .enumerate()
+        .map(|(index, embedding)| {
+            let similarities: Vec<f32> = label_vectors.iter().map(|label| cosine(embedding, label)).collect();
+            let mut scores: Vec<LabelScore> = softmax(&similarities, request.temperature)
+                .into_iter()
+                .zip(similarities)
+                .zip(&labels)
+                .map(|((score, similarity), label)| LabelScore {
+                    label: label.name.clone(),
+                    score,
+                    similarity,
+                })
+                .collect();
+
+            // Most probable first; ties keep the label order
+            scores.sort_by(|a, b| b.score.total_cmp(&a.score));
+            let label = scores[0].label.clone();
+            scores.truncate(request.top_k.unwrap_or(scores.len()));
+            Classification {
+                object: "classification".to_string(),
+                index,
+                label,
+                scores,
+            }
+        })
+        .collect();
+
+    let total_tokens = result.token_counts.iter().sum::<usize>() + label_tokens;
+    let response = ClassifyResponse {
+        object: "list".to_string(),
+        data,
+        model: request.model.unwrap_or_else(|| state.model_name.clone()),
+        usage: Usage {
+            prompt_tokens: total_tokens,
+            total_tokens,
+        },
+    };
+
+    Ok(codec::encode(response_format, &response))
+}
+
+/// Store a label set under a name, embedding its labels up front
+pub async fn put_label_set(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Path(name): Path<String>,
+    Payload(request): Payload<LabelSetRequest>,
+) -> Result<Response, HandlerError> {
+    if !is_valid_name(&name, MAX_NAME_LENGTH) {
+        return Err(InputError::new(
+            "Label set names must be 1-128 characters of letters, digits, '-' or '_'",
+            "invalid_name",
+        )
+        .into());
+    }
+
+    let labels: Vec<Label> = request.labels.into_iter().map(LabelInput::into_label).collect();
+    validate_labels(&state, &labels)?;
+    label_vectors(&state, &labels).await?;
+
+    let set = LabelSet { name, labels };
+    let stored = set.clone();
+    task::spawn_blocking(move || state.labels.insert(stored.name, stored.labels))
+        .await
+        .map_err(|e| server_error(format!("Label set task failed: {}", e)))?
+        .map_err(|e| server_error(format!("Failed to save label sets: {}", e)))?;
+
+    Ok(codec::encode(response_format, &set))
+}
+
+pub async fn list_label_sets(State(state): State<Arc<AppState>>, response_format: ResponseFormat) -> Response {
+    let list = LabelSetList {
+        object: "list".to_string(),
+        data: state.labels.list(),
+    };
+    codec::encode(response_format, &list)
+}
+
+pub async fn get_label_set(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Path(name): Path<String>,
+) -> Result<Response, HandlerError> {
+    let labels = state.labels.get(&name).ok_or_else(|| label_set_not_found(&name))?;
+    Ok(codec::encode(response_format, &LabelSet { name, labels }))
+}
+
+pub async fn delete_label_set(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, HandlerError> {
+    let removed_name = name.clone();
+    let removed = task::spawn_blocking(move || state.labels.remove(&removed_name))
+        .await
+        .map_err(|e| server_error(format!("Label set task failed: {}", e)))?
+        .map_err(|e| server_error(format!("Failed to save label sets: {}", e)))?;
+    if !removed {
+        return Err(label_set_not_found(&name));
+    }
+
+    Ok(Json(serde_json::json!({
+        "id": name,
+        "object": "label_set",
+        "deleted": true,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_softmax() {
+        let probabilities = softmax(&[0.9, 0.5, 0.1], 0.1);
+        assert!((probabilities.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!(probabilities[0] > 0.95);
+
+        // A higher temperature flattens the distribution
+        let flatter = softmax(&[0.9, 0.5, 0.1], 10.0);
+        assert!(flatter[0] < probabilities[0] && flatter[0] > flatter[1]);
+        assert_eq!(softmax(&[0.3, 0.3], 0.05), vec![0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_centroid() {
+        // Each vector counts equally whatever its length
+        let vector = centroid(&[vec![10.0, 0.0], vec![0.0, 1.0]]);
+        assert_eq!(vector, vec![0.5, 0.5]);
+        assert_eq!(centroid(&[vec![0.0, 0.0]]), vec![0.0, 0.0]);
+    }
+}
@@ -0,0 +1,653 @@
+use axum::{extract::State, http::StatusCode, response::Response, Json};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, PoisonError},
+};
+use tokio::task;
+use tracing::{debug, info};
+
+use crate::codec::{self, Payload, ResponseFormat};
+use crate::collections::lexical::Analyzer;
+use crate::error::{server_error, InputError};
+use crate::handlers::AppState;
+use crate::models::{
+    Cluster, ClusterAlgorithm, ClusterAssignment, ClusterKeyword, ClusterMember, ClusterRequest, ClusterResponse,
+    EmbeddingInput, ErrorResponse, Usage,
+};
+use crate::similarity::{dot, unit};
+
+/// Most items clustered in one request; density-based clustering compares every pair
+const MAX_CLUSTER_ITEMS: usize = 2_000;
+
+/// Largest cluster count accepted for k-means
+const MAX_K: usize = 100;
+
+/// Largest `max_k` when k is chosen automatically, since every count up to it is tried
+const MAX_AUTO_K: usize = 20;
+
+/// Iterations per count while choosing k; only the chosen count runs to `max_iterations`
+const SWEEP_ITERATIONS: usize = 20;
+
+/// Most k-means iterations accepted per run
+const MAX_ITERATIONS: usize = 1_000;
+
+/// Items the silhouette is measured on, evenly spaced over the input
+const SILHOUETTE_SAMPLE: usize = 500;
+
+type HandlerError = (StatusCode, Json<ErrorResponse>);
+
+/// splitmix64, so a seed always gives the same clustering
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    /// Uniform in [0, 1)
+    fn uniform(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// Unit-length mean of each cluster's members; zero for empty clusters
+fn means(units: &[Vec<f32>], assignments: &[Option<usize>], k: usize) -> Vec<Vec<f32>> {
+    let dimensions = units.first().map_or(0, Vec::len);
+    let mut sums = vec![vec![0.0; dimensions]; k];
+    for (vector, cluster) in units.iter().zip(assignments) {
+        if let Some(cluster) = cluster {
+            for (total, value) in sums[*cluster].iter_mut().zip(vector) {
+                *total += value;
+            }
+        }
+    }
+    sums.iter().map(|sum| unit(sum)).collect()
+}
+
+/// The centroid most similar to a unit vector
+fn nearest(centroids: &[Vec<f32>], vector: &[f32]) -> usize {
+    let similarities: Vec<f32> = centroids.iter().map(|centroid| dot(centroid, vector)).collect();
+    (0..similarities.len()).fold(0, |best, c| if similarities[c] > similarities[best] { c } else { best })
+}
+
+/// k-means++ seeding: each further centroid is drawn in proportion to the cosine distance from
+/// the closest centroid so far, which is proportional to the squared distance for unit vectors
+fn seed_centroids(units: &[Vec<f32>], k: usize, rng: &mut Rng) -> Vec<Vec<f32>> {
+    let mut centroids = vec![units[rng.below(units.len())].clone()];
+    let mut distances: Vec<f32> = units.iter().map(|vector| (1.0 - dot(vector, &centroids[0])).max(0.0)).collect();
+
+    while centroids.len() < k {
+        let total: f32 = distances.iter().sum();
+        let chosen = if total > 0.0 {
+            let mut target = rng.uniform() * total;
+            distances
+                .iter()
+                .position(|distance| {
+                    target -= distance;
+                    target < 0.0 && *distance > 0.0
+                })
+                .unwrap_or_else(|| distances.iter().rposition(|distance| *distance > 0.0).unwrap_or(0))
+        } else {
+            // Fewer distinct items than clusters
+            rng.below(units.len())
+        };
+
+        centroids.push(units[chosen].clone());
+        for (distance, vector) in distances.iter_mut().zip(units) {
+            *distance = distance.min((1.0 - dot(vector, &units[chosen])).max(0.0));
+        }
+    }
+    centroids
+}
+
+/// Spherical k-means over unit vectors: items join the centroid with the highest cosine
+/// similarity, and centroids are the normalized means of their members
+pub fn kmeans(units: &[Vec<f32>], k: usize, max_iterations: usize, seed: u64) -> Vec<usize> {
+    let mut rng = Rng(seed);
+    let mut centroids = seed_centroids(units, k, &mut rng);
+    let mut assignments = vec![usize::MAX; units.len()];
+
+    for _ in 0..max_iterations {
+        let mut changed = false;
+        for (assignment, vector) in assignments.iter_mut().zip(units) {
+            let cluster = nearest(&centroids, vector);
+            if *assignment != cluster {
+                *assignment = cluster;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+
+        let labels: Vec<Option<usize>> = assignments.iter().map(|&cluster| Some(cluster)).collect();
+        let mut sizes = vec![0; k];
+        for &cluster in &assignments {
+            sizes[cluster] += 1;
+        }
+        let mut updated = means(units, &labels, k);
+
+        // A cluster that lost all its members restarts at the item farthest from its centroid
+        for cluster in 0..k {
+            if sizes[cluster] > 0 {
+                continue;
+            }
+            let farthest = (0..units.len())
+                .filter(|&i| sizes[assignments[i]] > 1)
+                .min_by(|&a, &b| {
+                    dot(&units[a], &updated[assignments[a]]).total_cmp(&dot(&units[b], &updated[assignments[b]]))
+                });
+            if let Some(item) = farthest {
+                sizes[assignments[item]] -= 1;
+                sizes[cluster] = 1;
+                assignments[item] = cluster;
+                updated[cluster] = units[item].clone();
+            }
+        }
+        centroids = updated;
+    }
+
+    assignments
+}
+
+/// Density-based clustering in the manner of DBSCAN. Items with at least `min_points` items
+/// (themselves included) within cosine distance `eps` are core items; clusters are the core
+/// items reachable from each other plus their neighbours, and everything else is noise.
+pub fn density(units: &[Vec<f32>], eps: f32, min_points: usize) -> Vec<Option<usize>> {
+    let threshold = 1.0 - eps;
+    let neighbours: Vec<Vec<usize>> = units
+        .iter()
+        .map(|vector| (0..units.len()).filter(|&j| dot(vector, &units[j]) >= threshold).collect())
+        .collect();
+
+    let mut labels = vec![None; units.len()];
+    let mut next = 0;
+    for start in 0..units.len() {
+        if labels[start].is_some() || neighbours[start].len() < min_points {
+            continue;
+        }
+
+        labels[start] = Some(next);
+        let mut pending = vec![start];
+        while let Some(item) = pending.pop() {
+            if neighbours[item].len() < min_points {
+                continue;
+            }
+            for &neighbour in &neighbours[item] {
+                if labels[neighbour].is_none() {
+                    labels[neighbour] = Some(next);
+                    pending.push(neighbour);
+                }
+            }
+        }
+        next += 1;
+    }
+    labels
+}
+
+/// Evenly spaced positions, at most `limit` of them
+fn sample(count: usize, limit: usize) -> Vec<usize> {
+    if count <= limit {
+        (0..count).collect()
+    } else {
+        (0..limit).map(|i| i * count / limit).collect()
+    }
+}
+
+/// Cosine similarities between every pair of sampled items
+fn similarity_matrix(units: &[Vec<f32>], sample: &[usize]) -> Vec<Vec<f32>> {
+    sample
+        .iter()
+        .map(|&a| sample.iter().map(|&b| dot(&units[a], &units[b])).collect())
+        .collect()
+}
+
+/// Mean silhouette, with cosine distance, of the labelled items of a pairwise similarity
+/// matrix. None unless at least two clusters occur.
+pub fn silhouette(similarities: &[Vec<f32>], labels: &[Option<usize>]) -> Option<f32> {
+    let clusters: BTreeMap<usize, usize> = labels.iter().flatten().fold(BTreeMap::new(), |mut sizes, &cluster| {
+        *sizes.entry(cluster).or_default() += 1;
+        sizes
+    });
+    if clusters.len() < 2 {
+        return None;
+    }
+
+    let mut total = 0.0;
+    let mut count = 0;
+    for (i, label) in labels.iter().enumerate() {
+        let Some(own) = label else {
+            continue;
+        };
+        count += 1;
+        // A lone member's silhouette is 0 by convention
+        if clusters[own] == 1 {
+            continue;
+        }
+
+        let mut distances: BTreeMap<usize, f32> = BTreeMap::new();
+        for (j, other) in labels.iter().enumerate() {
+            if let (Some(other), true) = (other, i != j) {
+                *distances.entry(*other).or_default() += 1.0 - similarities[i][j];
+            }
+        }
+        let within = distances.get(own).copied().unwrap_or(0.0) / (clusters[own] - 1) as f32;
+        let between = distances
+            .iter()
+            .filter(|(cluster, _)| *cluster != own)
+            .map(|(cluster, distance)| distance / clusters[cluster] as f32)
+            .fold(f32::INFINITY, f32::min);
+
+        let scale = within.max(between);
+        if scale > 0.0 {
+            total += (between - within) / scale;
+        }
+    }
+    Some(total / count.max(1) as f32)
+}
+
+/// Run a short k-means for every k between `min_k` and `max_k`, then rerun the k with the best
+/// silhouette on a sample of the items to `max_iterations`
+pub fn auto_kmeans(units: &[Vec<f32>], min_k: usize, max_k: usize, max_iterations: usize, seed: u64) -> Vec<usize> {
+    // The silhouette needs at least two clusters and a cluster with more than one item
+    let max_k = max_k.min(units.len().saturating_sub(1));
+    if max_k < 2 {
+        return kmeans(units, 1, max_iterations, seed);
+    }
+
+    let positions = sample(units.len(), SILHOUETTE_SAMPLE);
+    let similarities = similarity_matrix(units, &positions);
+    let mut best: Option<(f32, usize)> = None;
+    for k in min_k.min(max_k)..=max_k {
+        let assignments = kmeans(units, k, max_iterations.min(SWEEP_ITERATIONS), seed);
+        let labels: Vec<Option<usize>> = positions.iter().map(|&i| Some(assignments[i])).collect();
+        let score = silhouette(&similarities, &labels).unwrap_or(-1.0);
+        debug!("k-means with k = {} has silhouette {}", k, score);
+        if best.is_none_or(|(best, _)| score > best) {
+            best = Some((score, k));
+        }
+    }
+    let k = best.map_or(1, |(_, k)| k);
+    kmeans(units, k, max_iterations, seed)
+}
+
+/// Rank the terms of each cluster by class-based TF-IDF: a term's share of the cluster's terms,
+/// weighted by how rare it is across all clusters
+pub fn keywords(clusters: &[Vec<&str>], top_n: usize) -> Vec<Vec<ClusterKeyword>> {
+    let analyzer = Analyzer::english();
+    let counts: Vec<HashMap<String, u32>> = clusters
+        .iter()
+        .map(|texts| {
+            let mut counts = HashMap::new();
+            for text in texts {
+                for term in analyzer.terms(text) {
+                    if term.chars().count() > 1 && !term.chars().all(|c| c.is_numeric()) {
+                        *counts.entry(term).or_default() += 1;
+                    }
+                }
+            }
+            counts
+        })
+        .collect();
+
+    let mut frequencies: HashMap<&str, u32> = HashMap::new();
+    for cluster in &counts {
+        for (term, count) in cluster {
+            *frequencies.entry(term.as_str()).or_default() += count;
+        }
+    }
+    let average = frequencies.values().sum::<u32>() as f32 / clusters.len().max(1) as f32;
+
+    counts
+        .iter()
+        .map(|cluster| {
+            let total = cluster.values().sum::<u32>().max(1) as f32;
+            let mut ranked: Vec<ClusterKeyword> = cluster
+                .iter()
+                .map(|(term, &count)| ClusterKeyword {
+                    term: term.clone(),
+                    score: count as f32 / total * (1.0 + average / frequencies[term.as_str()] as f32).ln(),
+                })
+                .collect();
+            ranked.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.term.cmp(&b.term)));
+            ranked.truncate(top_n);
+            ranked
+        })
+        .collect()
+}
+
+/// The items of a request: embeddings with the ids and texts reported back
+pub(crate) struct Items {
+    pub vectors: Vec<Vec<f32>>,
+    pub ids: Vec<Option<String>>,
+    pub texts: Vec<Option<String>>,
+}
+
+struct Clustering {
+    clusters: Vec<Cluster>,
+    assignments: Vec<ClusterAssignment>,
+    silhouette: Option<f32>,
+    noise: usize,
+}
+
+fn cluster_items(items: Items, algorithm: ClusterAlgorithm, representatives: usize, top_keywords: usize) -> Clustering {
+    let units: Vec<Vec<f32>> = items.vectors.iter().map(|vector| unit(vector)).collect();
+    let labels: Vec<Option<usize>> = match algorithm {
+        ClusterAlgorithm::Kmeans {
+            k: Some(k),
+            max_iterations,
+            seed,
+            ..
+        } => kmeans(&units, k, max_iterations, seed).into_iter().map(Some).collect(),
+        ClusterAlgorithm::Kmeans {
+            k: None,
+            min_k,
+            max_k,
+            max_iterations,
+            seed,
+        } => auto_kmeans(&units, min_k, max_k, max_iterations, seed).into_iter().map(Some).collect(),
+        ClusterAlgorithm::Density { eps, min_points } => density(&units, eps, min_points),
+    };
+
+    // Number clusters by size, largest first, ties by their first member
+    let mut members: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (item, label) in labels.iter().enumerate() {
+        if let Some(label) = label {
+            members.entry(*label).or_default().push(item);
+        }
+    }
+    let mut groups: Vec<Vec<usize>> = members.into_values().collect();
+    groups.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a[0].cmp(&b[0])));
+
+    let mut ids = vec![None; units.len()];
+    for (id, group) in groups.iter().enumerate() {
+        for &item in group {
+            ids[item] = Some(id);
+        }
+    }
+    let centroids = means(&units, &ids, groups.len());
+
+    let positions = sample(units.len(), SILHOUETTE_SAMPLE);
+    let sampled: Vec<Option<usize>> = positions.iter().map(|&i| ids[i]).collect();
+    let silhouette = silhouette(&similarity_matrix(&units, &positions), &sampled);
+
+    let texts: Vec<Vec<&str>> = groups
+        .iter()
+        .map(|group| group.iter().filter_map(|&item| items.texts[item].as_deref()).collect())
+        .collect();
+    let keywords = keywords(&texts, top_keywords);
+
+    let clusters = groups
+        .iter()
+        .zip(centroids)
+        .zip(keywords)
+        .enumerate()
+        .map(|(id, ((group, centroid), keywords))| {
+            let mut closest: Vec<(usize, f32)> = group.iter().map(|&item| (item, dot(&units[item], &centroid))).collect();
+            closest.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            closest.truncate(representatives);
+
+            Cluster {
+                id,
+                size: group.len(),
+                representatives: closest
+                    .into_iter()
+                    .map(|(index, similarity)| ClusterMember {
+                        index,
+                        id: items.ids[index].clone(),
+                        text: items.texts[index].clone(),
+                        similarity,
+                    })
+                    .collect(),
+                centroid,
+                keywords,
+            }
+        })
+        .collect();
+
+    let noise = ids.iter().filter(|id| id.is_none()).count();
+    let assignments = ids
+        .into_iter()
+        .zip(items.ids)
+        .enumerate()
+        .map(|(index, (cluster, id))| ClusterAssignment { index, id, cluster })
+        .collect();
+
+    Clustering {
+        clusters,
+        assignments,
+        silhouette,
+        noise,
+    }
+}
+
+fn validate_algorithm(algorithm: &ClusterAlgorithm, items: usize) -> Result<(), InputError> {
+    let invalid = |message: String| Err(InputError::new(message, "invalid_algorithm"));
+    match *algorithm {
+        ClusterAlgorithm::Kmeans {
+            k,
+            min_k,
+            max_k,
+            max_iterations,
+            ..
+        } => {
+            if let Some(k) = k {
+                if k == 0 || k > items.min(MAX_K) {
+                    return invalid(format!("k must be between 1 and the number of items, at most {}", MAX_K));
+                }
+            } else if min_k < 2 || max_k < min_k || max_k > MAX_AUTO_K {
+                return invalid(format!("min_k and max_k must satisfy 2 <= min_k <= max_k <= {}", MAX_AUTO_K));
+            }
+            if max_iterations == 0 || max_iterations > MAX_ITERATIONS {
+                return invalid(format!("max_iterations must be between 1 and {}", MAX_ITERATIONS));
+            }
+        }
+        ClusterAlgorithm::Density { eps, min_points } => {
+            if !(eps > 0.0 && eps <= 2.0) {
+                return invalid("eps must be a cosine distance above 0 and at most 2".to_string());
+            }
+            if min_points == 0 {
+                return invalid("min_points must be at least 1".to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Stored vectors and texts of every item in a collection
+fn collection_items(state: &AppState, name: &str, limit: usize) -> Result<Items, HandlerError> {
+    let collection = state.collections.get(name)?;
+    let collection = collection.read().unwrap_or_else(PoisonError::into_inner);
+    if collection.len() > limit {
+        return Err(InputError::new(
+            format!("Collection '{}' has more than {} items", name, limit),
+            "too_many_items",
+        )
+        .into());
+    }
+    if collection.is_empty() {
+        return Err(InputError::new(format!("Collection '{}' is empty", name), "empty_input").into());
+    }
+
+    let mut items = Items {
+        vectors: Vec::new(),
+        ids: Vec::new(),
+        texts: Vec::new(),
+    };
+    for position in collection.positions() {
+        let item = collection.item(position, false);
+        items.vectors.push(collection.vector(position).to_vec());
+        items.ids.push(Some(item.id));
+        items.texts.push(item.text);
+    }
+    Ok(items)
+}
+
+/// Embed the input texts, or read the stored items of a collection, along with the tokens used.
+/// Requests that work on the whole set at once are bound by `limit` rather than the batch size
+/// limit.
+pub(crate) async fn load_items(
+    state: &AppState,
+    input: Option<EmbeddingInput>,
+    collection: Option<&str>,
+    limit: usize,
+) -> Result<(Items, usize), HandlerError> {
+    match (input, collection) {
+        (Some(input), None) => {
+            let texts = input.into_texts();
+            if texts.is_empty() {
+                return Err(InputError::new("Input cannot be empty", "empty_input").into());
+            }
+            if texts.len() > limit {
+                return Err(InputError::new(format!("At most {} texts are accepted at once", limit), "too_many_items").into());
+            }
+            state.validate_lengths(&texts)?;
+
+            let result = state.encode_texts(texts.clone()).await?;
+            let items = Items {
+                vectors: result.embeddings,
+                ids: vec![None; texts.len()],
+                texts: texts.into_iter().map(Some).collect(),
+            };
+            Ok((items, result.token_counts.iter().sum()))
+        }
+        (None, Some(name)) => Ok((collection_items(state, name, limit)?, 0)),
+        _ => Err(InputError::new("Give either input or collection", "invalid_input").into()),
+    }
+}
+
+/// Group texts, or the items of a collection, into clusters with representative members and
+/// keywords
+pub async fn cluster(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Payload(request): Payload<ClusterRequest>,
+) -> Result<Response, HandlerError> {
+    debug!("Received cluster request with {:?}", request.algorithm);
+    let (items, total_tokens) =
+        load_items(&state, request.input, request.collection.as_deref(), MAX_CLUSTER_ITEMS).await?;
+    validate_algorithm(&request.algorithm, items.vectors.len())?;
+
+    let algorithm = request.algorithm;
+    let (representatives, keywords) = (request.representatives, request.keywords);
+    let count = items.vectors.len();
+    let clustering = task::spawn_blocking(move || cluster_items(items, algorithm, representatives, keywords))
+        .await
+        .map_err(|e| server_error(format!("Clustering task failed: {}", e)))?;
+    info!(
+        "Clustered {} items into {} clusters ({} noise)",
+        count,
+        clustering.clusters.len(),
+        clustering.noise
+    );
+
+    let response = ClusterResponse {
+        object: "clustering".to_string(),
+        algorithm: match algorithm {
+            ClusterAlgorithm::Kmeans { .. } => "kmeans",
+            ClusterAlgorithm::Density { .. } => "density",
+        }
+        .to_string(),
+        silhouette: clustering.silhouette,
+        clusters: clustering.clusters,
+        assignments: clustering.assignments,
+        noise: clustering.noise,
+        model: request.model.unwrap_or_else(|| state.model_name.clone()),
+        usage: Usage {
+            prompt_tokens: total_tokens,
+            total_tokens,
+        },
+    };
+
+    Ok(codec::encode(response_format, &response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three tight groups around orthogonal directions
+    fn blobs() -> Vec<Vec<f32>> {
+        let mut vectors = Vec::new();
+        for i in 0..30 {
+            let noise = (i as f32 * 0.71).sin() * 0.05;
+            let mut vector = vec![noise; 4];
+            vector[i % 3] = 1.0;
+            vectors.push(unit(&vector));
+        }
+        vectors
+    }
+
+    fn same_partition(a: &[usize], expected: impl Fn(usize) -> usize) -> bool {
+        (0..a.len()).all(|i| (0..a.len()).all(|j| (a[i] == a[j]) == (expected(i) == expected(j))))
+    }
+
+    #[test]
+    fn test_kmeans() {
+        let units = blobs();
+        let assignments = kmeans(&units, 3, 100, 7);
+        assert!(same_partition(&assignments, |i| i % 3));
+        assert_eq!(kmeans(&units, 3, 100, 7), assignments);
+
+        // Asking for more clusters than distinct directions still fills every cluster
+        let assignments = kmeans(&units, 5, 100, 1);
+        for cluster in 0..5 {
+            assert!(assignments.contains(&cluster));
+        }
+    }
+
+    #[test]
+    fn test_auto_kmeans() {
+        let units = blobs();
+        let assignments = auto_kmeans(&units, 2, 8, 100, 0);
+        assert!(same_partition(&assignments, |i| i % 3));
+
+        // Two items allow only one cluster
+        assert_eq!(auto_kmeans(&units[..2], 2, 8, 100, 0), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_density() {
+        let mut units = blobs()[..20].to_vec();
+        units.push(unit(&[0.0, 0.0, 0.0, 1.0]));
+        let labels = density(&units, 0.1, 3);
+
+        assert_eq!(labels[20], None);
+        let clustered: Vec<usize> = labels[..20].iter().map(|label| label.unwrap()).collect();
+        assert!(same_partition(&clustered, |i| i % 3));
+    }
+
+    #[test]
+    fn test_silhouette() {
+        let units = blobs();
+        let positions: Vec<usize> = (0..units.len()).collect();
+        let similarities = similarity_matrix(&units, &positions);
+
+        let good: Vec<Option<usize>> = (0..units.len()).map(|i| Some(i % 3)).collect();
+        let bad: Vec<Option<usize>> = (0..units.len()).map(|i| Some(i % 2)).collect();
+        assert!(silhouette(&similarities, &good).unwrap() > 0.9);
+        assert!(silhouette(&similarities, &bad).unwrap() < 0.1);
+        assert_eq!(silhouette(&similarities, &vec![Some(0); units.len()]), None);
+    }
+
+    #[test]
+    fn test_keywords() {
+        let clusters = vec![
+            vec!["The refund was late", "Refund my order", "Where is the refund?"],
+            vec!["Login fails with 2FA", "Cannot login to the app", "The app crashes"],
+        ];
+        let keywords = keywords(&clusters, 2);
+        assert_eq!(keywords[0][0].term, "refund");
+        assert_eq!(keywords[1][0].term, "app");
+        assert_eq!(keywords[1][1].term, "login");
+        assert!(keywords.iter().flatten().all(|keyword| keyword.term != "the"));
+    }
+}
@@ -0,0 +1,652 @@
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Query, Request},
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use arrow_array::{
+    Array, ArrayRef, FixedSizeListArray, Float16Array, Float32Array, Int8Array, RecordBatch, UInt32Array,
+    UInt8Array,
+};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema};
+use half::f16;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::error;
+
+use crate::models::{
+    EmbeddingData, EmbeddingFormat, EmbeddingResponse, EmbeddingVector, ErrorDetail, ErrorResponse,
+};
+
+pub const JSON: &str = "application/json";
+pub const MSGPACK: &str = "application/msgpack";
+pub const CBOR: &str = "application/cbor";
+pub const TENSOR: &str = "application/octet-stream";
+pub const ARROW_STREAM: &str = "application/vnd.apache.arrow.stream";
+pub const NPY: &str = "application/x-npy";
+
+/// Serialization format for request and response bodies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResponseFormat {
+    #[default]
+    Json,
+    MessagePack,
+    Cbor,
+    /// Raw little-endian tensor, only available for embedding responses
+    Tensor,
+    /// Arrow IPC stream, only available for embedding responses
+    Arrow,
+    /// NumPy `.npy` array, only available for embedding responses
+    Npy,
+}
+
+impl ResponseFormat {
+    /// Map a media type (parameters are ignored) to a known format
+    pub fn from_media_type(media_type: &str) -> Option<Self> {
+        let essence = media_type.split(';').next().unwrap_or_default().trim();
+
+        match essence.to_ascii_lowercase().as_str() {
+            JSON | "*/*" | "application/*" => Some(Self::Json),
+            MSGPACK | "application/x-msgpack" | "application/vnd.msgpack" => Some(Self::MessagePack),
+            CBOR => Some(Self::Cbor),
+            TENSOR => Some(Self::Tensor),
+            ARROW_STREAM => Some(Self::Arrow),
+            NPY => Some(Self::Npy),
+            _ => None,
+        }
+    }
+
+    /// Map a short format name (as used by the `format` query parameter) to a known format
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "msgpack" => Some(Self::MessagePack),
+            "cbor" => Some(Self::Cbor),
+            "tensor" => Some(Self::Tensor),
+            "arrow" => Some(Self::Arrow),
+            "npy" => Some(Self::Npy),
+            _ => None,
+        }
+    }
+
+    /// Whether this format encodes the embeddings as a single dense matrix
+    pub fn is_matrix(&self) -> bool {
+        matches!(self, Self::Tensor | Self::Arrow | Self::Npy)
+    }
+
+    /// Pick the recognized entry of the `Accept` header with the highest `q` weight, the first
+    /// one among equals, defaulting to JSON. Entries with `q=0` are never picked.
+    pub fn from_accept(headers: &HeaderMap) -> Self {
+        let mut entries: Vec<(f32, &str)> = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|entry| (quality(entry), entry))
+            .filter(|(q, _)| *q > 0.0)
+            .collect();
+        // Stable, so entries of equal weight keep the client's order
+        entries.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        entries
+            .into_iter()
+            .find_map(|(_, entry)| Self::from_media_type(entry))
+            .unwrap_or_default()
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Json => JSON,
+            Self::MessagePack => MSGPACK,
+            Self::Cbor => CBOR,
+            Self::Tensor => TENSOR,
+            Self::Arrow => ARROW_STREAM,
+            Self::Npy => NPY,
+        }
+    }
+}
+
+/// Negotiates the response format from the `format` query parameter, then the `Accept` header
+impl<S> FromRequestParts<S> for ResponseFormat
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Query(FormatParam { format }) = Query::try_from_uri(&parts.uri)
+            .map_err(|rejection| bad_request(rejection.body_text(), "invalid_query"))?;
+
+        match format {
+            Some(name) => Self::from_name(&name)
+                .ok_or_else(|| bad_request(format!("Unsupported response format: {}", name), "unsupported_format")),
+            None => Ok(Self::from_accept(&parts.headers)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct FormatParam {
+    format: Option<String>,
+}
+
+/// The `q` parameter of an `Accept` entry; 1 when absent or malformed
+fn quality(entry: &str) -> f32 {
+    entry
+        .split(';')
+        .skip(1)
+        .find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            name.trim()
+                .eq_ignore_ascii_case("q")
+                .then(|| value.trim().parse().unwrap_or(1.0))
+        })
+        .unwrap_or(1.0)
+}
+
+fn bad_request(message: String, code: &str) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            error: ErrorDetail {
+                message,
+                error_type: "invalid_request_error".to_string(),
+                code: Some(code.to_string()),
+            },
+        }),
+    )
+        .into_response()
+}
+
+/// Request body extractor accepting JSON, MessagePack or CBOR based on `Content-Type`.
+///
+/// JSON bodies are delegated to axum's `Json` extractor so existing rejections are unchanged.
+pub struct Payload<T>(pub T);
+
+impl<T, S> FromRequest<S> for Payload<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let format = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(ResponseFormat::from_media_type);
+
+        match format {
+            Some(ResponseFormat::MessagePack) => {
+                let bytes = Bytes::from_request(req, state)
+                    .await
+                    .map_err(IntoResponse::into_response)?;
+                rmp_serde::from_slice(&bytes)
+                    .map(Payload)
+                    .map_err(|e| decode_error(e.to_string()))
+            }
+            Some(ResponseFormat::Cbor) => {
+                let bytes = Bytes::from_request(req, state)
+                    .await
+                    .map_err(IntoResponse::into_response)?;
+                ciborium::from_reader(bytes.as_ref())
+                    .map(Payload)
+                    .map_err(|e| decode_error(e.to_string()))
+            }
+            _ => Json::<T>::from_request(req, state)
+                .await
+                .map(|Json(value)| Payload(value))
+                .map_err(IntoResponse::into_response),
+        }
+    }
+}
+
+fn decode_error(message: String) -> Response {
+    (
+        StatusCode::UNPROCESSABLE_ENTITY,
+        Json(ErrorResponse {
+            error: ErrorDetail {
+                message: format!("Failed to deserialize the request body: {}", message),
+                error_type: "invalid_request_error".to_string(),
+                code: Some("invalid_body".to_string()),
+            },
+        }),
+    )
+        .into_response()
+}
+
+fn encode_error(message: String) -> Response {
+    error!("Failed to encode response: {}", message);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ErrorResponse {
+            error: ErrorDetail {
+                message: format!("Failed to encode response: {}", message),
+                error_type: "server_error".to_string(),
+                code: None,
+            },
+        }),
+    )
+        .into_response()
+}
+
+/// Rejects a matrix format (tensor, Arrow, npy) negotiated for a response other than embeddings
+pub fn not_acceptable(format: ResponseFormat) -> Response {
+    (
+        StatusCode::NOT_ACCEPTABLE,
+        Json(ErrorResponse {
+            error: ErrorDetail {
+                message: format!(
+                    "{} responses are only available from /v1/embeddings",
+                    format.content_type()
+                ),
+                error_type: "invalid_request_error".to_string(),
+                code: Some("unsupported_format".to_string()),
+            },
+        }),
+    )
+        .into_response()
+}
+
+/// Serialize a value as JSON, MessagePack or CBOR.
+///
+/// Matrix formats (tensor, Arrow, npy) have no generic encoding and are answered with a 406.
+pub fn encode<T: Serialize>(format: ResponseFormat, value: &T) -> Response {
+    let bytes = match format {
+        ResponseFormat::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+        ResponseFormat::Cbor => {
+            let mut buffer = Vec::new();
+            ciborium::into_writer(value, &mut buffer)
+                .map(|_| buffer)
+                .map_err(|e| e.to_string())
+        }
+        ResponseFormat::Json => return Json(value).into_response(),
+        ResponseFormat::Tensor | ResponseFormat::Arrow | ResponseFormat::Npy => return not_acceptable(format),
+    };
+
+    match bytes {
+        Ok(bytes) => (
+            [(header::CONTENT_TYPE, HeaderValue::from_static(format.content_type()))],
+            bytes,
+        )
+            .into_response(),
+        Err(message) => encode_error(message),
+    }
+}
+
+/// Encode an embedding response in any negotiated format, including the matrix formats.
+///
+/// `token_counts` holds the per-input token counts and is only used by the Arrow format.
+pub fn embeddings_response(
+    response_format: ResponseFormat,
+    response: &EmbeddingResponse,
+    token_counts: &[usize],
+    format: EmbeddingFormat,
+) -> Response {
+    let body = match response_format {
+        ResponseFormat::Tensor => Matrix::from_data(&response.data, format).map(|m| m.to_tensor()),
+        ResponseFormat::Npy => Matrix::from_data(&response.data, format).map(|m| m.to_npy()),
+        ResponseFormat::Arrow => Matrix::from_data(&response.data, format)
+            .and_then(|m| m.into_arrow(&response.data, token_counts)),
+        other => return encode(other, response),
+    };
+    let body = match body {
+        Ok(body) => body,
+        Err(message) => return encode_error(message),
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(response_format.content_type()),
+    );
+    if let Ok(model) = HeaderValue::from_str(&response.model) {
+        headers.insert("x-embedding-model", model);
+    }
+    headers.insert("x-total-tokens", HeaderValue::from(response.usage.total_tokens));
+
+    (headers, body).into_response()
+}
+
+/// Element type codes written into the tensor header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TensorDtype {
+    Float32 = 0,
+    Float16 = 1,
+    Int8 = 2,
+    Uint8 = 3,
+}
+
+pub const TENSOR_HEADER_LEN: usize = 12;
+
+/// Embeddings flattened row-major into a single typed buffer
+enum Values {
+    Float32(Vec<f32>),
+    Float16(Vec<f16>),
+    Int8(Vec<i8>),
+    Uint8(Vec<u8>),
+}
+
+struct Matrix {
+    rows: usize,
+    columns: usize,
+    values: Values,
+}
+
+impl Matrix {
+    /// Flatten embedding vectors into a matrix whose element type follows `format`.
+    /// Packed binary formats use the packed byte length as the column count.
+    fn from_data(data: &[EmbeddingData], format: EmbeddingFormat) -> Result<Self, String> {
+        let columns = data.first().map(|d| vector_len(&d.embedding)).unwrap_or_default();
+        let capacity = data.len() * columns;
+
+        let mut values = match format {
+            EmbeddingFormat::Float16 => Values::Float16(Vec::with_capacity(capacity)),
+            EmbeddingFormat::Int8 | EmbeddingFormat::Binary => Values::Int8(Vec::with_capacity(capacity)),
+            EmbeddingFormat::Uint8 | EmbeddingFormat::Ubinary => Values::Uint8(Vec::with_capacity(capacity)),
+            EmbeddingFormat::Float | EmbeddingFormat::Base64 => Values::Float32(Vec::with_capacity(capacity)),
+        };
+
+        for item in data {
+            if vector_len(&item.embedding) != columns {
+                return Err("Embeddings have inconsistent dimensions".to_string());
+            }
+            match (&item.embedding, &mut values) {
+                (EmbeddingVector::Float(v), Values::Float32(out)) => out.extend_from_slice(v),
+                (EmbeddingVector::Float(v), Values::Float16(out)) => {
+                    out.extend(v.iter().map(|&x| f16::from_f32(x)))
+                }
+                (EmbeddingVector::Int8(v), Values::Int8(out)) => out.extend_from_slice(v),
+                (EmbeddingVector::Uint8(v), Values::Uint8(out)) => out.extend_from_slice(v),
+                _ => return Err(format!("{:?} embeddings cannot be written as a matrix", format)),
+            }
+        }
+
+        Ok(Self {
+            rows: data.len(),
+            columns,
+            values,
+        })
+    }
+
+    fn dtype(&self) -> TensorDtype {
+        match self.values {
+            Values::Float32(_) => TensorDtype::Float32,
+            Values::Float16(_) => TensorDtype::Float16,
+            Values::Int8(_) => TensorDtype::Int8,
+            Values::Uint8(_) => TensorDtype::Uint8,
+        }
+    }
+
+    fn le_bytes(&self) -> Vec<u8> {
+        match &self.values {
+            Values::Float32(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Values::Float16(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Values::Int8(v) => v.iter().map(|&x| x as u8).collect(),
+            Values::Uint8(v) => v.clone(),
+        }
+    }
+
+    /// Raw tensor layout (all little-endian): `u32` row count, `u32` row length,
+    /// `u32` dtype code (see [`TensorDtype`]), followed by the rows back to back.
+    fn to_tensor(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(TENSOR_HEADER_LEN);
+        body.extend_from_slice(&(self.rows as u32).to_le_bytes());
+        body.extend_from_slice(&(self.columns as u32).to_le_bytes());
+        body.extend_from_slice(&(self.dtype() as u32).to_le_bytes());
+        body.extend(self.le_bytes());
+        body
+    }
+
+    /// NumPy `.npy` (format version 1.0) with shape `(rows, columns)`
+    fn to_npy(&self) -> Vec<u8> {
+        let descr = match self.dtype() {
+            TensorDtype::Float32 => "<f4",
+            TensorDtype::Float16 => "<f2",
+            TensorDtype::Int8 => "|i1",
+            TensorDtype::Uint8 => "|u1",
+        };
+        let mut header = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+            descr, self.rows, self.columns
+        );
+        // Magic (6) + version (2) + header length (2) + header must be a multiple of 64 bytes
+        let unpadded = 10 + header.len() + 1;
+        header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+        header.push('\n');
+
+        let mut body = Vec::with_capacity(10 + header.len());
+        body.extend_from_slice(b"\x93NUMPY\x01\x00");
+        body.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        body.extend_from_slice(header.as_bytes());
+        body.extend(self.le_bytes());
+        body
+    }
+
+    /// Arrow IPC stream with columns `index`, `embedding` (fixed-size list) and `token_count`
+    fn into_arrow(self, data: &[EmbeddingData], token_counts: &[usize]) -> Result<Vec<u8>, String> {
+        let values: ArrayRef = match self.values {
+            Values::Float32(v) => Arc::new(Float32Array::from(v)),
+            Values::Float16(v) => Arc::new(Float16Array::from(v)),
+            Values::Int8(v) => Arc::new(Int8Array::from(v)),
+            Values::Uint8(v) => Arc::new(UInt8Array::from(v)),
+        };
+        let item = Arc::new(Field::new("item", values.data_type().clone(), false));
+        let embeddings = FixedSizeListArray::try_new(item, self.columns as i32, values, None)
+            .map_err(|e| e.to_string())?;
+
+        let indices = UInt32Array::from_iter_values(data.iter().map(|d| d.index as u32));
+        let token_counts = UInt32Array::from_iter_values(
+            (0..data.len()).map(|i| token_counts.get(i).copied().unwrap_or_default() as u32),
+        );
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("index", DataType::UInt32, false),
+            Field::new("embedding", embeddings.data_type().clone(), false),
+            Field::new("token_count", DataType::UInt32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(indices), Arc::new(embeddings), Arc::new(token_counts)],
+        )
+        .map_err(|e| e.to_string())?;
+
+        let mut writer = StreamWriter::try_new(Vec::new(), &schema).map_err(|e| e.to_string())?;
+        writer.write(&batch).map_err(|e| e.to_string())?;
+        writer.finish().map_err(|e| e.to_string())?;
+        writer.into_inner().map_err(|e| e.to_string())
+    }
+}
+
+fn vector_len(vector: &EmbeddingVector) -> usize {
+    match vector {
+        EmbeddingVector::Float(values) => values.len(),
+        EmbeddingVector::Int8(values) => values.len(),
+        EmbeddingVector::Uint8(values) => values.len(),
+        EmbeddingVector::Base64(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{EmbeddingInput, EmbeddingRequest, Usage};
+
+    fn response_with(embeddings: Vec<EmbeddingVector>) -> EmbeddingResponse {
+        EmbeddingResponse {
+            object: "list".to_string(),
+            data: embeddings
+                .into_iter()
+                .enumerate()
+                .map(|(index, embedding)| EmbeddingData {
+                    object: "embedding".to_string(),
+                    embedding,
+                    index,
+                })
+                .collect(),
+            model: "test-model".to_string(),
+            usage: Usage {
+                prompt_tokens: 3,
+                total_tokens: 3,
+            },
+        }
+    }
+
+    #[test]
+    fn test_accept_negotiation() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(ResponseFormat::from_accept(&headers), ResponseFormat::Json);
+
+        headers.insert(header::ACCEPT, HeaderValue::from_static("text/html, application/msgpack;q=0.9"));
+        assert_eq!(ResponseFormat::from_accept(&headers), ResponseFormat::MessagePack);
+
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/CBOR"));
+        assert_eq!(ResponseFormat::from_accept(&headers), ResponseFormat::Cbor);
+
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/octet-stream"));
+        assert_eq!(ResponseFormat::from_accept(&headers), ResponseFormat::Tensor);
+
+        headers.insert(header::ACCEPT, HeaderValue::from_static("text/plain"));
+        assert_eq!(ResponseFormat::from_accept(&headers), ResponseFormat::Json);
+
+        // Weights take precedence over order, and q=0 rules a format out
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/json;q=0.5, application/msgpack"));
+        assert_eq!(ResponseFormat::from_accept(&headers), ResponseFormat::MessagePack);
+
+        headers.insert(header::ACCEPT, HeaderValue::from_static("*/*;q=0.1, application/x-npy; Q=0.8"));
+        assert_eq!(ResponseFormat::from_accept(&headers), ResponseFormat::Npy);
+
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/msgpack;q=0, application/cbor;q=0.2"));
+        assert_eq!(ResponseFormat::from_accept(&headers), ResponseFormat::Cbor);
+    }
+
+    #[test]
+    fn test_msgpack_request_round_trip() {
+        let request = EmbeddingRequest {
+            input: EmbeddingInput::StringArray(vec!["a".to_string(), "b".to_string()]),
+            model: None,
+            encoding_format: Some(EmbeddingFormat::Int8),
+        };
+
+        let bytes = rmp_serde::to_vec_named(&request).unwrap();
+        let parsed: EmbeddingRequest = rmp_serde::from_slice(&bytes).unwrap();
+
+        match parsed.input {
+            EmbeddingInput::StringArray(texts) => assert_eq!(texts, vec!["a", "b"]),
+            EmbeddingInput::String(_) => panic!("Expected array input"),
+        }
+        assert_eq!(parsed.encoding_format, Some(EmbeddingFormat::Int8));
+    }
+
+    #[test]
+    fn test_cbor_response_round_trip() {
+        let response = response_with(vec![EmbeddingVector::Float(vec![0.5, -0.25])]);
+
+        let mut buffer = Vec::new();
+        ciborium::into_writer(&response, &mut buffer).unwrap();
+        let parsed: EmbeddingResponse = ciborium::from_reader(buffer.as_slice()).unwrap();
+
+        assert_eq!(parsed.data[0].embedding, EmbeddingVector::Float(vec![0.5, -0.25]));
+        assert_eq!(parsed.usage.total_tokens, 3);
+    }
+
+    #[test]
+    fn test_format_query_names() {
+        assert_eq!(ResponseFormat::from_name("npy"), Some(ResponseFormat::Npy));
+        assert_eq!(ResponseFormat::from_name("Arrow"), Some(ResponseFormat::Arrow));
+        assert_eq!(ResponseFormat::from_name("parquet"), None);
+        assert_eq!(
+            ResponseFormat::from_media_type("application/vnd.apache.arrow.stream"),
+            Some(ResponseFormat::Arrow)
+        );
+    }
+
+    #[test]
+    fn test_tensor_layout() {
+        let response = response_with(vec![
+            EmbeddingVector::Int8(vec![-1, 2, 3]),
+            EmbeddingVector::Int8(vec![4, 5, -128]),
+        ]);
+
+        let body = Matrix::from_data(&response.data, EmbeddingFormat::Int8).unwrap().to_tensor();
+        assert_eq!(&body[0..4], &2u32.to_le_bytes());
+        assert_eq!(&body[4..8], &3u32.to_le_bytes());
+        assert_eq!(&body[8..12], &(TensorDtype::Int8 as u32).to_le_bytes());
+        assert_eq!(&body[TENSOR_HEADER_LEN..], &[0xFF, 2, 3, 4, 5, 0x80]);
+
+        let http_response = embeddings_response(ResponseFormat::Tensor, &response, &[1, 2], EmbeddingFormat::Int8);
+        assert_eq!(http_response.headers()[header::CONTENT_TYPE], TENSOR);
+        assert_eq!(http_response.headers()["x-total-tokens"], "3");
+        assert_eq!(http_response.headers()["x-embedding-model"], "test-model");
+    }
+
+    #[test]
+    fn test_float_tensor_dtypes() {
+        let response = response_with(vec![EmbeddingVector::Float(vec![1.0, 0.5])]);
+
+        let body = Matrix::from_data(&response.data, EmbeddingFormat::Float).unwrap().to_tensor();
+        assert_eq!(body.len(), TENSOR_HEADER_LEN + 8);
+        assert_eq!(&body[TENSOR_HEADER_LEN..TENSOR_HEADER_LEN + 4], &1.0f32.to_le_bytes());
+
+        let body = Matrix::from_data(&response.data, EmbeddingFormat::Float16).unwrap().to_tensor();
+        assert_eq!(&body[8..12], &(TensorDtype::Float16 as u32).to_le_bytes());
+        assert_eq!(&body[TENSOR_HEADER_LEN..], &[0x00, 0x3C, 0x00, 0x38]);
+    }
+
+    #[test]
+    fn test_npy_layout() {
+        let response = response_with(vec![
+            EmbeddingVector::Float(vec![1.0, 2.0, 3.0]),
+            EmbeddingVector::Float(vec![4.0, 5.0, 6.0]),
+        ]);
+
+        let body = Matrix::from_data(&response.data, EmbeddingFormat::Float).unwrap().to_npy();
+        assert_eq!(&body[0..8], b"\x93NUMPY\x01\x00");
+
+        let header_len = u16::from_le_bytes([body[8], body[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+
+        let header = std::str::from_utf8(&body[10..10 + header_len]).unwrap();
+        assert!(header.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with('\n'));
+        assert_eq!(body.len(), 10 + header_len + 6 * 4);
+        assert_eq!(&body[10 + header_len..10 + header_len + 4], &1.0f32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_arrow_stream() {
+        use arrow_ipc::reader::StreamReader;
+
+        let response = response_with(vec![
+            EmbeddingVector::Float(vec![1.0, 2.0]),
+            EmbeddingVector::Float(vec![3.0, 4.0]),
+        ]);
+
+        let body = Matrix::from_data(&response.data, EmbeddingFormat::Float)
+            .unwrap()
+            .into_arrow(&response.data, &[5, 7])
+            .unwrap();
+
+        let mut reader = StreamReader::try_new(body.as_slice(), None).unwrap();
+        let batch = reader.next().unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.schema().field(1).name(), "embedding");
+
+        let embeddings = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<FixedSizeListArray>()
+            .unwrap();
+        assert_eq!(embeddings.value_length(), 2);
+        let second = embeddings.value(1);
+        let second = second.as_any().downcast_ref::<Float32Array>().unwrap();
+        assert_eq!(second.values(), &[3.0, 4.0]);
+
+        let token_counts = batch.column(2).as_any().downcast_ref::<UInt32Array>().unwrap();
+        assert_eq!(token_counts.values(), &[5, 7]);
+        assert!(token_counts.nulls().is_none());
+    }
+}
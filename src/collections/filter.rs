@@ -0,0 +1,436 @@
+use serde_json::Value;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Bound,
+};
+
+use crate::models::{Condition, Filter};
+
+impl Filter {
+    /// Reject conditions without operators and range bounds that are not numbers or dates
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Filter::And { and: filters } | Filter::Or { or: filters } => filters.iter().try_for_each(Filter::validate),
+            Filter::Not { not } => not.validate(),
+            Filter::Condition(condition) => condition.validate(),
+        }
+    }
+
+    pub fn matches(&self, metadata: Option<&Value>) -> bool {
+        match self {
+            Filter::And { and } => and.iter().all(|filter| filter.matches(metadata)),
+            Filter::Or { or } => or.iter().any(|filter| filter.matches(metadata)),
+            Filter::Not { not } => !not.matches(metadata),
+            Filter::Condition(condition) => condition.matches(metadata),
+        }
+    }
+}
+
+impl Condition {
+    fn bounds(&self) -> [(&Option<Value>, &'static str); 4] {
+        [(&self.gt, "gt"), (&self.gte, "gte"), (&self.lt, "lt"), (&self.lte, "lte")]
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.field.is_empty() {
+            return Err("Filter conditions need a field".to_string());
+        }
+        if self.eq.is_none() && self.any_of.is_none() && self.bounds().iter().all(|(bound, _)| bound.is_none()) {
+            return Err(format!("Filter on '{}' has no operator", self.field));
+        }
+        for (bound, name) in self.bounds() {
+            if bound.as_ref().is_some_and(|bound| ordinal(bound).is_none()) {
+                return Err(format!("'{}' on '{}' must be a number or an ISO 8601 date", name, self.field));
+            }
+        }
+        Ok(())
+    }
+
+    fn matches(&self, metadata: Option<&Value>) -> bool {
+        lookup(metadata, &self.field).is_some_and(|value| elements(value).any(|value| self.matches_value(value)))
+    }
+
+    fn matches_value(&self, value: &Value) -> bool {
+        if self.eq.as_ref().is_some_and(|eq| !equal(value, eq)) {
+            return false;
+        }
+        if self.any_of.as_ref().is_some_and(|any_of| !any_of.iter().any(|other| equal(value, other))) {
+            return false;
+        }
+        if self.bounds().iter().all(|(bound, _)| bound.is_none()) {
+            return true;
+        }
+
+        let Some(value) = ordinal(value) else {
+            return false;
+        };
+        let holds = |bound: &Option<Value>, accept: fn(Ordering) -> bool| {
+            bound
+                .as_ref()
+                .and_then(ordinal)
+                .is_none_or(|bound| accept(value.total_cmp(&bound)))
+        };
+        holds(&self.gt, Ordering::is_gt)
+            && holds(&self.gte, Ordering::is_ge)
+            && holds(&self.lt, Ordering::is_lt)
+            && holds(&self.lte, Ordering::is_le)
+    }
+}
+
+/// Follow a dotted path into the metadata
+pub fn lookup<'a>(metadata: Option<&'a Value>, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(metadata?, |value, key| value.get(key))
+}
+
+/// The elements of an array, or the value itself
+fn elements(value: &Value) -> impl Iterator<Item = &Value> {
+    match value {
+        Value::Array(values) => values.iter(),
+        value => std::slice::from_ref(value).iter(),
+    }
+}
+
+/// JSON equality, except that numbers compare by value so 1 equals 1.0
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Position of a number or date on a common scale; dates become Unix seconds
+fn ordinal(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => parse_timestamp(text),
+        _ => None,
+    }
+}
+
+/// Parse `YYYY-MM-DD`, optionally followed by `THH:MM[:SS[.fff]]` and `Z` or a `±HH:MM` offset,
+/// into Unix seconds. Times without an offset are taken as UTC.
+fn parse_timestamp(text: &str) -> Option<f64> {
+    let number = |s: &str| -> Option<i64> {
+        (!s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())).then(|| s.parse().ok())?
+    };
+
+    let (date, time) = match text.find(['T', 't', ' ']) {
+        Some(split) => (&text[..split], Some(&text[split + 1..])),
+        None => (text, None),
+    };
+
+    let mut parts = date.splitn(3, '-');
+    let (year, month, day) = (number(parts.next()?)?, number(parts.next()?)?, number(parts.next()?)?);
+    if date.len() != 10 || !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+    let mut seconds = days_from_civil(year, month, day) as f64 * 86400.0;
+
+    if let Some(time) = time {
+        let (clock, offset) = match time.find(['Z', 'z', '+', '-']) {
+            Some(split) => (&time[..split], &time[split..]),
+            None => (time, ""),
+        };
+
+        let mut parts = clock.splitn(3, ':');
+        let hours = number(parts.next()?)?;
+        let minutes = number(parts.next()?)?;
+        let second: f64 = match parts.next() {
+            Some(second) if second.as_bytes().get(..2).is_some_and(|digits| digits.iter().all(u8::is_ascii_digit)) => {
+                second.parse().ok()?
+            }
+            Some(_) => return None,
+            None => 0.0,
+        };
+        if hours > 23 || minutes > 59 || !(0.0..61.0).contains(&second) {
+            return None;
+        }
+        seconds += (hours * 3600 + minutes * 60) as f64 + second;
+
+        match offset {
+            "" | "Z" | "z" => {}
+            offset => {
+                let sign = if offset.starts_with('-') { 1.0 } else { -1.0 };
+                let (hours, minutes) = offset[1..].split_once(':')?;
+                let (hours, minutes) = (number(hours)?, number(minutes)?);
+                if hours > 23 || minutes > 59 {
+                    return None;
+                }
+                seconds += sign * (hours * 3600 + minutes * 60) as f64;
+            }
+        }
+    }
+
+    Some(seconds)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Key under which a value is indexed for equality; numbers are keyed by value
+fn key(value: &Value) -> String {
+    match value.as_f64() {
+        Some(number) => format!("n:{}", number),
+        None => value.to_string(),
+    }
+}
+
+/// Totally ordered f64 for range lookups
+#[derive(Debug, Clone, Copy)]
+struct Ordinal(f64);
+
+impl PartialEq for Ordinal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Ordinal {}
+
+impl Ord for Ordinal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl PartialOrd for Ordinal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Positions of the items holding each value of one metadata field, for equality and range
+/// lookups. Only live items are indexed.
+#[derive(Debug, Default)]
+pub struct PayloadIndex {
+    values: HashMap<String, HashSet<usize>>,
+    ordinals: BTreeMap<Ordinal, HashSet<usize>>,
+}
+
+impl PayloadIndex {
+    pub fn insert(&mut self, position: usize, value: &Value) {
+        for value in elements(value) {
+            self.values.entry(key(value)).or_default().insert(position);
+            if let Some(ordinal) = ordinal(value) {
+                self.ordinals.entry(Ordinal(ordinal)).or_default().insert(position);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, position: usize, value: &Value) {
+        for value in elements(value) {
+            let key = key(value);
+            if let Some(positions) = self.values.get_mut(&key) {
+                positions.remove(&position);
+                if positions.is_empty() {
+                    self.values.remove(&key);
+                }
+            }
+
+            if let Some(ordinal) = ordinal(value).map(Ordinal) {
+                if let Some(positions) = self.ordinals.get_mut(&ordinal) {
+                    positions.remove(&position);
+                    if positions.is_empty() {
+                        self.ordinals.remove(&ordinal);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Positions that may satisfy `condition`
+    fn candidates(&self, condition: &Condition) -> HashSet<usize> {
+        let mut sets = Vec::new();
+
+        if let Some(eq) = &condition.eq {
+            sets.push(self.values.get(&key(eq)).cloned().unwrap_or_default());
+        }
+        if let Some(any_of) = &condition.any_of {
+            sets.push(
+                any_of
+                    .iter()
+                    .filter_map(|value| self.values.get(&key(value)))
+                    .flatten()
+                    .copied()
+                    .collect(),
+            );
+        }
+
+        let bound = |value: &Option<Value>, inclusive: bool| match value.as_ref().and_then(ordinal) {
+            Some(ordinal) if inclusive => Bound::Included(Ordinal(ordinal)),
+            Some(ordinal) => Bound::Excluded(Ordinal(ordinal)),
+            None => Bound::Unbounded,
+        };
+        let lower = match bound(&condition.gt, false) {
+            Bound::Unbounded => bound(&condition.gte, true),
+            lower => lower,
+        };
+        let upper = match bound(&condition.lt, false) {
+            Bound::Unbounded => bound(&condition.lte, true),
+            upper => upper,
+        };
+        if lower != Bound::Unbounded || upper != Bound::Unbounded {
+            // BTreeMap::range panics on inverted ranges
+            let empty = match (lower, upper) {
+                (Bound::Included(l), Bound::Included(u)) => l > u,
+                (Bound::Included(l) | Bound::Excluded(l), Bound::Included(u) | Bound::Excluded(u)) => l >= u,
+                _ => false,
+            };
+            let positions = if empty {
+                HashSet::new()
+            } else {
+                self.ordinals.range((lower, upper)).flat_map(|(_, positions)| positions).copied().collect()
+            };
+            sets.push(positions);
+        }
+
+        intersect(sets).unwrap_or_default()
+    }
+}
+
+fn intersect(sets: Vec<HashSet<usize>>) -> Option<HashSet<usize>> {
+    sets.into_iter().reduce(|a, b| a.intersection(&b).copied().collect())
+}
+
+/// Positions that may match `filter`, from the payload indexes, or `None` when the filter
+/// involves fields without an index. Every candidate still has to be checked against the filter.
+pub fn candidates(indexes: &BTreeMap<String, PayloadIndex>, filter: &Filter) -> Option<HashSet<usize>> {
+    match filter {
+        Filter::Condition(condition) => indexes.get(&condition.field).map(|index| index.candidates(condition)),
+        // Any indexed branch bounds a conjunction
+        Filter::And { and } => intersect(and.iter().filter_map(|filter| candidates(indexes, filter)).collect()),
+        // A disjunction is only bounded if every branch is
+        Filter::Or { or } => or
+            .iter()
+            .map(|filter| candidates(indexes, filter))
+            .collect::<Option<Vec<_>>>()
+            .map(|sets| sets.into_iter().flatten().collect()),
+        Filter::Not { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn filter(value: Value) -> Filter {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-01"), Some(0.0));
+        assert_eq!(parse_timestamp("2024-02-29"), Some(1709164800.0));
+        assert_eq!(parse_timestamp("2024-02-29T12:30:00Z"), Some(1709209800.0));
+        assert_eq!(parse_timestamp("2024-02-29T14:30:00+02:00"), Some(1709209800.0));
+        assert_eq!(parse_timestamp("2024-02-29 12:30:00.5"), Some(1709209800.5));
+        assert_eq!(parse_timestamp("1969-12-31"), Some(-86400.0));
+
+        assert_eq!(parse_timestamp("2023-02-29"), None);
+        assert_eq!(parse_timestamp("2024-1-01"), None);
+        assert_eq!(parse_timestamp("2024-01-01T25:00"), None);
+        assert_eq!(parse_timestamp("tenant-a"), None);
+
+        // Non-ASCII text is rejected rather than sliced mid-character
+        assert_eq!(parse_timestamp("2024-01-01T10:00:0é"), None);
+        assert_eq!(parse_timestamp("2024-01-01T10:00:é"), None);
+        assert_eq!(parse_timestamp("2024-01-01T10:é0"), None);
+        assert_eq!(parse_timestamp("2024-01-01T10:00+0é:00"), None);
+        assert_eq!(parse_timestamp("2024-01-é1"), None);
+    }
+
+    #[test]
+    fn test_filter_matching() {
+        let metadata = json!({
+            "tenant": "acme",
+            "lang": "en",
+            "tags": ["billing", "faq"],
+            "views": 120,
+            "published": "2024-03-15",
+            "author": { "name": "kim" }
+        });
+        let matches = |value: Value| filter(value).matches(Some(&metadata));
+
+        assert!(matches(json!({ "field": "tenant", "eq": "acme" })));
+        assert!(matches(json!({ "field": "author.name", "eq": "kim" })));
+        assert!(matches(json!({ "field": "views", "eq": 120.0 })));
+        assert!(matches(json!({ "field": "lang", "in": ["de", "en"] })));
+        assert!(matches(json!({ "field": "tags", "eq": "faq" })));
+        assert!(matches(json!({ "field": "views", "gte": 100, "lt": 200 })));
+        assert!(matches(json!({ "field": "published", "gte": "2024-01-01", "lt": "2024-04-01T00:00:00Z" })));
+        assert!(!matches(json!({ "field": "published", "gt": "2024-03-15" })));
+        assert!(!matches(json!({ "field": "missing", "eq": 1 })));
+        assert!(!matches(json!({ "field": "tenant", "gt": 1 })));
+
+        assert!(matches(json!({
+            "and": [
+                { "field": "tenant", "eq": "acme" },
+                { "or": [{ "field": "lang", "eq": "de" }, { "field": "views", "gt": 100 }] },
+                { "not": { "field": "tags", "eq": "internal" } }
+            ]
+        })));
+        assert!(!matches(json!({ "not": { "field": "tenant", "eq": "acme" } })));
+        assert!(!filter(json!({ "field": "tenant", "eq": "acme" })).matches(None));
+
+        assert!(filter(json!({ "field": "views" })).validate().is_err());
+        assert!(filter(json!({ "and": [{ "field": "published", "gt": "yesterday" }] })).validate().is_err());
+        assert!(serde_json::from_value::<Filter>(json!({ "field": "x", "like": "y" })).is_err());
+    }
+
+    #[test]
+    fn test_payload_index_candidates() {
+        let mut indexes = BTreeMap::new();
+        let mut tenant = PayloadIndex::default();
+        let mut views = PayloadIndex::default();
+        for (position, (name, count)) in [("a", 1), ("b", 5), ("a", 10), ("c", 50)].into_iter().enumerate() {
+            tenant.insert(position, &json!(name));
+            views.insert(position, &json!(count));
+        }
+        views.remove(3, &json!(50));
+        indexes.insert("tenant".to_string(), tenant);
+        indexes.insert("views".to_string(), views);
+
+        let candidates = |value: Value| {
+            candidates(&indexes, &filter(value)).map(|set| {
+                let mut positions: Vec<usize> = set.into_iter().collect();
+                positions.sort();
+                positions
+            })
+        };
+
+        assert_eq!(candidates(json!({ "field": "tenant", "eq": "a" })), Some(vec![0, 2]));
+        assert_eq!(candidates(json!({ "field": "tenant", "in": ["b", "c"] })), Some(vec![1, 3]));
+        assert_eq!(candidates(json!({ "field": "views", "gt": 1, "lte": 50 })), Some(vec![1, 2]));
+        assert_eq!(candidates(json!({ "field": "views", "gt": 10, "lt": 5 })), Some(vec![]));
+        assert_eq!(candidates(json!({ "field": "views", "gt": 5, "lt": 5 })), Some(vec![]));
+        assert_eq!(candidates(json!({ "field": "views", "gte": 5, "lte": 5 })), Some(vec![1]));
+        assert_eq!(
+            candidates(json!({ "and": [{ "field": "tenant", "eq": "a" }, { "field": "lang", "eq": "en" }] })),
+            Some(vec![0, 2])
+        );
+        assert_eq!(
+            candidates(json!({ "or": [{ "field": "tenant", "eq": "b" }, { "field": "views", "eq": 10 }] })),
+            Some(vec![1, 2])
+        );
+        assert_eq!(candidates(json!({ "or": [{ "field": "tenant", "eq": "b" }, { "field": "lang", "eq": "en" }] })), None);
+        assert_eq!(candidates(json!({ "not": { "field": "tenant", "eq": "a" } })), None);
+    }
+}
@@ -0,0 +1,388 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Response,
+    Json,
+};
+use std::sync::{Arc, PoisonError};
+use tokio::task;
+use tracing::{debug, error};
+
+use super::{validate_query, validate_self_test, Collection, CollectionError};
+use crate::codec::{self, Payload, ResponseFormat};
+use crate::error::InputError;
+use crate::handlers::AppState;
+use crate::models::{
+    CollectionList, CreateCollectionRequest, CreatePayloadIndexRequest, DeleteItemsRequest, DeleteItemsResponse, ErrorDetail,
+    ErrorResponse, Fusion, QueryRequest, QueryResponse, QueryResult, SearchMode, SelfTestRequest, SnapshotList, UpsertRequest,
+    UpsertResponse, Usage,
+};
+
+type HandlerError = (StatusCode, Json<ErrorResponse>);
+
+pub async fn create_collection(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Payload(request): Payload<CreateCollectionRequest>,
+) -> Result<Response, HandlerError> {
+    if let Some(model) = request.model.filter(|model| *model != state.model_name) {
+        return Err(CollectionError::UnknownModel(model).into());
+    }
+
+    // The model's output size, found by embedding an empty probe
+    let probe = state.encode_texts(vec![String::new()]).await?;
+    let dimensions = probe.embeddings.first().map(Vec::len).unwrap_or_default();
+
+    let analyzer = state.collections.analyzer(&request.analyzer)?;
+    let mut collection = Collection::new(
+        request.name,
+        state.model_name.clone(),
+        request.metric,
+        dimensions,
+        request.index,
+        analyzer,
+    );
+    for field in &request.payload_indexes {
+        collection.add_payload_index(field);
+    }
+    let info = state.collections.create(collection)?;
+    debug!("Created collection {} with {} dimensions", info.name, info.dimensions);
+
+    Ok(codec::encode(response_format, &info))
+}
+
+pub async fn list_collections(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+) -> Response {
+    let list = CollectionList {
+        object: "list".to_string(),
+        data: state.collections.list(),
+    };
+    codec::encode(response_format, &list)
+}
+
+pub async fn get_collection(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Path(name): Path<String>,
+) -> Result<Response, HandlerError> {
+    let collection = state.collections.get(&name)?;
+    let info = collection.read().unwrap_or_else(PoisonError::into_inner).info();
+    Ok(codec::encode(response_format, &info))
+}
+
+pub async fn delete_collection(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, HandlerError> {
+    state.collections.delete(&name)?;
+    Ok(Json(serde_json::json!({
+        "id": name,
+        "object": "collection",
+        "deleted": true,
+    })))
+}
+
+/// Insert or replace items. Items with a vector are stored as given, the rest have their text
+/// embedded in one batch. Nothing is written unless every item is valid.
+pub async fn upsert_items(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Path(name): Path<String>,
+    Payload(request): Payload<UpsertRequest>,
+) -> Result<Response, HandlerError> {
+    let collection = state.collections.get(&name)?;
+    let mut items = request.items;
+    debug!("Received upsert of {} items into {}", items.len(), name);
+
+    if items.is_empty() {
+        return Err(InputError::new("Items cannot be empty", "empty_input").into());
+    }
+    if items.len() > state.max_batch_size {
+        return Err(InputError::new(
+            format!("Batch size exceeds maximum of {}", state.max_batch_size),
+            "batch_too_large",
+        )
+        .into());
+    }
+
+    let mut pending = Vec::new();
+    {
+        let collection = collection.read().unwrap_or_else(PoisonError::into_inner);
+        for (index, item) in items.iter().enumerate() {
+            match (&item.vector, &item.text) {
+                (Some(vector), _) => collection.check_dimensions(vector)?,
+                (None, Some(_)) => pending.push(index),
+                (None, None) => return Err(CollectionError::MissingContent(item.id.clone()).into()),
+            }
+        }
+    }
+
+    let mut total_tokens = 0;
+    if !pending.is_empty() {
+        let texts: Vec<String> = pending
+            .iter()
+            .map(|&index| items[index].text.clone().unwrap_or_default())
+            .collect();
+        state.validate_lengths(&texts)?;
+
+        let result = state.encode_texts(texts).await?;
+        total_tokens = result.token_counts.iter().sum();
+        for (index, vector) in pending.into_iter().zip(result.embeddings) {
+            items[index].vector = Some(vector);
+        }
+    }
+
+    // Index inserts and log syncs block, so the write happens on the blocking pool
+    let upserted = items.len();
+    let writer = Arc::clone(&state);
+    task::spawn_blocking(move || writer.collections.upsert(&name, items))
+        .await
+        .map_err(task_failed)??;
+
+    let response = UpsertResponse {
+        upserted,
+        usage: Usage {
+            prompt_tokens: total_tokens,
+            total_tokens,
+        },
+    };
+
+    Ok(codec::encode(response_format, &response))
+}
+
+/// Index a metadata field so filters on it can skip non-matching items
+pub async fn create_payload_index(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Path(name): Path<String>,
+    Payload(request): Payload<CreatePayloadIndexRequest>,
+) -> Result<Response, HandlerError> {
+    let info = task::spawn_blocking(move || state.collections.add_payload_index(&name, &request.field))
+        .await
+        .map_err(task_failed)??;
+    debug!("Collection {} has payload indexes {:?}", info.name, info.payload_indexes);
+
+    Ok(codec::encode(response_format, &info))
+}
+
+pub async fn get_item(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Path((name, id)): Path<(String, String)>,
+) -> Result<Response, HandlerError> {
+    let collection = state.collections.get(&name)?;
+    let collection = collection.read().unwrap_or_else(PoisonError::into_inner);
+    let position = collection.position(&id).ok_or(CollectionError::ItemNotFound(id))?;
+    Ok(codec::encode(response_format, &collection.item(position, true)))
+}
+
+pub async fn delete_items(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Path(name): Path<String>,
+    Payload(request): Payload<DeleteItemsRequest>,
+) -> Result<Response, HandlerError> {
+    // Deletes can trigger compaction, which rebuilds the index
+    let deleted = task::spawn_blocking(move || state.collections.delete_items(&name, &request.ids))
+        .await
+        .map_err(task_failed)??;
+
+    Ok(codec::encode(response_format, &DeleteItemsResponse { deleted }))
+}
+
+/// Top-k search by text or vector, through the collection's index when it has one, by BM25
+/// over item text, or both fused. Results can be restricted to items whose metadata matches a
+/// filter.
+pub async fn query(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Path(name): Path<String>,
+    Payload(request): Payload<QueryRequest>,
+) -> Result<Response, HandlerError> {
+    let collection = state.collections.get(&name)?;
+    validate_query(request.top_k, request.ef)?;
+    if let Some(filter) = &request.filter {
+        filter
+            .validate()
+            .map_err(|message| InputError::new(message, "invalid_filter"))?;
+    }
+    validate_fusion(request.fusion)?;
+
+    let mode = request.mode;
+    let text = request.text;
+    let (vector, total_tokens) = match (mode, request.vector, &text) {
+        (SearchMode::Lexical, None, Some(_)) => (None, 0),
+        (SearchMode::Vector, Some(vector), None) | (SearchMode::Hybrid, Some(vector), Some(_)) => (Some(vector), 0),
+        (SearchMode::Vector | SearchMode::Hybrid, None, Some(text)) => {
+            let texts = vec![text.clone()];
+            state.validate_lengths(&texts)?;
+            let result = state.encode_texts(texts).await?;
+            let total_tokens = result.token_counts.iter().sum();
+            (result.embeddings.into_iter().next(), total_tokens)
+        }
+        (SearchMode::Vector, ..) => {
+            return Err(InputError::new("Query needs exactly one of text or vector", "invalid_query").into());
+        }
+        (SearchMode::Lexical, ..) => {
+            return Err(InputError::new("Lexical queries need text and no vector", "invalid_query").into());
+        }
+        (SearchMode::Hybrid, ..) => {
+            return Err(InputError::new("Hybrid queries need text", "invalid_query").into());
+        }
+    };
+
+    if let Some(vector) = &vector {
+        collection
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .check_dimensions(vector)?;
+    }
+
+    let top_k = request.top_k;
+    let ef = request.ef;
+    let include_vectors = request.include_vectors;
+    let filter = request.filter;
+    let fusion = request.fusion;
+    let results = task::spawn_blocking(move || {
+        let collection = collection.read().unwrap_or_else(PoisonError::into_inner);
+        let text = text.unwrap_or_default();
+        let vector = vector.unwrap_or_default();
+        let hits: Vec<_> = match mode {
+            SearchMode::Vector => collection
+                .search(&vector, top_k, ef, filter.as_ref())
+                .into_iter()
+                .map(|hit| (hit, None))
+                .collect(),
+            SearchMode::Lexical => collection
+                .lexical_search(&text, top_k, filter.as_ref())
+                .into_iter()
+                .map(|hit| (hit, None))
+                .collect(),
+            SearchMode::Hybrid => collection
+                .hybrid_search(&text, &vector, top_k, ef, filter.as_ref(), fusion)
+                .into_iter()
+                .map(|(hit, scores)| (hit, Some(scores)))
+                .collect(),
+        };
+
+        hits.into_iter()
+            .map(|(hit, scores)| {
+                let item = collection.item(hit.position, include_vectors);
+                QueryResult {
+                    id: item.id,
+                    score: hit.score,
+                    text: item.text,
+                    metadata: item.metadata,
+                    vector: item.vector,
+                    scores,
+                }
+            })
+            .collect()
+    })
+    .await
+    .map_err(task_failed)?;
+
+    let response = QueryResponse {
+        results,
+        usage: Usage {
+            prompt_tokens: total_tokens,
+            total_tokens,
+        },
+    };
+
+    Ok(codec::encode(response_format, &response))
+}
+
+fn validate_fusion(fusion: Fusion) -> Result<(), InputError> {
+    match fusion {
+        Fusion::Rrf { k } if !(k >= 0.0 && k.is_finite()) => {
+            Err(InputError::new("RRF k must be a non-negative number", "invalid_fusion"))
+        }
+        Fusion::Weighted { vector_weight } if !(0.0..=1.0).contains(&vector_weight) => {
+            Err(InputError::new("vector_weight must be between 0 and 1", "invalid_fusion"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Recall and latency of the collection's index compared with exact search
+pub async fn self_test(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Path(name): Path<String>,
+    Payload(request): Payload<SelfTestRequest>,
+) -> Result<Response, HandlerError> {
+    let collection = state.collections.get(&name)?;
+    validate_self_test(request.sample_size, request.top_k, request.ef)?;
+
+    let report = task::spawn_blocking(move || {
+        let collection = collection.read().unwrap_or_else(PoisonError::into_inner);
+        collection.self_test(request.sample_size, request.top_k, request.ef)
+    })
+    .await
+    .map_err(task_failed)??;
+
+    debug!(
+        "Self-test of {}: recall {:.3} over {} queries",
+        name, report.recall, report.sample_size
+    );
+
+    Ok(codec::encode(response_format, &report))
+}
+
+/// Write every collection to a new snapshot now
+pub async fn create_snapshot(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+) -> Result<Response, HandlerError> {
+    let snapshot = task::spawn_blocking(move || state.collections.snapshot())
+        .await
+        .map_err(task_failed)??;
+    debug!("Wrote snapshot {} ({} bytes)", snapshot.id, snapshot.size_bytes);
+
+    Ok(codec::encode(response_format, &snapshot))
+}
+
+pub async fn list_snapshots(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+) -> Result<Response, HandlerError> {
+    let list = SnapshotList {
+        object: "list".to_string(),
+        data: state.collections.snapshots()?,
+    };
+    Ok(codec::encode(response_format, &list))
+}
+
+/// Replace every collection with the contents of a snapshot
+pub async fn restore_snapshot(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Path(id): Path<u64>,
+) -> Result<Response, HandlerError> {
+    let data = task::spawn_blocking(move || state.collections.restore(id))
+        .await
+        .map_err(task_failed)??;
+
+    let list = CollectionList {
+        object: "list".to_string(),
+        data,
+    };
+    Ok(codec::encode(response_format, &list))
+}
+
+fn task_failed(e: task::JoinError) -> HandlerError {
+    error!("Collection task failed: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ErrorResponse {
+            error: ErrorDetail {
+                message: format!("Collection task failed: {}", e),
+                error_type: "server_error".to_string(),
+                code: None,
+            },
+        }),
+    )
+}
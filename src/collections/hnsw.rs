@@ -0,0 +1,357 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashSet},
+};
+
+use crate::models::{HnswConfig, Metric};
+
+/// Upper bound on node levels; with M >= 2 higher levels are vanishingly rare
+const MAX_LEVEL: usize = 16;
+
+/// Fixed seed so the same inserts always build the same graph
+const SEED: u64 = 0x5eed_1e55_0fa1_1ce5;
+
+/// Flat vector storage the graph nodes point into
+#[derive(Clone, Copy)]
+pub struct Vectors<'a> {
+    pub data: &'a [f32],
+    pub dimensions: usize,
+}
+
+impl Vectors<'_> {
+    fn get(&self, node: u32) -> &[f32] {
+        &self.data[node as usize * self.dimensions..][..self.dimensions]
+    }
+}
+
+/// A node and its distance to the current query; smaller is closer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    pub distance: f32,
+    pub node: u32,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Hierarchical navigable small world graph (Malkov & Yashunin) over collection slots.
+/// Nodes are never removed; the collection filters deleted slots out of the results and
+/// rebuilds the graph when it compacts its storage.
+pub struct Hnsw {
+    config: HnswConfig,
+    metric: Metric,
+    /// `links[node][level]` holds the neighbours of `node` on `level`
+    links: Vec<Vec<Vec<u32>>>,
+    entry: Option<u32>,
+    level_factor: f64,
+    rng: u64,
+}
+
+impl Hnsw {
+    pub fn new(config: HnswConfig, metric: Metric) -> Self {
+        Self {
+            config,
+            metric,
+            links: Vec::new(),
+            entry: None,
+            level_factor: 1.0 / (config.m as f64).ln(),
+            rng: SEED,
+        }
+    }
+
+    pub fn config(&self) -> HnswConfig {
+        self.config
+    }
+
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// Metric scores turned into distances, so that smaller always means closer
+    fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        let score = self.metric.score(a, b);
+        if self.metric.higher_is_better() {
+            -score
+        } else {
+            score
+        }
+    }
+
+    /// Convert a distance back into the metric's score
+    pub fn score(&self, distance: f32) -> f32 {
+        if self.metric.higher_is_better() {
+            -distance
+        } else {
+            distance
+        }
+    }
+
+    /// Add the next slot of `vectors` to the graph. Slots must be inserted in order.
+    pub fn insert(&mut self, vectors: Vectors<'_>) {
+        let node = self.links.len() as u32;
+        let level = self.random_level();
+        self.links.push(vec![Vec::new(); level + 1]);
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(node);
+            return;
+        };
+
+        let query = vectors.get(node);
+        let top = self.links[entry as usize].len() - 1;
+        let mut nearest = vec![Candidate {
+            distance: self.distance(query, vectors.get(entry)),
+            node: entry,
+        }];
+        for level in (level + 1..=top).rev() {
+            nearest = self.search_level(query, &nearest, 1, level, vectors, |_| true);
+        }
+
+        for level in (0..=level.min(top)).rev() {
+            let found = self.search_level(query, &nearest, self.config.ef_construction, level, vectors, |_| true);
+            let neighbours = self.select(&found, self.config.m, vectors);
+
+            for &neighbour in &neighbours {
+                let links = &mut self.links[neighbour as usize][level];
+                links.push(node);
+                if links.len() > self.max_links(level) {
+                    self.shrink(neighbour, level, vectors);
+                }
+            }
+            self.links[node as usize][level] = neighbours;
+            nearest = found;
+        }
+
+        if level > top {
+            self.entry = Some(node);
+        }
+    }
+
+    /// The `top_k` closest nodes accepted by `keep`, closest first, exploring `ef` candidates.
+    /// Rejected nodes are still traversed, so selective filters return `top_k` results as long
+    /// as enough nodes are accepted.
+    pub fn search(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        ef: usize,
+        vectors: Vectors<'_>,
+        keep: impl Fn(u32) -> bool,
+    ) -> Vec<Candidate> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+
+        let mut nearest = vec![Candidate {
+            distance: self.distance(query, vectors.get(entry)),
+            node: entry,
+        }];
+        for level in (1..self.links[entry as usize].len()).rev() {
+            nearest = self.search_level(query, &nearest, 1, level, vectors, |_| true);
+        }
+
+        let mut found = self.search_level(query, &nearest, ef.max(top_k), 0, vectors, keep);
+        found.truncate(top_k);
+        found
+    }
+
+    /// Best-first search of one level, returning up to `ef` nodes accepted by `keep`, sorted
+    /// closest first. The search ends once the closest unexplored node is further away than
+    /// the furthest result, so it explores more of the graph when few nodes are accepted.
+    fn search_level(
+        &self,
+        query: &[f32],
+        entries: &[Candidate],
+        ef: usize,
+        level: usize,
+        vectors: Vectors<'_>,
+        keep: impl Fn(u32) -> bool,
+    ) -> Vec<Candidate> {
+        let mut visited: HashSet<u32> = entries.iter().map(|c| c.node).collect();
+        let mut candidates: BinaryHeap<Reverse<Candidate>> = entries.iter().copied().map(Reverse).collect();
+        let mut results: BinaryHeap<Candidate> = entries.iter().copied().filter(|c| keep(c.node)).collect();
+        while results.len() > ef {
+            results.pop();
+        }
+
+        while let Some(Reverse(candidate)) = candidates.pop() {
+            let furthest = results.peek().map_or(f32::INFINITY, |c| c.distance);
+            if candidate.distance > furthest && results.len() >= ef {
+                break;
+            }
+
+            for &neighbour in &self.links[candidate.node as usize][level] {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+
+                let distance = self.distance(query, vectors.get(neighbour));
+                let furthest = results.peek().map_or(f32::INFINITY, |c| c.distance);
+                if results.len() < ef || distance < furthest {
+                    let next = Candidate { distance, node: neighbour };
+                    candidates.push(Reverse(next));
+                    if keep(neighbour) {
+                        results.push(next);
+                        if results.len() > ef {
+                            results.pop();
+                        }
+                    }
+                }
+            }
+        }
+
+        results.into_sorted_vec()
+    }
+
+    /// Pick up to `m` neighbours from `candidates` (sorted closest first), skipping any that
+    /// are closer to an already selected neighbour than to the query, so links spread out
+    fn select(&self, candidates: &[Candidate], m: usize, vectors: Vectors<'_>) -> Vec<u32> {
+        let mut selected: Vec<u32> = Vec::with_capacity(m);
+        for candidate in candidates {
+            if selected.len() >= m {
+                break;
+            }
+            let vector = vectors.get(candidate.node);
+            let diverse = selected
+                .iter()
+                .all(|&other| self.distance(vector, vectors.get(other)) > candidate.distance);
+            if diverse {
+                selected.push(candidate.node);
+            }
+        }
+        selected
+    }
+
+    /// Trim the links of `node` on `level` back to the level's maximum
+    fn shrink(&mut self, node: u32, level: usize, vectors: Vectors<'_>) {
+        let vector = vectors.get(node);
+        let mut candidates: Vec<Candidate> = self.links[node as usize][level]
+            .iter()
+            .map(|&other| Candidate {
+                distance: self.distance(vector, vectors.get(other)),
+                node: other,
+            })
+            .collect();
+        candidates.sort();
+        self.links[node as usize][level] = self.select(&candidates, self.max_links(level), vectors);
+    }
+
+    /// The bottom level is denser since every search ends there
+    fn max_links(&self, level: usize) -> usize {
+        if level == 0 {
+            self.config.m * 2
+        } else {
+            self.config.m
+        }
+    }
+
+    /// Exponentially distributed level, so each level has about 1/M of the nodes below it
+    fn random_level(&mut self) -> usize {
+        // splitmix64
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        let uniform = ((z >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        ((-uniform.ln() * self.level_factor) as usize).min(MAX_LEVEL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random vectors
+    fn random_vectors(count: usize, dimensions: usize) -> Vec<f32> {
+        let mut state = 42u32;
+        (0..count * dimensions)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as f32 / u32::MAX as f32 - 0.5
+            })
+            .collect()
+    }
+
+    fn exact(query: &[f32], vectors: Vectors<'_>, count: usize, top_k: usize) -> Vec<u32> {
+        let mut all: Vec<Candidate> = (0..count as u32)
+            .map(|node| Candidate {
+                distance: -Metric::Cosine.score(query, vectors.get(node)),
+                node,
+            })
+            .collect();
+        all.sort();
+        all.into_iter().take(top_k).map(|c| c.node).collect()
+    }
+
+    #[test]
+    fn test_recall_against_exact_search() {
+        let (count, dimensions) = (1000, 16);
+        let data = random_vectors(count, dimensions);
+        let vectors = Vectors { data: &data, dimensions };
+
+        let mut index = Hnsw::new(HnswConfig { m: 12, ef_construction: 100, ef: 64 }, Metric::Cosine);
+        for _ in 0..count {
+            index.insert(vectors);
+        }
+        assert_eq!(index.len(), count);
+
+        let mut found = 0;
+        for query in (0..count as u32).step_by(20) {
+            let query = vectors.get(query);
+            let expected = exact(query, vectors, count, 10);
+            let results = index.search(query, 10, 64, vectors, |_| true);
+            assert_eq!(results.len(), 10);
+            assert!(results.windows(2).all(|w| w[0].distance <= w[1].distance));
+            found += results.iter().filter(|c| expected.contains(&c.node)).count();
+        }
+
+        let recall = found as f32 / (count / 20 * 10) as f32;
+        assert!(recall > 0.9, "recall {} is too low", recall);
+    }
+
+    #[test]
+    fn test_filter_during_search() {
+        let data = random_vectors(200, 8);
+        let vectors = Vectors { data: &data, dimensions: 8 };
+
+        let mut index = Hnsw::new(HnswConfig { m: 4, ef_construction: 32, ef: 16 }, Metric::Euclidean);
+        for _ in 0..200 {
+            index.insert(vectors);
+        }
+
+        let query = vectors.get(7);
+        assert_eq!(index.search(query, 1, 16, vectors, |_| true)[0].node, 7);
+        assert_eq!(index.search(query, 1, 16, vectors, |_| true)[0].distance, 0.0);
+
+        let results = index.search(query, 5, 16, vectors, |node| node != 7);
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(|c| c.node != 7));
+
+        // A filter matching 2% of the nodes still fills top-k
+        let results = index.search(query, 4, 4, vectors, |node| node % 50 == 0);
+        let mut nodes: Vec<u32> = results.iter().map(|c| c.node).collect();
+        nodes.sort();
+        assert_eq!(nodes, vec![0, 50, 100, 150]);
+    }
+}
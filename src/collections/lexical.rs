@@ -0,0 +1,359 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::Arc,
+};
+use tracing::warn;
+
+use super::{CollectionError, Hit};
+use crate::handlers::EmbeddingModel;
+use crate::models::{AnalyzerConfig, Fusion, ScoreBreakdown};
+
+/// BM25 term frequency saturation
+const K1: f32 = 1.2;
+
+/// BM25 document length normalization
+const B: f32 = 0.75;
+
+/// Common English words that carry little meaning on their own
+pub const ENGLISH_STOPWORDS: &[&str] = &[
+    "a", "about", "above", "after", "again", "against", "all", "am", "an", "and", "any", "are", "as", "at", "be",
+    "because", "been", "before", "being", "below", "between", "both", "but", "by", "can", "could", "did", "do",
+    "does", "doing", "down", "during", "each", "few", "for", "from", "further", "had", "has", "have", "having", "he",
+    "her", "here", "hers", "herself", "him", "himself", "his", "how", "i", "if", "in", "into", "is", "it", "its",
+    "itself", "just", "me", "more", "most", "my", "myself", "no", "nor", "not", "now", "of", "off", "on", "once",
+    "only", "or", "other", "our", "ours", "ourselves", "out", "over", "own", "same", "she", "should", "so", "some",
+    "such", "than", "that", "the", "their", "theirs", "them", "themselves", "then", "there", "these", "they", "this",
+    "those", "through", "to", "too", "under", "until", "up", "very", "was", "we", "were", "what", "when", "where",
+    "which", "while", "who", "whom", "why", "will", "with", "would", "you", "your", "yours", "yourself",
+    "yourselves",
+];
+
+/// Splits item text and query text into the terms of the lexical index
+pub enum Analyzer {
+    /// Lowercased runs of letters and digits
+    Standard { stopwords: BTreeSet<String> },
+    /// The model's own tokens, so terms line up with what the model embeds
+    Tokenizer(Arc<dyn EmbeddingModel>),
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Analyzer::Standard {
+            stopwords: BTreeSet::new(),
+        }
+    }
+}
+
+impl Analyzer {
+    /// Standard analyzer that leaves out [`ENGLISH_STOPWORDS`]
+    pub fn english() -> Self {
+        Analyzer::Standard {
+            stopwords: ENGLISH_STOPWORDS.iter().map(|word| word.to_string()).collect(),
+        }
+    }
+
+    /// The tokenizer analyzer needs a model that can tokenize
+    pub fn new(config: &AnalyzerConfig, model: Option<&Arc<dyn EmbeddingModel>>) -> Result<Self, CollectionError> {
+        match config {
+            AnalyzerConfig::Standard { stopwords } => Ok(Analyzer::Standard {
+                stopwords: stopwords.iter().map(|word| word.to_lowercase()).collect(),
+            }),
+            AnalyzerConfig::Tokenizer => {
+                let model = model.ok_or_else(|| CollectionError::InvalidAnalyzer("No model is loaded".to_string()))?;
+                model
+                    .tokenize(&[String::new()])
+                    .map_err(|e| CollectionError::InvalidAnalyzer(e.to_string()))?;
+                Ok(Analyzer::Tokenizer(Arc::clone(model)))
+            }
+        }
+    }
+
+    pub fn config(&self) -> AnalyzerConfig {
+        match self {
+            Analyzer::Standard { stopwords } => AnalyzerConfig::Standard {
+                stopwords: stopwords.iter().cloned().collect(),
+            },
+            Analyzer::Tokenizer(_) => AnalyzerConfig::Tokenizer,
+        }
+    }
+
+    pub fn terms(&self, text: &str) -> Vec<String> {
+        match self {
+            Analyzer::Standard { stopwords } => text
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|term| !term.is_empty())
+                .map(str::to_lowercase)
+                .filter(|term| !stopwords.contains(term))
+                .collect(),
+            Analyzer::Tokenizer(model) => match model.tokenize(&[text.to_string()]) {
+                Ok(tokenized) => tokenized.into_iter().flat_map(|tokenized| tokenized.tokens).collect(),
+                Err(e) => {
+                    warn!("Failed to tokenize text for the lexical index: {}", e);
+                    Vec::new()
+                }
+            },
+        }
+    }
+}
+
+/// Inverted index over item text, scored with Okapi BM25. Only live items with text are
+/// indexed.
+#[derive(Debug, Default)]
+pub struct Bm25 {
+    /// Occurrences of each term in each item
+    postings: HashMap<String, HashMap<usize, u32>>,
+    /// Term count of each item
+    lengths: HashMap<usize, u32>,
+    total_length: u64,
+}
+
+impl Bm25 {
+    pub fn insert(&mut self, position: usize, terms: &[String]) {
+        if terms.is_empty() {
+            return;
+        }
+
+        for term in terms {
+            *self.postings.entry(term.clone()).or_default().entry(position).or_default() += 1;
+        }
+        self.lengths.insert(position, terms.len() as u32);
+        self.total_length += terms.len() as u64;
+    }
+
+    /// Remove an item, given the terms it was inserted with
+    pub fn remove(&mut self, position: usize, terms: &[String]) {
+        let Some(length) = self.lengths.remove(&position) else {
+            return;
+        };
+        self.total_length -= length as u64;
+
+        for term in terms {
+            if let Some(postings) = self.postings.get_mut(term) {
+                postings.remove(&position);
+                if postings.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+    }
+
+    /// Inverse document frequency, always positive so common terms never count against an item
+    fn idf(&self, postings: &HashMap<usize, u32>) -> f32 {
+        let count = self.lengths.len() as f32;
+        let frequency = postings.len() as f32;
+        (1.0 + (count - frequency + 0.5) / (frequency + 0.5)).ln()
+    }
+
+    fn term_score(&self, idf: f32, occurrences: u32, length: u32) -> f32 {
+        let average = self.total_length as f32 / self.lengths.len().max(1) as f32;
+        let occurrences = occurrences as f32;
+        idf * occurrences * (K1 + 1.0) / (occurrences + K1 * (1.0 - B + B * length as f32 / average))
+    }
+
+    /// BM25 score of one item; 0 for items without text
+    pub fn score(&self, query: &[String], position: usize) -> f32 {
+        let Some(&length) = self.lengths.get(&position) else {
+            return 0.0;
+        };
+
+        unique(query)
+            .filter_map(|term| self.postings.get(term))
+            .filter_map(|postings| Some((self.idf(postings), *postings.get(&position)?)))
+            .map(|(idf, occurrences)| self.term_score(idf, occurrences, length))
+            .sum()
+    }
+
+    /// The `top_k` items accepted by `keep` with the highest BM25 scores, best first. Items
+    /// sharing no term with the query are never returned.
+    pub fn search(&self, query: &[String], top_k: usize, keep: impl Fn(usize) -> bool) -> Vec<Hit> {
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+        for postings in unique(query).filter_map(|term| self.postings.get(term)) {
+            let idf = self.idf(postings);
+            for (&position, &occurrences) in postings {
+                if keep(position) {
+                    *scores.entry(position).or_default() += self.term_score(idf, occurrences, self.lengths[&position]);
+                }
+            }
+        }
+
+        let mut hits: Vec<Hit> = scores.into_iter().map(|(position, score)| Hit { position, score }).collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.position.cmp(&b.position)));
+        hits.truncate(top_k);
+        hits
+    }
+}
+
+fn unique(terms: &[String]) -> impl Iterator<Item = &String> {
+    let mut seen = HashSet::new();
+    terms.iter().filter(move |term| seen.insert(*term))
+}
+
+/// Merge a vector ranking and a lexical ranking, both best first, into the `top_k` best items
+/// under `fusion`. Items missing from one ranking get their score on that side from
+/// `vector_score` or `lexical_score`, so every breakdown is complete.
+pub fn fuse(
+    vector: &[Hit],
+    lexical: &[Hit],
+    top_k: usize,
+    fusion: Fusion,
+    higher_is_better: bool,
+    vector_score: impl Fn(usize) -> f32,
+    lexical_score: impl Fn(usize) -> f32,
+) -> Vec<(Hit, ScoreBreakdown)> {
+    let ranks = |ranking: &[Hit]| -> HashMap<usize, usize> {
+        ranking.iter().enumerate().map(|(rank, hit)| (hit.position, rank + 1)).collect()
+    };
+    let (vector_ranks, lexical_ranks) = (ranks(vector), ranks(lexical));
+
+    let mut seen = HashSet::new();
+    let breakdowns: Vec<(usize, ScoreBreakdown)> = vector
+        .iter()
+        .chain(lexical)
+        .filter(|hit| seen.insert(hit.position))
+        .map(|hit| {
+            let breakdown = ScoreBreakdown {
+                vector: vector_score(hit.position),
+                lexical: lexical_score(hit.position),
+                vector_rank: vector_ranks.get(&hit.position).copied(),
+                lexical_rank: lexical_ranks.get(&hit.position).copied(),
+            };
+            (hit.position, breakdown)
+        })
+        .collect();
+
+    let normalized_vector = normalize(breakdowns.iter().map(|(_, b)| b.vector), higher_is_better);
+    let normalized_lexical = normalize(breakdowns.iter().map(|(_, b)| b.lexical), true);
+    let fused = |breakdown: &ScoreBreakdown| match fusion {
+        Fusion::Rrf { k } => [breakdown.vector_rank, breakdown.lexical_rank]
+            .into_iter()
+            .flatten()
+            .map(|rank| 1.0 / (k + rank as f32))
+            .sum(),
+        Fusion::Weighted { vector_weight } => {
+            vector_weight * normalized_vector(breakdown.vector)
+                + (1.0 - vector_weight) * normalized_lexical(breakdown.lexical)
+        }
+    };
+
+    let mut results: Vec<(Hit, ScoreBreakdown)> = breakdowns
+        .iter()
+        .map(|(position, breakdown)| {
+            let hit = Hit {
+                position: *position,
+                score: fused(breakdown),
+            };
+            (hit, *breakdown)
+        })
+        .collect();
+    results.sort_by(|(a, _), (b, _)| b.score.total_cmp(&a.score).then(a.position.cmp(&b.position)));
+    results.truncate(top_k);
+    results
+}
+
+/// Min-max scaling onto [0, 1] with 1 the best score; all scores are 1 when they are equal
+fn normalize(scores: impl Iterator<Item = f32>, higher_is_better: bool) -> impl Fn(f32) -> f32 {
+    let (min, max) = scores.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), score| {
+        (min.min(score), max.max(score))
+    });
+    move |score| {
+        if max <= min {
+            1.0
+        } else if higher_is_better {
+            (score - min) / (max - min)
+        } else {
+            (max - score) / (max - min)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(text: &str) -> Vec<String> {
+        Analyzer::default().terms(text)
+    }
+
+    #[test]
+    fn test_standard_analyzer() {
+        assert_eq!(terms("Reset the SKU-4471 password!"), vec!["reset", "the", "sku", "4471", "password"]);
+
+        let config = AnalyzerConfig::Standard {
+            stopwords: vec!["The".to_string(), "a".to_string()],
+        };
+        let analyzer = Analyzer::new(&config, None).unwrap();
+        assert_eq!(analyzer.terms("The price of a lamp"), vec!["price", "of", "lamp"]);
+        assert_eq!(
+            analyzer.config(),
+            AnalyzerConfig::Standard {
+                stopwords: vec!["a".to_string(), "the".to_string()]
+            }
+        );
+
+        assert!(matches!(
+            Analyzer::new(&AnalyzerConfig::Tokenizer, None),
+            Err(CollectionError::InvalidAnalyzer(_))
+        ));
+    }
+
+    #[test]
+    fn test_bm25_ranking() {
+        let mut index = Bm25::default();
+        let documents = [
+            "wireless mouse with usb receiver",
+            "usb cable",
+            "mouse pad",
+            "ergonomic wireless mouse, the best wireless mouse",
+        ];
+        for (position, document) in documents.iter().enumerate() {
+            index.insert(position, &terms(document));
+        }
+
+        let query = terms("wireless mouse");
+        let hits = index.search(&query, 10, |_| true);
+        let positions: Vec<usize> = hits.iter().map(|hit| hit.position).collect();
+        assert_eq!(positions, vec![3, 0, 2]);
+        assert_eq!(hits[1].score, index.score(&query, 0));
+        assert_eq!(index.score(&query, 1), 0.0);
+
+        // Filtered and removed items are not returned
+        assert_eq!(index.search(&query, 10, |position| position != 3).len(), 2);
+        index.remove(3, &terms(documents[3]));
+        assert_eq!(index.search(&query, 10, |_| true).len(), 2);
+        assert_eq!(index.search(&terms("ergonomic"), 10, |_| true), vec![]);
+        assert_eq!(index.search(&terms("cable"), 10, |_| true)[0].position, 1);
+    }
+
+    #[test]
+    fn test_fusion() {
+        let hit = |position, score| Hit { position, score };
+        let vector = [hit(0, 0.9), hit(1, 0.8), hit(2, 0.1)];
+        let lexical = [hit(1, 7.0), hit(2, 3.0)];
+        let vector_score = |position: usize| [0.9, 0.8, 0.1, 0.0][position];
+        let lexical_score = |position: usize| [0.0, 7.0, 3.0, 0.0][position];
+
+        // Item 1 ranks well in both lists
+        let results = fuse(&vector, &lexical, 3, Fusion::Rrf { k: 60.0 }, true, vector_score, lexical_score);
+        let positions: Vec<usize> = results.iter().map(|(hit, _)| hit.position).collect();
+        assert_eq!(positions, vec![1, 2, 0]);
+        assert_eq!(results[0].0.score, 1.0 / 62.0 + 1.0 / 61.0);
+        assert_eq!(
+            results[0].1,
+            ScoreBreakdown {
+                vector: 0.8,
+                lexical: 7.0,
+                vector_rank: Some(2),
+                lexical_rank: Some(1)
+            }
+        );
+        assert_eq!(results.iter().find(|(hit, _)| hit.position == 0).unwrap().1.lexical_rank, None);
+
+        let weighted = |vector_weight| {
+            fuse(&vector, &lexical, 1, Fusion::Weighted { vector_weight }, true, vector_score, lexical_score)[0].0.position
+        };
+        assert_eq!(weighted(1.0), 0);
+        assert_eq!(weighted(0.9), 0);
+        assert_eq!(weighted(0.5), 1);
+        assert_eq!(weighted(0.0), 1);
+    }
+}
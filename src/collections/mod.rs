@@ -0,0 +1,966 @@
+use axum::{http::StatusCode, Json};
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, MutexGuard, PoisonError, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::handlers::EmbeddingModel;
+use crate::models::{
+    AnalyzerConfig, CollectionInfo, CollectionItem, ErrorDetail, ErrorResponse, Filter, Fusion, IndexConfig, LatencyStats, Metric,
+    ScoreBreakdown, SelfTestResponse,
+};
+use filter::PayloadIndex;
+use hnsw::{Hnsw, Vectors};
+use lexical::{Analyzer, Bm25};
+use storage::{Record, Storage, Wal};
+
+pub mod filter;
+pub mod handlers;
+pub mod hnsw;
+pub mod lexical;
+pub mod storage;
+
+/// Longest accepted collection name
+const MAX_NAME_LENGTH: usize = 64;
+
+/// Largest accepted HNSW `m`, `ef_construction` and `ef`
+const MAX_HNSW_M: usize = 128;
+const MAX_HNSW_EF: usize = 4096;
+
+/// Largest accepted query `top_k`
+const MAX_TOP_K: usize = 1_000;
+
+/// Most queries run by a self-test, each of which also does an exact search
+const MAX_SELF_TEST_SAMPLE: usize = 1_000;
+
+/// Most payload index candidates scored one by one instead of searching the HNSW index
+const MAX_CANDIDATE_SCAN: usize = 10_000;
+
+/// Results taken from each ranking before hybrid queries fuse them
+const HYBRID_CANDIDATES: usize = 100;
+
+/// Tombstones tolerated regardless of collection size before storage is compacted
+const MIN_COMPACTION: usize = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CollectionError {
+    #[error("Collection '{0}' not found")]
+    NotFound(String),
+    #[error("Collection '{0}' already exists")]
+    AlreadyExists(String),
+    #[error("Item '{0}' not found")]
+    ItemNotFound(String),
+    #[error("Model '{0}' is not loaded")]
+    UnknownModel(String),
+    #[error("Collection names must be 1 to {MAX_NAME_LENGTH} letters, digits, '-' or '_'")]
+    InvalidName,
+    #[error("Vector has {actual} dimensions but the collection has {expected}")]
+    DimensionMismatch { expected: usize, actual: usize },
+    #[error("Item '{0}' needs a text or a vector")]
+    MissingContent(String),
+    #[error("{0}")]
+    InvalidIndex(String),
+    #[error("{0}")]
+    InvalidQuery(String),
+    #[error("Analyzer is unavailable: {0}")]
+    InvalidAnalyzer(String),
+    #[error("Collection '{0}' has no index to test")]
+    NoIndex(String),
+    #[error("Collections are not persisted; start the service with --data-dir")]
+    StorageDisabled,
+    #[error("Snapshot {0} not found")]
+    SnapshotNotFound(u64),
+    #[error("Data was written with model '{model}' (fingerprint {found}) but the loaded model's fingerprint is {expected}")]
+    FingerprintMismatch {
+        model: String,
+        found: String,
+        expected: String,
+    },
+    #[error("Storage error: {0}")]
+    Storage(#[from] std::io::Error),
+}
+
+impl CollectionError {
+    fn code(&self) -> &'static str {
+        match self {
+            CollectionError::NotFound(_) => "collection_not_found",
+            CollectionError::AlreadyExists(_) => "collection_exists",
+            CollectionError::ItemNotFound(_) => "item_not_found",
+            CollectionError::UnknownModel(_) => "model_not_found",
+            CollectionError::InvalidName => "invalid_collection_name",
+            CollectionError::DimensionMismatch { .. } => "dimension_mismatch",
+            CollectionError::MissingContent(_) => "invalid_item",
+            CollectionError::InvalidIndex(_) => "invalid_index",
+            CollectionError::InvalidQuery(_) => "invalid_query",
+            CollectionError::InvalidAnalyzer(_) => "invalid_analyzer",
+            CollectionError::NoIndex(_) => "index_not_configured",
+            CollectionError::StorageDisabled => "storage_not_configured",
+            CollectionError::SnapshotNotFound(_) => "snapshot_not_found",
+            CollectionError::FingerprintMismatch { .. } => "fingerprint_mismatch",
+            CollectionError::Storage(_) => "storage_error",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            CollectionError::NotFound(_) | CollectionError::ItemNotFound(_) | CollectionError::SnapshotNotFound(_) => {
+                StatusCode::NOT_FOUND
+            }
+            CollectionError::AlreadyExists(_) | CollectionError::FingerprintMismatch { .. } => StatusCode::CONFLICT,
+            CollectionError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl From<CollectionError> for (StatusCode, Json<ErrorResponse>) {
+    fn from(err: CollectionError) -> Self {
+        let error_type = match err {
+            CollectionError::Storage(_) => "server_error",
+            _ => "invalid_request_error",
+        };
+        (
+            err.status(),
+            Json(ErrorResponse {
+                error: ErrorDetail {
+                    message: err.to_string(),
+                    error_type: error_type.to_string(),
+                    code: Some(err.code().to_string()),
+                },
+            }),
+        )
+    }
+}
+
+/// The named collections of the service. With storage configured, every change is written to
+/// the log before it is applied; the log lock also orders changes across collections.
+#[derive(Default)]
+pub struct Collections {
+    collections: RwLock<HashMap<String, Arc<RwLock<Collection>>>>,
+    storage: Option<Storage>,
+    /// The loaded model, for collections analyzing text with its tokenizer
+    model: Option<Arc<dyn EmbeddingModel>>,
+}
+
+impl Collections {
+    pub fn new(model: Option<Arc<dyn EmbeddingModel>>) -> Self {
+        Self {
+            model,
+            ..Default::default()
+        }
+    }
+
+    /// Build a collection's analyzer from its configuration
+    pub fn analyzer(&self, config: &AnalyzerConfig) -> Result<Analyzer, CollectionError> {
+        Analyzer::new(config, self.model.as_ref())
+    }
+
+    /// Lock the write-ahead log, if there is one, for the duration of a change
+    fn log(&self) -> Option<MutexGuard<'_, Wal>> {
+        self.storage
+            .as_ref()
+            .map(|storage| storage.wal.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn create(&self, collection: Collection) -> Result<CollectionInfo, CollectionError> {
+        validate_name(&collection.name)?;
+        validate_index(collection.index_config())?;
+        collection.payload_indexes().iter().try_for_each(|field| validate_field(field))?;
+
+        let mut log = self.log();
+        let mut collections = self.collections.write().unwrap_or_else(PoisonError::into_inner);
+        if collections.contains_key(&collection.name) {
+            return Err(CollectionError::AlreadyExists(collection.name));
+        }
+
+        if let Some(log) = &mut log {
+            log.append(&Record::Create {
+                name: Cow::Borrowed(&collection.name),
+                model: Cow::Borrowed(&collection.model),
+                metric: collection.metric,
+                index: collection.index_config(),
+                payload_indexes: collection.payload_indexes(),
+                analyzer: collection.analyzer.config(),
+                dimensions: collection.dimensions,
+            })?;
+        }
+
+        let info = collection.info();
+        collections.insert(collection.name.clone(), Arc::new(RwLock::new(collection)));
+        Ok(info)
+    }
+
+    pub fn get(&self, name: &str) -> Result<Arc<RwLock<Collection>>, CollectionError> {
+        self.collections
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .cloned()
+            .ok_or_else(|| CollectionError::NotFound(name.to_string()))
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), CollectionError> {
+        let mut log = self.log();
+        let mut collections = self.collections.write().unwrap_or_else(PoisonError::into_inner);
+        if !collections.contains_key(name) {
+            return Err(CollectionError::NotFound(name.to_string()));
+        }
+
+        if let Some(log) = &mut log {
+            log.append(&Record::Drop { name: name.into() })?;
+        }
+
+        collections.remove(name);
+        Ok(())
+    }
+
+    /// Insert or replace items, all of which must carry a vector. Nothing is written unless
+    /// every item is valid.
+    pub fn upsert(&self, name: &str, items: Vec<CollectionItem>) -> Result<(), CollectionError> {
+        let mut log = self.log();
+        let collection = self.get(name)?;
+        let mut collection = collection.write().unwrap_or_else(PoisonError::into_inner);
+
+        for item in &items {
+            let vector = item
+                .vector
+                .as_ref()
+                .ok_or_else(|| CollectionError::MissingContent(item.id.clone()))?;
+            collection.check_dimensions(vector)?;
+        }
+
+        if let Some(log) = &mut log {
+            log.append(&Record::Upsert {
+                name: name.into(),
+                items: Cow::Borrowed(&items),
+            })?;
+        }
+
+        items.into_iter().try_for_each(|item| collection.upsert(item))
+    }
+
+    /// Delete items by id, returning how many existed
+    pub fn delete_items(&self, name: &str, ids: &[String]) -> Result<usize, CollectionError> {
+        let mut log = self.log();
+        let collection = self.get(name)?;
+        let mut collection = collection.write().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(log) = &mut log {
+            log.append(&Record::Delete {
+                name: name.into(),
+                ids: Cow::Borrowed(ids),
+            })?;
+        }
+
+        Ok(ids.iter().filter(|id| collection.delete(id)).count())
+    }
+
+    /// Index a metadata field of a collection; indexing a field twice does nothing
+    pub fn add_payload_index(&self, name: &str, field: &str) -> Result<CollectionInfo, CollectionError> {
+        validate_field(field)?;
+
+        let mut log = self.log();
+        let collection = self.get(name)?;
+        let mut collection = collection.write().unwrap_or_else(PoisonError::into_inner);
+        if collection.payload_indexes.contains_key(field) {
+            return Ok(collection.info());
+        }
+
+        if let Some(log) = &mut log {
+            log.append(&Record::CreatePayloadIndex {
+                name: name.into(),
+                field: field.into(),
+            })?;
+        }
+
+        collection.add_payload_index(field);
+        Ok(collection.info())
+    }
+
+    /// Info for every collection, sorted by name
+    pub fn list(&self) -> Vec<CollectionInfo> {
+        let mut list: Vec<CollectionInfo> = self
+            .collections
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .map(|collection| collection.read().unwrap_or_else(PoisonError::into_inner).info())
+            .collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+}
+
+/// Whether `name` is 1 to `max_length` ASCII letters, digits, '-' or '_', which keeps it safe to
+/// use in file names and URL paths. Shared by every kind of named resource.
+pub(crate) fn is_valid_name(name: &str, max_length: usize) -> bool {
+    !name.is_empty()
+        && name.len() <= max_length
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn validate_name(name: &str) -> Result<(), CollectionError> {
+    if is_valid_name(name, MAX_NAME_LENGTH) {
+        Ok(())
+    } else {
+        Err(CollectionError::InvalidName)
+    }
+}
+
+fn validate_index(index: IndexConfig) -> Result<(), CollectionError> {
+    let IndexConfig::Hnsw(config) = index else {
+        return Ok(());
+    };
+
+    if !(2..=MAX_HNSW_M).contains(&config.m) {
+        return Err(CollectionError::InvalidIndex(format!("m must be between 2 and {}", MAX_HNSW_M)));
+    }
+    if !(1..=MAX_HNSW_EF).contains(&config.ef_construction) || !(1..=MAX_HNSW_EF).contains(&config.ef) {
+        return Err(CollectionError::InvalidIndex(format!(
+            "ef_construction and ef must be between 1 and {}",
+            MAX_HNSW_EF
+        )));
+    }
+
+    Ok(())
+}
+
+fn validate_query(top_k: usize, ef: Option<usize>) -> Result<(), CollectionError> {
+    if !(1..=MAX_TOP_K).contains(&top_k) {
+        return Err(CollectionError::InvalidQuery(format!("top_k must be between 1 and {}", MAX_TOP_K)));
+    }
+    if ef.is_some_and(|ef| !(1..=MAX_HNSW_EF).contains(&ef)) {
+        return Err(CollectionError::InvalidQuery(format!("ef must be between 1 and {}", MAX_HNSW_EF)));
+    }
+    Ok(())
+}
+
+fn validate_self_test(sample_size: usize, top_k: usize, ef: Option<usize>) -> Result<(), CollectionError> {
+    if !(1..=MAX_SELF_TEST_SAMPLE).contains(&sample_size) {
+        return Err(CollectionError::InvalidQuery(format!(
+            "sample_size must be between 1 and {}",
+            MAX_SELF_TEST_SAMPLE
+        )));
+    }
+    validate_query(top_k, ef)
+}
+
+fn validate_field(field: &str) -> Result<(), CollectionError> {
+    if field.is_empty() || field.split('.').any(str::is_empty) {
+        return Err(CollectionError::InvalidIndex(format!("'{}' is not a metadata field path", field)));
+    }
+    Ok(())
+}
+
+/// A search result: the item position and its score under the collection metric
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub position: usize,
+    pub score: f32,
+}
+
+/// Vectors with ids, texts and metadata, searched by scanning every vector or through an HNSW
+/// index. Vectors are stored contiguously in slots. Deleting or replacing an item leaves a
+/// tombstone in its slot, so index nodes stay valid; storage is compacted and the index
+/// rebuilt once tombstones outnumber the live items.
+pub struct Collection {
+    pub name: String,
+    pub model: String,
+    pub metric: Metric,
+    pub dimensions: usize,
+    ids: Vec<String>,
+    vectors: Vec<f32>,
+    texts: Vec<Option<String>>,
+    metadata: Vec<Option<serde_json::Value>>,
+    live: Vec<bool>,
+    positions: HashMap<String, usize>,
+    index: Option<Hnsw>,
+    payload_indexes: BTreeMap<String, PayloadIndex>,
+    analyzer: Analyzer,
+    lexical: Bm25,
+}
+
+impl Collection {
+    pub fn new(
+        name: String,
+        model: String,
+        metric: Metric,
+        dimensions: usize,
+        index: IndexConfig,
+        analyzer: Analyzer,
+    ) -> Self {
+        Self {
+            name,
+            model,
+            metric,
+            dimensions,
+            ids: Vec::new(),
+            vectors: Vec::new(),
+            texts: Vec::new(),
+            metadata: Vec::new(),
+            live: Vec::new(),
+            positions: HashMap::new(),
+            index: match index {
+                IndexConfig::Flat => None,
+                IndexConfig::Hnsw(config) => Some(Hnsw::new(config, metric)),
+            },
+            payload_indexes: BTreeMap::new(),
+            analyzer,
+            lexical: Bm25::default(),
+        }
+    }
+
+    pub fn info(&self) -> CollectionInfo {
+        CollectionInfo {
+            name: self.name.clone(),
+            model: self.model.clone(),
+            metric: self.metric,
+            index: self.index_config(),
+            payload_indexes: self.payload_indexes(),
+            analyzer: self.analyzer.config(),
+            dimensions: self.dimensions,
+            count: self.len(),
+        }
+    }
+
+    pub fn index_config(&self) -> IndexConfig {
+        match &self.index {
+            Some(index) => IndexConfig::Hnsw(index.config()),
+            None => IndexConfig::Flat,
+        }
+    }
+
+    /// Metadata fields with a payload index
+    pub fn payload_indexes(&self) -> Vec<String> {
+        self.payload_indexes.keys().cloned().collect()
+    }
+
+    /// Index a metadata field for filtered queries, returning false if it already was
+    pub fn add_payload_index(&mut self, field: &str) -> bool {
+        if self.payload_indexes.contains_key(field) {
+            return false;
+        }
+
+        let mut index = PayloadIndex::default();
+        for position in self.positions() {
+            if let Some(value) = filter::lookup(self.metadata[position].as_ref(), field) {
+                index.insert(position, value);
+            }
+        }
+        self.payload_indexes.insert(field.to_string(), index);
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Insert an item or replace the item with the same id. The item must carry a vector.
+    pub fn upsert(&mut self, item: CollectionItem) -> Result<(), CollectionError> {
+        let vector = item.vector.ok_or_else(|| CollectionError::MissingContent(item.id.clone()))?;
+        self.check_dimensions(&vector)?;
+
+        if let Some(position) = self.positions.get(&item.id).copied() {
+            self.tombstone(position);
+        }
+
+        let position = self.ids.len();
+        for (field, index) in &mut self.payload_indexes {
+            if let Some(value) = filter::lookup(item.metadata.as_ref(), field) {
+                index.insert(position, value);
+            }
+        }
+
+        if let Some(text) = &item.text {
+            self.lexical.insert(position, &self.analyzer.terms(text));
+        }
+
+        self.positions.insert(item.id.clone(), position);
+        self.ids.push(item.id);
+        self.vectors.extend_from_slice(&vector);
+        self.texts.push(item.text);
+        self.metadata.push(item.metadata);
+        self.live.push(true);
+
+        let vectors = Vectors {
+            data: &self.vectors,
+            dimensions: self.dimensions,
+        };
+        if let Some(index) = &mut self.index {
+            index.insert(vectors);
+        }
+
+        self.compact_if_sparse();
+        Ok(())
+    }
+
+    /// Remove an item, returning whether it existed
+    pub fn delete(&mut self, id: &str) -> bool {
+        let Some(position) = self.positions.remove(id) else {
+            return false;
+        };
+
+        self.tombstone(position);
+        self.compact_if_sparse();
+        true
+    }
+
+    fn tombstone(&mut self, position: usize) {
+        self.live[position] = false;
+        if let Some(text) = &self.texts[position] {
+            self.lexical.remove(position, &self.analyzer.terms(text));
+        }
+        for (field, index) in &mut self.payload_indexes {
+            if let Some(value) = filter::lookup(self.metadata[position].as_ref(), field) {
+                index.remove(position, value);
+            }
+        }
+    }
+
+    fn compact_if_sparse(&mut self) {
+        let tombstones = self.ids.len() - self.len();
+        if tombstones > self.len().max(MIN_COMPACTION) {
+            self.compact();
+        }
+    }
+
+    /// Drop tombstoned slots and rebuild the indexes over the remaining items
+    fn compact(&mut self) {
+        let live = std::mem::take(&mut self.live);
+        let ids = std::mem::take(&mut self.ids);
+        let texts = std::mem::take(&mut self.texts);
+        let metadata = std::mem::take(&mut self.metadata);
+        let vectors = std::mem::take(&mut self.vectors);
+        self.positions.clear();
+        if let Some(index) = &mut self.index {
+            *index = Hnsw::new(index.config(), self.metric);
+        }
+        for index in self.payload_indexes.values_mut() {
+            *index = PayloadIndex::default();
+        }
+        self.lexical = Bm25::default();
+
+        let slots = ids.into_iter().zip(texts).zip(metadata).zip(vectors.chunks_exact(self.dimensions));
+        for (live, (((id, text), metadata), vector)) in live.into_iter().zip(slots) {
+            if live {
+                let item = CollectionItem {
+                    id,
+                    text,
+                    vector: Some(vector.to_vec()),
+                    metadata,
+                };
+                // Vectors already passed the dimension check when first inserted
+                let _ = self.upsert(item);
+            }
+        }
+    }
+
+    pub fn position(&self, id: &str) -> Option<usize> {
+        self.positions.get(id).copied()
+    }
+
+    pub fn item(&self, position: usize, include_vector: bool) -> CollectionItem {
+        CollectionItem {
+            id: self.ids[position].clone(),
+            text: self.texts[position].clone(),
+            vector: include_vector.then(|| self.vector(position).to_vec()),
+            metadata: self.metadata[position].clone(),
+        }
+    }
+
+    pub fn vector(&self, position: usize) -> &[f32] {
+        &self.vectors[position * self.dimensions..][..self.dimensions]
+    }
+
+    /// Positions of the items currently in the collection, in storage order
+    pub fn positions(&self) -> impl Iterator<Item = usize> + '_ {
+        self.live.iter().enumerate().filter(|(_, live)| **live).map(|(position, _)| position)
+    }
+
+    pub fn check_dimensions(&self, vector: &[f32]) -> Result<(), CollectionError> {
+        if vector.len() == self.dimensions {
+            Ok(())
+        } else {
+            Err(CollectionError::DimensionMismatch {
+                expected: self.dimensions,
+                actual: vector.len(),
+            })
+        }
+    }
+
+    /// The `top_k` items closest to `query` that match `filter`, best first. Uses the index
+    /// when there is one, exploring `ef` candidates or the collection's default. Filters are
+    /// applied during the search rather than to its results, so matching items are never
+    /// crowded out by closer ones that don't match.
+    pub fn search(&self, query: &[f32], top_k: usize, ef: Option<usize>, filter: Option<&Filter>) -> Vec<Hit> {
+        let Some(filter) = filter else {
+            return match &self.index {
+                Some(index) => self.index_search(index, query, top_k, ef.unwrap_or(index.config().ef), |_| true),
+                None => self.exact_search(query, top_k),
+            };
+        };
+
+        let matches = |position: usize| filter.matches(self.metadata[position].as_ref());
+        let candidates = filter::candidates(&self.payload_indexes, filter);
+        match (candidates, &self.index) {
+            // Payload indexes narrowed the search enough to score every candidate
+            (Some(candidates), index) if index.is_none() || candidates.len() <= MAX_CANDIDATE_SCAN => {
+                self.scan(query, top_k, candidates.into_iter().filter(|&position| matches(position)))
+            }
+            (_, Some(index)) => self.index_search(index, query, top_k, ef.unwrap_or(index.config().ef), matches),
+            (_, None) => self.scan(query, top_k, self.positions().filter(|&position| matches(position))),
+        }
+    }
+
+    /// The `top_k` items matching `filter` whose text scores highest for `query` under BM25
+    pub fn lexical_search(&self, query: &str, top_k: usize, filter: Option<&Filter>) -> Vec<Hit> {
+        let terms = self.analyzer.terms(query);
+        self.lexical.search(&terms, top_k, |position| {
+            filter.is_none_or(|filter| filter.matches(self.metadata[position].as_ref()))
+        })
+    }
+
+    /// Vector and lexical search fused into one ranking. Each side contributes its best
+    /// `HYBRID_CANDIDATES` matches, or `top_k` if that is more.
+    pub fn hybrid_search(
+        &self,
+        text: &str,
+        vector: &[f32],
+        top_k: usize,
+        ef: Option<usize>,
+        filter: Option<&Filter>,
+        fusion: Fusion,
+    ) -> Vec<(Hit, ScoreBreakdown)> {
+        let depth = top_k.max(HYBRID_CANDIDATES);
+        let terms = self.analyzer.terms(text);
+        let vector_hits = self.search(vector, depth, ef, filter);
+        let lexical_hits = self.lexical.search(&terms, depth, |position| {
+            filter.is_none_or(|filter| filter.matches(self.metadata[position].as_ref()))
+        });
+
+        lexical::fuse(
+            &vector_hits,
+            &lexical_hits,
+            top_k,
+            fusion,
+            self.metric.higher_is_better(),
+            |position| self.metric.score(vector, self.vector(position)),
+            |position| self.lexical.score(&terms, position),
+        )
+    }
+
+    /// Scan every item; always exact
+    pub fn exact_search(&self, query: &[f32], top_k: usize) -> Vec<Hit> {
+        self.scan(query, top_k, self.positions())
+    }
+
+    fn scan(&self, query: &[f32], top_k: usize, positions: impl Iterator<Item = usize>) -> Vec<Hit> {
+        let hits = positions
+            .map(|position| Hit {
+                position,
+                score: self.metric.score(query, self.vector(position)),
+            })
+            .collect();
+
+        best(hits, top_k, self.metric)
+    }
+
+    fn index_search(
+        &self,
+        index: &Hnsw,
+        query: &[f32],
+        top_k: usize,
+        ef: usize,
+        keep: impl Fn(usize) -> bool,
+    ) -> Vec<Hit> {
+        let vectors = Vectors {
+            data: &self.vectors,
+            dimensions: self.dimensions,
+        };
+        index
+            .search(query, top_k, ef, vectors, |node| self.live[node as usize] && keep(node as usize))
+            .into_iter()
+            .map(|candidate| Hit {
+                position: candidate.node as usize,
+                score: index.score(candidate.distance),
+            })
+            .collect()
+    }
+
+    /// Measure recall and latency of the index against exact search, using up to
+    /// `sample_size` of the collection's own vectors as queries
+    pub fn self_test(&self, sample_size: usize, top_k: usize, ef: Option<usize>) -> Result<SelfTestResponse, CollectionError> {
+        let index = self
+            .index
+            .as_ref()
+            .ok_or_else(|| CollectionError::NoIndex(self.name.clone()))?;
+        let ef = ef.unwrap_or(index.config().ef);
+
+        // Spread the sample evenly over the collection
+        let positions: Vec<usize> = self.positions().collect();
+        let step = (positions.len() / sample_size.max(1)).max(1);
+        let sample: Vec<usize> = positions.into_iter().step_by(step).take(sample_size).collect();
+
+        let mut index_times = Vec::with_capacity(sample.len());
+        let mut exact_times = Vec::with_capacity(sample.len());
+        let (mut found, mut expected) = (0, 0);
+
+        for &position in &sample {
+            let query = self.vector(position);
+
+            let start = Instant::now();
+            let approximate = self.index_search(index, query, top_k, ef, |_| true);
+            index_times.push(start.elapsed());
+
+            let start = Instant::now();
+            let exact = self.exact_search(query, top_k);
+            exact_times.push(start.elapsed());
+
+            expected += exact.len();
+            found += exact
+                .iter()
+                .filter(|hit| approximate.iter().any(|other| other.position == hit.position))
+                .count();
+        }
+
+        Ok(SelfTestResponse {
+            sample_size: sample.len(),
+            top_k,
+            ef,
+            recall: if expected == 0 { 1.0 } else { found as f32 / expected as f32 },
+            index_latency: latency_stats(index_times),
+            exact_latency: latency_stats(exact_times),
+        })
+    }
+}
+
+fn latency_stats(mut times: Vec<Duration>) -> LatencyStats {
+    if times.is_empty() {
+        return LatencyStats {
+            mean_us: 0.0,
+            p50_us: 0.0,
+            p99_us: 0.0,
+        };
+    }
+
+    times.sort();
+    let micros = |time: Duration| time.as_secs_f64() * 1e6;
+    let percentile = |p: f64| micros(times[((times.len() - 1) as f64 * p).round() as usize]);
+
+    LatencyStats {
+        mean_us: times.iter().copied().map(micros).sum::<f64>() / times.len() as f64,
+        p50_us: percentile(0.5),
+        p99_us: percentile(0.99),
+    }
+}
+
+/// Keep the `top_k` best hits under `metric`, sorted best first
+pub fn best(mut hits: Vec<Hit>, top_k: usize, metric: Metric) -> Vec<Hit> {
+    let order = |a: &Hit, b: &Hit| {
+        let ordering = b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal);
+        if metric.higher_is_better() {
+            ordering
+        } else {
+            ordering.reverse()
+        }
+    };
+
+    if top_k == 0 {
+        return Vec::new();
+    }
+    if hits.len() > top_k {
+        hits.select_nth_unstable_by(top_k - 1, order);
+        hits.truncate(top_k);
+    }
+    hits.sort_by(order);
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::HnswConfig;
+
+    fn item(id: &str, vector: Vec<f32>) -> CollectionItem {
+        CollectionItem {
+            id: id.to_string(),
+            text: None,
+            vector: Some(vector),
+            metadata: None,
+        }
+    }
+
+    fn collection(metric: Metric) -> Collection {
+        Collection::new("test".to_string(), "test-model".to_string(), metric, 2, IndexConfig::Flat, Analyzer::default())
+    }
+
+    #[test]
+    fn test_upsert_and_delete() {
+        let mut collection = collection(Metric::Cosine);
+        collection.upsert(item("a", vec![1.0, 0.0])).unwrap();
+        collection.upsert(item("b", vec![0.0, 1.0])).unwrap();
+        collection.upsert(item("c", vec![1.0, 1.0])).unwrap();
+        assert_eq!(collection.len(), 3);
+
+        // Replacing tombstones the old slot
+        collection.upsert(item("a", vec![2.0, 0.0])).unwrap();
+        assert_eq!(collection.len(), 3);
+        assert_eq!(collection.vector(collection.position("a").unwrap()), &[2.0, 0.0]);
+        assert_eq!(collection.positions().count(), 3);
+
+        assert!(collection.delete("a"));
+        assert!(!collection.delete("a"));
+        assert_eq!(collection.len(), 2);
+        assert_eq!(collection.vector(collection.position("c").unwrap()), &[1.0, 1.0]);
+        let hits = collection.search(&[1.0, 0.0], 10, None, None);
+        assert_eq!(hits.len(), 2);
+
+        assert!(matches!(
+            collection.upsert(item("d", vec![1.0])),
+            Err(CollectionError::DimensionMismatch { expected: 2, actual: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_compaction() {
+        let mut collection = collection(Metric::Euclidean);
+        for i in 0..200 {
+            collection.upsert(item(&i.to_string(), vec![i as f32, 0.0])).unwrap();
+        }
+        for i in 0..150 {
+            collection.delete(&i.to_string());
+        }
+
+        // Compaction ran once tombstones outnumbered the live items
+        assert_eq!(collection.len(), 50);
+        assert!(collection.ids.len() < 200);
+        assert_eq!(collection.vector(collection.position("199").unwrap()), &[199.0, 0.0]);
+        assert_eq!(collection.item(collection.search(&[160.0, 0.0], 1, None, None)[0].position, false).id, "160");
+    }
+
+    #[test]
+    fn test_search_order() {
+        let mut collection = collection(Metric::Euclidean);
+        collection.upsert(item("far", vec![10.0, 10.0])).unwrap();
+        collection.upsert(item("near", vec![1.0, 1.0])).unwrap();
+        collection.upsert(item("exact", vec![0.0, 0.0])).unwrap();
+
+        let hits = collection.search(&[0.0, 0.0], 2, None, None);
+        let ids: Vec<String> = hits.iter().map(|hit| collection.item(hit.position, false).id).collect();
+        assert_eq!(ids, vec!["exact", "near"]);
+        assert_eq!(hits[0].score, 0.0);
+
+        let mut collection = self::collection(Metric::Dot);
+        collection.upsert(item("small", vec![1.0, 0.0])).unwrap();
+        collection.upsert(item("large", vec![5.0, 0.0])).unwrap();
+        let hits = collection.search(&[1.0, 0.0], 10, None, None);
+        assert_eq!(collection.item(hits[0].position, false).id, "large");
+        assert_eq!(hits.len(), 2);
+    }
+
+    #[test]
+    fn test_collection_names() {
+        let collections = Collections::default();
+        let create = |name: &str| {
+            collections.create(Collection::new(name.to_string(), "m".to_string(), Metric::Cosine, 2, IndexConfig::Flat, Analyzer::default()))
+        };
+
+        create("docs_v1-en").unwrap();
+        assert!(matches!(create("docs_v1-en"), Err(CollectionError::AlreadyExists(_))));
+        assert!(matches!(create("../etc"), Err(CollectionError::InvalidName)));
+        assert!(matches!(create(""), Err(CollectionError::InvalidName)));
+
+        let index = IndexConfig::Hnsw(HnswConfig { m: 1, ..Default::default() });
+        let result = collections.create(Collection::new("hnsw".to_string(), "m".to_string(), Metric::Cosine, 2, index, Analyzer::default()));
+        assert!(matches!(result, Err(CollectionError::InvalidIndex(_))));
+
+        assert_eq!(collections.list().len(), 1);
+        collections.delete("docs_v1-en").unwrap();
+        assert!(matches!(collections.get("docs_v1-en"), Err(CollectionError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_hnsw_collection() {
+        let index = IndexConfig::Hnsw(HnswConfig { m: 8, ef_construction: 64, ef: 32 });
+        let mut collection = Collection::new("test".to_string(), "m".to_string(), Metric::Cosine, 2, index, Analyzer::default());
+        for i in 0..300 {
+            let angle = i as f32 / 300.0 * std::f32::consts::PI;
+            collection.upsert(item(&i.to_string(), vec![angle.cos(), angle.sin()])).unwrap();
+        }
+
+        let nearest = |collection: &Collection| {
+            let query = collection.vector(collection.position("100").unwrap()).to_vec();
+            collection.item(collection.search(&query, 1, None, None)[0].position, false).id
+        };
+        assert_eq!(nearest(&collection), "100");
+
+        // Deleted items are skipped, replaced items are found at their new vector
+        collection.delete("100");
+        collection.upsert(item("100", vec![-1.0, 0.0])).unwrap();
+        assert_eq!(nearest(&collection), "100");
+        let hits = collection.search(&[1.0, 0.0], 3, Some(16), None);
+        assert!(hits.iter().all(|hit| collection.item(hit.position, false).id != "100"));
+
+        let report = collection.self_test(50, 5, None).unwrap();
+        assert_eq!(report.sample_size, 50);
+        assert_eq!(report.ef, 32);
+        assert!(report.recall > 0.9);
+
+        assert!(matches!(
+            self::collection(Metric::Cosine).self_test(10, 5, None),
+            Err(CollectionError::NoIndex(_))
+        ));
+    }
+
+    #[test]
+    fn test_filtered_search() {
+        let index = IndexConfig::Hnsw(HnswConfig { m: 8, ef_construction: 64, ef: 8 });
+        for index in [IndexConfig::Flat, index] {
+            let mut collection = Collection::new("test".to_string(), "m".to_string(), Metric::Cosine, 2, index, Analyzer::default());
+            collection.add_payload_index("tenant");
+            for i in 0..500 {
+                let angle = i as f32 / 500.0 * std::f32::consts::PI;
+                let mut item = item(&i.to_string(), vec![angle.cos(), angle.sin()]);
+                item.metadata = Some(serde_json::json!({ "tenant": if i % 100 == 0 { "rare" } else { "common" }, "rank": i }));
+                collection.upsert(item).unwrap();
+            }
+
+            let search = |collection: &Collection, filter: serde_json::Value| {
+                let filter: Filter = serde_json::from_value(filter).unwrap();
+                let mut ids: Vec<String> = collection
+                    .search(&[1.0, 0.0], 5, None, Some(&filter))
+                    .into_iter()
+                    .map(|hit| collection.item(hit.position, false).id)
+                    .collect();
+                ids.sort();
+                ids
+            };
+
+            // Through the payload index, and without one during the graph search
+            let expected = vec!["0", "100", "200", "300", "400"];
+            assert_eq!(search(&collection, serde_json::json!({ "field": "tenant", "eq": "rare" })), expected);
+            assert_eq!(
+                search(&collection, serde_json::json!({ "or": [{ "field": "rank", "eq": 0 }, { "field": "rank", "gte": 100, "lte": 400, "in": [100, 200, 300, 400] }] })),
+                expected
+            );
+            assert_eq!(
+                search(&collection, serde_json::json!({ "and": [{ "field": "tenant", "eq": "common" }, { "not": { "field": "rank", "gt": 2 } }] })),
+                vec!["1", "2"]
+            );
+
+            // Replaced and deleted items leave the payload index
+            collection.delete("0");
+            let mut moved = item("100", vec![1.0, 0.0]);
+            moved.metadata = Some(serde_json::json!({ "tenant": "common" }));
+            collection.upsert(moved).unwrap();
+            assert_eq!(search(&collection, serde_json::json!({ "field": "tenant", "eq": "rare" })), vec!["200", "300", "400"]);
+        }
+    }
+}
@@ -0,0 +1,699 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    time::{Duration, UNIX_EPOCH},
+};
+use tokio::task;
+use tracing::{error, info, warn};
+
+use super::{lexical::Analyzer, Collection, CollectionError, Collections};
+use crate::handlers::{AppState, EmbeddingModel};
+use crate::models::{AnalyzerConfig, CollectionInfo, CollectionItem, IndexConfig, Metric, SnapshotInfo};
+
+const SNAPSHOT_PREFIX: &str = "snapshot-";
+const SNAPSHOT_EXTENSION: &str = "msgpack";
+const LOG_PREFIX: &str = "wal-";
+const LOG_EXTENSION: &str = "log";
+
+/// Texts embedded to fingerprint a model
+const FINGERPRINT_PROBE: [&str; 3] = ["", "fingerprint", "The quick brown fox jumps over the lazy dog"];
+
+/// Identify a model by its embeddings of a fixed probe, so stored vectors are never loaded
+/// under a model that would produce different ones. Values are rounded before hashing so
+/// floating point noise between builds does not change the fingerprint.
+pub fn fingerprint(model: &dyn EmbeddingModel) -> String {
+    let probe: Vec<String> = FINGERPRINT_PROBE.iter().map(|text| text.to_string()).collect();
+    let result = model.encode_with_stats(&probe);
+
+    // FNV-1a
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |value: u64| {
+        for byte in value.to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    for embedding in &result.embeddings {
+        feed(embedding.len() as u64);
+        for value in embedding {
+            feed((value * 1e4).round() as i64 as u64);
+        }
+    }
+
+    format!("{:016x}", hash)
+}
+
+/// A change to the collections, as written to the write-ahead log
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Record<'a> {
+    /// First record of every log file
+    Header {
+        fingerprint: Cow<'a, str>,
+        model: Cow<'a, str>,
+    },
+    Create {
+        name: Cow<'a, str>,
+        model: Cow<'a, str>,
+        metric: Metric,
+        index: IndexConfig,
+        #[serde(default)]
+        payload_indexes: Vec<String>,
+        #[serde(default)]
+        analyzer: AnalyzerConfig,
+        dimensions: usize,
+    },
+    Drop {
+        name: Cow<'a, str>,
+    },
+    Upsert {
+        name: Cow<'a, str>,
+        items: Cow<'a, [CollectionItem]>,
+    },
+    Delete {
+        name: Cow<'a, str>,
+        ids: Cow<'a, [String]>,
+    },
+    CreatePayloadIndex {
+        name: Cow<'a, str>,
+        field: Cow<'a, str>,
+    },
+}
+
+/// Every collection at one point in time, together with the model that embedded it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub fingerprint: String,
+    pub model: String,
+    pub collections: Vec<CollectionSnapshot>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CollectionSnapshot {
+    pub name: String,
+    pub model: String,
+    pub metric: Metric,
+    pub index: IndexConfig,
+    #[serde(default)]
+    pub payload_indexes: Vec<String>,
+    #[serde(default)]
+    pub analyzer: AnalyzerConfig,
+    pub dimensions: usize,
+    pub items: Vec<CollectionItem>,
+}
+
+impl Collection {
+    pub fn export(&self) -> CollectionSnapshot {
+        CollectionSnapshot {
+            name: self.name.clone(),
+            model: self.model.clone(),
+            metric: self.metric,
+            index: self.index_config(),
+            payload_indexes: self.payload_indexes(),
+            analyzer: self.analyzer.config(),
+            dimensions: self.dimensions,
+            items: self.positions().map(|position| self.item(position, true)).collect(),
+        }
+    }
+
+    /// Rebuild a collection, and its indexes, from a snapshot
+    pub fn import(snapshot: CollectionSnapshot, analyzer: Analyzer) -> Result<Self, CollectionError> {
+        let mut collection = Collection::new(
+            snapshot.name,
+            snapshot.model,
+            snapshot.metric,
+            snapshot.dimensions,
+            snapshot.index,
+            analyzer,
+        );
+        for field in &snapshot.payload_indexes {
+            collection.add_payload_index(field);
+        }
+        for item in snapshot.items {
+            collection.upsert(item)?;
+        }
+        Ok(collection)
+    }
+}
+
+/// The open log file. Records are synced to disk before the change they describe is applied.
+pub struct Wal {
+    file: File,
+    generation: u64,
+    /// Records appended since the file was started
+    records: u64,
+}
+
+impl Wal {
+    fn create(dir: &Path, generation: u64, fingerprint: &str, model: &str) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(file_path(dir, LOG_PREFIX, generation, LOG_EXTENSION))?;
+        let mut wal = Self {
+            file,
+            generation,
+            records: 0,
+        };
+        wal.append(&Record::Header {
+            fingerprint: fingerprint.into(),
+            model: model.into(),
+        })?;
+        wal.records = 0;
+        sync_dir(dir)?;
+        Ok(wal)
+    }
+
+    pub fn append(&mut self, record: &Record<'_>) -> io::Result<()> {
+        let payload = rmp_serde::to_vec_named(record).map_err(io::Error::other)?;
+
+        // Length and checksum first, so a torn write at the end of the file is detected
+        let mut frame = Vec::with_capacity(payload.len() + 8);
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        frame.extend_from_slice(&payload);
+
+        self.file.write_all(&frame)?;
+        self.file.sync_data()?;
+        self.records += 1;
+        Ok(())
+    }
+}
+
+/// Pass every record of a log file to `apply`. Returns whether the file ended in an
+/// incomplete or corrupt record, which is where reading stopped.
+fn read_log(path: &Path, mut apply: impl FnMut(Record<'static>) -> anyhow::Result<()>) -> anyhow::Result<bool> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    loop {
+        let mut header = [0u8; 8];
+        let mut filled = 0;
+        while filled < header.len() {
+            let read = reader.read(&mut header[filled..])?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+        }
+        match filled {
+            0 => return Ok(false),
+            8 => {}
+            _ => return Ok(true),
+        }
+
+        let length = u32::from_le_bytes(header[..4].try_into()?) as usize;
+        let checksum = u32::from_le_bytes(header[4..].try_into()?);
+
+        let mut payload = Vec::new();
+        let read = (&mut reader).take(length as u64).read_to_end(&mut payload)?;
+        if read < length || crc32fast::hash(&payload) != checksum {
+            return Ok(true);
+        }
+        let Ok(record) = rmp_serde::from_slice::<Record<'static>>(&payload) else {
+            return Ok(true);
+        };
+
+        apply(record)?;
+    }
+}
+
+/// Where collections are persisted: numbered snapshots and the write-ahead logs started after them.
+/// Snapshot `n` holds everything logged before log `n`.
+pub struct Storage {
+    dir: PathBuf,
+    fingerprint: String,
+    model: String,
+    /// Snapshots kept on disk; older ones are deleted
+    keep: usize,
+    pub(super) wal: Mutex<Wal>,
+    /// Held for the whole of a snapshot or restore so they do not interleave
+    snapshotting: Mutex<()>,
+}
+
+impl Storage {
+    fn snapshot_path(&self, generation: u64) -> PathBuf {
+        file_path(&self.dir, SNAPSHOT_PREFIX, generation, SNAPSHOT_EXTENSION)
+    }
+
+    fn check_fingerprint(&self, fingerprint: &str, model: &str) -> Result<(), CollectionError> {
+        check_fingerprint(fingerprint, model, &self.fingerprint)
+    }
+
+    /// Start a new log file for changes after this point
+    fn rotate(&self, wal: &mut Wal) -> io::Result<u64> {
+        let generation = wal.generation + 1;
+        *wal = Wal::create(&self.dir, generation, &self.fingerprint, &self.model)?;
+        Ok(generation)
+    }
+
+    /// Write a snapshot to a temporary file and move it into place once it is on disk
+    fn write_snapshot(&self, generation: u64, snapshot: &Snapshot) -> io::Result<SnapshotInfo> {
+        let path = self.snapshot_path(generation);
+        let temporary = path.with_extension("tmp");
+
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        rmp_serde::encode::write_named(&mut writer, snapshot).map_err(io::Error::other)?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&temporary, &path)?;
+        sync_dir(&self.dir)?;
+
+        snapshot_info(&path, generation)
+    }
+
+    fn read_snapshot(&self, generation: u64) -> Result<Snapshot, CollectionError> {
+        let path = self.snapshot_path(generation);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(CollectionError::SnapshotNotFound(generation)),
+            Err(e) => return Err(e.into()),
+        };
+        rmp_serde::from_read(BufReader::new(file)).map_err(|e| io::Error::other(e).into())
+    }
+
+    fn snapshots(&self) -> io::Result<Vec<SnapshotInfo>> {
+        generations(&self.dir, SNAPSHOT_PREFIX, SNAPSHOT_EXTENSION)?
+            .into_iter()
+            .map(|generation| snapshot_info(&self.snapshot_path(generation), generation))
+            .collect()
+    }
+
+    /// Delete logs covered by snapshot `generation` and snapshots beyond the newest `keep`
+    fn prune(&self, generation: u64) -> io::Result<()> {
+        for log in generations(&self.dir, LOG_PREFIX, LOG_EXTENSION)? {
+            if log < generation {
+                fs::remove_file(file_path(&self.dir, LOG_PREFIX, log, LOG_EXTENSION))?;
+            }
+        }
+
+        let snapshots = generations(&self.dir, SNAPSHOT_PREFIX, SNAPSHOT_EXTENSION)?;
+        for &old in snapshots.iter().rev().skip(self.keep.max(1)) {
+            fs::remove_file(self.snapshot_path(old))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Collections {
+    /// Load the collections persisted in `dir`: the newest snapshot, then every change logged
+    /// after it. An incomplete record at the end of the newest log, left by a crash mid-write,
+    /// is dropped. Refuses data written under a model with a different fingerprint.
+    /// `tokenizer` analyzes text for collections that use the model's tokenizer.
+    pub fn open(
+        dir: &Path,
+        fingerprint: String,
+        model: String,
+        keep: usize,
+        tokenizer: Option<Arc<dyn EmbeddingModel>>,
+    ) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let mut collections = Collections::new(tokenizer);
+
+        let snapshots = generations(dir, SNAPSHOT_PREFIX, SNAPSHOT_EXTENSION)?;
+        let start = snapshots.last().copied().unwrap_or(0);
+        if let Some(&generation) = snapshots.last() {
+            let file = File::open(file_path(dir, SNAPSHOT_PREFIX, generation, SNAPSHOT_EXTENSION))?;
+            let snapshot: Snapshot = rmp_serde::from_read(BufReader::new(file))?;
+            check_fingerprint(&snapshot.fingerprint, &snapshot.model, &fingerprint)?;
+            collections.load(snapshot.collections)?;
+        }
+
+        let logs: Vec<u64> = generations(dir, LOG_PREFIX, LOG_EXTENSION)?
+            .into_iter()
+            .filter(|&generation| generation >= start)
+            .collect();
+        let mut replayed = 0;
+        for (i, &generation) in logs.iter().enumerate() {
+            let path = file_path(dir, LOG_PREFIX, generation, LOG_EXTENSION);
+            let torn = read_log(&path, |record| {
+                if let Record::Header { fingerprint: found, model } = &record {
+                    check_fingerprint(found, model, &fingerprint)?;
+                } else {
+                    replayed += 1;
+                    collections.replay(record);
+                }
+                Ok(())
+            })?;
+
+            if torn && i + 1 < logs.len() {
+                anyhow::bail!("{} is corrupt", path.display());
+            } else if torn {
+                warn!("Dropping incomplete record at the end of {}", path.display());
+            }
+        }
+
+        // Continue in a fresh log, folding any replayed changes into a new snapshot
+        let generation = logs.last().copied().unwrap_or(start).max(start) + 1;
+        let wal = Wal::create(dir, generation, &fingerprint, &model)?;
+        let storage = Storage {
+            dir: dir.to_path_buf(),
+            fingerprint,
+            model,
+            keep,
+            wal: Mutex::new(wal),
+            snapshotting: Mutex::new(()),
+        };
+        if replayed > 0 {
+            storage.write_snapshot(generation, &collections.export(&storage))?;
+        }
+        storage.prune(generation)?;
+        collections.storage = Some(storage);
+
+        info!(
+            "Loaded {} collections from {} ({} logged changes replayed)",
+            collections.list().len(),
+            dir.display(),
+            replayed
+        );
+        Ok(collections)
+    }
+
+    fn storage(&self) -> Result<&Storage, CollectionError> {
+        self.storage.as_ref().ok_or(CollectionError::StorageDisabled)
+    }
+
+    /// Apply a logged change. Records that no longer apply are skipped.
+    fn replay(&self, record: Record<'static>) {
+        let result = match record {
+            Record::Header { .. } => Ok(()),
+            Record::Create {
+                name,
+                model,
+                metric,
+                index,
+                payload_indexes,
+                analyzer,
+                dimensions,
+            } => self.analyzer(&analyzer).and_then(|analyzer| {
+                let mut collection =
+                    Collection::new(name.into_owned(), model.into_owned(), metric, dimensions, index, analyzer);
+                for field in &payload_indexes {
+                    collection.add_payload_index(field);
+                }
+                self.create(collection).map(|_| ())
+            }),
+            Record::Drop { name } => self.delete(&name),
+            Record::Upsert { name, items } => self.upsert(&name, items.into_owned()),
+            Record::Delete { name, ids } => self.delete_items(&name, &ids).map(|_| ()),
+            Record::CreatePayloadIndex { name, field } => self.add_payload_index(&name, &field).map(|_| ()),
+        };
+
+        if let Err(e) = result {
+            warn!("Skipping logged change: {}", e);
+        }
+    }
+
+    fn load(&self, snapshots: Vec<CollectionSnapshot>) -> Result<(), CollectionError> {
+        let mut loaded = HashMap::new();
+        for snapshot in snapshots {
+            let analyzer = self.analyzer(&snapshot.analyzer)?;
+            let collection = Collection::import(snapshot, analyzer)?;
+            loaded.insert(collection.name.clone(), Arc::new(RwLock::new(collection)));
+        }
+        *self.collections.write().unwrap_or_else(PoisonError::into_inner) = loaded;
+        Ok(())
+    }
+
+    fn export(&self, storage: &Storage) -> Snapshot {
+        let mut collections: Vec<CollectionSnapshot> = self
+            .collections
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .map(|collection| collection.read().unwrap_or_else(PoisonError::into_inner).export())
+            .collect();
+        collections.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Snapshot {
+            fingerprint: storage.fingerprint.clone(),
+            model: storage.model.clone(),
+            collections,
+        }
+    }
+
+    /// Write every collection to a new snapshot and start a new log. Writes are only blocked
+    /// while the collections are copied, not while the snapshot is written out.
+    pub fn snapshot(&self) -> Result<SnapshotInfo, CollectionError> {
+        let storage = self.storage()?;
+        let _snapshotting = storage.snapshotting.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let (generation, snapshot) = {
+            let mut wal = storage.wal.lock().unwrap_or_else(PoisonError::into_inner);
+            let snapshot = self.export(storage);
+            (storage.rotate(&mut wal)?, snapshot)
+        };
+
+        let info = storage.write_snapshot(generation, &snapshot)?;
+        storage.prune(generation)?;
+        Ok(info)
+    }
+
+    /// Snapshot only if anything was logged since the last snapshot
+    pub fn snapshot_if_changed(&self) -> Result<Option<SnapshotInfo>, CollectionError> {
+        let changed = self
+            .storage()?
+            .wal
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .records
+            > 0;
+        if changed {
+            self.snapshot().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn snapshots(&self) -> Result<Vec<SnapshotInfo>, CollectionError> {
+        Ok(self.storage()?.snapshots()?)
+    }
+
+    /// Replace every collection with the contents of snapshot `id`. The snapshot is copied
+    /// to a new generation first, so the restore survives a restart.
+    pub fn restore(&self, id: u64) -> Result<Vec<CollectionInfo>, CollectionError> {
+        let storage = self.storage()?;
+        let _snapshotting = storage.snapshotting.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut wal = storage.wal.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let snapshot = storage.read_snapshot(id)?;
+        storage.check_fingerprint(&snapshot.fingerprint, &snapshot.model)?;
+
+        let generation = storage.rotate(&mut wal)?;
+        storage.write_snapshot(generation, &snapshot)?;
+        storage.prune(generation)?;
+        self.load(snapshot.collections)?;
+        drop(wal);
+
+        info!("Restored snapshot {} as snapshot {}", id, generation);
+        Ok(self.list())
+    }
+}
+
+/// Snapshot the collections every `interval` while changes are being logged
+pub async fn snapshot_every(state: Arc<AppState>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+        let state = Arc::clone(&state);
+        match task::spawn_blocking(move || state.collections.snapshot_if_changed()).await {
+            Ok(Ok(Some(snapshot))) => info!("Wrote snapshot {} ({} bytes)", snapshot.id, snapshot.size_bytes),
+            Ok(Ok(None)) => {}
+            Ok(Err(e)) => error!("Periodic snapshot failed: {}", e),
+            Err(e) => error!("Periodic snapshot task failed: {}", e),
+        }
+    }
+}
+
+fn check_fingerprint(found: &str, model: &str, expected: &str) -> Result<(), CollectionError> {
+    if found == expected {
+        Ok(())
+    } else {
+        Err(CollectionError::FingerprintMismatch {
+            model: model.to_string(),
+            found: found.to_string(),
+            expected: expected.to_string(),
+        })
+    }
+}
+
+fn file_path(dir: &Path, prefix: &str, generation: u64, extension: &str) -> PathBuf {
+    dir.join(format!("{}{:020}.{}", prefix, generation, extension))
+}
+
+/// Generations of the files in `dir` named `<prefix><generation>.<extension>`, oldest first
+fn generations(dir: &Path, prefix: &str, extension: &str) -> io::Result<Vec<u64>> {
+    let mut generations = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let generation = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(prefix))
+            .and_then(|name| name.strip_suffix(extension))
+            .and_then(|name| name.strip_suffix('.'))
+            .and_then(|number| number.parse::<u64>().ok());
+        generations.extend(generation);
+    }
+    generations.sort_unstable();
+    Ok(generations)
+}
+
+fn snapshot_info(path: &Path, generation: u64) -> io::Result<SnapshotInfo> {
+    let metadata = fs::metadata(path)?;
+    let created_at = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+
+    Ok(SnapshotInfo {
+        id: generation,
+        created_at,
+        size_bytes: metadata.len(),
+    })
+}
+
+/// Make renames and new files in `dir` durable
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("embedding-service-storage-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn open(dir: &Path, fingerprint: &str) -> anyhow::Result<Collections> {
+        Collections::open(dir, fingerprint.to_string(), "test-model".to_string(), 2, None)
+    }
+
+    fn item(id: &str, vector: Vec<f32>) -> CollectionItem {
+        CollectionItem {
+            id: id.to_string(),
+            text: Some(format!("text {}", id)),
+            vector: Some(vector),
+            metadata: Some(serde_json::json!({ "id": id })),
+        }
+    }
+
+    fn create(collections: &Collections, name: &str) {
+        let collection = Collection::new(name.to_string(), "test-model".to_string(), Metric::Cosine, 2, IndexConfig::Flat, Analyzer::default());
+        collections.create(collection).unwrap();
+    }
+
+    fn ids(collections: &Collections, name: &str) -> Vec<String> {
+        let collection = collections.get(name).unwrap();
+        let collection = collection.read().unwrap();
+        let mut ids: Vec<String> = collection.positions().map(|p| collection.item(p, false).id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_recovery_replays_log() {
+        let dir = temp_dir("replay");
+
+        let collections = open(&dir, "abc").unwrap();
+        create(&collections, "docs");
+        create(&collections, "dropped");
+        collections
+            .upsert("docs", vec![item("a", vec![1.0, 0.0]), item("b", vec![0.0, 1.0]), item("c", vec![1.0, 1.0])])
+            .unwrap();
+        collections.delete_items("docs", &["b".to_string()]).unwrap();
+        collections.delete("dropped").unwrap();
+        drop(collections);
+
+        let collections = open(&dir, "abc").unwrap();
+        assert_eq!(ids(&collections, "docs"), vec!["a", "c"]);
+        assert!(collections.get("dropped").is_err());
+        let collection = collections.get("docs").unwrap();
+        let item = collection.read().unwrap().item(0, true);
+        assert_eq!(item.metadata, Some(serde_json::json!({ "id": "a" })));
+        assert_eq!(item.vector, Some(vec![1.0, 0.0]));
+
+        // The replayed log was folded into a snapshot
+        assert_eq!(collections.snapshots().unwrap().len(), 1);
+        drop(collections);
+
+        // Another model's data is refused
+        let error = open(&dir, "xyz").err().unwrap();
+        assert!(error.to_string().contains("fingerprint"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_torn_log_tail_is_dropped() {
+        let dir = temp_dir("torn");
+
+        let collections = open(&dir, "abc").unwrap();
+        create(&collections, "docs");
+        collections.upsert("docs", vec![item("a", vec![1.0, 0.0])]).unwrap();
+        collections.upsert("docs", vec![item("b", vec![0.0, 1.0])]).unwrap();
+        drop(collections);
+
+        // Cut the last record short, as a crash in the middle of a write would
+        let log = file_path(&dir, LOG_PREFIX, generations(&dir, LOG_PREFIX, LOG_EXTENSION).unwrap()[0], LOG_EXTENSION);
+        let length = fs::metadata(&log).unwrap().len();
+        OpenOptions::new().write(true).open(&log).unwrap().set_len(length - 3).unwrap();
+
+        let collections = open(&dir, "abc").unwrap();
+        assert_eq!(ids(&collections, "docs"), vec!["a"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let dir = temp_dir("restore");
+
+        let collections = open(&dir, "abc").unwrap();
+        assert!(collections.snapshot_if_changed().unwrap().is_none());
+
+        create(&collections, "docs");
+        collections.upsert("docs", vec![item("a", vec![1.0, 0.0])]).unwrap();
+        let snapshot = collections.snapshot_if_changed().unwrap().unwrap();
+
+        collections.upsert("docs", vec![item("b", vec![0.0, 1.0])]).unwrap();
+        create(&collections, "later");
+        collections.snapshot().unwrap();
+        collections.snapshot().unwrap();
+
+        // Only the newest snapshots are kept
+        let kept: Vec<u64> = collections.snapshots().unwrap().iter().map(|s| s.id).collect();
+        assert_eq!(kept.len(), 2);
+        assert!(matches!(collections.restore(snapshot.id), Err(CollectionError::SnapshotNotFound(_))));
+
+        let restore = kept[0];
+        collections.upsert("docs", vec![item("c", vec![1.0, 1.0])]).unwrap();
+        let restored = collections.restore(restore).unwrap();
+        assert_eq!(restored.len(), 2);
+        assert_eq!(ids(&collections, "docs"), vec!["a", "b"]);
+        drop(collections);
+
+        // The restore survives a restart
+        let collections = open(&dir, "abc").unwrap();
+        assert_eq!(ids(&collections, "docs"), vec!["a", "b"]);
+
+        assert!(matches!(
+            Collections::default().snapshot(),
+            Err(CollectionError::StorageDisabled)
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,4 +1,7 @@
 use clap::Parser;
+use std::net::SocketAddr;
+
+use crate::listen::ListenSpec;
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
@@ -12,6 +15,29 @@ pub struct Config {
     #[arg(short, long, default_value = "8080")]
     pub port: u16,
 
+    /// Address to serve HTTP on: tcp://host:port or unix:///path[?mode=660][&auth=none].
+    /// May be repeated; replaces --host/--port when given
+    #[arg(long, value_name = "SPEC")]
+    pub listen: Vec<ListenSpec>,
+
+    /// Port for the gRPC server, on the host of the first TCP --listen address or --host.
+    /// If not specified, gRPC is disabled
+    #[arg(long)]
+    pub grpc_port: Option<u16>,
+
+    /// PEM certificate chain to serve HTTPS on TCP listeners (reloaded on change or SIGHUP)
+    #[arg(long, requires = "tls_key")]
+    pub tls_cert: Option<String>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, requires = "tls_cert")]
+    pub tls_key: Option<String>,
+
+    /// PEM CA bundle for client certificates. A verified certificate's subject
+    /// authenticates the caller without an API key
+    #[arg(long, requires = "tls_cert")]
+    pub tls_client_ca: Option<String>,
+
     /// Model ID from Hugging Face or local path to model directory
     #[arg(short, long, default_value = "minishlab/potion-base-8M")]
     pub model_path: String,
@@ -40,7 +66,103 @@ pub struct Config {
     #[arg(long, default_value = "8")]
     pub max_request_size_mb: usize,
 
+    /// Minimum response size in bytes before gzip/br/zstd compression is applied
+    #[arg(long, default_value = "1024")]
+    pub compression_min_size: u16,
+
     /// Whether to normalize embeddings
     #[arg(long, default_value = "false")]
     pub normalize_embeddings: bool,
-}
\ No newline at end of file
+
+    /// JSON file with per-dimension int8/uint8 quantization ranges ({"min": [...], "max": [...]})
+    #[arg(long, conflicts_with = "calibration_corpus")]
+    pub quantization_ranges: Option<String>,
+
+    /// Text file (one input per line) embedded at startup to derive int8/uint8 quantization ranges
+    #[arg(long)]
+    pub calibration_corpus: Option<String>,
+
+    /// JSON file of per-token IDF weights for sparse embeddings ({"idf": {"<token id>": weight}})
+    #[arg(long)]
+    pub idf_table: Option<String>,
+
+    /// JSON file of semantic routes ({"routes": [{"name", "examples", "threshold"}], "fallback"})
+    #[arg(long)]
+    pub routes: Option<String>,
+
+    /// Directory to persist collections in. If not specified, collections are kept in memory only
+    #[arg(long)]
+    pub data_dir: Option<String>,
+
+    /// Seconds between snapshots of changed collections
+    #[arg(long, default_value = "300")]
+    pub snapshot_interval_secs: u64,
+
+    /// Number of snapshots kept in --data-dir
+    #[arg(long, default_value = "3")]
+    pub snapshot_keep: usize,
+}
+impl Config {
+    /// The `--listen` addresses, or the `--host`/`--port` address when none are given
+    pub fn listen_specs(&self) -> anyhow::Result<Vec<ListenSpec>> {
+        if !self.listen.is_empty() {
+            return Ok(self.listen.clone());
+        }
+
+        Ok(vec![ListenSpec::Tcp(SocketAddr::new(self.host.parse()?, self.port))])
+    }
+
+    /// The gRPC address: `--grpc-port` on the same host as HTTP, i.e. the first TCP `--listen`
+    /// address, or `--host` when there is none
+    pub fn grpc_addr(&self) -> anyhow::Result<Option<SocketAddr>> {
+        let Some(port) = self.grpc_port else {
+            return Ok(None);
+        };
+
+        let tcp = self.listen.iter().find_map(|spec| match spec {
+            ListenSpec::Tcp(addr) => Some(addr.ip()),
+            ListenSpec::Unix { .. } => None,
+        });
+        let ip = match tcp {
+            Some(ip) => ip,
+            None => self.host.parse()?,
+        };
+
+        Ok(Some(SocketAddr::new(ip, port)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Config {
+        Config::try_parse_from(std::iter::once("embedding_service").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn test_grpc_addr() {
+        assert_eq!(parse(&[]).grpc_addr().unwrap(), None);
+        assert_eq!(
+            parse(&["--grpc-port", "50051"]).grpc_addr().unwrap(),
+            Some("127.0.0.1:50051".parse().unwrap())
+        );
+        assert_eq!(
+            parse(&["--host", "0.0.0.0", "--grpc-port", "50051"]).grpc_addr().unwrap(),
+            Some("0.0.0.0:50051".parse().unwrap())
+        );
+
+        // --listen replaces --host for gRPC as well
+        let config = parse(&[
+            "--host", "10.0.0.1",
+            "--listen", "unix:///run/embed.sock",
+            "--listen", "tcp://[::]:8080",
+            "--grpc-port", "50051",
+        ]);
+        assert_eq!(config.grpc_addr().unwrap(), Some("[::]:50051".parse().unwrap()));
+
+        // Only unix sockets: gRPC stays on --host
+        let config = parse(&["--listen", "unix:///run/embed.sock", "--grpc-port", "50051"]);
+        assert_eq!(config.grpc_addr().unwrap(), Some("127.0.0.1:50051".parse().unwrap()));
+    }
+}
@@ -0,0 +1,425 @@
+use axum::{extract::State, http::StatusCode, response::Response, Json};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Arc,
+};
+use tokio::task;
+use tracing::{debug, info};
+
+use crate::cluster::{load_items, Items};
+use crate::codec::{self, Payload, ResponseFormat};
+use crate::error::{server_error, InputError};
+use crate::handlers::AppState;
+use crate::models::{
+    CanonicalStrategy, DeduplicateRequest, DeduplicateResponse, DuplicateGroup, DuplicateItem, ErrorResponse,
+    MinHashConfig, Usage,
+};
+use crate::similarity::{dot, unit};
+
+/// Most items in one request when every pair is compared
+const MAX_PAIRWISE_ITEMS: usize = 2_000;
+
+/// Most items in one request when MinHash picks the pairs to compare
+const MAX_MINHASH_ITEMS: usize = 20_000;
+
+/// Largest MinHash signature accepted
+const MAX_NUM_HASHES: usize = 1_024;
+
+/// Longest shingle accepted, in characters
+const MAX_SHINGLE_SIZE: usize = 64;
+
+/// Buckets with more items than this are compared against their first item only, so a mass of
+/// identical texts does not cost a comparison per pair
+const MAX_BUCKET_SIZE: usize = 64;
+
+type HandlerError = (StatusCode, Json<ErrorResponse>);
+
+/// FNV-1a, a hash that stays the same across runs and platforms
+fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The splitmix64 finalizer, spreading a value over all 64 bits
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Hashes of the character shingles of a text, after lowercasing and collapsing whitespace.
+/// Texts shorter than a shingle are a single shingle.
+pub fn shingles(text: &str, size: usize) -> BTreeSet<u64> {
+    let normalized = text.to_lowercase().split_whitespace().collect::<Vec<_>>().join(" ");
+    let characters: Vec<char> = normalized.chars().collect();
+    if characters.is_empty() {
+        return BTreeSet::new();
+    }
+
+    characters
+        .windows(size.min(characters.len()))
+        .map(|window| fnv1a(window.iter().collect::<String>().into_bytes()))
+        .collect()
+}
+
+/// Minimum of each of `num_hashes` hash functions over the shingles; None without shingles.
+/// Two signatures agree at a position with probability equal to the Jaccard similarity of the
+/// shingle sets.
+pub fn signature(shingles: &BTreeSet<u64>, num_hashes: usize) -> Option<Vec<u64>> {
+    if shingles.is_empty() {
+        return None;
+    }
+    Some(
+        (0..num_hashes as u64)
+            .map(|i| {
+                let seed = mix(i.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+                shingles.iter().map(|shingle| mix(shingle ^ seed)).min().unwrap_or(u64::MAX)
+            })
+            .collect(),
+    )
+}
+
+/// Pairs of items whose signatures match in every row of at least one band
+pub fn candidate_pairs(signatures: &[Option<Vec<u64>>], bands: usize) -> BTreeSet<(usize, usize)> {
+    let mut pairs = BTreeSet::new();
+    let Some(length) = signatures.iter().flatten().map(Vec::len).next() else {
+        return pairs;
+    };
+    let rows = length / bands;
+
+    for band in 0..bands {
+        let mut buckets: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (item, signature) in signatures.iter().enumerate() {
+            if let Some(signature) = signature {
+                let key = fnv1a(signature[band * rows..][..rows].iter().flat_map(|value| value.to_le_bytes()));
+                buckets.entry(key).or_default().push(item);
+            }
+        }
+
+        for members in buckets.values().filter(|members| members.len() > 1) {
+            if members.len() > MAX_BUCKET_SIZE {
+                pairs.extend(members[1..].iter().map(|&member| (members[0], member)));
+                continue;
+            }
+            for (i, &a) in members.iter().enumerate() {
+                pairs.extend(members[i + 1..].iter().map(|&b| (a, b)));
+            }
+        }
+    }
+    pairs
+}
+
+/// Disjoint sets of items; each set's root is its first item
+struct UnionFind(Vec<usize>);
+
+impl UnionFind {
+    fn find(&mut self, mut item: usize) -> usize {
+        while self.0[item] != item {
+            self.0[item] = self.0[self.0[item]];
+            item = self.0[item];
+        }
+        item
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.0[a.max(b)] = a.min(b);
+    }
+}
+
+/// Groups of items connected by pairs at or above the threshold, in order of their first item.
+/// Returns the groups and the number of pairs compared.
+pub fn duplicate_groups(
+    units: &[Vec<f32>],
+    pairs: impl Iterator<Item = (usize, usize)>,
+    threshold: f32,
+) -> (Vec<Vec<usize>>, usize) {
+    let mut sets = UnionFind((0..units.len()).collect());
+    let mut comparisons = 0;
+    for (a, b) in pairs {
+        comparisons += 1;
+        if dot(&units[a], &units[b]) >= threshold {
+            sets.union(a, b);
+        }
+    }
+
+    let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for item in 0..units.len() {
+        groups.entry(sets.find(item)).or_default().push(item);
+    }
+    let groups = groups.into_values().filter(|group| group.len() > 1).collect();
+    (groups, comparisons)
+}
+
+/// The member of a group that is kept; ties go to the first member
+pub fn canonical(group: &[usize], strategy: CanonicalStrategy, units: &[Vec<f32>], texts: &[Option<String>]) -> usize {
+    let best_by = |score: &dyn Fn(usize) -> f32| {
+        group
+            .iter()
+            .copied()
+            .fold((group[0], f32::NEG_INFINITY), |best, item| {
+                let score = score(item);
+                if score > best.1 {
+                    (item, score)
+                } else {
+                    best
+                }
+            })
+            .0
+    };
+
+    match strategy {
+        CanonicalStrategy::First => group[0],
+        CanonicalStrategy::Longest => best_by(&|item| texts[item].as_ref().map_or(0, |text| text.chars().count()) as f32),
+        CanonicalStrategy::Central => best_by(&|item| {
+            group
+                .iter()
+                .filter(|&&other| other != item)
+                .map(|&other| dot(&units[item], &units[other]))
+                .sum()
+        }),
+    }
+}
+
+/// Split a connected group into the item kept and its duplicates, each at or above the threshold
+/// to the kept item. Pairs can chain items that are not similar to each other, so members too far
+/// from the kept item are split off and resolved again among themselves.
+fn resolve(
+    mut group: Vec<usize>,
+    threshold: f32,
+    strategy: CanonicalStrategy,
+    units: &[Vec<f32>],
+    texts: &[Option<String>],
+) -> Vec<(usize, Vec<usize>)> {
+    let mut resolved = Vec::new();
+    while group.len() > 1 {
+        let kept = canonical(&group, strategy, units, texts);
+        let (duplicates, rest): (Vec<usize>, Vec<usize>) = group
+            .into_iter()
+            .filter(|&member| member != kept)
+            .partition(|&member| dot(&units[member], &units[kept]) >= threshold);
+        if !duplicates.is_empty() {
+            resolved.push((kept, duplicates));
+        }
+        group = rest;
+    }
+    resolved
+}
+
+fn find_duplicates(
+    items: &Items,
+    threshold: f32,
+    strategy: CanonicalStrategy,
+    minhash: Option<MinHashConfig>,
+) -> (Vec<DuplicateGroup>, usize) {
+    let units: Vec<Vec<f32>> = items.vectors.iter().map(|vector| unit(vector)).collect();
+    let (groups, comparisons) = match minhash {
+        Some(config) => {
+            let signatures: Vec<Option<Vec<u64>>> = items
+                .texts
+                .iter()
+                .map(|text| signature(&shingles(text.as_deref().unwrap_or_default(), config.shingle_size), config.num_hashes))
+                .collect();
+            let pairs = candidate_pairs(&signatures, config.bands);
+            debug!("MinHash found {} candidate pairs among {} items", pairs.len(), units.len());
+            duplicate_groups(&units, pairs.into_iter(), threshold)
+        }
+        None => {
+            let count = units.len();
+            duplicate_groups(&units, (0..count).flat_map(|a| (a + 1..count).map(move |b| (a, b))), threshold)
+        }
+    };
+
+    let groups = groups
+        .into_iter()
+        .flat_map(|group| resolve(group, threshold, strategy, &units, &items.texts))
+        .map(|(kept, duplicates)| {
+            let item = |index: usize| DuplicateItem {
+                index,
+                id: items.ids[index].clone(),
+                similarity: dot(&units[index], &units[kept]),
+            };
+            DuplicateGroup {
+                object: "duplicate_group".to_string(),
+                canonical: item(kept),
+                duplicates: duplicates.into_iter().map(item).collect(),
+            }
+        })
+        .collect();
+    (groups, comparisons)
+}
+
+fn validate(request: &DeduplicateRequest) -> Result<(), InputError> {
+    if !(-1.0..=1.0).contains(&request.threshold) {
+        return Err(InputError::new("threshold must be between -1 and 1", "invalid_threshold"));
+    }
+    if let Some(config) = request.minhash {
+        let valid = (1..=MAX_SHINGLE_SIZE).contains(&config.shingle_size)
+            && (1..=MAX_NUM_HASHES).contains(&config.num_hashes)
+            && config.bands > 0
+            && config.num_hashes % config.bands == 0;
+        if !valid {
+            return Err(InputError::new(
+                format!(
+                    "minhash needs a shingle_size of 1 to {} and a num_hashes of at most {} that is a multiple of bands",
+                    MAX_SHINGLE_SIZE, MAX_NUM_HASHES
+                ),
+                "invalid_minhash",
+            ));
+        }
+    }
+    if request.delete && request.collection.is_none() {
+        return Err(InputError::new("delete needs a collection", "invalid_input"));
+    }
+    Ok(())
+}
+
+/// Find groups of near-duplicate texts, or of near-duplicate collection items, and pick the item
+/// to keep from each. Optionally deletes the rest from the collection.
+pub async fn deduplicate(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Payload(request): Payload<DeduplicateRequest>,
+) -> Result<Response, HandlerError> {
+    debug!("Received deduplication request with threshold {}", request.threshold);
+    validate(&request)?;
+
+    let limit = if request.minhash.is_some() { MAX_MINHASH_ITEMS } else { MAX_PAIRWISE_ITEMS };
+    let (items, total_tokens) = load_items(&state, request.input, request.collection.as_deref(), limit).await?;
+    // Items without text have no shingles, so MinHash could never pair them
+    if request.minhash.is_some() && items.texts.iter().any(Option::is_none) {
+        return Err(InputError::new(
+            "minhash needs text for every item; compare pairwise instead by leaving it out",
+            "minhash_needs_text",
+        )
+        .into());
+    }
+    let count = items.vectors.len();
+
+    let (threshold, strategy, minhash) = (request.threshold, request.canonical, request.minhash);
+    let (data, comparisons) = task::spawn_blocking(move || find_duplicates(&items, threshold, strategy, minhash))
+        .await
+        .map_err(|e| server_error(format!("Deduplication task failed: {}", e)))?;
+    let duplicates: usize = data.iter().map(|group| group.duplicates.len()).sum();
+    info!(
+        "Found {} duplicates in {} groups among {} items after {} comparisons",
+        duplicates,
+        data.len(),
+        count,
+        comparisons
+    );
+
+    let deleted = match request.collection {
+        Some(name) if request.delete => {
+            let ids: Vec<String> = data
+                .iter()
+                .flat_map(|group| group.duplicates.iter().filter_map(|item| item.id.clone()))
+                .collect();
+            let writer = Arc::clone(&state);
+            let deleted = task::spawn_blocking(move || writer.collections.delete_items(&name, &ids))
+                .await
+                .map_err(|e| server_error(format!("Deduplication task failed: {}", e)))??;
+            Some(deleted)
+        }
+        _ => None,
+    };
+
+    let response = DeduplicateResponse {
+        object: "list".to_string(),
+        data,
+        unique: count - duplicates,
+        duplicates,
+        comparisons,
+        deleted,
+        model: request.model.unwrap_or_else(|| state.model_name.clone()),
+        usage: Usage {
+            prompt_tokens: total_tokens,
+            total_tokens,
+        },
+    };
+
+    Ok(codec::encode(response_format, &response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minhash_candidates() {
+        let texts = [
+            "The quick brown fox jumps over the lazy dog",
+            "the quick brown fox  jumps over the lazy dog!",
+            "An entirely different sentence about invoices",
+            "",
+        ];
+        let signatures: Vec<Option<Vec<u64>>> = texts.iter().map(|text| signature(&shingles(text, 5), 128)).collect();
+        assert!(signatures[3].is_none());
+        assert_eq!(signature(&shingles(texts[0], 5), 128), signatures[0]);
+
+        let pairs = candidate_pairs(&signatures, 32);
+        assert!(pairs.contains(&(0, 1)));
+        assert!(!pairs.iter().any(|&(a, b)| a == 2 || b == 2 || a == 3 || b == 3));
+
+        // Texts shorter than a shingle are one shingle
+        assert_eq!(shingles("Hi", 5).len(), 1);
+        assert_eq!(shingles("ab ab", 2).len(), 3);
+    }
+
+    #[test]
+    fn test_duplicate_groups() {
+        let units = vec![
+            unit(&[1.0, 0.0, 0.0]),
+            unit(&[0.0, 1.0, 0.0]),
+            unit(&[1.0, 0.05, 0.0]),
+            unit(&[0.0, 1.0, 0.02]),
+            unit(&[0.0, 0.0, 1.0]),
+            unit(&[1.0, 0.1, 0.0]),
+        ];
+        let count = units.len();
+        let pairs = (0..count).flat_map(|a| (a + 1..count).map(move |b| (a, b)));
+        let (groups, comparisons) = duplicate_groups(&units, pairs, 0.99);
+        assert_eq!(groups, vec![vec![0, 2, 5], vec![1, 3]]);
+        assert_eq!(comparisons, 15);
+
+        // Only the given pairs are compared
+        let (groups, comparisons) = duplicate_groups(&units, [(1, 3)].into_iter(), 0.99);
+        assert_eq!(groups, vec![vec![1, 3]]);
+        assert_eq!(comparisons, 1);
+    }
+
+    #[test]
+    fn test_chained_duplicates() {
+        // 0 and 2 are 40 degrees apart, each 20 degrees from 1
+        let angle = |degrees: f32| vec![degrees.to_radians().cos(), degrees.to_radians().sin()];
+        let items = Items {
+            vectors: vec![angle(0.0), angle(20.0), angle(40.0), angle(60.0)],
+            ids: vec![None; 4],
+            texts: vec![None; 4],
+        };
+        let members = |group: &DuplicateGroup| {
+            let mut members = vec![group.canonical.index];
+            members.extend(group.duplicates.iter().map(|item| item.index));
+            members
+        };
+
+        // Every duplicate is within the threshold of the item kept; the rest form their own groups
+        let (groups, _) = find_duplicates(&items, 0.9, CanonicalStrategy::First, None);
+        assert_eq!(groups.iter().map(members).collect::<Vec<_>>(), vec![vec![0, 1], vec![2, 3]]);
+        assert!(groups.iter().flat_map(|group| &group.duplicates).all(|item| item.similarity >= 0.9));
+
+        let (groups, _) = find_duplicates(&items, 0.9, CanonicalStrategy::Central, None);
+        assert_eq!(groups.iter().map(members).collect::<Vec<_>>(), vec![vec![1, 0, 2]]);
+    }
+
+    #[test]
+    fn test_canonical() {
+        let units = vec![unit(&[1.0, 0.2]), unit(&[1.0, 0.1]), unit(&[1.0, 0.0])];
+        let texts = vec![Some("short".to_string()), None, Some("the longest".to_string())];
+        let group = [0, 1, 2];
+        assert_eq!(canonical(&group, CanonicalStrategy::First, &units, &texts), 0);
+        assert_eq!(canonical(&group, CanonicalStrategy::Longest, &units, &texts), 2);
+        assert_eq!(canonical(&group, CanonicalStrategy::Central, &units, &texts), 1);
+    }
+}
@@ -2,6 +2,7 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Json, Response},
 };
+use tracing::error;
 use crate::models::{ErrorResponse, ErrorDetail};
 
 // Define our own error type
@@ -54,4 +55,50 @@ where
     fn from(err: E) -> Self {
         Self::ModelError(err.into())
     }
-}
\ No newline at end of file
+}
+
+/// A request that failed validation, carrying an OpenAI-style error code
+#[derive(Debug)]
+pub struct InputError {
+    pub message: String,
+    pub code: &'static str,
+}
+
+impl InputError {
+    pub fn new(message: impl Into<String>, code: &'static str) -> Self {
+        Self {
+            message: message.into(),
+            code,
+        }
+    }
+}
+
+impl From<InputError> for (StatusCode, Json<ErrorResponse>) {
+    fn from(err: InputError) -> Self {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: ErrorDetail {
+                    message: err.message,
+                    error_type: "invalid_request_error".to_string(),
+                    code: Some(err.code.to_string()),
+                },
+            }),
+        )
+    }
+}
+
+/// A 500 response for failures outside the request's control, logged as they happen
+pub fn server_error(message: String) -> (StatusCode, Json<ErrorResponse>) {
+    error!("{}", message);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ErrorResponse {
+            error: ErrorDetail {
+                message,
+                error_type: "server_error".to_string(),
+                code: None,
+            },
+        }),
+    )
+}
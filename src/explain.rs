@@ -0,0 +1,184 @@
+use axum::{extract::State, http::StatusCode, response::Response, Json};
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::codec::{self, Payload, ResponseFormat};
+use crate::error::InputError;
+use crate::handlers::AppState;
+use crate::models::{
+    ErrorResponse, ExplainRequest, ExplainResponse, ExplainedText, Metric, TokenContribution, TokenEmbeddings, Usage,
+};
+use crate::similarity::{dot, norm};
+
+/// A text's embedding as a scaled sum of its token vectors, `pooled = scale * Σ vectors`
+struct Pooled {
+    pooled: Vec<f32>,
+    scale: f32,
+}
+
+impl Pooled {
+    /// Mean pooling, followed by scaling to unit length when the model normalizes
+    fn new(vectors: &[Vec<f32>], dimensions: usize, normalize: bool) -> Self {
+        let mut sum = vec![0.0; dimensions];
+        for vector in vectors {
+            for (total, value) in sum.iter_mut().zip(vector) {
+                *total += value;
+            }
+        }
+
+        let mut scale = if vectors.is_empty() { 0.0 } else { 1.0 / vectors.len() as f32 };
+        if normalize {
+            let length = norm(&sum) * scale;
+            scale = if length > 0.0 { scale / length } else { 0.0 };
+        }
+
+        Self {
+            pooled: sum.iter().map(|value| value * scale).collect(),
+            scale,
+        }
+    }
+}
+
+/// Per-token contributions for each text, as `(contribution, norm_contribution)` pairs
+#[derive(Debug, PartialEq)]
+pub struct Explanation {
+    pub score: f32,
+    pub source_norm: f32,
+    pub target_norm: f32,
+    pub source: Vec<(f32, f32)>,
+    pub target: Vec<(f32, f32)>,
+}
+
+/// Since the score is linear in each pooled vector, it splits into one term per token: the
+/// token's scaled vector against the other text's pooled vector. Each text's terms sum to the
+/// score on their own. Only `cosine` and `dot` decompose this way.
+pub fn explain(source: &[Vec<f32>], target: &[Vec<f32>], metric: Metric, normalize: bool) -> Explanation {
+    let dimensions = source.iter().chain(target).map(Vec::len).next().unwrap_or(0);
+    let (a, b) = (Pooled::new(source, dimensions, normalize), Pooled::new(target, dimensions, normalize));
+    let (a_norm, b_norm) = (norm(&a.pooled), norm(&b.pooled));
+
+    let denominator = match metric {
+        Metric::Cosine => a_norm * b_norm,
+        _ => 1.0,
+    };
+    let contributions = |side: &Pooled, length: f32, vectors: &[Vec<f32>], other: &Pooled| -> Vec<(f32, f32)> {
+        vectors
+            .iter()
+            .map(|vector| {
+                let contribution = if denominator > 0.0 { side.scale * dot(vector, &other.pooled) / denominator } else { 0.0 };
+                let norm_contribution = if length > 0.0 { side.scale * dot(vector, &side.pooled) / length } else { 0.0 };
+                (contribution, norm_contribution)
+            })
+            .collect()
+    };
+
+    Explanation {
+        score: if denominator > 0.0 { dot(&a.pooled, &b.pooled) / denominator } else { 0.0 },
+        source_norm: a_norm,
+        target_norm: b_norm,
+        source: contributions(&a, a_norm, source, &b),
+        target: contributions(&b, b_norm, target, &a),
+    }
+}
+
+fn explained_text(embeddings: TokenEmbeddings, norm: f32, contributions: Vec<(f32, f32)>) -> ExplainedText {
+    let mut tokens: Vec<TokenContribution> = embeddings
+        .tokens
+        .into_iter()
+        .zip(embeddings.ids)
+        .zip(embeddings.offsets)
+        .zip(contributions)
+        .map(|(((token, id), offset), (contribution, norm_contribution))| TokenContribution {
+            token,
+            id,
+            offset,
+            contribution,
+            norm_contribution,
+        })
+        .collect();
+
+    // Largest impact first, whether it raises or lowers the score
+    tokens.sort_by(|a, b| b.contribution.abs().total_cmp(&a.contribution.abs()));
+    ExplainedText { norm, tokens }
+}
+
+/// Break the similarity of two texts down into per-token contributions
+pub async fn explain_similarity(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Payload(request): Payload<ExplainRequest>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    debug!("Received explain request");
+
+    if !matches!(request.metric, Metric::Cosine | Metric::Dot) {
+        return Err(InputError::new("Only cosine and dot similarity can be explained", "unsupported_metric").into());
+    }
+    let texts = vec![request.source, request.target];
+    state.validate_lengths(&texts)?;
+
+    let mut embeddings = state.token_embeddings(texts).await?;
+    let total_tokens = embeddings.iter().map(|text| text.ids.len()).sum();
+    let (target, source) = (embeddings.pop().expect("target"), embeddings.pop().expect("source"));
+
+    let normalized = state.model.normalizes();
+    let explanation = explain(&source.embeddings, &target.embeddings, request.metric, normalized);
+
+    let response = ExplainResponse {
+        object: "similarity_explanation".to_string(),
+        metric: request.metric,
+        score: explanation.score,
+        normalized,
+        source: explained_text(source, explanation.source_norm, explanation.source),
+        target: explained_text(target, explanation.target_norm, explanation.target),
+        model: request.model.unwrap_or_else(|| state.model_name.clone()),
+        usage: Usage {
+            prompt_tokens: total_tokens,
+            total_tokens,
+        },
+    };
+
+    Ok(codec::encode(response_format, &response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::similarity::cosine;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn test_explain_sums_to_score() {
+        let source = vec![vec![1.0, 0.0, 0.5], vec![0.0, 2.0, 0.0]];
+        let target = vec![vec![1.0, 1.0, 0.0], vec![0.5, 0.0, -1.0], vec![0.0, 0.0, 1.0]];
+        let mean = |vectors: &[Vec<f32>]| -> Vec<f32> {
+            (0..3).map(|i| vectors.iter().map(|v| v[i]).sum::<f32>() / vectors.len() as f32).collect()
+        };
+        let (a, b) = (mean(&source), mean(&target));
+
+        for (metric, normalize, expected) in [
+            (Metric::Cosine, false, cosine(&a, &b)),
+            (Metric::Dot, false, dot(&a, &b)),
+            (Metric::Dot, true, cosine(&a, &b)),
+        ] {
+            let explanation = explain(&source, &target, metric, normalize);
+            assert!(close(explanation.score, expected));
+            assert!(close(explanation.source.iter().map(|(c, _)| c).sum(), expected));
+            assert!(close(explanation.target.iter().map(|(c, _)| c).sum(), expected));
+
+            let source_norm = if normalize { 1.0 } else { norm(&a) };
+            assert!(close(explanation.source_norm, source_norm));
+            assert!(close(explanation.source.iter().map(|(_, n)| n).sum(), source_norm));
+        }
+    }
+
+    #[test]
+    fn test_explain_empty_text() {
+        let explanation = explain(&[vec![1.0, 0.0]], &[], Metric::Cosine, true);
+        assert_eq!(explanation.score, 0.0);
+        assert_eq!(explanation.source, vec![(0.0, 1.0)]);
+        assert!(explanation.target.is_empty());
+    }
+}
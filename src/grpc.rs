@@ -0,0 +1,218 @@
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{
+    service::{interceptor::InterceptedService, Interceptor},
+    Request, Response, Status,
+};
+use tracing::{debug, error};
+
+use crate::auth::AuthConfig;
+use crate::config::Config;
+use crate::error::InputError;
+use crate::handlers::AppState;
+use crate::tls::TlsPeer;
+
+pub mod pb {
+    tonic::include_proto!("embedding.v1");
+}
+
+use pb::embedding_service_server::{EmbeddingService, EmbeddingServiceServer};
+use pb::{
+    EmbedRequest, EmbedResponse, Embedding, ListModelsRequest, ListModelsResponse, Model,
+    TokenizeRequest, TokenizeResponse, TokenizedText, Usage,
+};
+
+/// Build the gRPC service sharing state, auth and limits with the HTTP router
+pub fn service(
+    state: Arc<AppState>,
+    config: &Config,
+) -> InterceptedService<EmbeddingServiceServer<GrpcService>, AuthInterceptor> {
+    let auth_config = Arc::new(AuthConfig {
+        api_key: config.auth_key.clone(),
+    });
+
+    let server = EmbeddingServiceServer::new(GrpcService { state })
+        .max_decoding_message_size(config.max_request_size_mb * 1024 * 1024);
+
+    InterceptedService::new(server, AuthInterceptor { auth_config })
+}
+
+/// Checks the `authorization` metadata entry (`Bearer <key>`) on every call. Callers with a
+/// verified client certificate do not need a key.
+#[derive(Clone)]
+pub struct AuthInterceptor {
+    auth_config: Arc<AuthConfig>,
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let identity = request.extensions().get::<TlsPeer>().and_then(|peer| peer.identity.clone());
+        if let Some(identity) = identity {
+            debug!("Authenticated client {}", identity.0);
+            request.extensions_mut().insert(identity);
+            return Ok(request);
+        }
+
+        let authorization = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+
+        if self.auth_config.authorize(authorization) {
+            Ok(request)
+        } else {
+            Err(Status::unauthenticated("Invalid API key"))
+        }
+    }
+}
+
+impl From<InputError> for Status {
+    fn from(err: InputError) -> Self {
+        Status::invalid_argument(err.message)
+    }
+}
+
+#[derive(Clone)]
+pub struct GrpcService {
+    state: Arc<AppState>,
+}
+
+impl GrpcService {
+    /// Embed one batch; `offset` is the position of the first text in the original request
+    async fn embed_batch(
+        &self,
+        texts: Vec<String>,
+        offset: usize,
+        model: Option<String>,
+    ) -> Result<EmbedResponse, Status> {
+        let result = self.state.encode(texts).await.map_err(|e| {
+            error!("Failed to generate embeddings: {}", e);
+            Status::internal(format!("Embedding generation task failed: {}", e))
+        })?;
+
+        let dimensions = result.embeddings.first().map(Vec::len).unwrap_or_default();
+        let total_tokens: usize = result.token_counts.iter().sum();
+
+        let data = result
+            .embeddings
+            .into_iter()
+            .zip(result.token_counts)
+            .enumerate()
+            .map(|(i, (embedding, token_count))| Embedding {
+                index: (offset + i) as u32,
+                vector: embedding.iter().flat_map(|v| v.to_le_bytes()).collect(),
+                token_count: token_count as u32,
+            })
+            .collect();
+
+        Ok(EmbedResponse {
+            data,
+            model: model.unwrap_or_else(|| self.state.model_name.clone()),
+            dimensions: dimensions as u32,
+            usage: Some(Usage {
+                prompt_tokens: total_tokens as u64,
+                total_tokens: total_tokens as u64,
+            }),
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl EmbeddingService for GrpcService {
+    async fn embed(&self, request: Request<EmbedRequest>) -> Result<Response<EmbedResponse>, Status> {
+        let request = request.into_inner();
+        debug!("Received gRPC embedding request for {} texts", request.input.len());
+
+        self.state.validate_texts(&request.input)?;
+        let response = self.embed_batch(request.input, 0, request.model).await?;
+
+        Ok(Response::new(response))
+    }
+
+    type EmbedStreamStream = ReceiverStream<Result<EmbedResponse, Status>>;
+
+    async fn embed_stream(
+        &self,
+        request: Request<EmbedRequest>,
+    ) -> Result<Response<Self::EmbedStreamStream>, Status> {
+        let request = request.into_inner();
+        debug!("Received gRPC embedding stream for {} texts", request.input.len());
+
+        // The total is bounded by the message size limit; batches are capped at max_batch_size
+        if request.input.is_empty() {
+            return Err(InputError::new("Input cannot be empty", "empty_input").into());
+        }
+        self.state.validate_lengths(&request.input)?;
+
+        // A small buffer applies backpressure when the client reads slowly
+        let (tx, rx) = mpsc::channel(2);
+        let service = self.clone();
+        let batch_size = self.state.max_batch_size.max(1);
+
+        tokio::spawn(async move {
+            let mut input = request.input.into_iter();
+            let mut offset = 0;
+
+            loop {
+                let batch: Vec<String> = input.by_ref().take(batch_size).collect();
+                if batch.is_empty() {
+                    break;
+                }
+
+                let len = batch.len();
+                let result = service.embed_batch(batch, offset, request.model.clone()).await;
+                offset += len;
+
+                // Stop early if the client disconnected
+                if tx.send(result).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn tokenize(
+        &self,
+        request: Request<TokenizeRequest>,
+    ) -> Result<Response<TokenizeResponse>, Status> {
+        let request = request.into_inner();
+        self.state.validate_texts(&request.input)?;
+
+        let tokenized = self
+            .state
+            .tokenize(request.input)
+            .await
+            .map_err(|e| Status::internal(format!("Tokenization failed: {}", e)))?;
+
+        let total_tokens: usize = tokenized.iter().map(|t| t.ids.len()).sum();
+
+        Ok(Response::new(TokenizeResponse {
+            data: tokenized
+                .into_iter()
+                .map(|t| TokenizedText {
+                    ids: t.ids,
+                    tokens: t.tokens,
+                })
+                .collect(),
+            usage: Some(Usage {
+                prompt_tokens: total_tokens as u64,
+                total_tokens: total_tokens as u64,
+            }),
+        }))
+    }
+
+    async fn list_models(
+        &self,
+        _request: Request<ListModelsRequest>,
+    ) -> Result<Response<ListModelsResponse>, Status> {
+        Ok(Response::new(ListModelsResponse {
+            data: vec![Model {
+                id: self.state.model_name.clone(),
+                owned_by: "local".to_string(),
+            }],
+        }))
+    }
+}
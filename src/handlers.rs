@@ -1,97 +1,149 @@
 use axum::{
     extract::State,
     http::StatusCode,
-    response::Json,
+    response::{Json, Response},
 };
-use model2vec_rs::model::StaticModel;
+use model2vec_rs::model::{EncodeResult, StaticModel};
 use std::sync::Arc;
 use tokio::task;
 use tracing::{debug, error};
-use crate::models::{EmbeddingRequest, EmbeddingResponse, EmbeddingData, Usage, ErrorResponse, EmbeddingInput};
+use crate::classifiers::Classifiers;
+use crate::classify::Labels;
+use crate::codec::{self, Payload, ResponseFormat};
+use crate::collections::Collections;
+use crate::error::InputError;
+use crate::models::{EmbeddingRequest, EmbeddingResponse, EmbeddingData, EmbeddingFormat, Usage, ErrorResponse, EmbeddingInput, TokenEmbeddings, TokenizedText};
+use crate::quantization::{self, Calibration};
+use crate::semantic_router::SemanticRouter;
+use crate::sparse::Idf;
 
 pub trait EmbeddingModel: Send + Sync {
-    fn encode_with_stats(&self, texts: &[String]) -> model2vec_rs::model::EncodeResult;
+    fn encode_with_stats(&self, texts: &[String]) -> EncodeResult;
+
+    /// Split texts into the model's tokens. Models without an accessible tokenizer return an error.
+    fn tokenize(&self, _texts: &[String]) -> anyhow::Result<Vec<TokenizedText>> {
+        anyhow::bail!("Tokenization is not supported by this model")
+    }
+
+    /// The vector of each token as it enters pooling. Models without access to their token
+    /// vectors return an error.
+    fn token_embeddings(&self, _texts: &[String]) -> anyhow::Result<Vec<TokenEmbeddings>> {
+        Err(TokenVectorsUnavailable("Token embeddings are not supported by this model".to_string()).into())
+    }
+
+    /// Whether pooled embeddings are scaled to unit length
+    fn normalizes(&self) -> bool {
+        false
+    }
 }
 
 impl EmbeddingModel for StaticModel {
-    fn encode_with_stats(&self, texts: &[String]) -> model2vec_rs::model::EncodeResult {
+    fn encode_with_stats(&self, texts: &[String]) -> EncodeResult {
         self.encode_with_stats(texts, Some(512), 1024)
     }
 }
 
+/// Returned by models that cannot provide token vectors, e.g. a model2vec model whose
+/// `model.safetensors` is missing or unreadable
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct TokenVectorsUnavailable(pub String);
+
 pub struct AppState {
     pub model: Arc<dyn EmbeddingModel>,
     pub model_name: String,
     pub max_batch_size: usize,
     pub max_input_length: usize,
+    /// Request body limit in bytes; also bounds a single record of the streaming endpoint
+    pub max_request_size: usize,
+    pub calibration: Option<Calibration>,
+    pub collections: Collections,
+    pub idf: Idf,
+    pub labels: Labels,
+    pub classifiers: Classifiers,
+    pub router: SemanticRouter,
 }
 
-pub async fn create_embeddings(
-    State(state): State<Arc<AppState>>,
-    Json(request): Json<EmbeddingRequest>,
-) -> Result<Json<EmbeddingResponse>, (StatusCode, Json<ErrorResponse>)> {
-    debug!("Received embedding request for {} texts", 
-           match &request.input {
-               EmbeddingInput::String(_) => 1,
-               EmbeddingInput::StringArray(texts) => texts.len(),
-           });
+impl AppState {
+    /// Check a batch of texts against the configured batch size and input length limits
+    pub fn validate_texts(&self, texts: &[String]) -> Result<(), InputError> {
+        if texts.is_empty() {
+            return Err(InputError::new("Input cannot be empty", "empty_input"));
+        }
 
-    // Extract input texts
-    let texts = match request.input {
-        EmbeddingInput::String(text) => vec![text],
-        EmbeddingInput::StringArray(texts) => texts,
-    };
+        if texts.len() > self.max_batch_size {
+            return Err(InputError::new(
+                format!("Batch size exceeds maximum of {}", self.max_batch_size),
+                "batch_too_large",
+            ));
+        }
 
-    // Validate input
-    if texts.is_empty() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: crate::models::ErrorDetail {
-                    message: "Input cannot be empty".to_string(),
-                    error_type: "invalid_request_error".to_string(),
-                    code: Some("empty_input".to_string()),
-                },
-            }),
-        ));
+        self.validate_lengths(texts)
     }
 
-    if texts.len() > state.max_batch_size {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: crate::models::ErrorDetail {
-                    message: format!("Batch size exceeds maximum of {}", state.max_batch_size),
-                    error_type: "invalid_request_error".to_string(),
-                    code: Some("batch_too_large".to_string()),
-                },
-            }),
-        ));
+    /// Check each text against the configured input length limit
+    pub fn validate_lengths(&self, texts: &[String]) -> Result<(), InputError> {
+        if texts.iter().any(|text| text.len() > self.max_input_length) {
+            return Err(InputError::new(
+                format!("Input exceeds maximum length of {}", self.max_input_length),
+                "input_too_long",
+            ));
+        }
+
+        Ok(())
     }
 
-    // Validate input lengths
-    for text in &texts {
-        if text.len() > state.max_input_length {
-            return Err((
-                StatusCode::BAD_REQUEST,
+    /// Encode texts on the blocking thread pool
+    pub async fn encode(&self, texts: Vec<String>) -> Result<EncodeResult, task::JoinError> {
+        let model = Arc::clone(&self.model);
+        task::spawn_blocking(move || model.encode_with_stats(&texts)).await
+    }
+
+    /// Tokenize texts on the blocking thread pool
+    pub async fn tokenize(&self, texts: Vec<String>) -> anyhow::Result<Vec<TokenizedText>> {
+        let model = Arc::clone(&self.model);
+        task::spawn_blocking(move || model.tokenize(&texts)).await?
+    }
+
+    /// Per-token vectors of already validated texts. A model without token vectors maps to a 501
+    /// response, other failures to a 500.
+    pub async fn token_embeddings(&self, texts: Vec<String>) -> Result<Vec<TokenEmbeddings>, (StatusCode, Json<ErrorResponse>)> {
+        let model = Arc::clone(&self.model);
+        let result = match task::spawn_blocking(move || model.token_embeddings(&texts)).await {
+            Ok(result) => result,
+            Err(e) => Err(e.into()),
+        };
+        result.map_err(|e| {
+            if let Some(unavailable) = e.downcast_ref::<TokenVectorsUnavailable>() {
+                return (
+                    StatusCode::NOT_IMPLEMENTED,
+                    Json(ErrorResponse {
+                        error: crate::models::ErrorDetail {
+                            message: unavailable.to_string(),
+                            error_type: "server_error".to_string(),
+                            code: Some("token_vectors_unavailable".to_string()),
+                        },
+                    }),
+                );
+            }
+            error!("Failed to generate token embeddings: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
                 Json(ErrorResponse {
                     error: crate::models::ErrorDetail {
-                        message: format!("Input exceeds maximum length of {}", state.max_input_length),
-                        error_type: "invalid_request_error".to_string(),
-                        code: Some("input_too_long".to_string()),
+                        message: format!("Token embedding generation failed: {}", e),
+                        error_type: "server_error".to_string(),
+                        code: None,
                     },
                 }),
-            ));
-        }
+            )
+        })
     }
 
-    // Offload CPU-intensive model encoding to blocking thread pool
-    let model = Arc::clone(&state.model);
-    let texts_clone = texts.clone();
-    
-    let result = task::spawn_blocking(move || model.encode_with_stats(&texts_clone))
-        .await
-        .map_err(|e| {
+    /// Encode already validated texts into raw float vectors, mapping failures to a 500 response
+    pub async fn encode_texts(&self, texts: Vec<String>) -> Result<EncodeResult, (StatusCode, Json<ErrorResponse>)> {
+        // Offload CPU-intensive model encoding to blocking thread pool
+        self.encode(texts).await.map_err(|e| {
             error!("Failed to generate embeddings: {}", e);
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -103,31 +155,77 @@ pub async fn create_embeddings(
                     },
                 }),
             )
-        })?;
+        })
+    }
 
-    let mut embeddings_data = Vec::with_capacity(result.embeddings.len());
-    
-    for (index, embedding) in result.embeddings.into_iter().enumerate() {
-        embeddings_data.push(EmbeddingData {
-            object: "embedding".to_string(),
-            embedding,
-            index,
-        });
+    /// Validate and embed a batch of texts, returning the response and per-text token counts
+    pub async fn embed(
+        &self,
+        texts: Vec<String>,
+        model: Option<String>,
+        format: EmbeddingFormat,
+    ) -> Result<(EmbeddingResponse, Vec<usize>), (StatusCode, Json<ErrorResponse>)> {
+        // Validate input
+        self.validate_texts(&texts)?;
+
+        let result = self.encode_texts(texts).await?;
+
+        let mut embeddings_data = Vec::with_capacity(result.embeddings.len());
+
+        for (index, embedding) in result.embeddings.into_iter().enumerate() {
+            embeddings_data.push(EmbeddingData {
+                object: "embedding".to_string(),
+                embedding: quantization::convert(embedding, format, self.calibration.as_ref()),
+                index,
+            });
+        }
+
+        // Calculate accurate token usage using tokenizer counts
+        let total_tokens: usize = result.token_counts.iter().sum();
+
+        let response = EmbeddingResponse {
+            object: "list".to_string(),
+            data: embeddings_data,
+            model: model.unwrap_or_else(|| self.model_name.clone()),
+            usage: Usage {
+                prompt_tokens: total_tokens,
+                total_tokens,
+            },
+        };
+
+        Ok((response, result.token_counts))
     }
+}
 
-    // Calculate accurate token usage using tokenizer counts
-    let total_tokens: usize = result.token_counts.iter().sum();
-
-    // Return response
-    Ok(Json(EmbeddingResponse {
-        object: "list".to_string(),
-        data: embeddings_data,
-        model: request.model.unwrap_or_else(|| state.model_name.clone()),
-        usage: Usage {
-            prompt_tokens: total_tokens,
-            total_tokens,
-        },
-    }))
+pub async fn create_embeddings(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Payload(request): Payload<EmbeddingRequest>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    debug!("Received embedding request for {} texts", 
+           match &request.input {
+               EmbeddingInput::String(_) => 1,
+               EmbeddingInput::StringArray(texts) => texts.len(),
+           });
+
+    // Extract input texts
+    let texts = request.input.into_texts();
+
+    let mut format = request.encoding_format.unwrap_or_default();
+    // Matrix formats carry raw bytes, so base64 output is written as float32 instead
+    if response_format.is_matrix() && format == EmbeddingFormat::Base64 {
+        format = EmbeddingFormat::Float;
+    }
+    // and float16 matrices are filled from the float values rather than base64 strings
+    let vector_format = match format {
+        EmbeddingFormat::Float16 if response_format.is_matrix() => EmbeddingFormat::Float,
+        format => format,
+    };
+
+    let (response, token_counts) = state.embed(texts, request.model, vector_format).await?;
+
+    // Return response in the negotiated format
+    Ok(codec::embeddings_response(response_format, &response, &token_counts, format))
 }
 
 pub async fn list_models(
@@ -170,6 +268,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_accurate_token_counting() {
         // Test that we're now using accurate tokenizer-based counting
         // This test will verify the new encode_with_stats integration
@@ -0,0 +1,280 @@
+use axum::{extract::State, http::StatusCode, response::Response, Json};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use tracing::debug;
+
+use crate::codec::{self, Payload, ResponseFormat};
+use crate::collections::lexical::ENGLISH_STOPWORDS;
+use crate::error::InputError;
+use crate::handlers::AppState;
+use crate::models::{ErrorResponse, Keyphrase, KeyphraseRequest, KeyphraseResponse, Keyphrases, Usage};
+use crate::similarity::cosine;
+
+/// Longest candidate phrase, in words
+const MAX_NGRAM: usize = 5;
+
+/// Candidates embedded per text; texts with more keep their most frequent phrases
+const MAX_CANDIDATES: usize = 512;
+
+/// Distinct candidates embedded per request, over all texts
+const MAX_REQUEST_CANDIDATES: usize = 4_096;
+
+type HandlerError = (StatusCode, Json<ErrorResponse>);
+
+/// Byte ranges of the words of a text: runs of letters and digits, joined by inner hyphens and
+/// apostrophes as in "state-of-the-art" or "don't"
+fn words(text: &str) -> Vec<(usize, usize)> {
+    let characters: Vec<(usize, char)> = text.char_indices().collect();
+    let mut words = Vec::new();
+    let mut i = 0;
+    while i < characters.len() {
+        if !characters[i].1.is_alphanumeric() {
+            i += 1;
+            continue;
+        }
+
+        let mut end = i + 1;
+        while end < characters.len() {
+            let joined = matches!(characters[end].1, '-' | '\'' | '’')
+                && characters.get(end + 1).is_some_and(|(_, next)| next.is_alphanumeric());
+            if characters[end].1.is_alphanumeric() {
+                end += 1;
+            } else if joined {
+                end += 2;
+            } else {
+                break;
+            }
+        }
+        words.push((characters[i].0, characters.get(end).map_or(text.len(), |(offset, _)| *offset)));
+        i = end;
+    }
+    words
+}
+
+/// A candidate keyphrase and where it occurs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub phrase: String,
+    pub offsets: Vec<(usize, usize)>,
+}
+
+/// Every phrase of `min` to `max` consecutive words that neither starts nor ends with a stopword
+/// or a number, in order of first occurrence. Phrases do not span punctuation, so they stay
+/// within a sentence or clause.
+pub fn candidates(text: &str, min: usize, max: usize, stopwords: &HashSet<String>) -> Vec<Candidate> {
+    let words = words(text);
+    let lowered: Vec<String> = words.iter().map(|&(start, end)| text[start..end].to_lowercase()).collect();
+    let excluded = |i: usize| stopwords.contains(&lowered[i]) || lowered[i].chars().all(char::is_numeric);
+
+    let mut found: Vec<Candidate> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut segment = 0;
+    for end in 0..words.len() {
+        if end > 0 && !text[words[end - 1].1..words[end].0].trim().is_empty() {
+            segment = end;
+        }
+        for length in min..=max.min(end + 1 - segment) {
+            let start = end + 1 - length;
+            if excluded(start) || excluded(end) {
+                continue;
+            }
+
+            let phrase = lowered[start..=end].join(" ");
+            let offset = (words[start].0, words[end].1);
+            match positions.get(&phrase) {
+                Some(&position) => found[position].offsets.push(offset),
+                None => {
+                    positions.insert(phrase.clone(), found.len());
+                    found.push(Candidate {
+                        phrase,
+                        offsets: vec![offset],
+                    });
+                }
+            }
+        }
+    }
+
+    if found.len() > MAX_CANDIDATES {
+        // Stable, so equally frequent phrases keep their order
+        found.sort_by_key(|candidate| std::cmp::Reverse(candidate.offsets.len()));
+        found.truncate(MAX_CANDIDATES);
+    }
+    found
+}
+
+/// Maximal Marginal Relevance: start from the candidate most similar to the document, then
+/// repeatedly add the one with the best balance of similarity to the document and dissimilarity
+/// to those already chosen. Returns candidate positions with their similarity to the document.
+pub fn mmr(document: &[f32], candidates: &[&[f32]], top_n: usize, diversity: f32) -> Vec<(usize, f32)> {
+    let relevance: Vec<f32> = candidates.iter().map(|candidate| cosine(document, candidate)).collect();
+    // Highest similarity of each candidate to a selected one
+    let mut redundancy = vec![f32::NEG_INFINITY; candidates.len()];
+    let mut selected: Vec<usize> = Vec::new();
+
+    while selected.len() < top_n.min(candidates.len()) {
+        let score = |c: usize| {
+            if selected.is_empty() {
+                relevance[c]
+            } else {
+                (1.0 - diversity) * relevance[c] - diversity * redundancy[c]
+            }
+        };
+        let best = (0..candidates.len())
+            .filter(|c| !selected.contains(c))
+            .fold(None, |best: Option<usize>, c| match best {
+                Some(best) if score(best) >= score(c) => Some(best),
+                _ => Some(c),
+            })
+            .expect("an unselected candidate");
+
+        for (c, candidate) in candidates.iter().enumerate() {
+            redundancy[c] = redundancy[c].max(cosine(candidates[best], candidate));
+        }
+        selected.push(best);
+    }
+
+    selected.into_iter().map(|c| (c, relevance[c])).collect()
+}
+
+fn validate(request: &KeyphraseRequest) -> Result<(), InputError> {
+    let (min, max) = request.ngram_range;
+    if min == 0 || max < min || max > MAX_NGRAM {
+        return Err(InputError::new(
+            format!("ngram_range must satisfy 1 <= min <= max <= {}", MAX_NGRAM),
+            "invalid_ngram_range",
+        ));
+    }
+    if !(0.0..=1.0).contains(&request.diversity) {
+        return Err(InputError::new("diversity must be between 0 and 1", "invalid_diversity"));
+    }
+    Ok(())
+}
+
+/// Extract keyphrases from each input. Candidate phrases are embedded in the same batch as the
+/// texts and ranked against them with Maximal Marginal Relevance.
+pub async fn keyphrases(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Payload(request): Payload<KeyphraseRequest>,
+) -> Result<Response, HandlerError> {
+    validate(&request)?;
+    let texts = request.input.into_texts();
+    debug!("Received keyphrase request for {} texts", texts.len());
+    state.validate_texts(&texts)?;
+
+    let stopwords: HashSet<String> = match request.stopwords {
+        Some(stopwords) => stopwords.iter().map(|word| word.to_lowercase()).collect(),
+        None => ENGLISH_STOPWORDS.iter().map(|word| word.to_string()).collect(),
+    };
+    let (min, max) = request.ngram_range;
+    let found: Vec<Vec<Candidate>> = texts.iter().map(|text| candidates(text, min, max, &stopwords)).collect();
+
+    // Texts first, then each distinct phrase once
+    let mut batch = texts.clone();
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+    for candidate in found.iter().flatten() {
+        positions.entry(candidate.phrase.as_str()).or_insert_with(|| {
+            batch.push(candidate.phrase.clone());
+            batch.len() - 1
+        });
+    }
+    if batch.len() - texts.len() > MAX_REQUEST_CANDIDATES {
+        return Err(InputError::new(
+            format!(
+                "The input yields more than {} distinct candidate phrases; send fewer texts or narrow ngram_range",
+                MAX_REQUEST_CANDIDATES
+            ),
+            "too_many_candidates",
+        )
+        .into());
+    }
+    let result = state.encode_texts(batch).await?;
+
+    let data = found
+        .iter()
+        .enumerate()
+        .map(|(index, candidates)| {
+            let embeddings: Vec<&[f32]> = candidates
+                .iter()
+                .map(|candidate| result.embeddings[positions[candidate.phrase.as_str()]].as_slice())
+                .collect();
+            let keyphrases = mmr(&result.embeddings[index], &embeddings, request.top_n, request.diversity)
+                .into_iter()
+                .map(|(c, score)| Keyphrase {
+                    phrase: candidates[c].phrase.clone(),
+                    score,
+                    offsets: candidates[c].offsets.clone(),
+                })
+                .collect();
+            Keyphrases {
+                object: "keyphrases".to_string(),
+                index,
+                keyphrases,
+            }
+        })
+        .collect();
+
+    let total_tokens = result.token_counts.iter().sum();
+    let response = KeyphraseResponse {
+        object: "list".to_string(),
+        data,
+        model: request.model.unwrap_or_else(|| state.model_name.clone()),
+        usage: Usage {
+            prompt_tokens: total_tokens,
+            total_tokens,
+        },
+    };
+
+    Ok(codec::encode(response_format, &response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn english() -> HashSet<String> {
+        ENGLISH_STOPWORDS.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn test_candidates() {
+        let text = "State-of-the-art search. The search engine ranks 10 documents; search engine!";
+        let found = candidates(text, 1, 2, &english());
+        let phrases: Vec<&str> = found.iter().map(|candidate| candidate.phrase.as_str()).collect();
+        assert_eq!(phrases, ["state-of-the-art", "search", "state-of-the-art search", "engine", "search engine", "ranks", "engine ranks", "documents"]);
+
+        let search = &found[1];
+        assert_eq!(search.offsets.len(), 3);
+        assert_eq!(&text[search.offsets[0].0..search.offsets[0].1], "search");
+        let engine = found.iter().find(|candidate| candidate.phrase == "search engine").unwrap();
+        assert_eq!(engine.offsets, vec![(29, 42), (63, 76)]);
+
+        // Phrases do not cross punctuation, and stopwords may sit inside a phrase
+        assert!(!phrases.contains(&"search search"));
+        let found = candidates("Bank of America", 3, 3, &english());
+        assert_eq!(found[0].phrase, "bank of america");
+    }
+
+    #[test]
+    fn test_mmr() {
+        let document = [1.0, 1.0, 0.0];
+        let a = [1.0, 0.9, 0.0];
+        let a_copy = [1.0, 0.8, 0.0];
+        let b = [0.2, 1.0, 0.5];
+        let candidates: Vec<&[f32]> = vec![&a, &a_copy, &b];
+
+        // Relevance alone picks the two near-identical candidates first
+        let ranked: Vec<usize> = mmr(&document, &candidates, 2, 0.0).into_iter().map(|(c, _)| c).collect();
+        assert_eq!(ranked, [0, 1]);
+
+        // Diversity trades the copy for the different candidate
+        let ranked = mmr(&document, &candidates, 2, 0.7);
+        assert_eq!(ranked[0].0, 0);
+        assert_eq!(ranked[1].0, 2);
+        assert!((ranked[1].1 - cosine(&document, &b)).abs() < 1e-6);
+
+        assert_eq!(mmr(&document, &candidates, 10, 0.5).len(), 3);
+    }
+}
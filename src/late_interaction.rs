@@ -0,0 +1,141 @@
+use axum::{extract::State, http::StatusCode, response::Response, Json};
+use std::{cmp::Ordering, sync::Arc};
+use tracing::debug;
+
+use crate::codec::{self, Payload, ResponseFormat};
+use crate::handlers::AppState;
+use crate::models::{
+    ErrorResponse, MaxSimRequest, RerankResponse, RerankResult, RerankResultDocument, TokenEmbeddingData,
+    TokenEmbeddingRequest, TokenEmbeddingResponse, Usage,
+};
+use crate::similarity::cosine;
+
+/// Sum over query tokens of the best cosine similarity to any document token (ColBERT MaxSim).
+/// An empty document scores 0.
+pub fn maxsim(query: &[Vec<f32>], document: &[Vec<f32>]) -> f32 {
+    query
+        .iter()
+        .map(|q| document.iter().map(|d| cosine(q, d)).reduce(f32::max).unwrap_or(0.0))
+        .sum()
+}
+
+/// The vector of every token of each input, with the token strings and offsets
+pub async fn token_embeddings(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Payload(request): Payload<TokenEmbeddingRequest>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let texts = request.input.into_texts();
+    debug!("Received token embedding request for {} texts", texts.len());
+    state.validate_texts(&texts)?;
+
+    let embeddings = state.token_embeddings(texts).await?;
+    let total_tokens = embeddings.iter().map(|text| text.ids.len()).sum();
+
+    let response = TokenEmbeddingResponse {
+        object: "list".to_string(),
+        data: embeddings
+            .into_iter()
+            .enumerate()
+            .map(|(index, embeddings)| TokenEmbeddingData {
+                object: "token_embeddings".to_string(),
+                index,
+                embeddings,
+            })
+            .collect(),
+        model: request.model.unwrap_or_else(|| state.model_name.clone()),
+        usage: Usage {
+            prompt_tokens: total_tokens,
+            total_tokens,
+        },
+    };
+
+    Ok(codec::encode(response_format, &response))
+}
+
+/// Rank documents by MaxSim between the query's and each document's token vectors
+pub async fn maxsim_rerank(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Payload(request): Payload<MaxSimRequest>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    debug!("Received MaxSim request for {} documents", request.documents.len());
+
+    // As with rerank, the batch limit applies to the documents
+    let documents: Vec<String> = request.documents.into_iter().map(|d| d.into_text()).collect();
+    state.validate_texts(&documents)?;
+    state.validate_lengths(std::slice::from_ref(&request.query))?;
+
+    let mut texts = Vec::with_capacity(documents.len() + 1);
+    texts.push(request.query);
+    texts.extend(documents.iter().cloned());
+    let embeddings = state.token_embeddings(texts).await?;
+
+    let (query, document_tokens) = embeddings.split_first().expect("query token embeddings");
+    let query_tokens = query.embeddings.len().max(1) as f32;
+
+    let mut results: Vec<RerankResult> = document_tokens
+        .iter()
+        .enumerate()
+        .map(|(index, document)| {
+            let mut score = maxsim(&query.embeddings, &document.embeddings);
+            if request.normalize_scores {
+                score /= query_tokens;
+            }
+            RerankResult {
+                index,
+                relevance_score: score,
+                document: None,
+            }
+        })
+        .collect();
+
+    // Highest score first; ties keep the original document order
+    results.sort_by(|a, b| {
+        b.relevance_score
+            .partial_cmp(&a.relevance_score)
+            .unwrap_or(Ordering::Equal)
+    });
+    results.truncate(request.top_n.unwrap_or(results.len()));
+
+    if request.return_documents {
+        for result in &mut results {
+            result.document = Some(RerankResultDocument {
+                text: documents[result.index].clone(),
+            });
+        }
+    }
+
+    let total_tokens = embeddings.iter().map(|text| text.ids.len()).sum();
+    let response = RerankResponse {
+        model: request.model.unwrap_or_else(|| state.model_name.clone()),
+        results,
+        usage: Usage {
+            prompt_tokens: total_tokens,
+            total_tokens,
+        },
+    };
+
+    Ok(codec::encode(response_format, &response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maxsim() {
+        let query = vec![vec![1.0, 0.0], vec![0.0, 1.0]];
+
+        // Each query token finds an exact match
+        let document = vec![vec![0.0, 2.0], vec![3.0, 0.0], vec![1.0, 1.0]];
+        assert!((maxsim(&query, &document) - 2.0).abs() < 1e-6);
+
+        // Both query tokens settle for the same diagonal token
+        let document = vec![vec![1.0, 1.0]];
+        assert!((maxsim(&query, &document) - 2.0f32.sqrt()).abs() < 1e-6);
+
+        assert_eq!(maxsim(&query, &[]), 0.0);
+        assert_eq!(maxsim(&[], &document), 0.0);
+    }
+}
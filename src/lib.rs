@@ -1,54 +1,140 @@
 use axum::{
     middleware,
-    routing::{get, post},
-    Router,
+    routing::{get, post, put},
+    Extension, Router,
 };
-use model2vec_rs::model::StaticModel;
 use std::sync::Arc;
 
-use tower_http::{cors::CorsLayer, trace::TraceLayer, limit::RequestBodyLimitLayer};
+use tower_http::{
+    compression::{
+        predicate::{NotForContentType, Predicate, SizeAbove},
+        CompressionLayer,
+    },
+    cors::CorsLayer,
+    decompression::RequestDecompressionLayer,
+    limit::RequestBodyLimitLayer,
+    trace::TraceLayer,
+};
 
 use auth::{auth_middleware, AuthConfig};
+use classifiers::{
+    handlers::{delete_classifier, get_classifier, list_classifiers, predict, train_classifier},
+    Classifiers,
+};
+use classify::{classify, delete_label_set, get_label_set, list_label_sets, put_label_set, Labels};
+use cluster::cluster;
+use collections::handlers::{
+    create_collection, create_payload_index, create_snapshot, delete_collection, delete_items, get_collection, get_item, list_collections,
+    list_snapshots, query, restore_snapshot, self_test, upsert_items,
+};
+use collections::{storage::fingerprint, Collections};
 use config::Config;
+use dedup::deduplicate;
+use explain::explain_similarity;
 use handlers::{create_embeddings, list_models, AppState, EmbeddingModel};
+use keyphrases::keyphrases;
+use late_interaction::{maxsim_rerank, token_embeddings};
+use model2vec::Model2Vec;
+use quantization::Calibration;
+use rerank::rerank;
+use semantic_router::{delete_route, list_routes, match_routes, put_route, replace_routes, SemanticRouter};
+use similarity::similarity;
+use sparse::{get_idf, sparse_embeddings, update_idf, Idf};
+use stream::stream_embeddings;
+use websocket::websocket;
 
 // Library exports for testing
 pub mod auth;
+pub mod classifiers;
+pub mod classify;
+pub mod cluster;
+pub mod codec;
+pub mod collections;
 pub mod config;
+pub mod dedup;
 pub mod error;
+pub mod explain;
+pub mod grpc;
 pub mod handlers;
+pub mod keyphrases;
+pub mod late_interaction;
+pub mod listen;
+pub mod model2vec;
 pub mod models;
+pub mod quantization;
+pub mod rerank;
+pub mod semantic_router;
+pub mod similarity;
+pub mod sparse;
+pub mod stream;
+pub mod tls;
+pub mod websocket;
+
+/// Load the model configured by `--model-path` together with its tokenizer
+pub fn load_model(config: &Config) -> anyhow::Result<Model2Vec> {
+    Model2Vec::from_pretrained(&config.model_path, config.normalize_embeddings)
+}
 
 /// Create the application router for testing or production use
 pub fn create_app(config: Config) -> anyhow::Result<Router> {
     // Load model
-    let model = StaticModel::from_pretrained(
-        &config.model_path,
-        None,  // Hugging Face token
-        Some(config.normalize_embeddings),  // Normalize embeddings
-        None,  // Subfolder
-    )?;
+    let model = load_model(&config)?;
     
     create_app_with_model(config, model)
 }
 
 /// Create the application router with an existing model (for testing)
-pub fn create_app_with_model(config: Config, model: StaticModel) -> anyhow::Result<Router> {
-    
+pub fn create_app_with_model<M: EmbeddingModel + 'static>(config: Config, model: M) -> anyhow::Result<Router> {
+    let state = create_state(&config, Arc::new(model))?;
+
+    Ok(create_router(config, state))
+}
+
+/// Create the shared state used by the HTTP and gRPC servers
+pub fn create_state(config: &Config, model: Arc<dyn EmbeddingModel>) -> anyhow::Result<Arc<AppState>> {
     let model_name = std::path::Path::new(&config.model_path)
         .file_stem()
         .and_then(|s| s.to_str())
         .map(|s| format!("model2vec-{}", s))
         .unwrap_or_else(|| "model2vec-unknown".to_string());
 
-// Create shared state
-    let state = Arc::new(AppState { 
-        model: Arc::new(model) as Arc<dyn EmbeddingModel>, 
+    let calibration = load_calibration(config, model.as_ref())?;
+    let idf = Idf::load(config)?;
+    let labels = Labels::load(config)?;
+    let router = SemanticRouter::load(config, model.as_ref())?;
+
+    let (collections, classifiers) = match &config.data_dir {
+        Some(dir) => {
+            let fingerprint = fingerprint(model.as_ref());
+            let collections = Collections::open(
+                std::path::Path::new(dir),
+                fingerprint.clone(),
+                model_name.clone(),
+                config.snapshot_keep,
+                Some(Arc::clone(&model)),
+            )?;
+            (collections, Classifiers::open(std::path::Path::new(dir), fingerprint)?)
+        }
+        None => (Collections::new(Some(Arc::clone(&model))), Classifiers::default()),
+    };
+
+    Ok(Arc::new(AppState { 
+        model, 
         model_name,
         max_batch_size: config.max_batch_size,
         max_input_length: config.max_input_length,
-    });
+        max_request_size: config.max_request_size_mb * 1024 * 1024,
+        calibration,
+        collections,
+        idf,
+        labels,
+        classifiers,
+        router,
+    }))
+}
 
+/// Build the HTTP router around existing shared state
+pub fn create_router(config: Config, state: Arc<AppState>) -> Router {
     // Create auth config
     let auth_config = Arc::new(AuthConfig {
         api_key: config.auth_key,
@@ -66,27 +152,113 @@ pub fn create_app_with_model(config: Config, model: StaticModel) -> anyhow::Resu
             .allow_credentials(config.cors_allow_credentials)
             .allow_headers([
                 axum::http::header::CONTENT_TYPE,
+                axum::http::header::CONTENT_ENCODING,
                 axum::http::header::AUTHORIZATION,
             ])
             .allow_methods([
                 axum::http::Method::GET,
                 axum::http::Method::POST,
+                axum::http::Method::PUT,
+                axum::http::Method::DELETE,
                 axum::http::Method::OPTIONS,
             ])
     } else {
         CorsLayer::permissive()
     };
 
-    // Build our application with routes
-    let app = Router::new()
+    // Compress responses negotiated via Accept-Encoding. Streamed NDJSON is left alone so
+    // results are not held back by the encoder
+    let compression_layer = CompressionLayer::new().compress_when(
+        SizeAbove::new(config.compression_min_size)
+            .and(NotForContentType::GRPC)
+            .and(NotForContentType::IMAGES)
+            .and(NotForContentType::SSE)
+            .and(NotForContentType::const_new(stream::NDJSON)),
+    );
+
+    // Routes that buffer the whole request body are subject to the size limit
+    let buffered = Router::new()
         .route("/v1/embeddings", post(create_embeddings))
+        .route("/v1/embeddings/sparse", post(sparse_embeddings))
+        .route("/v1/embeddings/tokens", post(token_embeddings))
         .route("/v1/models", get(list_models))
+        .route("/v1/similarity", post(similarity))
+        .route("/v1/similarity/explain", post(explain_similarity))
+        .route("/v1/rerank", post(rerank))
+        .route("/v1/maxsim", post(maxsim_rerank))
+        .route("/v1/classify", post(classify))
+        .route("/v1/label_sets", get(list_label_sets))
+        .route("/v1/label_sets/{name}", put(put_label_set).get(get_label_set).delete(delete_label_set))
+        .route("/v1/route", post(match_routes))
+        .route("/v1/routes", get(list_routes).put(replace_routes))
+        .route("/v1/routes/{name}", put(put_route).delete(delete_route))
+        .route("/v1/cluster", post(cluster))
+        .route("/v1/deduplicate", post(deduplicate))
+        .route("/v1/keyphrases", post(keyphrases))
+        .route("/v1/classifiers", get(list_classifiers))
+        .route("/v1/classifiers/{name}", get(get_classifier).delete(delete_classifier))
+        .route("/v1/classifiers/{name}/train", post(train_classifier))
+        .route("/v1/classifiers/{name}/predict", post(predict))
+        .route("/v1/collections", post(create_collection).get(list_collections))
+        .route("/v1/collections/{name}", get(get_collection).delete(delete_collection))
+        .route("/v1/collections/{name}/items", post(upsert_items))
+        .route("/v1/collections/{name}/items/{id}", get(get_item))
+        .route("/v1/collections/{name}/delete", post(delete_items))
+        .route("/v1/collections/{name}/query", post(query))
+        .route("/v1/collections/{name}/payload_indexes", post(create_payload_index))
+        .route("/v1/collections/{name}/selftest", post(self_test))
+        .route("/v1/admin/snapshots", post(create_snapshot).get(list_snapshots))
+        .route("/v1/admin/snapshots/{id}/restore", post(restore_snapshot))
+        .route("/v1/admin/idf", post(update_idf).get(get_idf))
+        .layer(RequestBodyLimitLayer::new(config.max_request_size_mb * 1024 * 1024));
+
+    // Streaming routes read the body incrementally and enforce limits per record
+    let streaming = Router::new()
+        .route("/v1/embeddings/stream", post(stream_embeddings));
+
+    // Build our application with routes
+    buffered
+        .merge(streaming)
         .layer(middleware::from_fn_with_state(auth_config.clone(), auth_middleware))
+        // Browsers cannot set headers on the upgrade request, so the WebSocket route authenticates itself
+        .route("/v1/ws", get(websocket).layer(Extension(auth_config)))
         .route("/health", get(|| async { "OK" }))
+        .layer(compression_layer)
+        // Outside the body limit, so the limit applies to the decompressed size
+        .layer(RequestDecompressionLayer::new())
         .layer(TraceLayer::new_for_http())
-        .layer(RequestBodyLimitLayer::new(config.max_request_size_mb * 1024 * 1024))
         .layer(cors_layer)
-        .with_state(state);
+        .with_state(state)
+}
 
-    Ok(app)
-}
\ No newline at end of file
+/// Load int8/uint8 quantization ranges from a ranges file or by embedding a calibration corpus
+fn load_calibration(config: &Config, model: &dyn EmbeddingModel) -> anyhow::Result<Option<Calibration>> {
+    let calibration = if let Some(path) = &config.quantization_ranges {
+        Calibration::from_file(path)?
+    } else if let Some(path) = &config.calibration_corpus {
+        let texts: Vec<String> = std::fs::read_to_string(path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect();
+        Calibration::from_embeddings(&model.encode_with_stats(&texts).embeddings)?
+    } else {
+        return Ok(None);
+    };
+
+    let dimensions = model
+        .encode_with_stats(&[String::new()])
+        .embeddings
+        .first()
+        .map(Vec::len)
+        .unwrap_or_default();
+    if calibration.dimensions() != dimensions {
+        anyhow::bail!(
+            "Calibration ranges have {} dimensions but the model produces {}",
+            calibration.dimensions(),
+            dimensions
+        );
+    }
+
+    Ok(Some(calibration))
+}
@@ -0,0 +1,281 @@
+use axum::{middleware, Extension, Router};
+use std::{
+    fmt,
+    future::Future,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tokio::net::TcpListener;
+use tracing::info;
+
+use crate::auth::ClientIdentity;
+use crate::tls::{insert_identity, ReloadableTls, TlsListener, TlsPeer};
+
+/// An address the HTTP router is served on, given as `tcp://host:port` or
+/// `unix:///path[?mode=660][&auth=none]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenSpec {
+    Tcp(SocketAddr),
+    Unix {
+        path: PathBuf,
+        /// Octal file permissions applied to the socket after binding
+        mode: Option<u32>,
+        /// Skip the API key check, leaving access control to the socket's permissions
+        trusted: bool,
+    },
+}
+
+impl FromStr for ListenSpec {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        if let Some(addr) = spec.strip_prefix("tcp://") {
+            let addr = addr
+                .parse()
+                .map_err(|e| format!("invalid TCP address '{}': {}", addr, e))?;
+            return Ok(ListenSpec::Tcp(addr));
+        }
+
+        if let Some(rest) = spec.strip_prefix("unix://") {
+            let (path, query) = match rest.split_once('?') {
+                Some((path, query)) => (path, Some(query)),
+                None => (rest, None),
+            };
+            if path.is_empty() {
+                return Err("unix socket path cannot be empty".to_string());
+            }
+
+            let mut mode = None;
+            let mut trusted = false;
+            for option in query.into_iter().flat_map(|query| query.split('&')) {
+                match option.split_once('=') {
+                    Some(("mode", value)) => {
+                        let value = u32::from_str_radix(value, 8)
+                            .ok()
+                            .filter(|mode| *mode <= 0o777)
+                            .ok_or_else(|| format!("invalid octal socket mode '{}'", value))?;
+                        mode = Some(value);
+                    }
+                    Some(("auth", "none")) => trusted = true,
+                    Some(("auth", value)) => return Err(format!("invalid auth option '{}', expected 'none'", value)),
+                    _ => return Err(format!("unknown unix socket option '{}'", option)),
+                }
+            }
+
+            return Ok(ListenSpec::Unix {
+                path: PathBuf::from(path),
+                mode,
+                trusted,
+            });
+        }
+
+        Err(format!("listen address '{}' must start with tcp:// or unix://", spec))
+    }
+}
+
+impl fmt::Display for ListenSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenSpec::Tcp(addr) => write!(f, "tcp://{}", addr),
+            ListenSpec::Unix { path, .. } => write!(f, "unix://{}", path.display()),
+        }
+    }
+}
+
+/// Bind `spec` and serve the router until `shutdown` resolves.
+/// TCP listeners use TLS when it is configured; Unix sockets are protected by file permissions
+/// instead, and `auth=none` sockets rely on them alone. Unix socket files are removed again when
+/// the server stops.
+pub async fn serve<F>(spec: ListenSpec, app: Router, tls: Option<ReloadableTls>, shutdown: F) -> anyhow::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    match spec {
+        ListenSpec::Tcp(addr) => {
+            let listener = TcpListener::bind(addr).await?;
+
+            if let Some(tls) = tls {
+                info!("Starting HTTPS server on {}", spec);
+                let app = app.layer(middleware::from_fn(insert_identity));
+                axum::serve(
+                    TlsListener::new(listener, tls)?,
+                    app.into_make_service_with_connect_info::<TlsPeer>(),
+                )
+                .with_graceful_shutdown(shutdown)
+                .await?;
+            } else {
+                info!("Starting server on {}", spec);
+                axum::serve(listener, app).with_graceful_shutdown(shutdown).await?;
+            }
+        }
+        #[cfg(unix)]
+        ListenSpec::Unix { ref path, mode, trusted } => {
+            remove_stale_socket(path)?;
+            let listener = bind_unix(path, mode)?;
+            let _guard = SocketFile(path.clone());
+
+            // Callers on a trusted socket pass the API key check like client certificate holders
+            let app = if trusted {
+                info!("Starting server on {} without API key authentication", spec);
+                app.layer(Extension(ClientIdentity(spec.to_string())))
+            } else {
+                info!("Starting server on {}", spec);
+                app
+            };
+            axum::serve(listener, app).with_graceful_shutdown(shutdown).await?;
+        }
+        #[cfg(not(unix))]
+        ListenSpec::Unix { .. } => {
+            anyhow::bail!("Unix sockets are not supported on this platform");
+        }
+    }
+
+    Ok(())
+}
+
+/// Bind a Unix socket that is never reachable with wider permissions than `mode`: it is bound
+/// inside a private directory, given its mode there, and then moved to `path`. Without a mode
+/// the socket keeps the permissions the process umask gives it.
+#[cfg(unix)]
+fn bind_unix(path: &Path, mode: Option<u32>) -> anyhow::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    // Short, as socket paths are limited to about 100 bytes
+    let private = parent.join(format!(".embed-{}", std::process::id()));
+    // Left behind by a crashed server with the same process id, e.g. in a container
+    let _ = std::fs::remove_dir_all(&private);
+    std::fs::DirBuilder::new().mode(0o700).create(&private)?;
+    let staged = private.join("s");
+
+    let bound = (|| {
+        let listener = tokio::net::UnixListener::bind(&staged)?;
+        if let Some(mode) = mode {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(mode))?;
+        }
+        std::fs::rename(&staged, path)?;
+        anyhow::Ok(listener)
+    })();
+
+    let _ = std::fs::remove_file(&staged);
+    let _ = std::fs::remove_dir(&private);
+    bound
+}
+
+/// Remove a socket file left behind by a server that did not shut down cleanly.
+/// Fails if another server is still accepting connections on it, or if it is not a socket.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> anyhow::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    if !metadata.file_type().is_socket() {
+        anyhow::bail!("{} exists and is not a socket", path.display());
+    }
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        anyhow::bail!("{} is in use by another server", path.display());
+    }
+
+    info!("Removing stale socket {}", path.display());
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+/// Removes the socket file when the server stops
+#[cfg(unix)]
+struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listen_specs() {
+        assert_eq!(
+            "tcp://127.0.0.1:8080".parse::<ListenSpec>(),
+            Ok(ListenSpec::Tcp("127.0.0.1:8080".parse().unwrap()))
+        );
+        assert_eq!(
+            "tcp://[::1]:9000".parse::<ListenSpec>(),
+            Ok(ListenSpec::Tcp("[::1]:9000".parse().unwrap()))
+        );
+        assert_eq!(
+            "unix:///run/embed.sock".parse::<ListenSpec>(),
+            Ok(ListenSpec::Unix { path: PathBuf::from("/run/embed.sock"), mode: None, trusted: false })
+        );
+        assert_eq!(
+            "unix://embed.sock?mode=660".parse::<ListenSpec>(),
+            Ok(ListenSpec::Unix { path: PathBuf::from("embed.sock"), mode: Some(0o660), trusted: false })
+        );
+        assert_eq!(
+            "unix:///run/embed.sock?mode=600&auth=none".parse::<ListenSpec>(),
+            Ok(ListenSpec::Unix { path: PathBuf::from("/run/embed.sock"), mode: Some(0o600), trusted: true })
+        );
+
+        assert!("127.0.0.1:8080".parse::<ListenSpec>().is_err());
+        assert!("tcp://localhost".parse::<ListenSpec>().is_err());
+        assert!("unix://".parse::<ListenSpec>().is_err());
+        assert!("unix:///tmp/a.sock?mode=999".parse::<ListenSpec>().is_err());
+        assert!("unix:///tmp/a.sock?owner=me".parse::<ListenSpec>().is_err());
+        assert!("unix:///tmp/a.sock?auth=key".parse::<ListenSpec>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_bind_unix_socket() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("embedding-service-bind-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("embed.sock");
+
+        let listener = bind_unix(&path, Some(0o600)).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        // Only the socket is left, not the private directory it was bound in
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        let (connected, accepted) = tokio::join!(tokio::net::UnixStream::connect(&path), listener.accept());
+        assert!(connected.is_ok() && accepted.is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remove_stale_socket() {
+        let dir = std::env::temp_dir().join(format!("embedding-service-listen-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // A socket nobody listens on is removed
+        let stale = dir.join("stale.sock");
+        drop(std::os::unix::net::UnixListener::bind(&stale).unwrap());
+        assert!(stale.exists());
+        remove_stale_socket(&stale).unwrap();
+        assert!(!stale.exists());
+
+        // A live socket and regular files are left alone
+        let live = dir.join("live.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&live).unwrap();
+        assert!(remove_stale_socket(&live).is_err());
+        assert!(live.exists());
+
+        let file = dir.join("file.sock");
+        std::fs::write(&file, "").unwrap();
+        assert!(remove_stale_socket(&file).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
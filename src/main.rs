@@ -1,10 +1,13 @@
 use clap::Parser;
-use std::net::SocketAddr;
-use tokio::{net::TcpListener, signal};
+use std::{sync::Arc, time::Duration};
+use tokio::{net::TcpListener, signal, sync::watch, task::JoinSet};
 use tracing::info;
 use tracing_subscriber::EnvFilter;
 
-use embedding_service::{config, create_app};
+use embedding_service::{
+    collections::storage::snapshot_every, config, create_router, create_state, grpc, listen, load_model,
+    tls::{ReloadableTls, TlsListener},
+};
 
 use config::Config;
 
@@ -47,21 +50,76 @@ async fn main() -> anyhow::Result<()> {
     // Parse configuration
     let config = Config::parse();
 
-    // Load model and create app
+    // Load model and create shared state
     info!("Loading model from: {}", config.model_path);
-    let app = create_app(config.clone())?;
+    let model = load_model(&config)?;
+    let state = create_state(&config, Arc::new(model))?;
+    let app = create_router(config.clone(), state.clone());
 
-    // Run the server
-    let addr = SocketAddr::new(
-        config.host.parse()?,
-        config.port,
-    );
-    info!("Starting server on {}", addr);
+    // Persist collection changes in periodic snapshots
+    if config.data_dir.is_some() {
+        tokio::spawn(snapshot_every(state.clone(), Duration::from_secs(config.snapshot_interval_secs)));
+    }
+
+    // Broadcast the shutdown signal to every server
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = shutdown_tx.send(());
+    });
+    let shutdown = |mut rx: watch::Receiver<()>| async move {
+        let _ = rx.changed().await;
+    };
+
+    // Load the TLS certificate and reload it whenever it changes
+    let tls = ReloadableTls::from_config(&config)?;
+    if let Some(tls) = &tls {
+        tokio::spawn(tls.clone().watch());
+    }
+
+    // Serve the same router on every listen address
+    let mut listeners = JoinSet::new();
+    for spec in config.listen_specs()? {
+        listeners.spawn(listen::serve(spec, app.clone(), tls.clone(), shutdown(shutdown_rx.clone())));
+    }
+    let http_server = async {
+        while let Some(result) = listeners.join_next().await {
+            result??;
+        }
+        anyhow::Ok(())
+    };
+
+    let grpc_server = async {
+        if let Some(addr) = config.grpc_addr()? {
+            let server = tonic::transport::Server::builder().add_service(grpc::service(state.clone(), &config));
+
+            match &tls {
+                Some(tls) => {
+                    let tls = tls.for_grpc()?;
+                    tokio::spawn(tls.clone().watch());
+                    let listener = TlsListener::new(TcpListener::bind(addr).await?, tls)?;
+                    info!("Starting gRPC server on {} (TLS)", addr);
+                    server
+                        .serve_with_incoming_shutdown(listener.into_incoming(), shutdown(shutdown_rx.clone()))
+                        .await?;
+                }
+                None => {
+                    info!("Starting gRPC server on {}", addr);
+                    server.serve_with_shutdown(addr, shutdown(shutdown_rx.clone())).await?;
+                }
+            }
+        }
+        anyhow::Ok(())
+    };
 
-    let listener = TcpListener::bind(addr).await?;
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    tokio::try_join!(http_server, grpc_server)?;
+
+    // Fold the log into a final snapshot so the next start does not replay it
+    if config.data_dir.is_some() {
+        if let Some(snapshot) = state.collections.snapshot_if_changed()? {
+            info!("Wrote snapshot {} on shutdown", snapshot.id);
+        }
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
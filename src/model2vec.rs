@@ -0,0 +1,316 @@
+use half::{bf16, f16};
+use hf_hub::api::sync::Api;
+use model2vec_rs::model::{EncodeResult, StaticModel};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+use tokenizers::Tokenizer;
+use tracing::info;
+
+use crate::handlers::{EmbeddingModel, TokenVectorsUnavailable};
+use crate::models::{TokenEmbeddings, TokenizedText};
+
+/// Tokens per text that are pooled, matching the limit used when encoding
+const MAX_TOKENS: usize = 512;
+
+/// A model2vec `StaticModel` paired with its tokenizer, so token-level operations are available
+pub struct Model2Vec {
+    model: StaticModel,
+    tokenizer: Tokenizer,
+    model_path: String,
+    normalize: bool,
+    /// Token id the model drops when pooling
+    unknown: Option<u32>,
+    /// Read from `model.safetensors` on first use, as few deployments need it
+    vectors: OnceLock<Result<TokenVectors, String>>,
+}
+
+impl Model2Vec {
+    /// Load the model and its `tokenizer.json` from a local directory or Hugging Face repo
+    pub fn from_pretrained(model_path: &str, normalize: bool) -> anyhow::Result<Self> {
+        let model = StaticModel::from_pretrained(
+            model_path,
+            None,             // Hugging Face token
+            Some(normalize),  // Normalize embeddings
+            None,             // Subfolder
+        )?;
+        let tokenizer_file = resolve_file(model_path, "tokenizer.json")?;
+        let tokenizer = Tokenizer::from_file(&tokenizer_file).map_err(anyhow::Error::msg)?;
+        let unknown = unknown_token(&tokenizer_file).and_then(|token| tokenizer.token_to_id(&token));
+
+        Ok(Self {
+            model,
+            tokenizer,
+            model_path: model_path.to_string(),
+            normalize,
+            unknown,
+            vectors: OnceLock::new(),
+        })
+    }
+
+    fn vectors(&self) -> anyhow::Result<&TokenVectors> {
+        self.vectors
+            .get_or_init(|| {
+                let file = resolve_file(&self.model_path, "model.safetensors").map_err(|e| e.to_string())?;
+                let vectors = TokenVectors::from_file(&file)
+                    .map_err(|e| format!("Failed to read token vectors from {}: {}", file.display(), e))?;
+                info!("Loaded {} token vectors of {} dimensions", vectors.rows(), vectors.dimensions);
+                Ok(vectors)
+            })
+            .as_ref()
+            .map_err(|e| TokenVectorsUnavailable(e.clone()).into())
+    }
+}
+
+/// The `unk_token` of the tokenizer model, read from `tokenizer.json`
+fn unknown_token(path: &Path) -> Option<String> {
+    let tokenizer: serde_json::Value = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    tokenizer["model"]["unk_token"].as_str().map(str::to_string)
+}
+
+/// The token vector table of a model2vec model: an `embeddings` matrix, plus optional per-token
+/// `weights` and a `mapping` from token id to matrix row in vocabulary-quantized models
+pub struct TokenVectors {
+    embeddings: Vec<f32>,
+    dimensions: usize,
+    weights: Option<Vec<f32>>,
+    mapping: Option<Vec<usize>>,
+}
+
+#[derive(Deserialize)]
+struct TensorInfo {
+    dtype: String,
+    shape: Vec<usize>,
+    data_offsets: (usize, usize),
+}
+
+impl TokenVectors {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Parse the safetensors layout: a little-endian u64 header length, a JSON header describing
+    /// each tensor, then the tensor data
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let header_length = bytes
+            .get(..8)
+            .map(|length| u64::from_le_bytes(length.try_into().expect("eight bytes")))
+            .ok_or_else(|| anyhow::anyhow!("File is too short"))?;
+        let data_start = usize::try_from(header_length)
+            .ok()
+            .and_then(|length| length.checked_add(8))
+            .ok_or_else(|| anyhow::anyhow!("Header length {} is too large", header_length))?;
+        let header = bytes
+            .get(8..data_start)
+            .ok_or_else(|| anyhow::anyhow!("Header runs past the end of the file"))?;
+        let mut header: HashMap<String, serde_json::Value> = serde_json::from_slice(header)?;
+        header.remove("__metadata__");
+        let data = &bytes[data_start..];
+
+        let mut tensors: HashMap<String, TensorInfo> = header
+            .into_iter()
+            .map(|(name, info)| Ok((name, serde_json::from_value(info)?)))
+            .collect::<anyhow::Result<_>>()?;
+
+        let embeddings = tensors
+            .remove("embeddings")
+            .ok_or_else(|| anyhow::anyhow!("No embeddings tensor"))?;
+        let [rows, dimensions] = embeddings.shape[..] else {
+            anyhow::bail!("Embeddings tensor has shape {:?}, expected two dimensions", embeddings.shape);
+        };
+        let vectors = Self {
+            embeddings: read_floats(data, &embeddings)?,
+            dimensions,
+            weights: tensors.get("weights").map(|info| read_floats(data, info)).transpose()?,
+            mapping: tensors.get("mapping").map(|info| read_indices(data, info)).transpose()?,
+        };
+
+        if let Some(row) = vectors.mapping.iter().flatten().find(|&&row| row >= rows) {
+            anyhow::bail!("Token mapping refers to row {} of {}", row, rows);
+        }
+        Ok(vectors)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.embeddings.len() / self.dimensions.max(1)
+    }
+
+    /// The vector of a token id as it enters pooling, scaled by the token's weight if any
+    pub fn get(&self, id: u32) -> Option<Vec<f32>> {
+        let id = id as usize;
+        let row = match &self.mapping {
+            Some(mapping) => *mapping.get(id)?,
+            None => id,
+        };
+        let vector = self.embeddings.get(row * self.dimensions..(row + 1) * self.dimensions)?;
+
+        let weight = match &self.weights {
+            Some(weights) => *weights.get(id)?,
+            None => 1.0,
+        };
+        Some(vector.iter().map(|value| value * weight).collect())
+    }
+}
+
+fn tensor_bytes<'a>(data: &'a [u8], info: &TensorInfo, width: usize) -> anyhow::Result<&'a [u8]> {
+    let (start, end) = info.data_offsets;
+    let bytes = data
+        .get(start..end)
+        .ok_or_else(|| anyhow::anyhow!("Tensor data runs past the end of the file"))?;
+    if bytes.len() != info.shape.iter().product::<usize>() * width {
+        anyhow::bail!("Tensor data does not match its shape {:?}", info.shape);
+    }
+    Ok(bytes)
+}
+
+fn read_floats(data: &[u8], info: &TensorInfo) -> anyhow::Result<Vec<f32>> {
+    Ok(match info.dtype.as_str() {
+        "F32" => tensor_bytes(data, info, 4)?
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        "F64" => tensor_bytes(data, info, 8)?
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().expect("eight bytes")) as f32)
+            .collect(),
+        "F16" => tensor_bytes(data, info, 2)?
+            .chunks_exact(2)
+            .map(|b| f16::from_le_bytes([b[0], b[1]]).to_f32())
+            .collect(),
+        "BF16" => tensor_bytes(data, info, 2)?
+            .chunks_exact(2)
+            .map(|b| bf16::from_le_bytes([b[0], b[1]]).to_f32())
+            .collect(),
+        dtype => anyhow::bail!("Unsupported tensor type {}", dtype),
+    })
+}
+
+fn read_indices(data: &[u8], info: &TensorInfo) -> anyhow::Result<Vec<usize>> {
+    let indices: Vec<i64> = match info.dtype.as_str() {
+        "I32" => tensor_bytes(data, info, 4)?
+            .chunks_exact(4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as i64)
+            .collect(),
+        "I64" => tensor_bytes(data, info, 8)?
+            .chunks_exact(8)
+            .map(|b| i64::from_le_bytes(b.try_into().expect("eight bytes")))
+            .collect(),
+        dtype => anyhow::bail!("Unsupported index type {}", dtype),
+    };
+    indices
+        .into_iter()
+        .map(|index| usize::try_from(index).map_err(|_| anyhow::anyhow!("Negative token mapping {}", index)))
+        .collect()
+}
+
+/// Find a model file locally, falling back to the Hugging Face cache (downloading if needed)
+pub fn resolve_file(model_path: &str, file: &str) -> anyhow::Result<PathBuf> {
+    let local = Path::new(model_path);
+    if local.exists() {
+        return Ok(local.join(file));
+    }
+
+    Ok(Api::new()?.model(model_path.to_string()).get(file)?)
+}
+
+impl EmbeddingModel for Model2Vec {
+    fn encode_with_stats(&self, texts: &[String]) -> EncodeResult {
+        EmbeddingModel::encode_with_stats(&self.model, texts)
+    }
+
+    fn tokenize(&self, texts: &[String]) -> anyhow::Result<Vec<TokenizedText>> {
+        // Special tokens are not added, matching how the model tokenizes for encoding
+        let encodings = self
+            .tokenizer
+            .encode_batch(texts.to_vec(), false)
+            .map_err(anyhow::Error::msg)?;
+
+        Ok(encodings
+            .into_iter()
+            .map(|encoding| TokenizedText {
+                ids: encoding.get_ids().to_vec(),
+                tokens: encoding.get_tokens().to_vec(),
+                offsets: encoding.get_offsets().to_vec(),
+            })
+            .collect())
+    }
+
+    fn normalizes(&self) -> bool {
+        self.normalize
+    }
+
+    fn token_embeddings(&self, texts: &[String]) -> anyhow::Result<Vec<TokenEmbeddings>> {
+        let vectors = self.vectors()?;
+
+        Ok(self
+            .tokenize(texts)?
+            .into_iter()
+            .map(|tokenized| {
+                let mut embeddings = TokenEmbeddings::default();
+                let tokens = tokenized.ids.into_iter().zip(tokenized.tokens).zip(tokenized.offsets);
+                for ((id, token), offset) in tokens.filter(|((id, _), _)| Some(*id) != self.unknown).take(MAX_TOKENS) {
+                    embeddings.embeddings.push(vectors.get(id).unwrap_or_else(|| vec![0.0; vectors.dimensions]));
+                    embeddings.ids.push(id);
+                    embeddings.tokens.push(token);
+                    embeddings.offsets.push(offset);
+                }
+                embeddings
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn safetensors(tensors: &[(&str, &str, Vec<usize>, Vec<u8>)]) -> Vec<u8> {
+        let mut header = serde_json::Map::new();
+        let mut data = Vec::new();
+        for (name, dtype, shape, bytes) in tensors {
+            header.insert(
+                name.to_string(),
+                serde_json::json!({"dtype": dtype, "shape": shape, "data_offsets": [data.len(), data.len() + bytes.len()]}),
+            );
+            data.extend_from_slice(bytes);
+        }
+        let header = serde_json::to_vec(&header).unwrap();
+
+        let mut file = (header.len() as u64).to_le_bytes().to_vec();
+        file.extend(header);
+        file.extend(data);
+        file
+    }
+
+    #[test]
+    fn test_token_vectors() {
+        let embeddings: Vec<u8> = [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let file = safetensors(&[("embeddings", "F32", vec![3, 2], embeddings.clone())]);
+        let vectors = TokenVectors::from_bytes(&file).unwrap();
+        assert_eq!(vectors.rows(), 3);
+        assert_eq!(vectors.get(1), Some(vec![3.0, 4.0]));
+        assert_eq!(vectors.get(3), None);
+
+        // Half precision rows, with a weight per token and tokens 0 and 2 sharing a row
+        let half: Vec<u8> = [1.0f32, 2.0, 3.0, 4.0].iter().flat_map(|v| f16::from_f32(*v).to_le_bytes()).collect();
+        let weights: Vec<u8> = [1.0f32, 0.5, 2.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let mapping: Vec<u8> = [0i64, 1, 0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let file = safetensors(&[
+            ("embeddings", "F16", vec![2, 2], half),
+            ("weights", "F32", vec![3], weights),
+            ("mapping", "I64", vec![3], mapping),
+        ]);
+        let vectors = TokenVectors::from_bytes(&file).unwrap();
+        assert_eq!(vectors.get(1), Some(vec![1.5, 2.0]));
+        assert_eq!(vectors.get(2), Some(vec![2.0, 4.0]));
+
+        let file = safetensors(&[("embeddings", "F32", vec![4, 2], embeddings)]);
+        assert!(TokenVectors::from_bytes(&file).is_err());
+        assert!(TokenVectors::from_bytes(&[1, 0]).is_err());
+        assert!(TokenVectors::from_bytes(&u64::MAX.to_le_bytes()).is_err());
+    }
+}
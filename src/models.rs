@@ -1,10 +1,12 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 // Request structure mimicking OpenAI's embeddings API
 #[derive(Debug, Deserialize, Serialize)]
 pub struct EmbeddingRequest {
     pub input: EmbeddingInput,
     pub model: Option<String>,
+    #[serde(default, alias = "embedding_type", skip_serializing_if = "Option::is_none")]
+    pub encoding_format: Option<EmbeddingFormat>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -14,6 +16,29 @@ pub enum EmbeddingInput {
     StringArray(Vec<String>),
 }
 
+impl EmbeddingInput {
+    pub fn into_texts(self) -> Vec<String> {
+        match self {
+            EmbeddingInput::String(text) => vec![text],
+            EmbeddingInput::StringArray(texts) => texts,
+        }
+    }
+}
+
+// Output format for each embedding vector
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingFormat {
+    #[default]
+    Float,
+    Base64,
+    Float16,
+    Int8,
+    Uint8,
+    Binary,
+    Ubinary,
+}
+
 // Response structure mimicking OpenAI's embeddings API
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EmbeddingResponse {
@@ -26,16 +51,1348 @@ pub struct EmbeddingResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EmbeddingData {
     pub object: String,
-    pub embedding: Vec<f32>,
+    pub embedding: EmbeddingVector,
     pub index: usize,
 }
 
+// JSON keeps the untagged OpenAI wire format, so reading it back goes by the values: integer
+// arrays are tried before float arrays, and ones that fit both int8 and uint8 (including empty
+// ones) come back as int8. The binary codecs (MessagePack, CBOR) tag each vector with its type,
+// e.g. `{"uint8": [...]}`, so they round-trip exactly.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmbeddingVector {
+    Int8(Vec<i8>),
+    Uint8(Vec<u8>),
+    Float(Vec<f32>),
+    Base64(String),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UntaggedVector {
+    Int8(Vec<i8>),
+    Uint8(Vec<u8>),
+    Float(Vec<f32>),
+    Base64(String),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TaggedVector {
+    Int8(Vec<i8>),
+    Uint8(Vec<u8>),
+    Float(Vec<f32>),
+    Base64(String),
+}
+
+impl Serialize for EmbeddingVector {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        const NAME: &str = "EmbeddingVector";
+
+        match (self, serializer.is_human_readable()) {
+            (EmbeddingVector::Int8(v), true) => v.serialize(serializer),
+            (EmbeddingVector::Uint8(v), true) => v.serialize(serializer),
+            (EmbeddingVector::Float(v), true) => v.serialize(serializer),
+            (EmbeddingVector::Base64(v), true) => v.serialize(serializer),
+            (EmbeddingVector::Int8(v), false) => serializer.serialize_newtype_variant(NAME, 0, "int8", v),
+            (EmbeddingVector::Uint8(v), false) => serializer.serialize_newtype_variant(NAME, 1, "uint8", v),
+            (EmbeddingVector::Float(v), false) => serializer.serialize_newtype_variant(NAME, 2, "float", v),
+            (EmbeddingVector::Base64(v), false) => serializer.serialize_newtype_variant(NAME, 3, "base64", v),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for EmbeddingVector {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            Ok(match UntaggedVector::deserialize(deserializer)? {
+                UntaggedVector::Int8(v) => EmbeddingVector::Int8(v),
+                UntaggedVector::Uint8(v) => EmbeddingVector::Uint8(v),
+                UntaggedVector::Float(v) => EmbeddingVector::Float(v),
+                UntaggedVector::Base64(v) => EmbeddingVector::Base64(v),
+            })
+        } else {
+            Ok(match TaggedVector::deserialize(deserializer)? {
+                TaggedVector::Int8(v) => EmbeddingVector::Int8(v),
+                TaggedVector::Uint8(v) => EmbeddingVector::Uint8(v),
+                TaggedVector::Float(v) => EmbeddingVector::Float(v),
+                TaggedVector::Base64(v) => EmbeddingVector::Base64(v),
+            })
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Usage {
     pub prompt_tokens: usize,
     pub total_tokens: usize,
 }
 
+// Request for sparse lexical vectors over the model's vocabulary
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SparseEmbeddingRequest {
+    pub input: EmbeddingInput,
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SparseEmbeddingResponse {
+    pub object: String,
+    pub data: Vec<SparseEmbeddingData>,
+    pub model: String,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SparseEmbeddingData {
+    pub object: String,
+    pub embedding: SparseVector,
+    pub index: usize,
+}
+
+// Token ids in ascending order and their weights
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SparseVector {
+    pub indices: Vec<u32>,
+    pub values: Vec<f32>,
+}
+
+// Corpus to compute the IDF table for sparse embeddings from
+#[derive(Debug, Deserialize, Serialize)]
+pub struct IdfRequest {
+    pub input: EmbeddingInput,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct IdfInfo {
+    /// Whether sparse values are weighted by IDF rather than plain term frequency
+    pub loaded: bool,
+    /// Token ids with their own weight
+    pub terms: usize,
+    /// Size of the corpus the table was computed from, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub documents: Option<usize>,
+}
+
+// Per-token vectors of a single text, as they enter pooling
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenEmbeddings {
+    pub ids: Vec<u32>,
+    pub tokens: Vec<String>,
+    /// Byte offsets of each token in the original text
+    pub offsets: Vec<(usize, usize)>,
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+// Request for the per-token vectors of each input
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TokenEmbeddingRequest {
+    pub input: EmbeddingInput,
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenEmbeddingResponse {
+    pub object: String,
+    pub data: Vec<TokenEmbeddingData>,
+    pub model: String,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenEmbeddingData {
+    pub object: String,
+    pub index: usize,
+    #[serde(flatten)]
+    pub embeddings: TokenEmbeddings,
+}
+
+// Late-interaction scoring of documents against a query, answered in the rerank shape
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MaxSimRequest {
+    pub model: Option<String>,
+    pub query: String,
+    pub documents: Vec<RerankDocument>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_n: Option<usize>,
+    #[serde(default)]
+    pub return_documents: bool,
+    /// Divide each score by the number of query tokens, giving a mean rather than a sum
+    #[serde(default)]
+    pub normalize_scores: bool,
+}
+
+// Request to break the similarity of two texts down by token
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ExplainRequest {
+    pub source: String,
+    pub target: String,
+    /// `cosine` or `dot`
+    #[serde(default)]
+    pub metric: Metric,
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExplainResponse {
+    pub object: String,
+    pub metric: Metric,
+    pub score: f32,
+    /// Whether the pooled embeddings are scaled to unit length before scoring
+    pub normalized: bool,
+    pub source: ExplainedText,
+    pub target: ExplainedText,
+    pub model: String,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExplainedText {
+    /// Length of the pooled embedding
+    pub norm: f32,
+    /// Largest absolute contribution first
+    pub tokens: Vec<TokenContribution>,
+}
+
+// One token's share of the score and of its text's pooled embedding
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenContribution {
+    pub token: String,
+    pub id: u32,
+    /// Byte offsets of the token in its text
+    pub offset: (usize, usize),
+    /// The contributions of a text's tokens sum to the score
+    pub contribution: f32,
+    /// Projection onto the pooled embedding; the tokens of a text sum to its norm
+    pub norm_contribution: f32,
+}
+
+// A candidate label; a plain string is a label described by its name alone
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum LabelInput {
+    Name(String),
+    Label(Label),
+}
+
+impl LabelInput {
+    pub fn into_label(self) -> Label {
+        match self {
+            LabelInput::Name(name) => Label {
+                name,
+                description: None,
+                examples: Vec::new(),
+            },
+            LabelInput::Label(label) => label,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Label {
+    pub name: String,
+    /// Embedded in place of the name when given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Example phrases averaged into the label's embedding
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<String>,
+}
+
+// Zero-shot classification against inline labels or a stored label set
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ClassifyRequest {
+    pub input: EmbeddingInput,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<LabelInput>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label_set: Option<String>,
+    /// Softmax temperature; lower values give more peaked distributions
+    #[serde(default = "default_temperature")]
+    pub temperature: f32,
+    /// Scores to return per input, highest first; all labels when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<usize>,
+    pub model: Option<String>,
+}
+
+fn default_temperature() -> f32 {
+    0.05
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClassifyResponse {
+    pub object: String,
+    pub data: Vec<Classification>,
+    pub model: String,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Classification {
+    pub object: String,
+    pub index: usize,
+    /// The most probable label
+    pub label: String,
+    pub scores: Vec<LabelScore>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LabelScore {
+    pub label: String,
+    /// Softmax probability over the candidate labels
+    pub score: f32,
+    /// Cosine similarity between the input and the label
+    pub similarity: f32,
+}
+
+// Labels stored under a name for later classify requests
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LabelSetRequest {
+    pub labels: Vec<LabelInput>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LabelSet {
+    pub name: String,
+    pub labels: Vec<Label>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LabelSetList {
+    pub object: String,
+    pub data: Vec<LabelSet>,
+}
+
+// Labelled texts to train a classifier head from
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TrainClassifierRequest {
+    pub examples: Vec<TrainingExample>,
+    #[serde(default)]
+    pub head: HeadKind,
+    /// Fraction of each label's examples held out for evaluation
+    #[serde(default = "default_holdout")]
+    pub holdout: f32,
+    #[serde(default = "default_epochs")]
+    pub epochs: usize,
+    #[serde(default = "default_learning_rate")]
+    pub learning_rate: f32,
+    /// L2 penalty on the weights
+    #[serde(default = "default_l2")]
+    pub l2: f32,
+    /// Seed for the held-out split
+    #[serde(default)]
+    pub seed: u64,
+}
+
+fn default_holdout() -> f32 {
+    0.2
+}
+
+fn default_epochs() -> usize {
+    300
+}
+
+fn default_learning_rate() -> f32 {
+    1.0
+}
+
+fn default_l2() -> f32 {
+    1e-4
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TrainingExample {
+    pub text: String,
+    pub label: String,
+}
+
+// Logistic heads give probabilities; linear heads give least-squares decision values
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeadKind {
+    #[default]
+    Logistic,
+    Linear,
+}
+
+// Evaluation of a head on its held-out examples
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ClassifierMetrics {
+    pub accuracy: f32,
+    pub macro_f1: f32,
+    pub train_examples: usize,
+    pub test_examples: usize,
+    pub per_label: Vec<LabelMetrics>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LabelMetrics {
+    pub label: String,
+    pub precision: f32,
+    pub recall: f32,
+    pub f1: f32,
+    /// Held-out examples of the label
+    pub support: usize,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClassifierInfo {
+    pub name: String,
+    pub model: String,
+    /// Version used when a prediction does not ask for one
+    pub latest: u32,
+    pub versions: Vec<ClassifierVersionInfo>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClassifierVersionInfo {
+    pub version: u32,
+    pub head: HeadKind,
+    pub labels: Vec<String>,
+    /// Unix timestamp in seconds
+    pub created_at: u64,
+    /// Missing when nothing was held out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<ClassifierMetrics>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ClassifierList {
+    pub object: String,
+    pub data: Vec<ClassifierInfo>,
+}
+
+// Request to label inputs with a trained classifier head
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PredictRequest {
+    pub input: EmbeddingInput,
+    /// The latest version when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    /// Scores to return per input, highest first; all labels when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<usize>,
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PredictResponse {
+    pub object: String,
+    pub data: Vec<Prediction>,
+    pub classifier: String,
+    pub version: u32,
+    pub model: String,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Prediction {
+    pub object: String,
+    pub index: usize,
+    pub label: String,
+    pub scores: Vec<PredictionScore>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PredictionScore {
+    pub label: String,
+    pub score: f32,
+}
+
+// A semantic route and the example utterances that define it
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RouteConfig {
+    pub name: String,
+    pub examples: Vec<String>,
+    /// Lowest confidence that selects the route; the router's threshold when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f32>,
+}
+
+// The route table, as read from `--routes` and saved in `--data-dir`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RoutesConfig {
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+    /// Route returned when no route reaches its threshold
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+    #[serde(default = "default_route_threshold")]
+    pub threshold: f32,
+}
+
+impl Default for RoutesConfig {
+    fn default() -> Self {
+        Self {
+            routes: Vec::new(),
+            fallback: None,
+            threshold: default_route_threshold(),
+        }
+    }
+}
+
+fn default_route_threshold() -> f32 {
+    0.5
+}
+
+// Request to add or replace a route
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RouteRequest {
+    pub examples: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteStrategy {
+    /// Similarity to the mean of the route's examples
+    #[default]
+    Centroid,
+    /// Mean similarity to the route's `k` nearest examples
+    Knn,
+}
+
+// Request to match texts to routes
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RouteMatchRequest {
+    pub input: EmbeddingInput,
+    #[serde(default)]
+    pub strategy: RouteStrategy,
+    #[serde(default = "default_route_k")]
+    pub k: usize,
+    /// Overrides the router's fallback route
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+    /// Route scores to return per input, highest first; all routes when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<usize>,
+    pub model: Option<String>,
+}
+
+fn default_route_k() -> usize {
+    3
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RouteMatchResponse {
+    pub object: String,
+    pub data: Vec<RouteMatch>,
+    pub model: String,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RouteMatch {
+    pub object: String,
+    pub index: usize,
+    /// The best route, or the fallback when it is below its threshold
+    pub route: Option<String>,
+    /// Score of the best route
+    pub confidence: f32,
+    /// Whether the best route reached its threshold
+    pub matched: bool,
+    pub scores: Vec<RouteScore>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RouteScore {
+    pub route: String,
+    pub score: f32,
+}
+
+// Request to group texts, or the items of a collection, into clusters
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ClusterRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<EmbeddingInput>,
+    /// Cluster the stored vectors of a collection instead of `input`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+    #[serde(default)]
+    pub algorithm: ClusterAlgorithm,
+    /// Members returned per cluster, closest to its centroid first
+    #[serde(default = "default_cluster_representatives")]
+    pub representatives: usize,
+    /// Keywords returned per cluster, from the text of its members
+    #[serde(default = "default_cluster_keywords")]
+    pub keywords: usize,
+    pub model: Option<String>,
+}
+
+fn default_cluster_representatives() -> usize {
+    3
+}
+
+fn default_cluster_keywords() -> usize {
+    5
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ClusterAlgorithm {
+    /// Spherical k-means; without `k`, the count between `min_k` and `max_k` with the best
+    /// silhouette is used
+    Kmeans {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        k: Option<usize>,
+        #[serde(default = "default_min_k")]
+        min_k: usize,
+        #[serde(default = "default_max_k")]
+        max_k: usize,
+        #[serde(default = "default_max_iterations")]
+        max_iterations: usize,
+        #[serde(default)]
+        seed: u64,
+    },
+    /// Density-based clustering: items with `min_points` neighbours within cosine distance
+    /// `eps` grow clusters, and items reached by no cluster are noise
+    Density {
+        #[serde(default = "default_eps")]
+        eps: f32,
+        #[serde(default = "default_min_points")]
+        min_points: usize,
+    },
+}
+
+impl Default for ClusterAlgorithm {
+    fn default() -> Self {
+        ClusterAlgorithm::Kmeans {
+            k: None,
+            min_k: default_min_k(),
+            max_k: default_max_k(),
+            max_iterations: default_max_iterations(),
+            seed: 0,
+        }
+    }
+}
+
+fn default_min_k() -> usize {
+    2
+}
+
+fn default_max_k() -> usize {
+    10
+}
+
+fn default_max_iterations() -> usize {
+    100
+}
+
+fn default_eps() -> f32 {
+    0.3
+}
+
+fn default_min_points() -> usize {
+    3
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClusterResponse {
+    pub object: String,
+    pub algorithm: String,
+    /// Mean silhouette of the clustered items; absent with fewer than two clusters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub silhouette: Option<f32>,
+    /// Largest cluster first
+    pub clusters: Vec<Cluster>,
+    pub assignments: Vec<ClusterAssignment>,
+    /// Items left out of every cluster
+    pub noise: usize,
+    pub model: String,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cluster {
+    pub id: usize,
+    pub size: usize,
+    /// Unit-length mean direction of the members
+    pub centroid: Vec<f32>,
+    pub representatives: Vec<ClusterMember>,
+    pub keywords: Vec<ClusterKeyword>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClusterMember {
+    pub index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Cosine similarity to the cluster centroid
+    pub similarity: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClusterKeyword {
+    pub term: String,
+    pub score: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClusterAssignment {
+    pub index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// None for noise
+    pub cluster: Option<usize>,
+}
+
+// Request to find near-duplicate texts, or near-duplicate items of a collection
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeduplicateRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<EmbeddingInput>,
+    /// Scan the stored vectors of a collection instead of `input`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+    /// Lowest cosine similarity at which two items are duplicates
+    #[serde(default = "default_duplicate_threshold")]
+    pub threshold: f32,
+    #[serde(default)]
+    pub canonical: CanonicalStrategy,
+    /// Only compare items whose MinHash signatures collide, instead of every pair
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minhash: Option<MinHashConfig>,
+    /// Delete every duplicate but the canonical item from the collection
+    #[serde(default)]
+    pub delete: bool,
+    pub model: Option<String>,
+}
+
+fn default_duplicate_threshold() -> f32 {
+    0.95
+}
+
+// Which member of a duplicate group is kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CanonicalStrategy {
+    /// The member that comes first
+    #[default]
+    First,
+    /// The member with the longest text
+    Longest,
+    /// The member with the highest mean similarity to the rest of the group
+    Central,
+}
+
+// Locality-sensitive hashing of character shingles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MinHashConfig {
+    /// Characters per shingle
+    #[serde(default = "default_shingle_size")]
+    pub shingle_size: usize,
+    /// Hash functions per signature; must be a multiple of `bands`
+    #[serde(default = "default_num_hashes")]
+    pub num_hashes: usize,
+    /// Items are compared when all rows of any band match
+    #[serde(default = "default_bands")]
+    pub bands: usize,
+}
+
+impl Default for MinHashConfig {
+    fn default() -> Self {
+        Self {
+            shingle_size: default_shingle_size(),
+            num_hashes: default_num_hashes(),
+            bands: default_bands(),
+        }
+    }
+}
+
+fn default_shingle_size() -> usize {
+    5
+}
+
+fn default_num_hashes() -> usize {
+    128
+}
+
+fn default_bands() -> usize {
+    32
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeduplicateResponse {
+    pub object: String,
+    pub data: Vec<DuplicateGroup>,
+    /// Items left once every duplicate is dropped
+    pub unique: usize,
+    /// Items that are duplicates of a canonical item
+    pub duplicates: usize,
+    /// Pairs whose embeddings were compared
+    pub comparisons: usize,
+    /// Items deleted from the collection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted: Option<usize>,
+    pub model: String,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    pub object: String,
+    pub canonical: DuplicateItem,
+    /// The other members, in input order
+    pub duplicates: Vec<DuplicateItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DuplicateItem {
+    pub index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Cosine similarity to the canonical item
+    pub similarity: f32,
+}
+
+// Request to extract keyphrases from each input text
+#[derive(Debug, Deserialize, Serialize)]
+pub struct KeyphraseRequest {
+    pub input: EmbeddingInput,
+    /// Shortest and longest candidate phrases, in words
+    #[serde(default = "default_ngram_range")]
+    pub ngram_range: (usize, usize),
+    /// Keyphrases returned per text
+    #[serde(default = "default_top_n")]
+    pub top_n: usize,
+    /// Maximal Marginal Relevance trade-off: 0 ranks by relevance alone, 1 by novelty alone
+    #[serde(default = "default_diversity")]
+    pub diversity: f32,
+    /// Words a candidate cannot start or end with; English stopwords when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stopwords: Option<Vec<String>>,
+    pub model: Option<String>,
+}
+
+fn default_ngram_range() -> (usize, usize) {
+    (1, 2)
+}
+
+fn default_top_n() -> usize {
+    5
+}
+
+fn default_diversity() -> f32 {
+    0.5
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyphraseResponse {
+    pub object: String,
+    pub data: Vec<Keyphrases>,
+    pub model: String,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Keyphrases {
+    pub object: String,
+    pub index: usize,
+    /// In the order Maximal Marginal Relevance selected them
+    pub keyphrases: Vec<Keyphrase>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Keyphrase {
+    /// Lowercased words of the phrase
+    pub phrase: String,
+    /// Cosine similarity to the text
+    pub score: f32,
+    /// Byte offsets of each occurrence in the text
+    pub offsets: Vec<(usize, usize)>,
+}
+
+// A single input line of the NDJSON streaming endpoint
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StreamRecord {
+    pub id: serde_json::Value,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+// A single output line of the NDJSON streaming endpoint; either `embedding` or `error` is set
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StreamResult {
+    pub id: Option<serde_json::Value>,
+    pub index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<EmbeddingVector>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_count: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorDetail>,
+}
+
+impl StreamResult {
+    pub fn failed(id: Option<serde_json::Value>, index: usize, error: ErrorDetail) -> Self {
+        Self {
+            id,
+            index,
+            embedding: None,
+            token_count: None,
+            metadata: None,
+            error: Some(error),
+        }
+    }
+}
+
+// Tokenizer output for a single text
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenizedText {
+    pub ids: Vec<u32>,
+    pub tokens: Vec<String>,
+    /// Byte offsets of each token in the original text
+    pub offsets: Vec<(usize, usize)>,
+}
+
+// Similarity metric used to compare embeddings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    #[default]
+    Cosine,
+    Dot,
+    /// Euclidean distance; lower scores are more similar
+    Euclidean,
+}
+
+// Request to score texts against each other without returning their vectors
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SimilarityRequest {
+    pub source: EmbeddingInput,
+    /// Texts each source is scored against; sources are compared pairwise when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub targets: Option<EmbeddingInput>,
+    #[serde(default)]
+    pub metric: Metric,
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SimilarityResponse {
+    pub object: String,
+    pub metric: Metric,
+    /// `scores[i][j]` compares source `i` with target `j`
+    pub scores: Vec<Vec<f32>>,
+    pub model: String,
+    pub usage: Usage,
+}
+
+// Rerank request in the Cohere/Jina shape
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RerankRequest {
+    pub model: Option<String>,
+    pub query: String,
+    pub documents: Vec<RerankDocument>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_n: Option<usize>,
+    #[serde(default)]
+    pub return_documents: bool,
+    /// Weight of query term overlap blended into the embedding score, from 0 to 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lexical_weight: Option<f32>,
+}
+
+// Documents may be plain strings or `{"text": ...}` objects
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum RerankDocument {
+    Text(String),
+    Object { text: String },
+}
+
+impl RerankDocument {
+    pub fn into_text(self) -> String {
+        match self {
+            RerankDocument::Text(text) | RerankDocument::Object { text } => text,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RerankResponse {
+    pub model: String,
+    pub results: Vec<RerankResult>,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RerankResult {
+    /// Position of the document in the request
+    pub index: usize,
+    pub relevance_score: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document: Option<RerankResultDocument>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RerankResultDocument {
+    pub text: String,
+}
+
+// Request to create a named vector collection
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateCollectionRequest {
+    pub name: String,
+    /// Must match the loaded model when given
+    pub model: Option<String>,
+    #[serde(default)]
+    pub metric: Metric,
+    #[serde(default)]
+    pub index: IndexConfig,
+    /// Metadata fields to index for filtering
+    #[serde(default)]
+    pub payload_indexes: Vec<String>,
+    /// How item text is split into terms for lexical search
+    #[serde(default)]
+    pub analyzer: AnalyzerConfig,
+}
+
+// Splits text into terms for the lexical index: lowercased words, or the model's own tokens
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum AnalyzerConfig {
+    Standard {
+        /// Words left out of the index and of queries
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        stopwords: Vec<String>,
+    },
+    Tokenizer,
+}
+
+impl Default for AnalyzerConfig {
+    fn default() -> Self {
+        AnalyzerConfig::Standard { stopwords: Vec::new() }
+    }
+}
+
+// How a collection is searched: exact scans, or an approximate HNSW graph
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum IndexConfig {
+    #[default]
+    Flat,
+    Hnsw(HnswConfig),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HnswConfig {
+    /// Links per node; the bottom level allows twice as many
+    #[serde(default = "default_hnsw_m")]
+    pub m: usize,
+    /// Candidates considered when linking a new node
+    #[serde(default = "default_ef_construction")]
+    pub ef_construction: usize,
+    /// Candidates considered per query unless the query sets its own `ef`
+    #[serde(default = "default_ef")]
+    pub ef: usize,
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        Self {
+            m: default_hnsw_m(),
+            ef_construction: default_ef_construction(),
+            ef: default_ef(),
+        }
+    }
+}
+
+fn default_hnsw_m() -> usize {
+    16
+}
+
+fn default_ef_construction() -> usize {
+    200
+}
+
+fn default_ef() -> usize {
+    64
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CollectionInfo {
+    pub name: String,
+    pub model: String,
+    pub metric: Metric,
+    pub index: IndexConfig,
+    pub payload_indexes: Vec<String>,
+    pub analyzer: AnalyzerConfig,
+    pub dimensions: usize,
+    pub count: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreatePayloadIndexRequest {
+    pub field: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CollectionList {
+    pub object: String,
+    pub data: Vec<CollectionInfo>,
+}
+
+// A collection item; `vector` is used as-is, otherwise `text` is embedded
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CollectionItem {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector: Option<Vec<f32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpsertRequest {
+    pub items: Vec<CollectionItem>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpsertResponse {
+    pub upserted: usize,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteItemsRequest {
+    pub ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteItemsResponse {
+    pub deleted: usize,
+}
+
+// Top-k search by text (embedded first) or by vector, lexically over item text, or both fused
+#[derive(Debug, Deserialize, Serialize)]
+pub struct QueryRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector: Option<Vec<f32>>,
+    #[serde(default = "default_top_k")]
+    pub top_k: usize,
+    #[serde(default)]
+    pub include_vectors: bool,
+    /// Overrides the collection's HNSW `ef` for this query
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ef: Option<usize>,
+    /// Only items whose metadata matches are returned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+    #[serde(default)]
+    pub mode: SearchMode,
+    /// How hybrid queries combine the vector and lexical rankings
+    #[serde(default)]
+    pub fusion: Fusion,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    #[default]
+    Vector,
+    /// BM25 over item text
+    Lexical,
+    Hybrid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Fusion {
+    /// Reciprocal rank fusion: each ranking contributes `1 / (k + rank)`
+    Rrf {
+        #[serde(default = "default_rrf_k")]
+        k: f32,
+    },
+    /// Min-max normalized scores, mixed with `vector_weight` on the vector side
+    Weighted {
+        #[serde(default = "default_vector_weight")]
+        vector_weight: f32,
+    },
+}
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Fusion::Rrf { k: default_rrf_k() }
+    }
+}
+
+fn default_rrf_k() -> f32 {
+    60.0
+}
+
+fn default_vector_weight() -> f32 {
+    0.5
+}
+
+// Boolean expression over item metadata
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum Filter {
+    And { and: Vec<Filter> },
+    Or { or: Vec<Filter> },
+    Not { not: Box<Filter> },
+    Condition(Condition),
+}
+
+// Test of one metadata field; every operator given must hold. Array fields match when any
+// element does. Range bounds are numbers or ISO 8601 dates.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Condition {
+    /// Dotted path into the metadata, e.g. `author.name`
+    pub field: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eq: Option<serde_json::Value>,
+    #[serde(default, rename = "in", skip_serializing_if = "Option::is_none")]
+    pub any_of: Option<Vec<serde_json::Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gt: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gte: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lt: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lte: Option<serde_json::Value>,
+}
+
+fn default_top_k() -> usize {
+    10
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct QueryResponse {
+    pub results: Vec<QueryResult>,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct QueryResult {
+    pub id: String,
+    pub score: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector: Option<Vec<f32>>,
+    /// How a hybrid score was made up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scores: Option<ScoreBreakdown>,
+}
+
+// Both scores of a hybrid result, and its rank in each ranking it made
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct ScoreBreakdown {
+    pub vector: f32,
+    pub lexical: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector_rank: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lexical_rank: Option<usize>,
+}
+
+// Compare a collection's index against exact search on a sample of its own vectors
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SelfTestRequest {
+    #[serde(default = "default_sample_size")]
+    pub sample_size: usize,
+    #[serde(default = "default_top_k")]
+    pub top_k: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ef: Option<usize>,
+}
+
+fn default_sample_size() -> usize {
+    100
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SelfTestResponse {
+    pub sample_size: usize,
+    pub top_k: usize,
+    pub ef: usize,
+    /// Fraction of the exact top-k results the index also returned
+    pub recall: f32,
+    pub index_latency: LatencyStats,
+    pub exact_latency: LatencyStats,
+}
+
+// Per-query latencies in microseconds
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LatencyStats {
+    pub mean_us: f64,
+    pub p50_us: f64,
+    pub p99_us: f64,
+}
+
+// A persisted snapshot of every collection
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SnapshotInfo {
+    pub id: u64,
+    /// Unix timestamp in seconds
+    pub created_at: u64,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SnapshotList {
+    pub object: String,
+    pub data: Vec<SnapshotInfo>,
+}
+
+// A message sent by the client over the WebSocket endpoint
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsRequest {
+    Auth {
+        token: String,
+    },
+    Embed {
+        #[serde(default)]
+        id: Option<serde_json::Value>,
+        #[serde(flatten)]
+        request: EmbeddingRequest,
+    },
+    Tokenize {
+        #[serde(default)]
+        id: Option<serde_json::Value>,
+        input: EmbeddingInput,
+    },
+}
+
+// A message sent by the server over the WebSocket endpoint; `id` echoes the request it answers
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsResponse {
+    Authenticated,
+    Embeddings {
+        id: Option<serde_json::Value>,
+        #[serde(flatten)]
+        response: EmbeddingResponse,
+    },
+    Tokens {
+        id: Option<serde_json::Value>,
+        data: Vec<TokenizedText>,
+        usage: Usage,
+    },
+    Error {
+        id: Option<serde_json::Value>,
+        error: ErrorDetail,
+    },
+}
+
 // Error response
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
@@ -98,7 +1455,7 @@ mod tests {
             data: vec![
                 EmbeddingData {
                     object: "embedding".to_string(),
-                    embedding: vec![0.1, 0.2, 0.3],
+                    embedding: EmbeddingVector::Float(vec![0.1, 0.2, 0.3]),
                     index: 0,
                 }
             ],
@@ -114,12 +1471,50 @@ mod tests {
         
         assert_eq!(parsed.object, "list");
         assert_eq!(parsed.data.len(), 1);
-        assert_eq!(parsed.data[0].embedding, vec![0.1, 0.2, 0.3]);
+        assert_eq!(parsed.data[0].embedding, EmbeddingVector::Float(vec![0.1, 0.2, 0.3]));
         assert_eq!(parsed.model, "test-model");
         assert_eq!(parsed.usage.prompt_tokens, 2);
         assert_eq!(parsed.usage.total_tokens, 2);
     }
 
+    #[test]
+    fn test_embedding_vector_round_trip() {
+        let vectors = [
+            EmbeddingVector::Float(vec![1.0, 0.0, -0.5]),
+            EmbeddingVector::Int8(vec![-128, 0, 127]),
+            EmbeddingVector::Uint8(vec![0, 128, 255]),
+            EmbeddingVector::Base64("AACAPw==".to_string()),
+        ];
+
+        for vector in vectors {
+            let json: EmbeddingVector = serde_json::from_str(&serde_json::to_string(&vector).unwrap()).unwrap();
+            assert_eq!(json, vector);
+            let msgpack: EmbeddingVector = rmp_serde::from_slice(&rmp_serde::to_vec(&vector).unwrap()).unwrap();
+            assert_eq!(msgpack, vector);
+        }
+    }
+
+    #[test]
+    fn test_binary_codecs_keep_vector_type() {
+        // Indistinguishable by value, so JSON reads them back as int8
+        let vectors = [
+            EmbeddingVector::Uint8(vec![0, 1, 127]),
+            EmbeddingVector::Uint8(vec![]),
+            EmbeddingVector::Float(vec![]),
+            EmbeddingVector::Float(vec![1.0, 0.0]),
+        ];
+
+        for vector in vectors {
+            let msgpack: EmbeddingVector = rmp_serde::from_slice(&rmp_serde::to_vec_named(&vector).unwrap()).unwrap();
+            assert_eq!(msgpack, vector);
+
+            let mut buffer = Vec::new();
+            ciborium::into_writer(&vector, &mut buffer).unwrap();
+            let cbor: EmbeddingVector = ciborium::from_reader(buffer.as_slice()).unwrap();
+            assert_eq!(cbor, vector);
+        }
+    }
+
     #[test]
     fn test_error_response_serialization() {
         let error = ErrorResponse {
@@ -156,6 +1551,22 @@ mod tests {
         assert_eq!(parsed.error.code, None);
     }
 
+    #[test]
+    fn test_encoding_format_parsing() {
+        let request: EmbeddingRequest = serde_json::from_str(r#"{"input": "hi"}"#).unwrap();
+        assert_eq!(request.encoding_format, None);
+
+        let request: EmbeddingRequest =
+            serde_json::from_str(r#"{"input": "hi", "encoding_format": "base64"}"#).unwrap();
+        assert_eq!(request.encoding_format, Some(EmbeddingFormat::Base64));
+
+        let request: EmbeddingRequest =
+            serde_json::from_str(r#"{"input": "hi", "embedding_type": "ubinary"}"#).unwrap();
+        assert_eq!(request.encoding_format, Some(EmbeddingFormat::Ubinary));
+
+        assert!(serde_json::from_str::<EmbeddingRequest>(r#"{"input": "hi", "encoding_format": "int4"}"#).is_err());
+    }
+
     #[test]
     fn test_empty_array_input() {
         let request_str = r#"{"input": [], "model": "test"}"#;
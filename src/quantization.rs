@@ -0,0 +1,228 @@
+use base64::{prelude::BASE64_STANDARD, Engine};
+use half::f16;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{EmbeddingFormat, EmbeddingVector};
+
+/// Per-dimension value ranges used for scalar (int8/uint8) quantization.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Calibration {
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+}
+
+impl Calibration {
+    /// Load calibration ranges from a JSON file of the form `{"min": [...], "max": [...]}`
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let calibration: Calibration = serde_json::from_str(&contents)?;
+
+        if calibration.min.len() != calibration.max.len() {
+            anyhow::bail!(
+                "Calibration ranges have mismatched lengths: {} min values, {} max values",
+                calibration.min.len(),
+                calibration.max.len()
+            );
+        }
+
+        Ok(calibration)
+    }
+
+    /// Derive calibration ranges from a set of embeddings (e.g. an embedded calibration corpus)
+    pub fn from_embeddings(embeddings: &[Vec<f32>]) -> anyhow::Result<Self> {
+        let dimensions = match embeddings.first() {
+            Some(first) => first.len(),
+            None => anyhow::bail!("Calibration corpus is empty"),
+        };
+
+        let mut min = vec![f32::INFINITY; dimensions];
+        let mut max = vec![f32::NEG_INFINITY; dimensions];
+
+        for embedding in embeddings {
+            if embedding.len() != dimensions {
+                anyhow::bail!("Calibration embeddings have inconsistent dimensions");
+            }
+            for (i, &value) in embedding.iter().enumerate() {
+                min[i] = min[i].min(value);
+                max[i] = max[i].max(value);
+            }
+        }
+
+        Ok(Self { min, max })
+    }
+
+    /// Fallback ranges of [-1, 1] for every dimension, suitable for normalized embeddings
+    pub fn unit(dimensions: usize) -> Self {
+        Self {
+            min: vec![-1.0; dimensions],
+            max: vec![1.0; dimensions],
+        }
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.min.len()
+    }
+
+    /// Map each value onto 256 evenly spaced buckets within its dimension's range
+    fn buckets(&self, embedding: &[f32]) -> Vec<u8> {
+        embedding
+            .iter()
+            .zip(self.min.iter().zip(self.max.iter()))
+            .map(|(&value, (&min, &max))| {
+                let range = max - min;
+                if range <= f32::EPSILON {
+                    return 128;
+                }
+                let scaled = (value - min) / range * 255.0;
+                scaled.round().clamp(0.0, 255.0) as u8
+            })
+            .collect()
+    }
+}
+
+/// Convert a float embedding into the requested output format.
+///
+/// `calibration` is only consulted for the scalar formats (`int8`, `uint8`); when it is
+/// `None` a unit range of [-1, 1] is assumed.
+pub fn convert(
+    embedding: Vec<f32>,
+    format: EmbeddingFormat,
+    calibration: Option<&Calibration>,
+) -> EmbeddingVector {
+    match format {
+        EmbeddingFormat::Float => EmbeddingVector::Float(embedding),
+        EmbeddingFormat::Base64 => EmbeddingVector::Base64(encode_base64(&embedding)),
+        EmbeddingFormat::Float16 => EmbeddingVector::Base64(encode_base64_f16(&embedding)),
+        EmbeddingFormat::Int8 => EmbeddingVector::Int8(to_signed(quantize_uint8(&embedding, calibration))),
+        EmbeddingFormat::Uint8 => EmbeddingVector::Uint8(quantize_uint8(&embedding, calibration)),
+        EmbeddingFormat::Binary => EmbeddingVector::Int8(to_signed(pack_bits(&embedding))),
+        EmbeddingFormat::Ubinary => EmbeddingVector::Uint8(pack_bits(&embedding)),
+    }
+}
+
+fn quantize_uint8(embedding: &[f32], calibration: Option<&Calibration>) -> Vec<u8> {
+    match calibration {
+        Some(calibration) => calibration.buckets(embedding),
+        None => Calibration::unit(embedding.len()).buckets(embedding),
+    }
+}
+
+/// Shift unsigned bytes into the signed range (0 -> -128, 255 -> 127)
+fn to_signed(values: Vec<u8>) -> Vec<i8> {
+    values.into_iter().map(|v| (v as i16 - 128) as i8).collect()
+}
+
+/// Pack the sign of each value into bits (1 for positive), most significant bit first.
+pub fn pack_bits(embedding: &[f32]) -> Vec<u8> {
+    embedding
+        .chunks(8)
+        .map(|chunk| {
+            chunk.iter().enumerate().fold(0u8, |byte, (i, &value)| {
+                if value > 0.0 {
+                    byte | (0x80 >> i)
+                } else {
+                    byte
+                }
+            })
+        })
+        .collect()
+}
+
+/// Base64-encode the little-endian bytes of a float32 vector (OpenAI `encoding_format: "base64"`).
+pub fn encode_base64(embedding: &[f32]) -> String {
+    let bytes: Vec<u8> = embedding.iter().flat_map(|v| v.to_le_bytes()).collect();
+    BASE64_STANDARD.encode(bytes)
+}
+
+/// Base64-encode the little-endian bytes of a vector rounded to half precision
+pub fn encode_base64_f16(embedding: &[f32]) -> String {
+    let bytes: Vec<u8> = embedding.iter().flat_map(|&v| f16::from_f32(v).to_le_bytes()).collect();
+    BASE64_STANDARD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_bits() {
+        let embedding = vec![0.5, -0.1, 0.0, 0.2, -0.3, 0.1, 0.1, -0.9, 0.4];
+        // 1001 0110, 1000 0000
+        assert_eq!(pack_bits(&embedding), vec![0b1001_0110, 0b1000_0000]);
+    }
+
+    #[test]
+    fn test_binary_formats() {
+        let embedding = vec![1.0; 8];
+
+        match convert(embedding.clone(), EmbeddingFormat::Ubinary, None) {
+            EmbeddingVector::Uint8(v) => assert_eq!(v, vec![255]),
+            other => panic!("Unexpected output {:?}", other),
+        }
+        match convert(embedding, EmbeddingFormat::Binary, None) {
+            EmbeddingVector::Int8(v) => assert_eq!(v, vec![127]),
+            other => panic!("Unexpected output {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_scalar_quantization_with_calibration() {
+        let calibration = Calibration {
+            min: vec![0.0, -2.0, 5.0],
+            max: vec![1.0, 2.0, 5.0],
+        };
+
+        match convert(vec![1.0, -2.0, 5.0], EmbeddingFormat::Uint8, Some(&calibration)) {
+            EmbeddingVector::Uint8(v) => assert_eq!(v, vec![255, 0, 128]),
+            other => panic!("Unexpected output {:?}", other),
+        }
+        match convert(vec![2.0, 0.0, 5.0], EmbeddingFormat::Int8, Some(&calibration)) {
+            // Out-of-range values are clamped
+            EmbeddingVector::Int8(v) => assert_eq!(v, vec![127, 0, 0]),
+            other => panic!("Unexpected output {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_scalar_quantization_defaults_to_unit_range() {
+        match convert(vec![-1.0, 0.0, 1.0], EmbeddingFormat::Int8, None) {
+            EmbeddingVector::Int8(v) => assert_eq!(v, vec![-128, 0, 127]),
+            other => panic!("Unexpected output {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_calibration_from_embeddings() {
+        let calibration =
+            Calibration::from_embeddings(&[vec![0.1, -0.5], vec![0.3, 0.2], vec![-0.2, 0.0]])
+                .unwrap();
+
+        assert_eq!(calibration.min, vec![-0.2, -0.5]);
+        assert_eq!(calibration.max, vec![0.3, 0.2]);
+        assert!(Calibration::from_embeddings(&[]).is_err());
+        assert!(Calibration::from_embeddings(&[vec![0.1], vec![0.1, 0.2]]).is_err());
+    }
+
+    #[test]
+    fn test_float16_rounding() {
+        match convert(vec![0.1, 1.0], EmbeddingFormat::Float16, None) {
+            EmbeddingVector::Base64(encoded) => {
+                let bytes = BASE64_STANDARD.decode(encoded).unwrap();
+                assert_eq!(bytes.len(), 4);
+                let v0 = f16::from_le_bytes([bytes[0], bytes[1]]).to_f32();
+                let v1 = f16::from_le_bytes([bytes[2], bytes[3]]).to_f32();
+                assert_eq!(v1, 1.0);
+                assert!((v0 - 0.1).abs() < 1e-3);
+                assert_ne!(v0, 0.1);
+            }
+            other => panic!("Unexpected output {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_encode_base64() {
+        // 1.0f32 == 0x3F800000, little-endian bytes 00 00 80 3F
+        assert_eq!(encode_base64(&[1.0]), "AACAPw==");
+        assert_eq!(encode_base64(&[]), "");
+    }
+}
@@ -0,0 +1,118 @@
+use axum::{extract::State, http::StatusCode, response::Response, Json};
+use std::{cmp::Ordering, collections::HashSet, sync::Arc};
+use tracing::debug;
+
+use crate::codec::{self, Payload, ResponseFormat};
+use crate::error::InputError;
+use crate::handlers::AppState;
+use crate::models::{
+    ErrorResponse, RerankRequest, RerankResponse, RerankResult, RerankResultDocument, Usage,
+};
+use crate::similarity::cosine;
+
+/// Rank documents by embedding similarity to the query, optionally blended with term overlap
+pub async fn rerank(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Payload(request): Payload<RerankRequest>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    debug!("Received rerank request for {} documents", request.documents.len());
+
+    let lexical_weight = request.lexical_weight.unwrap_or(0.0);
+    if !(0.0..=1.0).contains(&lexical_weight) {
+        return Err(InputError::new("lexical_weight must be between 0 and 1", "invalid_lexical_weight").into());
+    }
+
+    // The batch limit applies to the documents; the query is embedded alongside them
+    let documents: Vec<String> = request.documents.into_iter().map(|d| d.into_text()).collect();
+    state.validate_texts(&documents)?;
+    state.validate_lengths(std::slice::from_ref(&request.query))?;
+
+    let mut texts = Vec::with_capacity(documents.len() + 1);
+    texts.push(request.query.clone());
+    texts.extend(documents.iter().cloned());
+    let result = state.encode_texts(texts).await?;
+
+    let (query, document_vectors) = result.embeddings.split_first().expect("query embedding");
+    let query_terms = terms(&request.query);
+
+    let mut results: Vec<RerankResult> = document_vectors
+        .iter()
+        .enumerate()
+        .map(|(index, vector)| {
+            let mut score = cosine(query, vector);
+            if lexical_weight > 0.0 {
+                let overlap = lexical_overlap(&query_terms, &documents[index]);
+                score = (1.0 - lexical_weight) * score + lexical_weight * overlap;
+            }
+            RerankResult {
+                index,
+                relevance_score: score,
+                document: None,
+            }
+        })
+        .collect();
+
+    // Highest score first; ties keep the original document order
+    results.sort_by(|a, b| {
+        b.relevance_score
+            .partial_cmp(&a.relevance_score)
+            .unwrap_or(Ordering::Equal)
+    });
+    results.truncate(request.top_n.unwrap_or(results.len()));
+
+    if request.return_documents {
+        for result in &mut results {
+            result.document = Some(RerankResultDocument {
+                text: documents[result.index].clone(),
+            });
+        }
+    }
+
+    let total_tokens: usize = result.token_counts.iter().sum();
+
+    let response = RerankResponse {
+        model: request.model.unwrap_or_else(|| state.model_name.clone()),
+        results,
+        usage: Usage {
+            prompt_tokens: total_tokens,
+            total_tokens,
+        },
+    };
+
+    Ok(codec::encode(response_format, &response))
+}
+
+/// Lowercased alphanumeric words of a text
+fn terms(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Fraction of the query terms that appear in the document
+fn lexical_overlap(query_terms: &HashSet<String>, document: &str) -> f32 {
+    if query_terms.is_empty() {
+        return 0.0;
+    }
+
+    let document_terms = terms(document);
+    let matched = query_terms.iter().filter(|term| document_terms.contains(*term)).count();
+    matched as f32 / query_terms.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lexical_overlap() {
+        let query = terms("How do I reset my password?");
+        assert_eq!(query.len(), 6);
+
+        assert_eq!(lexical_overlap(&query, "Password RESET: how to"), 0.5);
+        assert_eq!(lexical_overlap(&query, "Billing FAQ"), 0.0);
+        assert_eq!(lexical_overlap(&terms("   "), "anything"), 0.0);
+    }
+}
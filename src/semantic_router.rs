@@ -0,0 +1,462 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Response,
+    Json,
+};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, PoisonError, RwLock},
+};
+use tokio::task;
+use tracing::{debug, info};
+
+use crate::codec::{self, Payload, ResponseFormat};
+use crate::collections::is_valid_name;
+use crate::config::Config;
+use crate::error::{server_error, InputError};
+use crate::handlers::{AppState, EmbeddingModel};
+use crate::models::{
+    ErrorDetail, ErrorResponse, RouteConfig, RouteMatch, RouteMatchRequest, RouteMatchResponse, RouteRequest, RouteScore,
+    RouteStrategy, RoutesConfig, Usage,
+};
+use crate::similarity::{cosine, norm};
+
+/// Where the route table is saved in `--data-dir` once it is changed through the API
+const ROUTES_FILE: &str = "routes.json";
+
+const MAX_NAME_LENGTH: usize = 64;
+
+type HandlerError = (StatusCode, Json<ErrorResponse>);
+
+/// A route with its example embeddings, scaled to unit length, and their centroid
+#[derive(Debug)]
+struct Route {
+    config: RouteConfig,
+    examples: Vec<Vec<f32>>,
+    centroid: Vec<f32>,
+}
+
+impl Route {
+    fn new(config: RouteConfig, embeddings: Vec<Vec<f32>>) -> Self {
+        let examples: Vec<Vec<f32>> = embeddings
+            .into_iter()
+            .map(|embedding| {
+                let length = norm(&embedding);
+                if length > 0.0 {
+                    embedding.iter().map(|value| value / length).collect()
+                } else {
+                    embedding
+                }
+            })
+            .collect();
+
+        let mut centroid = vec![0.0; examples.first().map_or(0, Vec::len)];
+        for example in &examples {
+            for (total, value) in centroid.iter_mut().zip(example) {
+                *total += value / examples.len() as f32;
+            }
+        }
+
+        Self {
+            config,
+            examples,
+            centroid,
+        }
+    }
+
+    fn score(&self, embedding: &[f32], strategy: RouteStrategy, k: usize) -> f32 {
+        match strategy {
+            RouteStrategy::Centroid => cosine(embedding, &self.centroid),
+            RouteStrategy::Knn => {
+                let mut similarities: Vec<f32> = self.examples.iter().map(|example| cosine(embedding, example)).collect();
+                similarities.sort_by(|a, b| b.total_cmp(a));
+                similarities.truncate(k);
+                similarities.iter().sum::<f32>() / similarities.len().max(1) as f32
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Table {
+    routes: BTreeMap<String, Route>,
+    fallback: Option<String>,
+    threshold: f32,
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        build_table(RoutesConfig::default(), &mut std::iter::empty())
+    }
+}
+
+impl Table {
+    fn config(&self) -> RoutesConfig {
+        RoutesConfig {
+            routes: self.routes.values().map(|route| route.config.clone()).collect(),
+            fallback: self.fallback.clone(),
+            threshold: self.threshold,
+        }
+    }
+}
+
+/// Routes texts to the route whose example utterances they are closest to. Example embeddings
+/// are computed when routes are loaded or added, never per request.
+#[derive(Debug, Default)]
+pub struct SemanticRouter {
+    table: RwLock<Table>,
+    path: Option<PathBuf>,
+}
+
+impl SemanticRouter {
+    /// Use routes saved in `--data-dir` by the API, else the `--routes` file, else no routes
+    pub fn load(config: &Config, model: &dyn EmbeddingModel) -> anyhow::Result<Self> {
+        let path = config.data_dir.as_ref().map(|dir| std::path::Path::new(dir).join(ROUTES_FILE));
+        let file = path
+            .clone()
+            .filter(|path| path.exists())
+            .or_else(|| config.routes.as_ref().map(PathBuf::from));
+
+        let mut table = Table::default();
+        if let Some(file) = file {
+            let routes: RoutesConfig = serde_json::from_str(&fs::read_to_string(&file)?)
+                .map_err(|e| anyhow::anyhow!("Failed to load routes {}: {}", file.display(), e))?;
+            validate_routes(&routes).map_err(|e| anyhow::anyhow!("Invalid routes in {}: {}", file.display(), e.message))?;
+
+            let examples: Vec<String> = routes.routes.iter().flat_map(|route| route.examples.iter().cloned()).collect();
+            let mut embeddings = model.encode_with_stats(&examples).embeddings.into_iter();
+            info!("Loaded {} routes with {} examples from {}", routes.routes.len(), examples.len(), file.display());
+            table = build_table(routes, &mut embeddings);
+        }
+
+        Ok(Self {
+            table: RwLock::new(table),
+            path,
+        })
+    }
+
+    pub fn config(&self) -> RoutesConfig {
+        self.table.read().unwrap_or_else(PoisonError::into_inner).config()
+    }
+
+    /// Replace the whole table, given the embeddings of every route's examples in order
+    pub fn replace(&self, routes: RoutesConfig, embeddings: Vec<Vec<f32>>) -> anyhow::Result<RoutesConfig> {
+        let mut table = self.table.write().unwrap_or_else(PoisonError::into_inner);
+        let updated = build_table(routes, &mut embeddings.into_iter());
+        self.save(&updated.config())?;
+        *table = updated;
+        Ok(table.config())
+    }
+
+    /// Add or replace a single route
+    pub fn insert(&self, route: RouteConfig, embeddings: Vec<Vec<f32>>) -> anyhow::Result<RoutesConfig> {
+        let mut table = self.table.write().unwrap_or_else(PoisonError::into_inner);
+        let mut config = table.config();
+        config.routes.retain(|existing| existing.name != route.name);
+        config.routes.push(route.clone());
+        self.save(&config)?;
+
+        table.routes.insert(route.name.clone(), Route::new(route, embeddings));
+        Ok(table.config())
+    }
+
+    pub fn remove(&self, name: &str) -> anyhow::Result<bool> {
+        let mut table = self.table.write().unwrap_or_else(PoisonError::into_inner);
+        if !table.routes.contains_key(name) {
+            return Ok(false);
+        }
+        let mut config = table.config();
+        config.routes.retain(|route| route.name != name);
+        self.save(&config)?;
+
+        table.routes.remove(name);
+        Ok(true)
+    }
+
+    fn save(&self, config: &RoutesConfig) -> anyhow::Result<()> {
+        if let Some(path) = &self.path {
+            let temporary = path.with_extension("json.tmp");
+            fs::write(&temporary, serde_json::to_vec(config)?)?;
+            fs::rename(&temporary, path)?;
+        }
+        Ok(())
+    }
+
+    /// Match each embedding to its best route, falling back when that route's score is below
+    /// its threshold
+    pub fn route(
+        &self,
+        embeddings: &[Vec<f32>],
+        strategy: RouteStrategy,
+        k: usize,
+        fallback: Option<&str>,
+        top_k: Option<usize>,
+    ) -> Vec<RouteMatch> {
+        let table = self.table.read().unwrap_or_else(PoisonError::into_inner);
+        let fallback = fallback.map(str::to_string).or_else(|| table.fallback.clone());
+
+        embeddings
+            .iter()
+            .enumerate()
+            .map(|(index, embedding)| {
+                let mut scores: Vec<(&Route, f32)> = table
+                    .routes
+                    .values()
+                    .map(|route| (route, route.score(embedding, strategy, k)))
+                    .collect();
+                // Highest first; ties keep the name order
+                scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+                let (route, confidence, matched) = match scores.first() {
+                    Some((best, score)) => {
+                        let matched = *score >= best.config.threshold.unwrap_or(table.threshold);
+                        let route = if matched { Some(best.config.name.clone()) } else { fallback.clone() };
+                        (route, *score, matched)
+                    }
+                    None => (fallback.clone(), 0.0, false),
+                };
+
+                scores.truncate(top_k.unwrap_or(scores.len()));
+                RouteMatch {
+                    object: "route_match".to_string(),
+                    index,
+                    route,
+                    confidence,
+                    matched,
+                    scores: scores
+                        .into_iter()
+                        .map(|(route, score)| RouteScore {
+                            route: route.config.name.clone(),
+                            score,
+                        })
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Build a table, taking each route's example embeddings from the iterator in order
+fn build_table(routes: RoutesConfig, embeddings: &mut impl Iterator<Item = Vec<f32>>) -> Table {
+    Table {
+        routes: routes
+            .routes
+            .into_iter()
+            .map(|route| {
+                let examples = embeddings.by_ref().take(route.examples.len()).collect();
+                (route.name.clone(), Route::new(route, examples))
+            })
+            .collect(),
+        fallback: routes.fallback,
+        threshold: routes.threshold,
+    }
+}
+
+fn validate_threshold(threshold: f32) -> Result<(), InputError> {
+    if (-1.0..=1.0).contains(&threshold) {
+        Ok(())
+    } else {
+        Err(InputError::new("Route thresholds must be between -1 and 1", "invalid_route"))
+    }
+}
+
+fn validate_route(route: &RouteConfig) -> Result<(), InputError> {
+    if !is_valid_name(&route.name, MAX_NAME_LENGTH) {
+        return Err(InputError::new(
+            format!("Route names must be 1 to {} letters, digits, '-' or '_'", MAX_NAME_LENGTH),
+            "invalid_route",
+        ));
+    }
+    if route.examples.is_empty() {
+        return Err(InputError::new(format!("Route '{}' needs at least one example", route.name), "invalid_route"));
+    }
+    route.threshold.map_or(Ok(()), validate_threshold)
+}
+
+fn validate_routes(routes: &RoutesConfig) -> Result<(), InputError> {
+    validate_threshold(routes.threshold)?;
+    let mut names = std::collections::HashSet::new();
+    for route in &routes.routes {
+        validate_route(route)?;
+        if !names.insert(route.name.as_str()) {
+            return Err(InputError::new(format!("Duplicate route '{}'", route.name), "invalid_route"));
+        }
+    }
+    Ok(())
+}
+
+fn route_not_found(name: &str) -> HandlerError {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: ErrorDetail {
+                message: format!("Route '{}' not found", name),
+                error_type: "invalid_request_error".to_string(),
+                code: Some("route_not_found".to_string()),
+            },
+        }),
+    )
+}
+
+/// Match each input to a route
+pub async fn match_routes(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Payload(request): Payload<RouteMatchRequest>,
+) -> Result<Response, HandlerError> {
+    let texts = request.input.into_texts();
+    debug!("Received route request for {} texts", texts.len());
+    state.validate_texts(&texts)?;
+    if request.k == 0 {
+        return Err(InputError::new("k must be at least 1", "invalid_k").into());
+    }
+
+    let result = state.encode_texts(texts).await?;
+    let data = state.router.route(
+        &result.embeddings,
+        request.strategy,
+        request.k,
+        request.fallback.as_deref(),
+        request.top_k,
+    );
+
+    let total_tokens = result.token_counts.iter().sum();
+    let response = RouteMatchResponse {
+        object: "list".to_string(),
+        data,
+        model: request.model.unwrap_or_else(|| state.model_name.clone()),
+        usage: Usage {
+            prompt_tokens: total_tokens,
+            total_tokens,
+        },
+    };
+
+    Ok(codec::encode(response_format, &response))
+}
+
+pub async fn list_routes(State(state): State<Arc<AppState>>, response_format: ResponseFormat) -> Response {
+    codec::encode(response_format, &state.router.config())
+}
+
+/// Replace the whole route table, including the fallback and default threshold
+pub async fn replace_routes(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Payload(routes): Payload<RoutesConfig>,
+) -> Result<Response, HandlerError> {
+    validate_routes(&routes)?;
+    let examples: Vec<String> = routes.routes.iter().flat_map(|route| route.examples.iter().cloned()).collect();
+    state.validate_lengths(&examples)?;
+
+    let embeddings = if examples.is_empty() {
+        Vec::new()
+    } else {
+        state.encode_texts(examples).await?.embeddings
+    };
+    let config = task::spawn_blocking(move || state.router.replace(routes, embeddings))
+        .await
+        .map_err(|e| server_error(format!("Route task failed: {}", e)))?
+        .map_err(|e| server_error(format!("Failed to save routes: {}", e)))?;
+
+    Ok(codec::encode(response_format, &config))
+}
+
+/// Add or replace one route, embedding its examples now
+pub async fn put_route(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Path(name): Path<String>,
+    Payload(request): Payload<RouteRequest>,
+) -> Result<Response, HandlerError> {
+    let route = RouteConfig {
+        name,
+        examples: request.examples,
+        threshold: request.threshold,
+    };
+    validate_route(&route)?;
+    state.validate_lengths(&route.examples)?;
+
+    let embeddings = state.encode_texts(route.examples.clone()).await?.embeddings;
+    let stored = route.clone();
+    task::spawn_blocking(move || state.router.insert(stored, embeddings))
+        .await
+        .map_err(|e| server_error(format!("Route task failed: {}", e)))?
+        .map_err(|e| server_error(format!("Failed to save routes: {}", e)))?;
+
+    Ok(codec::encode(response_format, &route))
+}
+
+pub async fn delete_route(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, HandlerError> {
+    let removed_name = name.clone();
+    let removed = task::spawn_blocking(move || state.router.remove(&removed_name))
+        .await
+        .map_err(|e| server_error(format!("Route task failed: {}", e)))?
+        .map_err(|e| server_error(format!("Failed to save routes: {}", e)))?;
+    if !removed {
+        return Err(route_not_found(&name));
+    }
+
+    Ok(Json(serde_json::json!({
+        "id": name,
+        "object": "route",
+        "deleted": true,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(name: &str, threshold: Option<f32>) -> RouteConfig {
+        RouteConfig {
+            name: name.to_string(),
+            examples: vec![String::new(); 3],
+            threshold,
+        }
+    }
+
+    #[test]
+    fn test_route_scores() {
+        let greeting = Route::new(route("greeting", None), vec![vec![2.0, 0.0], vec![1.0, 1.0], vec![0.0, 1.0]]);
+
+        // The centroid of the unit examples points between them
+        let centroid = greeting.score(&[1.0, 1.0], RouteStrategy::Centroid, 3);
+        assert!((centroid - 1.0).abs() < 1e-5);
+
+        // kNN averages the closest examples only
+        let nearest = greeting.score(&[1.0, 0.0], RouteStrategy::Knn, 1);
+        assert!((nearest - 1.0).abs() < 1e-5);
+        let two = greeting.score(&[1.0, 0.0], RouteStrategy::Knn, 2);
+        assert!((two - (1.0 + 0.5f32.sqrt()) / 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_route_thresholds() {
+        let routes = RoutesConfig {
+            routes: vec![route("billing", Some(0.9)), route("login", None)],
+            fallback: Some("human".to_string()),
+            threshold: 0.5,
+        };
+        let embeddings = vec![vec![1.0, 0.0]; 3].into_iter().chain(vec![vec![0.0, 1.0]; 3]);
+        let router = SemanticRouter::default();
+        router.replace(routes, embeddings.collect()).unwrap();
+
+        let matches = router.route(&[vec![1.0, 0.0], vec![0.6, 0.8], vec![0.8, 0.6]], RouteStrategy::Centroid, 3, None, None);
+
+        assert_eq!(matches[0].route.as_deref(), Some("billing"));
+        assert!(matches[0].matched);
+        // Close to login, which has the router's lower threshold
+        assert_eq!(matches[1].route.as_deref(), Some("login"));
+        // Closest to billing, but below its own threshold
+        assert_eq!(matches[2].route.as_deref(), Some("human"));
+        assert!(!matches[2].matched);
+        assert!((matches[2].confidence - 0.8).abs() < 1e-5);
+        assert_eq!(matches[2].scores[0].route, "billing");
+    }
+}
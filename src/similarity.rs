@@ -0,0 +1,165 @@
+use axum::{extract::State, http::StatusCode, response::Response, Json};
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::codec::{self, Payload, ResponseFormat};
+use crate::handlers::AppState;
+use crate::models::{ErrorResponse, Metric, SimilarityRequest, SimilarityResponse, Usage};
+
+/// Lanes summed independently so the loops vectorize
+const LANES: usize = 8;
+
+/// Dot product of two equal-length vectors
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let mut sums = [0.0f32; LANES];
+    let chunks = a.len() / LANES * LANES;
+
+    for (x, y) in a[..chunks].chunks_exact(LANES).zip(b[..chunks].chunks_exact(LANES)) {
+        for i in 0..LANES {
+            sums[i] += x[i] * y[i];
+        }
+    }
+
+    let tail: f32 = a[chunks..].iter().zip(&b[chunks..]).map(|(x, y)| x * y).sum();
+    sums.iter().sum::<f32>() + tail
+}
+
+pub fn norm(a: &[f32]) -> f32 {
+    dot(a, a).sqrt()
+}
+
+/// Unit-length copy of a vector; zero vectors are returned as they are
+pub fn unit(a: &[f32]) -> Vec<f32> {
+    let length = norm(a);
+    if length > 0.0 {
+        a.iter().map(|value| value / length).collect()
+    } else {
+        a.to_vec()
+    }
+}
+
+/// Euclidean distance between two equal-length vectors
+pub fn euclidean(a: &[f32], b: &[f32]) -> f32 {
+    let mut sums = [0.0f32; LANES];
+    let chunks = a.len() / LANES * LANES;
+
+    for (x, y) in a[..chunks].chunks_exact(LANES).zip(b[..chunks].chunks_exact(LANES)) {
+        for i in 0..LANES {
+            let d = x[i] - y[i];
+            sums[i] += d * d;
+        }
+    }
+
+    let tail: f32 = a[chunks..].iter().zip(&b[chunks..]).map(|(x, y)| (x - y) * (x - y)).sum();
+    (sums.iter().sum::<f32>() + tail).sqrt()
+}
+
+/// Cosine similarity; zero vectors have a similarity of 0 to everything
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let denominator = norm(a) * norm(b);
+    if denominator == 0.0 {
+        0.0
+    } else {
+        dot(a, b) / denominator
+    }
+}
+
+impl Metric {
+    pub fn score(self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Metric::Cosine => cosine(a, b),
+            Metric::Dot => dot(a, b),
+            Metric::Euclidean => euclidean(a, b),
+        }
+    }
+
+    /// Whether higher scores mean more similar vectors
+    pub fn higher_is_better(self) -> bool {
+        !matches!(self, Metric::Euclidean)
+    }
+}
+
+/// Score source texts against targets, or pairwise, from a single encoding batch
+pub async fn similarity(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Payload(request): Payload<SimilarityRequest>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let mut texts = request.source.into_texts();
+    let sources = texts.len();
+    let pairwise = request.targets.is_none();
+    if let Some(targets) = request.targets {
+        texts.extend(targets.into_texts());
+    }
+
+    debug!("Received similarity request for {} texts", texts.len());
+
+    state.validate_texts(&texts)?;
+    let result = state.encode_texts(texts).await?;
+    let (source_vectors, target_vectors) = if pairwise {
+        (&result.embeddings[..], &result.embeddings[..])
+    } else {
+        result.embeddings.split_at(sources)
+    };
+
+    let scores = source_vectors
+        .iter()
+        .map(|source| {
+            target_vectors
+                .iter()
+                .map(|target| request.metric.score(source, target))
+                .collect()
+        })
+        .collect();
+
+    let total_tokens: usize = result.token_counts.iter().sum();
+
+    let response = SimilarityResponse {
+        object: "similarity".to_string(),
+        metric: request.metric,
+        scores,
+        model: request.model.unwrap_or_else(|| state.model_name.clone()),
+        usage: Usage {
+            prompt_tokens: total_tokens,
+            total_tokens,
+        },
+    };
+
+    Ok(codec::encode(response_format, &response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_metrics() {
+        // Longer than one lane block so the tail is exercised too
+        let a: Vec<f32> = (0..19).map(|i| i as f32).collect();
+        let b: Vec<f32> = (0..19).map(|i| (i % 3) as f32 - 1.0).collect();
+
+        let expected_dot: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+        let expected_distance = a.iter().zip(&b).map(|(x, y)| (x - y).powi(2)).sum::<f32>().sqrt();
+
+        assert_close(dot(&a, &b), expected_dot);
+        assert_close(euclidean(&a, &b), expected_distance);
+        assert_close(cosine(&a, &b), expected_dot / (norm(&a) * norm(&b)));
+    }
+
+    #[test]
+    fn test_metric_edge_cases() {
+        let v = [3.0, 4.0];
+        assert_close(Metric::Cosine.score(&v, &v), 1.0);
+        assert_close(Metric::Cosine.score(&v, &[-3.0, -4.0]), -1.0);
+        assert_close(Metric::Euclidean.score(&v, &v), 0.0);
+        assert_close(Metric::Dot.score(&v, &v), 25.0);
+        assert_eq!(cosine(&v, &[0.0, 0.0]), 0.0);
+
+        assert!(Metric::Cosine.higher_is_better());
+        assert!(!Metric::Euclidean.higher_is_better());
+    }
+}
@@ -0,0 +1,282 @@
+use axum::{extract::State, http::StatusCode, response::Response, Json};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, PoisonError, RwLock},
+};
+use tokio::task;
+use tracing::{debug, info};
+
+use crate::codec::{self, Payload, ResponseFormat};
+use crate::config::Config;
+use crate::error::{server_error, InputError};
+use crate::handlers::AppState;
+use crate::models::{
+    ErrorResponse, IdfInfo, IdfRequest, SparseEmbeddingData, SparseEmbeddingRequest,
+    SparseEmbeddingResponse, SparseVector, Usage,
+};
+
+/// Name of the IDF table computed from an uploaded corpus in `--data-dir`, and of the table
+/// looked for in a local model directory
+const IDF_FILE: &str = "idf.json";
+
+type HandlerError = (StatusCode, Json<ErrorResponse>);
+
+/// Inverse document frequency of each token id, stored as JSON:
+/// `{"idf": {"1996": 3.2, ...}, "default": 9.1, "documents": 50000}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdfTable {
+    pub idf: HashMap<u32, f32>,
+    /// Weight of tokens missing from the table; the table's highest weight when not given
+    #[serde(default)]
+    pub default: Option<f32>,
+    /// Size of the corpus the table was computed from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub documents: Option<usize>,
+}
+
+impl IdfTable {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut table: IdfTable = serde_json::from_str(&contents)?;
+
+        if let Some((id, weight)) = table.idf.iter().find(|(_, weight)| !weight.is_finite() || **weight < 0.0) {
+            anyhow::bail!("IDF weight {} of token {} is not a non-negative number", weight, id);
+        }
+        if table.default.is_none() {
+            table.default = Some(table.idf.values().copied().fold(1.0, f32::max));
+        }
+
+        Ok(table)
+    }
+
+    /// Smoothed IDF, `ln((1 + N) / (1 + df)) + 1`, over the token ids of a corpus. Tokens the
+    /// corpus never contains get the weight of a token seen in no document.
+    pub fn from_corpus(documents: &[Vec<u32>]) -> Self {
+        let mut frequencies: HashMap<u32, usize> = HashMap::new();
+        for ids in documents {
+            for &id in ids.iter().collect::<HashSet<_>>() {
+                *frequencies.entry(id).or_default() += 1;
+            }
+        }
+
+        let count = documents.len() as f32;
+        let weight = |frequency: usize| ((1.0 + count) / (1.0 + frequency as f32)).ln() + 1.0;
+        Self {
+            idf: frequencies.into_iter().map(|(id, frequency)| (id, weight(frequency))).collect(),
+            default: Some(weight(0)),
+            documents: Some(documents.len()),
+        }
+    }
+
+    pub fn weight(&self, id: u32) -> f32 {
+        self.idf.get(&id).copied().or(self.default).unwrap_or(1.0)
+    }
+}
+
+/// Term frequency of each token times its IDF weight, or the plain term frequency without a
+/// table. Indices are sorted.
+pub fn sparse_vector(ids: &[u32], table: Option<&IdfTable>) -> SparseVector {
+    let mut counts: BTreeMap<u32, u32> = BTreeMap::new();
+    for &id in ids {
+        *counts.entry(id).or_default() += 1;
+    }
+
+    let (indices, values) = counts
+        .into_iter()
+        .map(|(id, count)| (id, count as f32 * table.map_or(1.0, |table| table.weight(id))))
+        .unzip();
+    SparseVector { indices, values }
+}
+
+/// The IDF table in use, and where a table computed from an uploaded corpus is saved
+#[derive(Debug, Default)]
+pub struct Idf {
+    table: RwLock<Option<IdfTable>>,
+    path: Option<PathBuf>,
+}
+
+impl Idf {
+    /// Use `--idf-table` if given, else a table computed earlier and saved in `--data-dir`,
+    /// else an `idf.json` shipped in a local model directory
+    pub fn load(config: &Config) -> anyhow::Result<Self> {
+        let path = config.data_dir.as_ref().map(|dir| Path::new(dir).join(IDF_FILE));
+        let candidates = [
+            config.idf_table.as_ref().map(PathBuf::from),
+            path.clone().filter(|path| path.exists()),
+            Some(Path::new(&config.model_path).join(IDF_FILE)).filter(|path| path.exists()),
+        ];
+
+        let table = match candidates.into_iter().flatten().next() {
+            Some(file) => {
+                let table = IdfTable::from_file(&file)
+                    .map_err(|e| anyhow::anyhow!("Failed to load IDF table {}: {}", file.display(), e))?;
+                info!("Loaded IDF weights for {} tokens from {}", table.idf.len(), file.display());
+                Some(table)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            table: RwLock::new(table),
+            path,
+        })
+    }
+
+    pub fn info(&self) -> IdfInfo {
+        let table = self.table.read().unwrap_or_else(PoisonError::into_inner);
+        IdfInfo {
+            loaded: table.is_some(),
+            terms: table.as_ref().map_or(0, |table| table.idf.len()),
+            documents: table.as_ref().and_then(|table| table.documents),
+        }
+    }
+
+    /// Switch to a new table, saving it first when there is a data directory
+    pub fn replace(&self, table: IdfTable) -> anyhow::Result<IdfInfo> {
+        if let Some(path) = &self.path {
+            let temporary = path.with_extension("json.tmp");
+            fs::write(&temporary, serde_json::to_vec(&table)?)?;
+            fs::rename(&temporary, path)?;
+        }
+
+        *self.table.write().unwrap_or_else(PoisonError::into_inner) = Some(table);
+        Ok(self.info())
+    }
+
+    pub fn vectors(&self, tokenized: &[Vec<u32>]) -> Vec<SparseVector> {
+        let table = self.table.read().unwrap_or_else(PoisonError::into_inner);
+        tokenized.iter().map(|ids| sparse_vector(ids, table.as_ref())).collect()
+    }
+}
+
+/// Sparse lexical vectors over the model's vocabulary, one per input
+pub async fn sparse_embeddings(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Payload(request): Payload<SparseEmbeddingRequest>,
+) -> Result<Response, HandlerError> {
+    let texts = request.input.into_texts();
+    debug!("Received sparse embedding request for {} texts", texts.len());
+    state.validate_texts(&texts)?;
+
+    let tokenized: Vec<Vec<u32>> = state
+        .tokenize(texts)
+        .await
+        .map_err(tokenization_failed)?
+        .into_iter()
+        .map(|tokenized| tokenized.ids)
+        .collect();
+    let total_tokens = tokenized.iter().map(Vec::len).sum();
+
+    let data = state
+        .idf
+        .vectors(&tokenized)
+        .into_iter()
+        .enumerate()
+        .map(|(index, embedding)| SparseEmbeddingData {
+            object: "sparse_embedding".to_string(),
+            embedding,
+            index,
+        })
+        .collect();
+
+    let response = SparseEmbeddingResponse {
+        object: "list".to_string(),
+        data,
+        model: request.model.unwrap_or_else(|| state.model_name.clone()),
+        usage: Usage {
+            prompt_tokens: total_tokens,
+            total_tokens,
+        },
+    };
+
+    Ok(codec::encode(response_format, &response))
+}
+
+pub async fn get_idf(State(state): State<Arc<AppState>>, response_format: ResponseFormat) -> Response {
+    codec::encode(response_format, &state.idf.info())
+}
+
+/// Compute the IDF table from a corpus and use it from now on. The corpus is not bound by the
+/// batch size limit, only by the request size limit.
+pub async fn update_idf(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Payload(request): Payload<IdfRequest>,
+) -> Result<Response, HandlerError> {
+    let texts = request.input.into_texts();
+    if texts.is_empty() {
+        return Err(InputError::new("Input cannot be empty", "empty_input").into());
+    }
+    state.validate_lengths(&texts)?;
+
+    let documents: Vec<Vec<u32>> = state
+        .tokenize(texts)
+        .await
+        .map_err(tokenization_failed)?
+        .into_iter()
+        .map(|tokenized| tokenized.ids)
+        .collect();
+
+    let info = task::spawn_blocking(move || state.idf.replace(IdfTable::from_corpus(&documents)))
+        .await
+        .map_err(|e| server_error(format!("IDF task failed: {}", e)))?
+        .map_err(|e| server_error(format!("Failed to save IDF table: {}", e)))?;
+    info!("Computed IDF weights for {} tokens from {:?} documents", info.terms, info.documents);
+
+    Ok(codec::encode(response_format, &info))
+}
+
+fn tokenization_failed(e: anyhow::Error) -> HandlerError {
+    server_error(format!("Tokenization failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparse_vector() {
+        let vector = sparse_vector(&[7, 3, 7, 9], None);
+        assert_eq!(vector.indices, vec![3, 7, 9]);
+        assert_eq!(vector.values, vec![1.0, 2.0, 1.0]);
+
+        let table = IdfTable {
+            idf: HashMap::from([(3, 0.5), (7, 2.0)]),
+            default: Some(4.0),
+            documents: None,
+        };
+        let vector = sparse_vector(&[7, 3, 7, 9], Some(&table));
+        assert_eq!(vector.values, vec![0.5, 4.0, 4.0]);
+        assert_eq!(sparse_vector(&[], Some(&table)), SparseVector::default());
+    }
+
+    #[test]
+    fn test_idf_from_corpus() {
+        let table = IdfTable::from_corpus(&[vec![1, 2, 2], vec![1, 3], vec![1]]);
+        assert_eq!(table.documents, Some(3));
+        assert_eq!(table.weight(1), 1.0);
+        assert_eq!(table.weight(2), 2.0f32.ln() + 1.0);
+        assert_eq!(table.weight(2), table.weight(3));
+        assert_eq!(table.weight(99), 4.0f32.ln() + 1.0);
+        assert!(table.weight(99) > table.weight(2));
+    }
+
+    #[test]
+    fn test_idf_file() {
+        let path = std::env::temp_dir().join(format!("embedding-service-idf-{}.json", std::process::id()));
+
+        fs::write(&path, r#"{"idf": {"5": 1.5, "8": 3.0}}"#).unwrap();
+        let table = IdfTable::from_file(&path).unwrap();
+        assert_eq!(table.weight(5), 1.5);
+        assert_eq!(table.weight(6), 3.0);
+
+        fs::write(&path, r#"{"idf": {"5": -1.0}}"#).unwrap();
+        assert!(IdfTable::from_file(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,322 @@
+use axum::{
+    body::{Body, BodyDataStream, Bytes},
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, sync::Arc, time::Duration};
+use tokio::{sync::mpsc, time::timeout};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tracing::{debug, error};
+
+use crate::codec::{self, ResponseFormat};
+use crate::handlers::AppState;
+use crate::models::{EmbeddingFormat, ErrorDetail, StreamRecord, StreamResult};
+use crate::quantization;
+
+pub const NDJSON: &str = "application/x-ndjson";
+
+/// Output lines buffered before the request body stops being read
+const OUTPUT_BUFFER: usize = 64;
+
+/// How long a partial batch waits for more input before it is embedded anyway
+const BATCH_LINGER: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Deserialize)]
+pub struct StreamParams {
+    pub encoding_format: Option<EmbeddingFormat>,
+}
+
+/// Embed newline-delimited JSON records from a streaming request body.
+///
+/// Records are embedded in batches of up to `max_batch_size` lines and results are streamed back as
+/// NDJSON in input order. Neither the input nor the output is buffered in full: the bounded
+/// output channel stops the body from being read while the client is not consuming results.
+pub async fn stream_embeddings(
+    State(state): State<Arc<AppState>>,
+    response_format: ResponseFormat,
+    Query(params): Query<StreamParams>,
+    body: Body,
+) -> Response {
+    // Results are always NDJSON lines, which cannot hold a matrix
+    if response_format.is_matrix() {
+        return codec::not_acceptable(response_format);
+    }
+
+    let (tx, rx) = mpsc::channel(OUTPUT_BUFFER);
+    let format = params.encoding_format.unwrap_or_default();
+
+    tokio::spawn(run(state, format, body.into_data_stream(), tx));
+
+    (
+        [(header::CONTENT_TYPE, NDJSON)],
+        Body::from_stream(ReceiverStream::new(rx)),
+    )
+        .into_response()
+}
+
+type Output = mpsc::Sender<Result<Bytes, Infallible>>;
+
+/// A parsed input line waiting for its batch to be embedded
+enum Pending {
+    Record(usize, StreamRecord),
+    Failed(usize, Option<serde_json::Value>, ErrorDetail),
+}
+
+async fn run(state: Arc<AppState>, format: EmbeddingFormat, mut input: BodyDataStream, tx: Output) {
+    let mut lines = LineSplitter::new(state.max_request_size);
+    let mut pending = Vec::new();
+    let mut index = 0;
+
+    loop {
+        // Flush a partial batch if the client pauses, so interactive producers see results
+        let next = if pending.is_empty() {
+            input.next().await
+        } else {
+            match timeout(BATCH_LINGER, input.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    if !flush(&state, format, &mut pending, &tx).await {
+                        return;
+                    }
+                    continue;
+                }
+            }
+        };
+
+        match next {
+            Some(Ok(chunk)) => {
+                for line in lines.push(&chunk) {
+                    queue(&state, line, &mut index, &mut pending);
+                    // Failed lines count too, so a run of bad records is not held in memory
+                    if pending.len() >= state.max_batch_size && !flush(&state, format, &mut pending, &tx).await {
+                        return;
+                    }
+                }
+            }
+            Some(Err(e)) => {
+                error!("Failed to read streaming request body: {}", e);
+                let error = error_detail(format!("Failed to read request body: {}", e), "invalid_body");
+                if flush(&state, format, &mut pending, &tx).await {
+                    send(&tx, &StreamResult::failed(None, index, error)).await;
+                }
+                return;
+            }
+            None => {
+                if let Some(line) = lines.finish() {
+                    queue(&state, line, &mut index, &mut pending);
+                }
+                flush(&state, format, &mut pending, &tx).await;
+                debug!("Finished embedding stream of {} records", index);
+                return;
+            }
+        }
+    }
+}
+
+/// Parse one input line into the pending batch
+fn queue(state: &AppState, line: Result<Vec<u8>, LineTooLong>, index: &mut usize, pending: &mut Vec<Pending>) {
+    let line = match line {
+        Ok(line) if line.iter().all(u8::is_ascii_whitespace) => return,
+        Ok(line) => line,
+        Err(LineTooLong) => {
+            pending.push(Pending::Failed(*index, None, error_detail(
+                format!("Record exceeds maximum size of {} bytes", state.max_request_size),
+                "record_too_large",
+            )));
+            *index += 1;
+            return;
+        }
+    };
+
+    let position = *index;
+    *index += 1;
+
+    match serde_json::from_slice::<StreamRecord>(&line) {
+        Ok(record) => match state.validate_lengths(std::slice::from_ref(&record.text)) {
+            Ok(()) => pending.push(Pending::Record(position, record)),
+            Err(e) => pending.push(Pending::Failed(position, Some(record.id), error_detail(e.message, e.code))),
+        },
+        Err(e) => {
+            pending.push(Pending::Failed(position, None, error_detail(
+                format!("Invalid record: {}", e),
+                "invalid_record",
+            )));
+        }
+    }
+}
+
+/// Embed the pending records and send every pending line in order.
+/// Returns `false` once the client has gone away.
+async fn flush(state: &AppState, format: EmbeddingFormat, pending: &mut Vec<Pending>, tx: &Output) -> bool {
+    if pending.is_empty() {
+        return true;
+    }
+
+    let texts: Vec<String> = pending
+        .iter()
+        .filter_map(|p| match p {
+            Pending::Record(_, record) => Some(record.text.clone()),
+            Pending::Failed(..) => None,
+        })
+        .collect();
+
+    let mut encoded = if texts.is_empty() {
+        None
+    } else {
+        match state.encode(texts).await {
+            Ok(result) => Some(result.embeddings.into_iter().zip(result.token_counts)),
+            Err(e) => {
+                error!("Failed to generate embeddings: {}", e);
+                None
+            }
+        }
+    };
+
+    for entry in pending.drain(..) {
+        let result = match entry {
+            Pending::Record(index, record) => match encoded.as_mut().and_then(Iterator::next) {
+                Some((embedding, token_count)) => StreamResult {
+                    id: Some(record.id),
+                    index,
+                    embedding: Some(quantization::convert(embedding, format, state.calibration.as_ref())),
+                    token_count: Some(token_count),
+                    metadata: record.metadata,
+                    error: None,
+                },
+                None => StreamResult::failed(Some(record.id), index, ErrorDetail {
+                    message: "Embedding generation task failed".to_string(),
+                    error_type: "server_error".to_string(),
+                    code: None,
+                }),
+            },
+            Pending::Failed(index, id, error) => StreamResult::failed(id, index, error),
+        };
+
+        if !send(tx, &result).await {
+            return false;
+        }
+    }
+
+    true
+}
+
+async fn send<T: Serialize>(tx: &Output, value: &T) -> bool {
+    let mut line = match serde_json::to_vec(value) {
+        Ok(line) => line,
+        Err(e) => {
+            error!("Failed to serialize stream result: {}", e);
+            return true;
+        }
+    };
+    line.push(b'\n');
+
+    tx.send(Ok(Bytes::from(line))).await.is_ok()
+}
+
+fn error_detail(message: String, code: &str) -> ErrorDetail {
+    ErrorDetail {
+        message,
+        error_type: "invalid_request_error".to_string(),
+        code: Some(code.to_string()),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct LineTooLong;
+
+/// Splits a byte stream into lines without holding more than `max_line` bytes of one line.
+/// Oversized lines are skipped up to the next newline and reported as `LineTooLong`.
+pub struct LineSplitter {
+    buffer: Vec<u8>,
+    max_line: usize,
+    overflowed: bool,
+}
+
+impl LineSplitter {
+    pub fn new(max_line: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_line,
+            overflowed: false,
+        }
+    }
+
+    pub fn push(&mut self, mut chunk: &[u8]) -> Vec<Result<Vec<u8>, LineTooLong>> {
+        let mut lines = Vec::new();
+
+        while !chunk.is_empty() {
+            let (part, rest, complete) = match chunk.iter().position(|&b| b == b'\n') {
+                Some(pos) => (&chunk[..pos], &chunk[pos + 1..], true),
+                None => (chunk, &[][..], false),
+            };
+            chunk = rest;
+
+            if !self.overflowed {
+                if self.buffer.len() + part.len() > self.max_line {
+                    self.overflowed = true;
+                    self.buffer.clear();
+                } else {
+                    self.buffer.extend_from_slice(part);
+                }
+            }
+
+            if complete {
+                lines.push(self.take());
+            }
+        }
+
+        lines
+    }
+
+    /// Return the trailing line if the stream did not end with a newline
+    pub fn finish(&mut self) -> Option<Result<Vec<u8>, LineTooLong>> {
+        if self.buffer.is_empty() && !self.overflowed {
+            None
+        } else {
+            Some(self.take())
+        }
+    }
+
+    fn take(&mut self) -> Result<Vec<u8>, LineTooLong> {
+        if std::mem::take(&mut self.overflowed) {
+            Err(LineTooLong)
+        } else {
+            Ok(std::mem::take(&mut self.buffer))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_splitter_across_chunks() {
+        let mut splitter = LineSplitter::new(100);
+
+        assert_eq!(splitter.push(b"{\"a\":"), vec![]);
+        assert_eq!(splitter.push(b"1}\n{\"b\""), vec![Ok(b"{\"a\":1}".to_vec())]);
+        assert_eq!(splitter.push(b":2}\n\n"), vec![Ok(b"{\"b\":2}".to_vec()), Ok(vec![])]);
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[test]
+    fn test_line_splitter_trailing_line() {
+        let mut splitter = LineSplitter::new(100);
+
+        assert_eq!(splitter.push(b"first\nsecond"), vec![Ok(b"first".to_vec())]);
+        assert_eq!(splitter.finish(), Some(Ok(b"second".to_vec())));
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[test]
+    fn test_line_splitter_oversized_line() {
+        let mut splitter = LineSplitter::new(4);
+
+        assert_eq!(splitter.push(b"ok\ntoo"), vec![Ok(b"ok".to_vec())]);
+        assert_eq!(splitter.push(b" long"), vec![]);
+        assert_eq!(splitter.push(b"!\nfine\n"), vec![Err(LineTooLong), Ok(b"fine".to_vec())]);
+    }
+}
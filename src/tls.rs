@@ -0,0 +1,385 @@
+use axum::{
+    extract::{connect_info::Connected, ConnectInfo, Request},
+    middleware::Next,
+    response::Response,
+    serve::{IncomingStream, Listener},
+};
+use rustls::{
+    crypto::ring::default_provider,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConfig,
+};
+use std::{
+    io,
+    net::SocketAddr,
+    path::PathBuf,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, Semaphore},
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+use tokio_stream::Stream;
+use tracing::{debug, error, info};
+
+use crate::auth::ClientIdentity;
+use crate::config::Config;
+
+/// How often certificate files are checked for changes
+const RELOAD_POLL: Duration = Duration::from_secs(10);
+
+/// Connections that have not completed the handshake in time are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Completed handshakes waiting to be picked up by the server
+const ACCEPT_BACKLOG: usize = 64;
+
+/// Handshakes in progress at once; further connections are dropped until one finishes
+pub const MAX_PENDING_HANDSHAKES: usize = 256;
+
+struct TlsFiles {
+    cert: PathBuf,
+    key: PathBuf,
+    client_ca: Option<PathBuf>,
+    alpn: Vec<Vec<u8>>,
+}
+
+impl TlsFiles {
+    fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        [&self.cert, &self.key].into_iter().chain(&self.client_ca)
+    }
+
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        self.paths()
+            .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+
+    fn load(&self) -> anyhow::Result<Arc<ServerConfig>> {
+        let provider = Arc::new(default_provider());
+
+        let certs = CertificateDer::pem_file_iter(&self.cert)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", self.cert.display(), e))?;
+        let key = PrivateKeyDer::from_pem_file(&self.key)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", self.key.display(), e))?;
+
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+
+        let builder = match &self.client_ca {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in CertificateDer::pem_file_iter(path)
+                    .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?
+                {
+                    roots.add(cert.map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?)?;
+                }
+
+                // Clients without a certificate may still connect and authenticate with an API key
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                    .allow_unauthenticated()
+                    .build()?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let mut config = builder.with_single_cert(certs, key)?;
+        config.alpn_protocols = self.alpn.clone();
+
+        Ok(Arc::new(config))
+    }
+}
+
+/// Server TLS settings from `--tls-cert`/`--tls-key`, swappable while listeners are running
+#[derive(Clone)]
+pub struct ReloadableTls {
+    files: Arc<TlsFiles>,
+    config: Arc<RwLock<Arc<ServerConfig>>>,
+}
+
+impl ReloadableTls {
+    /// Load the configured certificate, or return `None` when TLS is not enabled
+    pub fn from_config(config: &Config) -> anyhow::Result<Option<Self>> {
+        let (Some(cert), Some(key)) = (&config.tls_cert, &config.tls_key) else {
+            return Ok(None);
+        };
+
+        let files = TlsFiles {
+            cert: cert.into(),
+            key: key.into(),
+            client_ca: config.tls_client_ca.as_ref().map(PathBuf::from),
+            alpn: vec![b"http/1.1".to_vec()],
+        };
+
+        Self::load(files).map(Some)
+    }
+
+    /// The same certificate for the gRPC port, which negotiates HTTP/2 instead of HTTP/1.1.
+    /// It is reloaded separately, so it needs its own [`watch`](Self::watch) task.
+    pub fn for_grpc(&self) -> anyhow::Result<Self> {
+        Self::load(TlsFiles {
+            cert: self.files.cert.clone(),
+            key: self.files.key.clone(),
+            client_ca: self.files.client_ca.clone(),
+            alpn: vec![b"h2".to_vec()],
+        })
+    }
+
+    fn load(files: TlsFiles) -> anyhow::Result<Self> {
+        let server_config = files.load()?;
+        Ok(Self {
+            files: Arc::new(files),
+            config: Arc::new(RwLock::new(server_config)),
+        })
+    }
+
+    /// Re-read the certificate files. New connections use the new certificate; on error the
+    /// current one stays in place.
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let server_config = self.files.load()?;
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = server_config;
+        Ok(())
+    }
+
+    fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.config.read().unwrap_or_else(|e| e.into_inner()).clone())
+    }
+
+    /// Reload the certificate on SIGHUP or when any of its files change
+    pub async fn watch(self) {
+        #[cfg(unix)]
+        let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+            Ok(signal) => Some(signal),
+            Err(e) => {
+                error!("Failed to install SIGHUP handler: {}", e);
+                None
+            }
+        };
+
+        let mut modified = self.files.modified();
+
+        loop {
+            #[cfg(unix)]
+            let hangup_received = async {
+                match hangup.as_mut() {
+                    Some(signal) => signal.recv().await,
+                    None => std::future::pending().await,
+                }
+            };
+            #[cfg(not(unix))]
+            let hangup_received = std::future::pending::<Option<()>>();
+
+            tokio::select! {
+                _ = hangup_received => info!("SIGHUP received, reloading TLS certificate"),
+                _ = sleep(RELOAD_POLL) => {
+                    let current = self.files.modified();
+                    if current == modified {
+                        continue;
+                    }
+                    modified = current;
+                    info!("TLS certificate files changed, reloading");
+                }
+            }
+
+            match self.reload() {
+                Ok(()) => info!("Reloaded TLS certificate"),
+                Err(e) => error!("Failed to reload TLS certificate, keeping the current one: {}", e),
+            }
+        }
+    }
+}
+
+/// The remote address of a TLS connection and the identity from its client certificate
+#[derive(Debug, Clone)]
+pub struct TlsPeer {
+    pub addr: SocketAddr,
+    pub identity: Option<ClientIdentity>,
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for TlsPeer {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        stream.remote_addr().clone()
+    }
+}
+
+/// A TCP listener that completes TLS handshakes in the background,
+/// so slow clients do not hold up accepting other connections
+pub struct TlsListener {
+    connections: mpsc::Receiver<(TlsStream<TcpStream>, TlsPeer)>,
+    local_addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl TlsListener {
+    pub fn new(listener: TcpListener, tls: ReloadableTls) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (tx, connections) = mpsc::channel(ACCEPT_BACKLOG);
+        let handshakes = Arc::new(Semaphore::new(MAX_PENDING_HANDSHAKES));
+
+        let task = tokio::spawn(async move {
+            loop {
+                let (stream, addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("Failed to accept connection: {}", e);
+                        sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+
+                let Ok(permit) = handshakes.clone().try_acquire_owned() else {
+                    debug!("Dropping connection from {}: too many pending TLS handshakes", addr);
+                    continue;
+                };
+
+                let acceptor = tls.acceptor();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    let stream = match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => stream,
+                        Ok(Err(e)) => return debug!("TLS handshake with {} failed: {}", addr, e),
+                        Err(_) => return debug!("TLS handshake with {} timed out", addr),
+                    };
+
+                    let identity = stream
+                        .get_ref()
+                        .1
+                        .peer_certificates()
+                        .and_then(|certs| certs.first())
+                        .and_then(client_identity);
+
+                    let _ = tx.send((stream, TlsPeer { addr, identity })).await;
+                });
+            }
+        });
+
+        Ok(Self {
+            connections,
+            local_addr,
+            task,
+        })
+    }
+}
+
+impl Drop for TlsListener {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = TlsPeer;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            // The accept task only stops when the listener is dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(TlsPeer {
+            addr: self.local_addr,
+            identity: None,
+        })
+    }
+}
+
+impl TlsListener {
+    /// Accepted connections as a stream for the gRPC server
+    pub fn into_incoming(self) -> TlsIncoming {
+        TlsIncoming(self)
+    }
+}
+
+/// The connections of a [`TlsListener`], as expected by `tonic`'s `serve_with_incoming`
+pub struct TlsIncoming(TlsListener);
+
+impl Stream for TlsIncoming {
+    type Item = io::Result<TlsConnection>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.0.connections.poll_recv(cx) {
+            Poll::Ready(Some((stream, peer))) => Poll::Ready(Some(Ok(TlsConnection { stream, peer }))),
+            // The accept task only stops when the listener is dropped
+            Poll::Ready(None) | Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// A TLS connection that hands its [`TlsPeer`] to gRPC calls as a request extension
+pub struct TlsConnection {
+    stream: TlsStream<TcpStream>,
+    peer: TlsPeer,
+}
+
+impl tonic::transport::server::Connected for TlsConnection {
+    type ConnectInfo = TlsPeer;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.peer.clone()
+    }
+}
+
+impl AsyncRead for TlsConnection {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConnection {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// The subject of a verified client certificate, e.g. `CN=indexer, O=Internal`
+fn client_identity(cert: &CertificateDer<'_>) -> Option<ClientIdentity> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert).ok()?;
+    Some(ClientIdentity(cert.subject().to_string()))
+}
+
+/// Attach the client certificate identity of the connection to each request
+pub async fn insert_identity(
+    ConnectInfo(peer): ConnectInfo<TlsPeer>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if let Some(identity) = peer.identity {
+        request.extensions_mut().insert(identity);
+    }
+    next.run(request).await
+}
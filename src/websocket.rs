@@ -0,0 +1,200 @@
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    http::{header, HeaderMap},
+    response::Response,
+    Extension,
+};
+use std::{sync::Arc, time::Duration};
+use tokio::time::{timeout_at, Instant};
+use tracing::{debug, error};
+
+use crate::auth::{unauthorized, AuthConfig, ClientIdentity};
+use crate::handlers::AppState;
+use crate::models::{ErrorDetail, Usage, WsRequest, WsResponse};
+
+/// Subprotocol the server selects; clients should offer it alongside any `bearer.<key>` protocol
+pub const PROTOCOL: &str = "embeddings";
+
+/// Prefix of the subprotocol carrying the API key, for clients that cannot set headers
+const BEARER_PROTOCOL: &str = "bearer.";
+
+/// Time an unauthenticated connection has to send its `auth` message
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Malformed messages tolerated before authentication; the next one closes the connection
+const MAX_UNAUTHENTICATED_ERRORS: usize = 3;
+
+/// Upgrade to a WebSocket session for repeated embed/tokenize requests.
+///
+/// The API key may be given once, as an `Authorization` header, as a `bearer.<key>`
+/// subprotocol, or in an `{"type": "auth"}` first message. A key that is present on the
+/// upgrade request but wrong is rejected with 401 before the connection is opened; connections
+/// that do not authenticate within 10 seconds are closed.
+pub async fn websocket(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_config): Extension<Arc<AuthConfig>>,
+    identity: Option<Extension<ClientIdentity>>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok());
+    let protocol_token = headers
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|protocols| {
+            protocols
+                .split(',')
+                .find_map(|protocol| protocol.trim().strip_prefix(BEARER_PROTOCOL))
+        });
+
+    let authenticated = if authorization.is_some() {
+        auth_config.authorize(authorization)
+    } else if protocol_token.is_some() {
+        auth_config.authorize_token(protocol_token)
+    } else {
+        // Without credentials the client certificate or the first message must authenticate
+        identity.is_some() || auth_config.authorize_token(None)
+    };
+
+    if !authenticated && (authorization.is_some() || protocol_token.is_some()) {
+        return unauthorized();
+    }
+
+    ws.protocols([PROTOCOL])
+        .max_message_size(state.max_request_size)
+        .on_upgrade(move |socket| session(socket, state, auth_config, authenticated))
+}
+
+async fn session(mut socket: WebSocket, state: Arc<AppState>, auth_config: Arc<AuthConfig>, mut authenticated: bool) {
+    let deadline = Instant::now() + AUTH_TIMEOUT;
+    let mut rejected = 0;
+
+    loop {
+        let message = if authenticated {
+            socket.recv().await
+        } else {
+            match timeout_at(deadline, socket.recv()).await {
+                Ok(message) => message,
+                Err(_) => return close_policy(socket, "Authentication timed out").await,
+            }
+        };
+        let Some(message) = message else { break };
+
+        let request = match message {
+            Ok(Message::Text(text)) => serde_json::from_str::<WsRequest>(&text)
+                .map_err(|e| error_detail(format!("Invalid message: {}", e), "invalid_message")),
+            Ok(Message::Binary(_)) => Err(error_detail("Binary messages are not supported".to_string(), "invalid_message")),
+            Ok(Message::Close(_)) => break,
+            // Pings are answered by axum
+            Ok(_) => continue,
+            Err(e) => {
+                debug!("WebSocket connection closed: {}", e);
+                return;
+            }
+        };
+
+        let request = match request {
+            Ok(request) => request,
+            Err(error) => {
+                if !authenticated {
+                    rejected += 1;
+                    if rejected >= MAX_UNAUTHENTICATED_ERRORS {
+                        return close_policy(socket, "Authentication required").await;
+                    }
+                }
+                if !send(&mut socket, &WsResponse::Error { id: None, error }).await {
+                    return;
+                }
+                continue;
+            }
+        };
+
+        let response = match request {
+            WsRequest::Auth { token } => {
+                authenticated = authenticated || auth_config.authorize_token(Some(&token));
+                if !authenticated {
+                    return close_policy(socket, "Invalid API key").await;
+                }
+                WsResponse::Authenticated
+            }
+            _ if !authenticated => return close_policy(socket, "Authentication required").await,
+            WsRequest::Embed { id, request } => {
+                let format = request.encoding_format.unwrap_or_default();
+                match state.embed(request.input.into_texts(), request.model, format).await {
+                    Ok((response, _)) => WsResponse::Embeddings { id, response },
+                    Err((_, error)) => WsResponse::Error { id, error: error.0.error },
+                }
+            }
+            WsRequest::Tokenize { id, input } => tokenize(&state, id, input.into_texts()).await,
+        };
+
+        if !send(&mut socket, &response).await {
+            return;
+        }
+    }
+
+    debug!("WebSocket session ended");
+}
+
+async fn tokenize(state: &AppState, id: Option<serde_json::Value>, texts: Vec<String>) -> WsResponse {
+    if let Err(e) = state.validate_texts(&texts) {
+        return WsResponse::Error { id, error: error_detail(e.message, e.code) };
+    }
+
+    match state.tokenize(texts).await {
+        Ok(data) => {
+            let total_tokens: usize = data.iter().map(|t| t.ids.len()).sum();
+            WsResponse::Tokens {
+                id,
+                data,
+                usage: Usage {
+                    prompt_tokens: total_tokens,
+                    total_tokens,
+                },
+            }
+        }
+        Err(e) => WsResponse::Error {
+            id,
+            error: ErrorDetail {
+                message: format!("Tokenization failed: {}", e),
+                error_type: "server_error".to_string(),
+                code: None,
+            },
+        },
+    }
+}
+
+/// Send one response message. Returns `false` once the client has gone away.
+async fn send(socket: &mut WebSocket, response: &WsResponse) -> bool {
+    let text = match serde_json::to_string(response) {
+        Ok(text) => text,
+        Err(e) => {
+            error!("Failed to serialize WebSocket response: {}", e);
+            return true;
+        }
+    };
+
+    socket.send(Message::Text(text.into())).await.is_ok()
+}
+
+/// Close the connection with a policy violation, used for failed authentication
+async fn close_policy(mut socket: WebSocket, reason: &'static str) {
+    let frame = CloseFrame {
+        code: close_code::POLICY,
+        reason: reason.into(),
+    };
+    let _ = socket.send(Message::Close(Some(frame))).await;
+}
+
+fn error_detail(message: String, code: &str) -> ErrorDetail {
+    ErrorDetail {
+        message,
+        error_type: "invalid_request_error".to_string(),
+        code: Some(code.to_string()),
+    }
+}
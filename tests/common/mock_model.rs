@@ -1,4 +1,5 @@
 use embedding_service::handlers::EmbeddingModel;
+use embedding_service::models::{TokenEmbeddings, TokenizedText};
 use model2vec_rs::model::EncodeResult;
 
 pub struct MockModel;
@@ -38,4 +39,60 @@ impl EmbeddingModel for MockModel {
             token_counts,
         }
     }
-}
\ No newline at end of file
+
+    fn tokenize(&self, texts: &[String]) -> anyhow::Result<Vec<TokenizedText>> {
+        // Mock tokenization - whitespace-separated words, matching the mock token counts
+        Ok(texts
+            .iter()
+            .map(|text| {
+                let mut tokenized = TokenizedText {
+                    ids: Vec::new(),
+                    tokens: Vec::new(),
+                    offsets: Vec::new(),
+                };
+                for word in text.split_whitespace() {
+                    let start = word.as_ptr() as usize - text.as_ptr() as usize;
+                    tokenized.ids.push(word.chars().map(|c| c as u32).sum::<u32>() % 30000);
+                    tokenized.tokens.push(word.to_lowercase());
+                    tokenized.offsets.push((start, start + word.len()));
+                }
+                tokenized
+            })
+            .collect())
+    }
+
+    fn token_embeddings(&self, texts: &[String]) -> anyhow::Result<Vec<TokenEmbeddings>> {
+        // Mock token vectors - nearly one-hot by token id, so equal words match and others barely do
+        Ok(self
+            .tokenize(texts)?
+            .into_iter()
+            .map(|tokenized| TokenEmbeddings {
+                embeddings: tokenized
+                    .ids
+                    .iter()
+                    .map(|&id| {
+                        let mut vector = vec![0.01; 384];
+                        vector[id as usize % 384] = 1.0;
+                        vector
+                    })
+                    .collect(),
+                ids: tokenized.ids,
+                tokens: tokenized.tokens,
+                offsets: tokenized.offsets,
+            })
+            .collect())
+    }
+}
+
+/// A model with pooled embeddings only, like a model2vec model without its token vectors
+pub struct PooledOnlyModel;
+
+impl EmbeddingModel for PooledOnlyModel {
+    fn encode_with_stats(&self, texts: &[String]) -> EncodeResult {
+        MockModel.encode_with_stats(texts)
+    }
+
+    fn tokenize(&self, texts: &[String]) -> anyhow::Result<Vec<TokenizedText>> {
+        MockModel.tokenize(texts)
+    }
+}
@@ -12,6 +12,11 @@ async fn test_e2e_embedding_request() {
     let config = config::Config {
         host: "127.0.0.1".to_string(),
         port: 8080, // Use port 0 to let OS assign a random free port
+        listen: vec![],
+        grpc_port: None,
+        tls_cert: None,
+        tls_key: None,
+        tls_client_ca: None,
         model_path: "minishlab/potion-base-8M".to_string(),
         auth_key: None,
         cors_origins: None,
@@ -19,7 +24,15 @@ async fn test_e2e_embedding_request() {
         max_batch_size: 100,
         max_input_length: 8192,
         max_request_size_mb: 8,
+        compression_min_size: 1024,
         normalize_embeddings: false,
+        quantization_ranges: None,
+        calibration_corpus: None,
+        idf_table: None,
+        routes: None,
+        data_dir: None,
+        snapshot_interval_secs: 300,
+        snapshot_keep: 3,
     };
 
     // Create the app
@@ -63,11 +76,19 @@ async fn create_test_app(config: config::Config) -> axum::Router {
         .unwrap_or_else(|| "model2vec-unknown".to_string());
 
     // Create shared state
+    let model: std::sync::Arc<dyn handlers::EmbeddingModel> = std::sync::Arc::new(model);
     let state = std::sync::Arc::new(handlers::AppState { 
-        model: std::sync::Arc::new(model), 
+        model: std::sync::Arc::clone(&model), 
         model_name,
         max_batch_size: config.max_batch_size,
         max_input_length: config.max_input_length,
+        max_request_size: config.max_request_size_mb * 1024 * 1024,
+        calibration: None,
+        idf: Default::default(),
+        labels: Default::default(),
+        classifiers: Default::default(),
+        router: Default::default(),
+        collections: embedding_service::collections::Collections::new(Some(model)),
     });
 
     // Create auth config
@@ -116,6 +137,7 @@ async fn test_server_endpoint(addr: SocketAddr) -> Result<(), Box<dyn std::error
     let request = EmbeddingRequest {
         input: EmbeddingInput::String("Hello world".to_string()),
         model: None,
+        encoding_format: None,
     };
 
     let embedding_response = timeout(
@@ -142,6 +164,11 @@ async fn test_e2e_with_auth() {
     let config = config::Config {
         host: "127.0.0.1".to_string(),
         port: 8080,
+        listen: vec![],
+        grpc_port: None,
+        tls_cert: None,
+        tls_key: None,
+        tls_client_ca: None,
         model_path: "minishlab/potion-base-8M".to_string(),
         auth_key: Some("test-secret-key".to_string()),
         cors_origins: None,
@@ -149,7 +176,15 @@ async fn test_e2e_with_auth() {
         max_batch_size: 100,
         max_input_length: 8192,
         max_request_size_mb: 8,
+        compression_min_size: 1024,
         normalize_embeddings: false,
+        quantization_ranges: None,
+        calibration_corpus: None,
+        idf_table: None,
+        routes: None,
+        data_dir: None,
+        snapshot_interval_secs: 300,
+        snapshot_keep: 3,
     };
 
     let app = create_test_app(config).await;
@@ -177,6 +212,7 @@ async fn test_auth_endpoint(addr: SocketAddr) -> Result<(), Box<dyn std::error::
     let request = EmbeddingRequest {
         input: EmbeddingInput::String("Hello world".to_string()),
         model: None,
+        encoding_format: None,
     };
 
     // Test without auth key (should fail)
@@ -0,0 +1,132 @@
+mod common;
+
+use common::{create_test_config, create_test_state};
+use embedding_service::grpc::{
+    self,
+    pb::{embedding_service_client::EmbeddingServiceClient, EmbedRequest, ListModelsRequest, TokenizeRequest},
+};
+use tokio_stream::{wrappers::TcpListenerStream, StreamExt};
+use tonic::{transport::Channel, Code, Request};
+
+/// Start a gRPC server on a random port and connect a client to it
+async fn start_server(
+    max_batch_size: usize,
+    auth_key: Option<String>,
+) -> EmbeddingServiceClient<Channel> {
+    let config = create_test_config(max_batch_size, 8192, auth_key);
+    let state = create_test_state(&config);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(grpc::service(state, &config))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    EmbeddingServiceClient::connect(format!("http://{}", addr)).await.unwrap()
+}
+
+fn embed_request(texts: &[&str]) -> EmbedRequest {
+    EmbedRequest {
+        input: texts.iter().map(|t| t.to_string()).collect(),
+        model: None,
+    }
+}
+
+#[tokio::test]
+async fn test_grpc_embed() {
+    let mut client = start_server(100, None).await;
+
+    let response = client
+        .embed(embed_request(&["First text", "Second text"]))
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(response.model, "test-model");
+    assert_eq!(response.dimensions, 384);
+    assert_eq!(response.data.len(), 2);
+    assert_eq!(response.data[1].index, 1);
+    assert_eq!(response.data[0].vector.len(), 384 * 4);
+    assert_eq!(response.data[0].token_count, 2);
+    assert_eq!(response.usage.unwrap().total_tokens, 4);
+
+    // Packed vectors are little-endian float32 values
+    let first = f32::from_le_bytes(response.data[0].vector[0..4].try_into().unwrap());
+    assert!(first.is_finite());
+}
+
+#[tokio::test]
+async fn test_grpc_embed_shares_limits() {
+    let mut client = start_server(2, None).await;
+
+    let status = client
+        .embed(embed_request(&["a", "b", "c"]))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert!(status.message().contains("Batch size exceeds maximum of 2"));
+
+    let status = client.embed(embed_request(&[])).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_grpc_embed_stream_batches() {
+    let mut client = start_server(2, None).await;
+
+    let mut stream = client
+        .embed_stream(embed_request(&["one", "two", "three", "four", "five"]))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let mut indices = Vec::new();
+    let mut batches = 0;
+    while let Some(batch) = stream.next().await {
+        let batch = batch.unwrap();
+        assert!(batch.data.len() <= 2);
+        indices.extend(batch.data.iter().map(|d| d.index));
+        batches += 1;
+    }
+
+    assert_eq!(batches, 3);
+    assert_eq!(indices, vec![0, 1, 2, 3, 4]);
+}
+
+#[tokio::test]
+async fn test_grpc_tokenize_and_list_models() {
+    let mut client = start_server(100, None).await;
+
+    let response = client
+        .tokenize(TokenizeRequest {
+            input: vec!["Hello big world".to_string()],
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.data[0].tokens, vec!["hello", "big", "world"]);
+    assert_eq!(response.data[0].ids.len(), 3);
+    assert_eq!(response.usage.unwrap().total_tokens, 3);
+
+    let models = client.list_models(ListModelsRequest {}).await.unwrap().into_inner();
+    assert_eq!(models.data.len(), 1);
+    assert_eq!(models.data[0].id, "test-model");
+}
+
+#[tokio::test]
+async fn test_grpc_auth() {
+    let mut client = start_server(100, Some("test-key".to_string())).await;
+
+    let status = client.list_models(ListModelsRequest {}).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    let mut request = Request::new(embed_request(&["Hello world"]));
+    request
+        .metadata_mut()
+        .insert("authorization", "Bearer test-key".parse().unwrap());
+    let response = client.embed(request).await.unwrap().into_inner();
+    assert_eq!(response.data.len(), 1);
+}
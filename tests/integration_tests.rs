@@ -1,7 +1,7 @@
 mod common;
 
-use axum_test::TestServer;
-use embedding_service::models::{EmbeddingRequest, EmbeddingInput};
+use axum_test::{TestServer, WsMessage};
+use embedding_service::models::{EmbeddingRequest, EmbeddingInput, EmbeddingResponse, EmbeddingVector};
 use common::{create_test_server, create_test_server_with_config};
 use serial_test::serial;
 use axum_test::http::StatusCode;
@@ -45,6 +45,7 @@ async fn test_batch_embedding_no_auth() {
             "Second text".to_string(),
         ]),
         model: Some("test-model".to_string()),
+        encoding_format: None,
     };
     
     let response = server.post("/v1/embeddings").json(&request).await;
@@ -68,6 +69,7 @@ async fn test_embedding_with_valid_auth() {
     let request = EmbeddingRequest {
         input: EmbeddingInput::String("Hello world".to_string()),
         model: Some("test-model".to_string()),
+        encoding_format: None,
     };
     
     let response = server
@@ -91,6 +93,7 @@ async fn test_embedding_with_invalid_auth() {
     let request = EmbeddingRequest {
         input: EmbeddingInput::String("Hello world".to_string()),
         model: Some("test-model".to_string()),
+        encoding_format: None,
     };
     
     let response = server
@@ -114,6 +117,7 @@ async fn test_embedding_missing_auth() {
     let request = EmbeddingRequest {
         input: EmbeddingInput::String("Hello world".to_string()),
         model: Some("test-model".to_string()),
+        encoding_format: None,
     };
     
     let response = server.post("/v1/embeddings").json(&request).await;
@@ -133,6 +137,7 @@ async fn test_empty_input_array() {
     let request = EmbeddingRequest {
         input: EmbeddingInput::StringArray(vec![]),
         model: Some("test-model".to_string()),
+        encoding_format: None,
     };
     
     let response = server.post("/v1/embeddings").json(&request).await;
@@ -161,6 +166,7 @@ async fn test_oversized_batch() {
             "text3".to_string(), // Exceeds batch size of 2
         ]),
         model: Some("test-model".to_string()),
+        encoding_format: None,
     };
     
     let response = server.post("/v1/embeddings").json(&request).await;
@@ -186,6 +192,7 @@ async fn test_oversized_input_length() {
     let request = EmbeddingRequest {
         input: EmbeddingInput::String(long_text),
         model: Some("test-model".to_string()),
+        encoding_format: None,
     };
     
     let response = server.post("/v1/embeddings").json(&request).await;
@@ -236,6 +243,7 @@ async fn test_unicode_text_handling() {
     let request = EmbeddingRequest {
         input: EmbeddingInput::String("Hello 世界 🌍".to_string()),
         model: Some("test-model".to_string()),
+        encoding_format: None,
     };
     
     let response = server.post("/v1/embeddings").json(&request).await;
@@ -255,6 +263,7 @@ async fn test_empty_string_input() {
     let request = EmbeddingRequest {
         input: EmbeddingInput::String("".to_string()),
         model: Some("test-model".to_string()),
+        encoding_format: None,
     };
     
     let response = server.post("/v1/embeddings").json(&request).await;
@@ -275,6 +284,7 @@ async fn test_default_model_when_not_specified() {
     let request = EmbeddingRequest {
         input: EmbeddingInput::String("Hello world".to_string()),
         model: None, // No model specified
+        encoding_format: None,
     };
     
     let response = server.post("/v1/embeddings").json(&request).await;
@@ -318,6 +328,7 @@ async fn test_large_valid_batch() {
     let request = EmbeddingRequest {
         input: EmbeddingInput::StringArray(texts),
         model: Some("test-model".to_string()),
+        encoding_format: None,
     };
     
     let response = server.post("/v1/embeddings").json(&request).await;
@@ -328,4 +339,2279 @@ async fn test_large_valid_batch() {
     assert_eq!(json["data"].as_array().unwrap().len(), 50);
     assert_eq!(json["usage"]["prompt_tokens"], 200); // 4 words per text ("Test text number N") * 50 texts
     assert_eq!(json["usage"]["total_tokens"], 200);
-}
\ No newline at end of file
+}
+#[tokio::test]
+#[serial]
+async fn test_quantized_embedding_formats() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/embeddings")
+        .json(&serde_json::json!({
+            "input": ["Hello world", "Second text"],
+            "encoding_format": "int8"
+        }))
+        .await;
+
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    let embedding = json["data"][0]["embedding"].as_array().unwrap();
+    assert_eq!(embedding.len(), 384);
+    assert!(embedding.iter().all(|v| (-128..=127).contains(&v.as_i64().unwrap())));
+
+    // Packed bits: 384 dimensions -> 48 bytes
+    let response = server
+        .post("/v1/embeddings")
+        .json(&serde_json::json!({
+            "input": "Hello world",
+            "embedding_type": "ubinary"
+        }))
+        .await;
+
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    let embedding = json["data"][0]["embedding"].as_array().unwrap();
+    assert_eq!(embedding.len(), 48);
+    // Mock embeddings are all positive, so every bit is set
+    assert!(embedding.iter().all(|v| v.as_u64() == Some(255)));
+}
+
+#[tokio::test]
+#[serial]
+async fn test_base64_embedding_format() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/embeddings")
+        .json(&serde_json::json!({
+            "input": "Hello world",
+            "encoding_format": "base64"
+        }))
+        .await;
+
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    let encoded = json["data"][0]["embedding"].as_str().unwrap();
+    // 384 float32 values -> 1536 bytes -> 2048 base64 characters
+    assert_eq!(encoded.len(), 2048);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_float16_embedding_format() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/embeddings")
+        .json(&serde_json::json!({
+            "input": "Hello world",
+            "encoding_format": "float16"
+        }))
+        .await;
+
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    let encoded = json["data"][0]["embedding"].as_str().unwrap();
+    // 384 float16 values -> 768 bytes -> 1024 base64 characters
+    assert_eq!(encoded.len(), 1024);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_unknown_encoding_format() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/embeddings")
+        .json(&serde_json::json!({
+            "input": "Hello world",
+            "encoding_format": "int4"
+        }))
+        .await;
+
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_msgpack_request_and_response() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let request = EmbeddingRequest {
+        input: EmbeddingInput::StringArray(vec!["First text".to_string(), "Second text".to_string()]),
+        model: None,
+        encoding_format: None,
+    };
+
+    let response = server
+        .post("/v1/embeddings")
+        .add_header("Accept", "application/msgpack")
+        .content_type("application/msgpack")
+        .bytes(rmp_serde::to_vec_named(&request).unwrap().into())
+        .await;
+
+    response.assert_status_ok();
+    assert_eq!(response.header("content-type"), "application/msgpack");
+
+    let parsed: EmbeddingResponse = rmp_serde::from_slice(response.as_bytes()).unwrap();
+    assert_eq!(parsed.object, "list");
+    assert_eq!(parsed.data.len(), 2);
+    assert_eq!(parsed.data[1].index, 1);
+    assert_eq!(parsed.model, "test-model");
+    assert_eq!(parsed.usage.total_tokens, 4);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_cbor_response() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/embeddings")
+        .add_header("Accept", "application/cbor")
+        .json(&serde_json::json!({ "input": "Hello world" }))
+        .await;
+
+    response.assert_status_ok();
+    assert_eq!(response.header("content-type"), "application/cbor");
+
+    let parsed: EmbeddingResponse = ciborium::from_reader(response.as_bytes().as_ref()).unwrap();
+    assert_eq!(parsed.data.len(), 1);
+    match &parsed.data[0].embedding {
+        EmbeddingVector::Float(values) => assert_eq!(values.len(), 384),
+        other => panic!("Unexpected embedding {:?}", other),
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_tensor_response() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/embeddings")
+        .add_header("Accept", "application/octet-stream")
+        .json(&serde_json::json!({
+            "input": ["First text", "Second text", "Third text"],
+            "encoding_format": "float16"
+        }))
+        .await;
+
+    response.assert_status_ok();
+    assert_eq!(response.header("content-type"), "application/octet-stream");
+    assert_eq!(response.header("x-embedding-model"), "test-model");
+    assert_eq!(response.header("x-total-tokens"), "6");
+
+    let body = response.as_bytes();
+    assert_eq!(u32::from_le_bytes(body[0..4].try_into().unwrap()), 3);
+    assert_eq!(u32::from_le_bytes(body[4..8].try_into().unwrap()), 384);
+    assert_eq!(u32::from_le_bytes(body[8..12].try_into().unwrap()), 1); // float16
+    assert_eq!(body.len(), 12 + 3 * 384 * 2);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_invalid_msgpack_body() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/embeddings")
+        .content_type("application/msgpack")
+        .bytes(vec![0xc1, 0x00].into())
+        .await;
+
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["error"]["code"], "invalid_body");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_npy_response_via_query_parameter() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/embeddings?format=npy")
+        .json(&serde_json::json!({ "input": ["First text", "Second text"] }))
+        .await;
+
+    response.assert_status_ok();
+    assert_eq!(response.header("content-type"), "application/x-npy");
+
+    let body = response.as_bytes();
+    assert_eq!(&body[0..6], b"\x93NUMPY");
+    let header_len = u16::from_le_bytes([body[8], body[9]]) as usize;
+    let header = std::str::from_utf8(&body[10..10 + header_len]).unwrap();
+    assert!(header.contains("'shape': (2, 384)"));
+    assert_eq!(body.len(), 10 + header_len + 2 * 384 * 4);
+
+    // The parameter is percent-decoded like any other query value
+    let response = server
+        .post("/v1/embeddings?model=a%26b&format=%6Epy")
+        .json(&serde_json::json!({ "input": "Hello" }))
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.header("content-type"), "application/x-npy");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_arrow_response_via_accept() {
+    use arrow_array::Array;
+
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/embeddings")
+        .add_header("Accept", "application/vnd.apache.arrow.stream")
+        .json(&serde_json::json!({ "input": ["First text", "Second longer text"] }))
+        .await;
+
+    response.assert_status_ok();
+    assert_eq!(response.header("content-type"), "application/vnd.apache.arrow.stream");
+
+    let mut reader = arrow_ipc::reader::StreamReader::try_new(response.as_bytes().as_ref(), None).unwrap();
+    let batch = reader.next().unwrap().unwrap();
+    assert_eq!(batch.num_rows(), 2);
+
+    let token_counts = batch
+        .column_by_name("token_count")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<arrow_array::UInt32Array>()
+        .unwrap();
+    assert_eq!(token_counts.values(), &[2, 3]);
+    assert_eq!(token_counts.len(), 2);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_unsupported_format_query_parameter() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/embeddings?format=parquet")
+        .json(&serde_json::json!({ "input": "Hello world" }))
+        .await;
+
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["error"]["code"], "unsupported_format");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_matrix_formats_outside_embeddings() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/similarity?format=npy")
+        .json(&serde_json::json!({ "source": "Hello", "targets": ["Hello world"] }))
+        .await;
+    response.assert_status(StatusCode::NOT_ACCEPTABLE);
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["error"]["code"], "unsupported_format");
+
+    let response = server
+        .post("/v1/embeddings/stream")
+        .add_header("Accept", "application/vnd.apache.arrow.stream")
+        .text("{\"id\": 1, \"text\": \"Hello\"}\n")
+        .await;
+    response.assert_status(StatusCode::NOT_ACCEPTABLE);
+}
+
+fn parse_ndjson(body: &str) -> Vec<serde_json::Value> {
+    body.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+#[tokio::test]
+#[serial]
+async fn test_stream_embeddings_in_order() {
+    let server = TestServer::new(create_test_server_with_config(2, 8192, None)).unwrap();
+
+    let body: String = (0..5)
+        .map(|i| format!("{{\"id\": \"doc-{}\", \"text\": \"text number {}\", \"metadata\": {{\"n\": {}}}}}\n", i, i, i))
+        .collect();
+
+    let response = server
+        .post("/v1/embeddings/stream")
+        .content_type("application/x-ndjson")
+        .text(body)
+        .await;
+
+    response.assert_status_ok();
+    assert_eq!(response.header("content-type"), "application/x-ndjson");
+
+    let results = parse_ndjson(&response.text());
+    assert_eq!(results.len(), 5);
+    for (i, result) in results.iter().enumerate() {
+        assert_eq!(result["id"], format!("doc-{}", i));
+        assert_eq!(result["index"], i);
+        assert_eq!(result["metadata"]["n"], i);
+        assert_eq!(result["token_count"], 3);
+        assert_eq!(result["embedding"].as_array().unwrap().len(), 384);
+        assert!(result.get("error").is_none());
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_stream_embeddings_record_errors() {
+    let server = TestServer::new(create_test_server_with_config(100, 10, None)).unwrap();
+
+    let body = concat!(
+        "{\"id\": 1, \"text\": \"short\"}\n",
+        "not json\n",
+        "\n",
+        "{\"id\": 2, \"text\": \"this text is far too long\"}\n",
+        "{\"id\": 3, \"text\": \"ok\"}",
+    );
+
+    let response = server
+        .post("/v1/embeddings/stream?encoding_format=ubinary")
+        .text(body)
+        .await;
+
+    response.assert_status_ok();
+    let results = parse_ndjson(&response.text());
+    assert_eq!(results.len(), 4);
+
+    assert_eq!(results[0]["id"], 1);
+    assert_eq!(results[0]["embedding"].as_array().unwrap().len(), 48);
+
+    assert_eq!(results[1]["index"], 1);
+    assert_eq!(results[1]["error"]["code"], "invalid_record");
+
+    assert_eq!(results[2]["id"], 2);
+    assert_eq!(results[2]["error"]["code"], "input_too_long");
+
+    assert_eq!(results[3]["id"], 3);
+    assert_eq!(results[3]["index"], 3);
+    assert!(results[3]["embedding"].is_array());
+}
+
+#[tokio::test]
+#[serial]
+async fn test_stream_embeddings_flushes_failed_records() {
+    use axum::body::{Body, Bytes};
+    use tokio_stream::StreamExt;
+    use tower::ServiceExt;
+
+    // An endless run of invalid lines with no pause: failed records must still fill a batch
+    let input = tokio_stream::iter(std::iter::repeat_with(|| Ok::<_, std::io::Error>(Bytes::from_static(b"not json\n"))));
+    let request = axum::http::Request::post("/v1/embeddings/stream")
+        .body(Body::from_stream(input))
+        .unwrap();
+    let response = create_test_server_with_config(2, 8192, None).oneshot(request).await.unwrap();
+
+    let mut output = response.into_body().into_data_stream();
+    let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), output.next())
+        .await
+        .expect("results before the end of the input")
+        .unwrap()
+        .unwrap();
+    let result: serde_json::Value = serde_json::from_slice(chunk.split(|&b| b == b'\n').next().unwrap()).unwrap();
+    assert_eq!(result["index"], 0);
+    assert_eq!(result["error"]["code"], "invalid_record");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_stream_embeddings_bypasses_request_size_limit() {
+    let mut config = common::create_test_config(100, 8192, None);
+    config.max_request_size_mb = 1;
+    let server = TestServer::new(common::create_test_app(config)).unwrap();
+
+    // ~1.5 MB of records, more than the buffered endpoints accept
+    let text = "x".repeat(500);
+    let body: String = (0..3000)
+        .map(|i| format!("{{\"id\": {}, \"text\": \"{}\"}}\n", i, text))
+        .collect();
+    assert!(body.len() > 1024 * 1024);
+
+    let response = server.post("/v1/embeddings/stream").text(body.clone()).await;
+    response.assert_status_ok();
+    let results = parse_ndjson(&response.text());
+    assert_eq!(results.len(), 3000);
+    assert_eq!(results[2999]["id"], 2999);
+
+    let response = server
+        .post("/v1/embeddings")
+        .json(&serde_json::json!({ "input": body }))
+        .await;
+    response.assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_stream_embeddings_requires_auth() {
+    let server = TestServer::new(create_test_server(true)).unwrap();
+
+    let response = server
+        .post("/v1/embeddings/stream")
+        .text("{\"id\": 1, \"text\": \"hello\"}\n")
+        .await;
+
+    response.assert_status(StatusCode::UNAUTHORIZED);
+}
+
+fn websocket_server(with_auth: bool) -> TestServer {
+    TestServer::builder()
+        .http_transport()
+        .build(create_test_server(with_auth))
+        .unwrap()
+}
+
+#[tokio::test]
+#[serial]
+async fn test_websocket_embed_and_tokenize() {
+    let server = websocket_server(false);
+    let mut socket = server.get_websocket("/v1/ws").await.into_websocket().await;
+
+    socket
+        .send_json(&serde_json::json!({ "type": "embed", "id": "q1", "input": ["hello world", "test"] }))
+        .await;
+    let response: serde_json::Value = socket.receive_json().await;
+    assert_eq!(response["type"], "embeddings");
+    assert_eq!(response["id"], "q1");
+    assert_eq!(response["data"].as_array().unwrap().len(), 2);
+    assert_eq!(response["data"][0]["embedding"].as_array().unwrap().len(), 384);
+    assert_eq!(response["model"], "test-model");
+
+    socket
+        .send_json(&serde_json::json!({ "type": "tokenize", "id": 7, "input": "Hello world" }))
+        .await;
+    let response: serde_json::Value = socket.receive_json().await;
+    assert_eq!(response["type"], "tokens");
+    assert_eq!(response["id"], 7);
+    assert_eq!(response["data"][0]["tokens"], serde_json::json!(["hello", "world"]));
+    assert_eq!(response["usage"]["total_tokens"], 2);
+
+    socket
+        .send_json(&serde_json::json!({ "type": "embed", "id": "q2", "input": "hi", "encoding_format": "binary" }))
+        .await;
+    let response: serde_json::Value = socket.receive_json().await;
+    assert_eq!(response["id"], "q2");
+    assert_eq!(response["data"][0]["embedding"].as_array().unwrap().len(), 48);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_websocket_request_errors() {
+    let server = TestServer::builder()
+        .http_transport()
+        .build(create_test_server_with_config(2, 10, None))
+        .unwrap();
+    let mut socket = server.get_websocket("/v1/ws").await.into_websocket().await;
+
+    socket
+        .send_json(&serde_json::json!({ "type": "embed", "id": 1, "input": ["a", "b", "c"] }))
+        .await;
+    let response: serde_json::Value = socket.receive_json().await;
+    assert_eq!(response["type"], "error");
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["error"]["code"], "batch_too_large");
+
+    socket
+        .send_json(&serde_json::json!({ "type": "tokenize", "id": 2, "input": "far too long for the limit" }))
+        .await;
+    let response: serde_json::Value = socket.receive_json().await;
+    assert_eq!(response["id"], 2);
+    assert_eq!(response["error"]["code"], "input_too_long");
+
+    socket.send_text("not json").await;
+    let response: serde_json::Value = socket.receive_json().await;
+    assert_eq!(response["type"], "error");
+    assert!(response["id"].is_null());
+    assert_eq!(response["error"]["code"], "invalid_message");
+
+    // The connection stays usable after errors
+    socket
+        .send_json(&serde_json::json!({ "type": "embed", "id": 3, "input": "ok" }))
+        .await;
+    let response: serde_json::Value = socket.receive_json().await;
+    assert_eq!(response["type"], "embeddings");
+    assert_eq!(response["id"], 3);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_websocket_auth_first_message() {
+    let server = websocket_server(true);
+
+    let mut socket = server.get_websocket("/v1/ws").await.into_websocket().await;
+    socket
+        .send_json(&serde_json::json!({ "type": "auth", "token": "test-key" }))
+        .await;
+    let response: serde_json::Value = socket.receive_json().await;
+    assert_eq!(response["type"], "authenticated");
+
+    socket
+        .send_json(&serde_json::json!({ "type": "embed", "id": 1, "input": "hello" }))
+        .await;
+    let response: serde_json::Value = socket.receive_json().await;
+    assert_eq!(response["type"], "embeddings");
+
+    // Requests before authentication close the connection
+    let mut socket = server.get_websocket("/v1/ws").await.into_websocket().await;
+    socket
+        .send_json(&serde_json::json!({ "type": "embed", "id": 1, "input": "hello" }))
+        .await;
+    match socket.receive_message().await {
+        WsMessage::Close(Some(frame)) => assert_eq!(u16::from(frame.code), 1008),
+        other => panic!("Expected close frame, got {:?}", other),
+    }
+
+    let mut socket = server.get_websocket("/v1/ws").await.into_websocket().await;
+    socket
+        .send_json(&serde_json::json!({ "type": "auth", "token": "wrong-key" }))
+        .await;
+    assert!(matches!(socket.receive_message().await, WsMessage::Close(Some(_))));
+
+    // Malformed messages are answered, but only a few times before authentication
+    let mut socket = server.get_websocket("/v1/ws").await.into_websocket().await;
+    for _ in 0..2 {
+        socket.send_text("not json").await;
+        let response: serde_json::Value = socket.receive_json().await;
+        assert_eq!(response["error"]["code"], "invalid_message");
+    }
+    socket.send_text("not json").await;
+    match socket.receive_message().await {
+        WsMessage::Close(Some(frame)) => assert_eq!(u16::from(frame.code), 1008),
+        other => panic!("Expected close frame, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_websocket_auth_on_upgrade() {
+    let server = websocket_server(true);
+
+    // Key in the subprotocol list, as browsers send it
+    let response = server
+        .get_websocket("/v1/ws")
+        .add_header("Sec-WebSocket-Protocol", "embeddings, bearer.test-key")
+        .await;
+    response.assert_status(StatusCode::SWITCHING_PROTOCOLS);
+    assert_eq!(response.header("sec-websocket-protocol"), "embeddings");
+    let mut socket = response.into_websocket().await;
+    socket
+        .send_json(&serde_json::json!({ "type": "embed", "id": 1, "input": "hello" }))
+        .await;
+    let response: serde_json::Value = socket.receive_json().await;
+    assert_eq!(response["type"], "embeddings");
+
+    let response = server
+        .get_websocket("/v1/ws")
+        .add_header("Authorization", "Bearer test-key")
+        .await;
+    response.assert_status(StatusCode::SWITCHING_PROTOCOLS);
+
+    let response = server
+        .get_websocket("/v1/ws")
+        .add_header("Sec-WebSocket-Protocol", "embeddings, bearer.wrong-key")
+        .await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+#[serial]
+async fn test_serve_unix_socket() {
+    use embedding_service::listen::{self, ListenSpec};
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let path = std::env::temp_dir().join(format!("embedding-service-{}.sock", std::process::id()));
+    let spec: ListenSpec = format!("unix://{}?mode=600", path.display()).parse().unwrap();
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(listen::serve(spec, create_test_server(false), None, async {
+        let _ = shutdown_rx.await;
+    }));
+
+    let mut stream = loop {
+        match tokio::net::UnixStream::connect(&path).await {
+            Ok(stream) => break stream,
+            Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+        }
+    };
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+    stream
+        .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.ends_with("OK"));
+
+    // The socket file is removed on graceful shutdown
+    shutdown_tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+    assert!(!path.exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+#[serial]
+async fn test_unix_socket_without_auth() {
+    use embedding_service::listen::{self, ListenSpec};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn embed(path: &std::path::Path) -> String {
+        let mut stream = loop {
+            match tokio::net::UnixStream::connect(path).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+        let body = r#"{"input": "hello"}"#;
+        let request = format!(
+            "POST /v1/embeddings HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    // The API key applies on unix sockets unless the listener opts out
+    for (options, status) in [("mode=600", "HTTP/1.1 401"), ("mode=600&auth=none", "HTTP/1.1 200")] {
+        let path = std::env::temp_dir().join(format!("embedding-service-auth-{}.sock", std::process::id()));
+        let spec: ListenSpec = format!("unix://{}?{}", path.display(), options).parse().unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(listen::serve(spec, create_test_server(true), None, async {
+            let _ = shutdown_rx.await;
+        }));
+
+        assert!(embed(&path).await.starts_with(status));
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_response_compression() {
+    use std::io::Read;
+
+    let server = TestServer::new(create_test_server(false)).unwrap();
+    let request = serde_json::json!({ "input": ["First text", "Second text"] });
+
+    let response = server
+        .post("/v1/embeddings")
+        .add_header("Accept-Encoding", "gzip")
+        .json(&request)
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.header("content-encoding"), "gzip");
+
+    let mut json = String::new();
+    flate2::read::GzDecoder::new(response.as_bytes().as_ref())
+        .read_to_string(&mut json)
+        .unwrap();
+    let embedding_response: EmbeddingResponse = serde_json::from_str(&json).unwrap();
+    assert_eq!(embedding_response.data.len(), 2);
+
+    let response = server
+        .post("/v1/embeddings")
+        .add_header("Accept-Encoding", "zstd")
+        .json(&request)
+        .await;
+    assert_eq!(response.header("content-encoding"), "zstd");
+    let json = zstd::decode_all(response.as_bytes().as_ref()).unwrap();
+    let embedding_response: EmbeddingResponse = serde_json::from_slice(&json).unwrap();
+    assert_eq!(embedding_response.data.len(), 2);
+
+    // Responses below the size threshold are sent as-is
+    let response = server
+        .get("/health")
+        .add_header("Accept-Encoding", "gzip")
+        .await;
+    assert!(response.maybe_header("content-encoding").is_none());
+    assert_eq!(response.text(), "OK");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_request_decompression() {
+    use std::io::Write;
+
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder
+        .write_all(br#"{"input": ["First text", "Second text"]}"#)
+        .unwrap();
+    let response = server
+        .post("/v1/embeddings")
+        .add_header("Content-Type", "application/json")
+        .add_header("Content-Encoding", "gzip")
+        .bytes(encoder.finish().unwrap().into())
+        .await;
+    response.assert_status_ok();
+    let embedding_response: EmbeddingResponse = response.json();
+    assert_eq!(embedding_response.data.len(), 2);
+
+    let body = zstd::encode_all(&br#"{"input": "hello"}"#[..], 3).unwrap();
+    let response = server
+        .post("/v1/embeddings")
+        .add_header("Content-Type", "application/json")
+        .add_header("Content-Encoding", "zstd")
+        .bytes(body.into())
+        .await;
+    response.assert_status_ok();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_request_size_limit_applies_after_decompression() {
+    let mut config = common::create_test_config(100, 8192, None);
+    config.max_request_size_mb = 1;
+    let server = TestServer::new(common::create_test_app(config)).unwrap();
+
+    // A few kilobytes on the wire that expand to 4 MB
+    let json = format!(r#"{{"input": "{}"}}"#, " ".repeat(4 * 1024 * 1024));
+    let body = zstd::encode_all(json.as_bytes(), 3).unwrap();
+    assert!(body.len() < 64 * 1024);
+
+    let response = server
+        .post("/v1/embeddings")
+        .add_header("Content-Type", "application/json")
+        .add_header("Content-Encoding", "zstd")
+        .bytes(body.into())
+        .await;
+    response.assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_similarity_against_targets() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/similarity")
+        .json(&serde_json::json!({
+            "source": "hello world",
+            "targets": ["hello world", "something else", "third"]
+        }))
+        .await;
+
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["object"], "similarity");
+    assert_eq!(body["metric"], "cosine");
+    assert_eq!(body["model"], "test-model");
+    assert_eq!(body["usage"]["total_tokens"], 7);
+
+    let scores = body["scores"].as_array().unwrap();
+    assert_eq!(scores.len(), 1);
+    assert_eq!(scores[0].as_array().unwrap().len(), 3);
+    assert!((scores[0][0].as_f64().unwrap() - 1.0).abs() < 1e-5);
+    assert!(scores[0][1].as_f64().unwrap() < 1.0);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_similarity_pairwise_metrics() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/similarity")
+        .json(&serde_json::json!({ "source": ["a", "b", "c"], "metric": "euclidean" }))
+        .await;
+
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    let scores = body["scores"].as_array().unwrap();
+    assert_eq!(scores.len(), 3);
+    for (i, row) in scores.iter().enumerate() {
+        assert_eq!(row.as_array().unwrap().len(), 3);
+        assert_eq!(row[i], 0.0);
+        for (j, column) in scores.iter().enumerate() {
+            assert_eq!(row[j], column[i]);
+        }
+    }
+    assert!(scores[0][1].as_f64().unwrap() > 0.0);
+
+    let response = server
+        .post("/v1/similarity")
+        .json(&serde_json::json!({ "source": "a", "targets": "a", "metric": "dot" }))
+        .await;
+    let body: serde_json::Value = response.json();
+    assert!(body["scores"][0][0].as_f64().unwrap() > 1.0);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_similarity_shares_embedding_limits() {
+    let server = TestServer::new(create_test_server_with_config(3, 8192, None)).unwrap();
+
+    // Sources and targets are embedded in one batch
+    let response = server
+        .post("/v1/similarity")
+        .json(&serde_json::json!({ "source": ["a", "b"], "targets": ["c", "d"] }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let error: serde_json::Value = response.json();
+    assert_eq!(error["error"]["code"], "batch_too_large");
+
+    let response = server
+        .post("/v1/similarity")
+        .json(&serde_json::json!({ "source": "a", "metric": "manhattan" }))
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_rerank() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/rerank")
+        .json(&serde_json::json!({
+            "query": "reset password",
+            "documents": ["billing", "reset password", {"text": "account settings"}],
+            "top_n": 2,
+            "return_documents": true
+        }))
+        .await;
+
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["model"], "test-model");
+
+    let results = body["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["index"], 1);
+    assert_eq!(results[0]["document"]["text"], "reset password");
+    assert!((results[0]["relevance_score"].as_f64().unwrap() - 1.0).abs() < 1e-5);
+    assert!(results[0]["relevance_score"].as_f64() >= results[1]["relevance_score"].as_f64());
+
+    // Documents are omitted unless requested
+    let response = server
+        .post("/v1/rerank")
+        .json(&serde_json::json!({ "query": "reset password", "documents": ["a", "b", "c"] }))
+        .await;
+    let body: serde_json::Value = response.json();
+    let results = body["results"].as_array().unwrap();
+    assert_eq!(results.len(), 3);
+    assert!(results[0].get("document").is_none());
+    let mut indices: Vec<u64> = results.iter().map(|r| r["index"].as_u64().unwrap()).collect();
+    indices.sort();
+    assert_eq!(indices, vec![0, 1, 2]);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_rerank_lexical_blend() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    // With full lexical weight the score is the fraction of query terms in the document
+    let response = server
+        .post("/v1/rerank")
+        .json(&serde_json::json!({
+            "query": "reset my password",
+            "documents": ["unrelated text", "password reset steps"],
+            "lexical_weight": 1.0
+        }))
+        .await;
+
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["results"][0]["index"], 1);
+    assert!((body["results"][0]["relevance_score"].as_f64().unwrap() - 2.0 / 3.0).abs() < 1e-5);
+    assert_eq!(body["results"][1]["relevance_score"], 0.0);
+
+    let response = server
+        .post("/v1/rerank")
+        .json(&serde_json::json!({ "query": "q", "documents": ["a"], "lexical_weight": 2.0 }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let error: serde_json::Value = response.json();
+    assert_eq!(error["error"]["code"], "invalid_lexical_weight");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_rerank_limits() {
+    let server = TestServer::new(create_test_server_with_config(2, 20, None)).unwrap();
+
+    // The query does not count towards the batch size
+    let response = server
+        .post("/v1/rerank")
+        .json(&serde_json::json!({ "query": "q", "documents": ["a", "b"] }))
+        .await;
+    response.assert_status_ok();
+
+    let response = server
+        .post("/v1/rerank")
+        .json(&serde_json::json!({ "query": "q", "documents": ["a", "b", "c"] }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    let response = server
+        .post("/v1/rerank")
+        .json(&serde_json::json!({ "query": "q".repeat(21), "documents": ["a"] }))
+        .await;
+    let error: serde_json::Value = response.json();
+    assert_eq!(error["error"]["code"], "input_too_long");
+
+    let response = server
+        .post("/v1/rerank")
+        .json(&serde_json::json!({ "query": "q", "documents": [] }))
+        .await;
+    let error: serde_json::Value = response.json();
+    assert_eq!(error["error"]["code"], "empty_input");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_sparse_embeddings() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+    // The mock tokenizer's token id is the sum of the word's characters
+    let id = |word: &str| word.chars().map(|c| c as u32).sum::<u32>();
+
+    let response = server
+        .post("/v1/embeddings/sparse")
+        .json(&serde_json::json!({ "input": ["usb cable usb", "lamp"] }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["object"], "list");
+    assert_eq!(body["data"][0]["object"], "sparse_embedding");
+    assert_eq!(body["usage"]["total_tokens"], 4);
+
+    // Without an IDF table values are term frequencies
+    let mut expected = [(id("usb"), 2.0), (id("cable"), 1.0)];
+    expected.sort_by_key(|(id, _)| *id);
+    let embedding = &body["data"][0]["embedding"];
+    assert_eq!(embedding["indices"], serde_json::json!(expected.iter().map(|(id, _)| id).collect::<Vec<_>>()));
+    assert_eq!(embedding["values"], serde_json::json!(expected.iter().map(|(_, value)| value).collect::<Vec<_>>()));
+
+    let body: serde_json::Value = server.get("/v1/admin/idf").await.json();
+    assert_eq!(body, serde_json::json!({ "loaded": false, "terms": 0 }));
+
+    let response = server
+        .post("/v1/admin/idf")
+        .json(&serde_json::json!({ "input": ["usb cable", "usb hub", "usb lamp", "desk"] }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body, serde_json::json!({ "loaded": true, "terms": 5, "documents": 4 }));
+
+    // Rare tokens now outweigh common ones
+    let response = server.post("/v1/embeddings/sparse").json(&serde_json::json!({ "input": "usb cable" })).await;
+    let body: serde_json::Value = response.json();
+    let embedding = &body["data"][0]["embedding"];
+    let value = |word: &str| {
+        let position = embedding["indices"].as_array().unwrap().iter().position(|i| i == id(word)).unwrap();
+        embedding["values"][position].as_f64().unwrap()
+    };
+    assert!(value("cable") > value("usb"));
+
+    let response = server.post("/v1/admin/idf").json(&serde_json::json!({ "input": [] })).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let response = server.post("/v1/embeddings/sparse").json(&serde_json::json!({ "input": [] })).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_token_embeddings() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/embeddings/tokens")
+        .json(&serde_json::json!({ "input": ["usb  cable", "lamp"] }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["object"], "list");
+    assert_eq!(body["usage"]["total_tokens"], 3);
+
+    let data = &body["data"][0];
+    assert_eq!(data["object"], "token_embeddings");
+    assert_eq!(data["index"], 0);
+    assert_eq!(data["tokens"], serde_json::json!(["usb", "cable"]));
+    assert_eq!(data["offsets"], serde_json::json!([[0, 3], [5, 10]]));
+    assert_eq!(data["embeddings"].as_array().unwrap().len(), 2);
+    assert_eq!(data["embeddings"][0].as_array().unwrap().len(), 384);
+
+    let response = server.post("/v1/embeddings/tokens").json(&serde_json::json!({ "input": [] })).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_token_endpoints_without_token_vectors() {
+    let config = common::create_test_config(100, 8192, None);
+    let app = embedding_service::create_app_with_model(config, common::mock_model::PooledOnlyModel).unwrap();
+    let server = TestServer::new(app).unwrap();
+
+    for (path, body) in [
+        ("/v1/embeddings/tokens", serde_json::json!({ "input": "usb cable" })),
+        ("/v1/maxsim", serde_json::json!({ "query": "usb cable", "documents": ["usb hub"] })),
+        ("/v1/similarity/explain", serde_json::json!({ "source": "usb cable", "target": "usb hub" })),
+    ] {
+        let response = server.post(path).json(&body).await;
+        response.assert_status(StatusCode::NOT_IMPLEMENTED);
+        let json: serde_json::Value = response.json();
+        assert_eq!(json["error"]["code"], "token_vectors_unavailable");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_maxsim() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/maxsim")
+        .json(&serde_json::json!({
+            "query": "usb cable",
+            "documents": ["desk lamp", "usb cable for phones", { "text": "usb hub" }],
+            "return_documents": true
+        }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    let results = body["results"].as_array().unwrap();
+    let order: Vec<u64> = results.iter().map(|r| r["index"].as_u64().unwrap()).collect();
+    assert_eq!(order, vec![1, 2, 0]);
+    assert_eq!(results[0]["document"]["text"], "usb cable for phones");
+    // Both query tokens have an exact match, so the sum of best similarities is 2
+    assert!((results[0]["relevance_score"].as_f64().unwrap() - 2.0).abs() < 1e-4);
+    assert_eq!(body["usage"]["total_tokens"], 2 + 2 + 4 + 2);
+
+    let response = server
+        .post("/v1/maxsim")
+        .json(&serde_json::json!({
+            "query": "usb cable",
+            "documents": ["usb hub", "usb cable"],
+            "top_n": 1,
+            "normalize_scores": true
+        }))
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["results"].as_array().unwrap().len(), 1);
+    assert!((body["results"][0]["relevance_score"].as_f64().unwrap() - 1.0).abs() < 1e-4);
+    assert!(body["results"][0].get("document").is_none());
+
+    let response = server
+        .post("/v1/maxsim")
+        .json(&serde_json::json!({ "query": "usb", "documents": [] }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_explain_similarity() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/similarity/explain")
+        .json(&serde_json::json!({ "source": "usb cable", "target": "usb hub hub" }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["object"], "similarity_explanation");
+    assert_eq!(body["metric"], "cosine");
+    assert_eq!(body["normalized"], false);
+    assert_eq!(body["usage"]["total_tokens"], 5);
+
+    // The shared token accounts for most of the score, and each side sums to it
+    let score = body["score"].as_f64().unwrap();
+    let source = body["source"]["tokens"].as_array().unwrap();
+    assert_eq!(source[0]["token"], "usb");
+    assert_eq!(source[0]["offset"], serde_json::json!([0, 3]));
+    assert!(source[0]["contribution"].as_f64().unwrap() > source[1]["contribution"].as_f64().unwrap());
+    for side in ["source", "target"] {
+        let tokens = body[side]["tokens"].as_array().unwrap();
+        let total: f64 = tokens.iter().map(|t| t["contribution"].as_f64().unwrap()).sum();
+        assert!((total - score).abs() < 1e-4);
+        let norm: f64 = tokens.iter().map(|t| t["norm_contribution"].as_f64().unwrap()).sum();
+        assert!((norm - body[side]["norm"].as_f64().unwrap()).abs() < 1e-4);
+    }
+
+    let response = server
+        .post("/v1/similarity/explain")
+        .json(&serde_json::json!({ "source": "usb", "target": "usb", "metric": "euclidean" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "unsupported_metric");
+}
+#[tokio::test]
+#[serial]
+async fn test_idf_table_persists() {
+    let dir = std::env::temp_dir().join(format!("embedding-service-idf-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut config = common::create_test_config(100, 8192, None);
+    config.data_dir = Some(dir.display().to_string());
+    let start = |config: embedding_service::config::Config| {
+        let app = embedding_service::create_app_with_model(config, common::mock_model::MockModel::new()).unwrap();
+        TestServer::new(app).unwrap()
+    };
+
+    let server = start(config.clone());
+    server
+        .post("/v1/admin/idf")
+        .json(&serde_json::json!({ "input": ["usb cable", "usb hub"] }))
+        .await
+        .assert_status_ok();
+    drop(server);
+
+    let server = start(config.clone());
+    let body: serde_json::Value = server.get("/v1/admin/idf").await.json();
+    assert_eq!(body, serde_json::json!({ "loaded": true, "terms": 3, "documents": 2 }));
+    drop(server);
+
+    // An explicit table takes precedence
+    let table = dir.join("custom.json");
+    std::fs::write(&table, r#"{"idf": {"1": 2.0}}"#).unwrap();
+    config.idf_table = Some(table.display().to_string());
+    let server = start(config);
+    let body: serde_json::Value = server.get("/v1/admin/idf").await.json();
+    assert_eq!(body, serde_json::json!({ "loaded": true, "terms": 1 }));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_classify() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    // A label whose description is the input itself has cosine similarity 1
+    let response = server
+        .post("/v1/classify")
+        .json(&serde_json::json!({
+            "input": ["usb cable", "desk lamp"],
+            "labels": [
+                "hardware",
+                { "name": "lighting", "description": "desk lamp" },
+                { "name": "cables", "description": "usb cable", "examples": ["usb cable"] }
+            ],
+            "top_k": 2
+        }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["object"], "list");
+    assert_eq!(body["data"][0]["object"], "classification");
+    assert_eq!(body["data"][0]["label"], "cables");
+    assert_eq!(body["data"][1]["label"], "lighting");
+
+    let scores = body["data"][0]["scores"].as_array().unwrap();
+    assert_eq!(scores.len(), 2);
+    assert_eq!(scores[0]["label"], "cables");
+    assert!((scores[0]["similarity"].as_f64().unwrap() - 1.0).abs() < 1e-5);
+    assert!(scores[0]["score"].as_f64().unwrap() > scores[1]["score"].as_f64().unwrap());
+
+    // Labels are embedded once; cached labels cost no tokens
+    assert_eq!(body["usage"]["total_tokens"], 4 + 1 + 2 + 2);
+    let response = server
+        .post("/v1/classify")
+        .json(&serde_json::json!({ "input": "usb cable", "labels": ["hardware", "desk lamp"] }))
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["usage"]["total_tokens"], 2);
+    let total: f64 = body["data"][0]["scores"].as_array().unwrap().iter().map(|s| s["score"].as_f64().unwrap()).sum();
+    assert!((total - 1.0).abs() < 1e-5);
+
+    for request in [
+        serde_json::json!({ "input": "usb", "labels": [] }),
+        serde_json::json!({ "input": "usb", "labels": ["a"], "label_set": "products" }),
+        serde_json::json!({ "input": "usb", "labels": ["a", "a"] }),
+        serde_json::json!({ "input": "usb", "labels": ["a"], "temperature": 0.0 }),
+    ] {
+        let response = server.post("/v1/classify").json(&request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    let response = server
+        .post("/v1/classify")
+        .json(&serde_json::json!({ "input": "usb", "label_set": "missing" }))
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "label_set_not_found");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_label_sets() {
+    let dir = std::env::temp_dir().join(format!("embedding-service-labels-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut config = common::create_test_config(100, 8192, None);
+    config.data_dir = Some(dir.display().to_string());
+    let start = |config: embedding_service::config::Config| {
+        let app = embedding_service::create_app_with_model(config, common::mock_model::MockModel::new()).unwrap();
+        TestServer::new(app).unwrap()
+    };
+
+    let server = start(config.clone());
+    let response = server
+        .put("/v1/label_sets/products")
+        .json(&serde_json::json!({
+            "labels": ["hardware", { "name": "cables", "description": "usb cable", "examples": ["hdmi cable"] }]
+        }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["name"], "products");
+    assert_eq!(body["labels"][0], serde_json::json!({ "name": "hardware" }));
+
+    let response = server
+        .put("/v1/label_sets/bad.name")
+        .json(&serde_json::json!({ "labels": ["a"] }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    drop(server);
+
+    // Stored sets survive a restart
+    let server = start(config.clone());
+    let body: serde_json::Value = server.get("/v1/label_sets").await.json();
+    assert_eq!(body["data"].as_array().unwrap().len(), 1);
+    let body: serde_json::Value = server.get("/v1/label_sets/products").await.json();
+    assert_eq!(body["labels"][1]["examples"], serde_json::json!(["hdmi cable"]));
+
+    let response = server
+        .post("/v1/classify")
+        .json(&serde_json::json!({ "input": "hardware", "label_set": "products" }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["data"][0]["label"], "hardware");
+
+    server.delete("/v1/label_sets/products").await.assert_status_ok();
+    server.delete("/v1/label_sets/products").await.assert_status(StatusCode::NOT_FOUND);
+    server.get("/v1/label_sets/products").await.assert_status(StatusCode::NOT_FOUND);
+    drop(server);
+
+    let server = start(config);
+    let body: serde_json::Value = server.get("/v1/label_sets").await.json();
+    assert_eq!(body["data"], serde_json::json!([]));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_classifier_heads() {
+    let dir = std::env::temp_dir().join(format!("embedding-service-classifiers-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut config = common::create_test_config(100, 8192, None);
+    config.data_dir = Some(dir.display().to_string());
+    let start = |config: embedding_service::config::Config| {
+        let app = embedding_service::create_app_with_model(config, common::mock_model::MockModel::new()).unwrap();
+        TestServer::new(app).unwrap()
+    };
+
+    let examples: Vec<serde_json::Value> = ["usb cable", "hdmi cable", "power cord", "ethernet cable"]
+        .iter()
+        .map(|text| serde_json::json!({ "text": text, "label": "cables" }))
+        .chain(
+            ["desk lamp", "floor lamp", "led bulb", "night light"]
+                .iter()
+                .map(|text| serde_json::json!({ "text": text, "label": "lighting" })),
+        )
+        .collect();
+
+    let server = start(config.clone());
+    let response = server
+        .post("/v1/classifiers/products/train")
+        .json(&serde_json::json!({ "examples": examples, "holdout": 0.0 }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["name"], "products");
+    assert_eq!(body["latest"], 1);
+    assert_eq!(body["versions"][0]["head"], "logistic");
+    assert_eq!(body["versions"][0]["labels"], serde_json::json!(["cables", "lighting"]));
+    assert!(body["versions"][0].get("metrics").is_none());
+
+    // Retraining publishes a new version, evaluated on held-out examples
+    let response = server
+        .post("/v1/classifiers/products/train")
+        .json(&serde_json::json!({ "examples": examples, "head": "linear", "holdout": 0.5 }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["latest"], 2);
+    let metrics = &body["versions"][1]["metrics"];
+    assert_eq!(metrics["train_examples"], 4);
+    assert_eq!(metrics["test_examples"], 4);
+    let accuracy = metrics["accuracy"].as_f64().unwrap();
+    assert!((0.0..=1.0).contains(&accuracy));
+    assert!(metrics["macro_f1"].as_f64().is_some());
+    assert_eq!(metrics["per_label"].as_array().unwrap().len(), 2);
+    drop(server);
+
+    // Versions survive a restart, and earlier ones stay usable
+    let server = start(config.clone());
+    let response = server
+        .post("/v1/classifiers/products/predict")
+        .json(&serde_json::json!({ "input": ["usb cable", "desk lamp"], "version": 1 }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["classifier"], "products");
+    assert_eq!(body["version"], 1);
+    assert_eq!(body["data"][0]["object"], "prediction");
+    assert_eq!(body["data"][0]["label"], "cables");
+    assert_eq!(body["data"][1]["label"], "lighting");
+    let total: f64 = body["data"][0]["scores"].as_array().unwrap().iter().map(|s| s["score"].as_f64().unwrap()).sum();
+    assert!((total - 1.0).abs() < 1e-4);
+
+    let response = server
+        .post("/v1/classifiers/products/predict")
+        .json(&serde_json::json!({ "input": "usb cable", "top_k": 1 }))
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["version"], 2);
+    assert_eq!(body["data"][0]["scores"].as_array().unwrap().len(), 1);
+
+    let response = server
+        .post("/v1/classifiers/products/predict")
+        .json(&serde_json::json!({ "input": "usb cable", "version": 9 }))
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "version_not_found");
+
+    let body: serde_json::Value = server.get("/v1/classifiers").await.json();
+    assert_eq!(body["data"].as_array().unwrap().len(), 1);
+
+    for request in [
+        serde_json::json!({ "examples": [{ "text": "usb", "label": "cables" }] }),
+        serde_json::json!({ "examples": examples, "holdout": 0.95 }),
+        serde_json::json!({ "examples": examples, "epochs": 0 }),
+    ] {
+        let response = server.post("/v1/classifiers/products/train").json(&request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["error"]["code"], "invalid_training_data");
+    }
+    let response = server
+        .post("/v1/classifiers/bad.name/train")
+        .json(&serde_json::json!({ "examples": examples }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    server.delete("/v1/classifiers/products").await.assert_status_ok();
+    server.get("/v1/classifiers/products").await.assert_status(StatusCode::NOT_FOUND);
+    server
+        .post("/v1/classifiers/products/predict")
+        .json(&serde_json::json!({ "input": "usb cable" }))
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+    drop(server);
+
+    let server = start(config);
+    let body: serde_json::Value = server.get("/v1/classifiers").await.json();
+    assert_eq!(body["data"], serde_json::json!([]));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_classifier_training_limits() {
+    // Training sets larger than max_batch_size are embedded in batches
+    let server = TestServer::new(create_test_server_with_config(2, 8192, None)).unwrap();
+    let example = |i: usize| {
+        let label = if i.is_multiple_of(2) { "even" } else { "odd" };
+        serde_json::json!({ "text": format!("example {}", i), "label": label })
+    };
+
+    let examples: Vec<serde_json::Value> = (0..7).map(example).collect();
+    let response = server
+        .post("/v1/classifiers/numbers/train")
+        .json(&serde_json::json!({ "examples": examples, "holdout": 0.0 }))
+        .await;
+    response.assert_status_ok();
+
+    let too_many: Vec<serde_json::Value> = (0..10_001).map(example).collect();
+    let too_long: Vec<serde_json::Value> = (0..4_000).map(example).collect();
+    for request in [
+        serde_json::json!({ "examples": too_many, "epochs": 1 }),
+        serde_json::json!({ "examples": too_long, "epochs": 1_000 }),
+    ] {
+        let response = server.post("/v1/classifiers/numbers/train").json(&request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["error"]["code"], "invalid_training_data");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_semantic_router() {
+    let dir = std::env::temp_dir().join(format!("embedding-service-routes-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let routes = dir.join("routes.seed.json");
+    std::fs::write(
+        &routes,
+        r#"{"routes": [{"name": "greeting", "examples": ["hello there", "hi"]}], "fallback": "human"}"#,
+    )
+    .unwrap();
+
+    let mut config = common::create_test_config(100, 8192, None);
+    config.routes = Some(routes.display().to_string());
+    config.data_dir = Some(dir.join("data").display().to_string());
+    let start = |config: embedding_service::config::Config| {
+        let app = embedding_service::create_app_with_model(config, common::mock_model::MockModel::new()).unwrap();
+        TestServer::new(app).unwrap()
+    };
+
+    // Routes from the --routes file are ready at startup
+    let server = start(config.clone());
+    let body: serde_json::Value = server.get("/v1/routes").await.json();
+    assert_eq!(body["routes"][0]["name"], "greeting");
+    assert_eq!(body["fallback"], "human");
+    assert_eq!(body["threshold"], 0.5);
+
+    let response = server
+        .put("/v1/routes/billing")
+        .json(&serde_json::json!({ "examples": ["refund please", "invoice"], "threshold": 1.0 }))
+        .await;
+    response.assert_status_ok();
+
+    let response = server
+        .post("/v1/route")
+        .json(&serde_json::json!({ "input": ["hi", "invoice", "where is my parcel"], "strategy": "knn", "k": 1 }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["data"][0]["object"], "route_match");
+    assert_eq!(body["data"][0]["route"], "greeting");
+    assert_eq!(body["data"][0]["matched"], true);
+    assert!((body["data"][0]["confidence"].as_f64().unwrap() - 1.0).abs() < 1e-5);
+    assert_eq!(body["data"][0]["scores"].as_array().unwrap().len(), 2);
+    assert_eq!(body["usage"]["total_tokens"], 1 + 1 + 4);
+
+    // Billing only matches at its own threshold of 1, so near misses fall back
+    server.delete("/v1/routes/greeting").await.assert_status_ok();
+    let response = server
+        .post("/v1/route")
+        .json(&serde_json::json!({ "input": "refund now", "fallback": "support", "top_k": 1 }))
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["data"][0]["scores"][0]["route"], "billing");
+    assert_eq!(body["data"][0]["route"], "support");
+    assert_eq!(body["data"][0]["matched"], false);
+    assert_eq!(body["data"][0]["scores"].as_array().unwrap().len(), 1);
+
+    for request in [
+        serde_json::json!({ "examples": [] }),
+        serde_json::json!({ "examples": ["hi"], "threshold": 2.0 }),
+    ] {
+        let response = server.put("/v1/routes/chat").json(&request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+    let response = server.post("/v1/route").json(&serde_json::json!({ "input": "hi", "k": 0 })).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    drop(server);
+
+    // Changes made through the API take precedence over --routes after a restart
+    let server = start(config.clone());
+    let body: serde_json::Value = server.get("/v1/routes").await.json();
+    assert_eq!(body["routes"][0]["name"], "billing");
+    assert_eq!(body["routes"].as_array().unwrap().len(), 1);
+
+    server.delete("/v1/routes/billing").await.assert_status_ok();
+    server.delete("/v1/routes/billing").await.assert_status(StatusCode::NOT_FOUND);
+
+    let response = server
+        .put("/v1/routes")
+        .json(&serde_json::json!({ "routes": [{ "name": "faq", "examples": ["opening hours"] }], "threshold": 0.2 }))
+        .await;
+    response.assert_status_ok();
+    drop(server);
+
+    let server = start(config);
+    let body: serde_json::Value = server.get("/v1/routes").await.json();
+    assert_eq!(body["routes"], serde_json::json!([{ "name": "faq", "examples": ["opening hours"] }]));
+    assert!(body.get("fallback").is_none());
+    let body: serde_json::Value = server
+        .post("/v1/route")
+        .json(&serde_json::json!({ "input": "opening hours" }))
+        .await
+        .json();
+    assert_eq!(body["data"][0]["route"], "faq");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_cluster() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/cluster")
+        .json(&serde_json::json!({
+            "input": ["refund please", "refund my order", "login is broken"],
+            "algorithm": { "type": "kmeans", "k": 2 }
+        }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["object"], "clustering");
+    assert_eq!(body["algorithm"], "kmeans");
+    assert_eq!(body["clusters"].as_array().unwrap().len(), 2);
+    assert_eq!(body["assignments"].as_array().unwrap().len(), 3);
+    assert_eq!(body["clusters"][0]["size"], 2);
+    assert_eq!(body["clusters"][0]["centroid"].as_array().unwrap().len(), 384);
+    assert_eq!(body["noise"], 0);
+    assert_eq!(body["usage"]["total_tokens"], 8);
+
+    // Stored vectors in two directions, plus one item far from both
+    let direction = |axis: usize, offset: f32| -> Vec<f32> {
+        (0..384)
+            .map(|i| if i == axis { 1.0 } else if i == 2 { offset } else { 0.0 })
+            .collect()
+    };
+    server
+        .post("/v1/collections")
+        .json(&serde_json::json!({ "name": "tickets" }))
+        .await
+        .assert_status_ok();
+    let response = server
+        .post("/v1/collections/tickets/items")
+        .json(&serde_json::json!({
+            "items": [
+                { "id": "r1", "text": "refund for my order", "vector": direction(0, 0.0) },
+                { "id": "r2", "text": "refund not received", "vector": direction(0, 0.1) },
+                { "id": "r3", "text": "late refund", "vector": direction(0, 0.05) },
+                { "id": "l1", "text": "login fails", "vector": direction(1, 0.0) },
+                { "id": "l2", "text": "cannot login", "vector": direction(1, 0.1) },
+                { "id": "l3", "text": "login page error", "vector": direction(1, 0.05) },
+                { "id": "x", "text": "hello", "vector": direction(3, 0.0) }
+            ]
+        }))
+        .await;
+    response.assert_status_ok();
+
+    let response = server
+        .post("/v1/cluster")
+        .json(&serde_json::json!({
+            "collection": "tickets",
+            "algorithm": { "type": "density", "eps": 0.1, "min_points": 3 },
+            "representatives": 1,
+            "keywords": 1
+        }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["algorithm"], "density");
+    assert_eq!(body["noise"], 1);
+    assert_eq!(body["usage"]["total_tokens"], 0);
+    assert!(body["silhouette"].as_f64().unwrap() > 0.9);
+    let clusters = body["clusters"].as_array().unwrap();
+    assert_eq!(clusters.len(), 2);
+    assert_eq!(clusters[0]["representatives"][0]["id"], "r3");
+    assert_eq!(clusters[0]["keywords"][0]["term"], "refund");
+    assert_eq!(clusters[1]["keywords"][0]["term"], "login");
+    let outlier = body["assignments"].as_array().unwrap().iter().find(|a| a["id"] == "x").unwrap();
+    assert!(outlier["cluster"].is_null());
+
+    // Auto-k picks the two directions
+    let response = server.post("/v1/cluster").json(&serde_json::json!({ "collection": "tickets" })).await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    let assignments = body["assignments"].as_array().unwrap();
+    assert_eq!(assignments[0]["cluster"], assignments[2]["cluster"]);
+    assert_ne!(assignments[0]["cluster"], assignments[3]["cluster"]);
+
+    let response = server
+        .post("/v1/cluster")
+        .json(&serde_json::json!({ "input": ["a"], "collection": "tickets" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(response.json::<serde_json::Value>()["error"]["code"], "invalid_input");
+
+    let response = server
+        .post("/v1/cluster")
+        .json(&serde_json::json!({ "input": ["a", "b"], "algorithm": { "type": "kmeans", "k": 3 } }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(response.json::<serde_json::Value>()["error"]["code"], "invalid_algorithm");
+
+    // Choosing k tries every count up to max_k, so it is capped lower than a fixed k
+    let response = server
+        .post("/v1/cluster")
+        .json(&serde_json::json!({ "input": ["a", "b"], "algorithm": { "type": "kmeans", "max_k": 50 } }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(response.json::<serde_json::Value>()["error"]["code"], "invalid_algorithm");
+
+    let response = server.post("/v1/cluster").json(&serde_json::json!({ "collection": "missing" })).await;
+    response.assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_deduplicate() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+    let input = ["hello world", "a different text", "hello world", "something else", "hello world"];
+
+    let response = server
+        .post("/v1/deduplicate")
+        .json(&serde_json::json!({ "input": input, "threshold": 0.9999 }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["data"].as_array().unwrap().len(), 1);
+    assert_eq!(body["data"][0]["object"], "duplicate_group");
+    assert_eq!(body["data"][0]["canonical"]["index"], 0);
+    assert_eq!(body["data"][0]["duplicates"][0]["index"], 2);
+    assert_eq!(body["data"][0]["duplicates"][1]["index"], 4);
+    assert_eq!(body["unique"], 3);
+    assert_eq!(body["duplicates"], 2);
+    assert_eq!(body["comparisons"], 10);
+    assert!(body.get("deleted").is_none());
+
+    // The MinHash pre-filter only compares texts that share shingles
+    let response = server
+        .post("/v1/deduplicate")
+        .json(&serde_json::json!({ "input": input, "threshold": 0.9999, "minhash": {} }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["duplicates"], 2);
+    assert_eq!(body["comparisons"], 3);
+
+    // Duplicates can be deleted from a collection, keeping the canonical item
+    let vector = |axis: usize| -> Vec<f32> { (0..384).map(|i| if i == axis { 1.0 } else { 0.0 }).collect() };
+    server
+        .post("/v1/collections")
+        .json(&serde_json::json!({ "name": "pages" }))
+        .await
+        .assert_status_ok();
+    server
+        .post("/v1/collections/pages/items")
+        .json(&serde_json::json!({
+            "items": [
+                { "id": "a", "text": "short", "vector": vector(0) },
+                { "id": "b", "text": "a longer copy", "vector": vector(0) },
+                { "id": "c", "vector": vector(1) }
+            ]
+        }))
+        .await
+        .assert_status_ok();
+    let response = server
+        .post("/v1/deduplicate")
+        .json(&serde_json::json!({ "collection": "pages", "canonical": "longest", "delete": true }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["data"][0]["canonical"]["id"], "b");
+    assert_eq!(body["data"][0]["duplicates"][0]["id"], "a");
+    assert_eq!(body["deleted"], 1);
+    assert_eq!(body["usage"]["total_tokens"], 0);
+    let body: serde_json::Value = server.get("/v1/collections/pages").await.json();
+    assert_eq!(body["count"], 2);
+
+    // Item "c" has no text, so MinHash could never pair it
+    let response = server
+        .post("/v1/deduplicate")
+        .json(&serde_json::json!({ "collection": "pages", "minhash": {}, "delete": true }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(response.json::<serde_json::Value>()["error"]["code"], "minhash_needs_text");
+    let body: serde_json::Value = server.get("/v1/collections/pages").await.json();
+    assert_eq!(body["count"], 2);
+
+    let response = server
+        .post("/v1/deduplicate")
+        .json(&serde_json::json!({ "input": input, "delete": true }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(response.json::<serde_json::Value>()["error"]["code"], "invalid_input");
+
+    let response = server
+        .post("/v1/deduplicate")
+        .json(&serde_json::json!({ "input": input, "minhash": { "num_hashes": 100, "bands": 32 } }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(response.json::<serde_json::Value>()["error"]["code"], "invalid_minhash");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_keyphrases() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+    let text = "Vector search engines rank the documents. Vector search is fast.";
+
+    let response = server
+        .post("/v1/keyphrases")
+        .json(&serde_json::json!({ "input": [text, "of the and"], "top_n": 3 }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["data"][0]["object"], "keyphrases");
+    let keyphrases = body["data"][0]["keyphrases"].as_array().unwrap();
+    assert_eq!(keyphrases.len(), 3);
+    for keyphrase in keyphrases {
+        assert!(keyphrase["score"].is_number());
+        for offset in keyphrase["offsets"].as_array().unwrap() {
+            let (start, end) = (offset[0].as_u64().unwrap() as usize, offset[1].as_u64().unwrap() as usize);
+            assert_eq!(text[start..end].to_lowercase(), keyphrase["phrase"]);
+        }
+    }
+    // Only stopwords leaves no candidates
+    assert!(body["data"][1]["keyphrases"].as_array().unwrap().is_empty());
+    // The texts (13 words) and the distinct candidates (6 unigrams and 3 bigrams, 12 words) share one batch
+    assert_eq!(body["usage"]["total_tokens"], 25);
+
+    let response = server
+        .post("/v1/keyphrases")
+        .json(&serde_json::json!({ "input": "of the and", "ngram_range": [3, 3], "stopwords": [] }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["data"][0]["keyphrases"][0]["phrase"], "of the and");
+    assert_eq!(body["data"][0]["keyphrases"][0]["offsets"][0], serde_json::json!([0, 10]));
+
+    let response = server
+        .post("/v1/keyphrases")
+        .json(&serde_json::json!({ "input": text, "ngram_range": [2, 1] }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(response.json::<serde_json::Value>()["error"]["code"], "invalid_ngram_range");
+
+    let response = server
+        .post("/v1/keyphrases")
+        .json(&serde_json::json!({ "input": text, "diversity": 1.5 }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(response.json::<serde_json::Value>()["error"]["code"], "invalid_diversity");
+
+    // Distinct words in every text add up past the per-request candidate limit
+    let texts: Vec<String> = (0..10)
+        .map(|t| (0..500).map(|w| format!("w{}x{}", t, w)).collect::<Vec<_>>().join(" "))
+        .collect();
+    let response = server
+        .post("/v1/keyphrases")
+        .json(&serde_json::json!({ "input": texts, "ngram_range": [1, 1] }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(response.json::<serde_json::Value>()["error"]["code"], "too_many_candidates");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_collection_lifecycle() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/collections")
+        .json(&serde_json::json!({ "name": "docs", "metric": "cosine" }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["model"], "test-model");
+    assert_eq!(body["dimensions"], 384);
+    assert_eq!(body["count"], 0);
+
+    let response = server.post("/v1/collections").json(&serde_json::json!({ "name": "docs" })).await;
+    response.assert_status(StatusCode::CONFLICT);
+
+    let response = server
+        .post("/v1/collections")
+        .json(&serde_json::json!({ "name": "other", "model": "unknown-model" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "model_not_found");
+
+    // Texts are embedded, vectors are stored as given
+    let vector: Vec<f32> = (0..384).map(|i| if i == 0 { 1.0 } else { 0.0 }).collect();
+    let response = server
+        .post("/v1/collections/docs/items")
+        .json(&serde_json::json!({
+            "items": [
+                { "id": "a", "text": "reset your password", "metadata": { "lang": "en" } },
+                { "id": "b", "text": "billing questions" },
+                { "id": "c", "vector": vector }
+            ]
+        }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["upserted"], 3);
+    assert_eq!(body["usage"]["total_tokens"], 5);
+
+    let response = server.get("/v1/collections/docs/items/a").await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["text"], "reset your password");
+    assert_eq!(body["metadata"]["lang"], "en");
+    assert_eq!(body["vector"].as_array().unwrap().len(), 384);
+
+    // Querying with an item's own text ranks it first with a perfect score
+    let response = server
+        .post("/v1/collections/docs/query")
+        .json(&serde_json::json!({ "text": "reset your password", "top_k": 2 }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    let results = body["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["id"], "a");
+    assert_eq!(results[0]["metadata"]["lang"], "en");
+    assert!((results[0]["score"].as_f64().unwrap() - 1.0).abs() < 1e-5);
+    assert!(results[0].get("vector").is_none());
+
+    let response = server
+        .post("/v1/collections/docs/query")
+        .json(&serde_json::json!({ "vector": vector, "top_k": 1, "include_vectors": true }))
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["results"][0]["id"], "c");
+    assert_eq!(body["results"][0]["vector"].as_array().unwrap().len(), 384);
+
+    let response = server
+        .post("/v1/collections/docs/delete")
+        .json(&serde_json::json!({ "ids": ["a", "missing"] }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["deleted"], 1);
+
+    server.get("/v1/collections/docs/items/a").await.assert_status(StatusCode::NOT_FOUND);
+
+    let response = server.get("/v1/collections").await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["data"][0]["name"], "docs");
+    assert_eq!(body["data"][0]["count"], 2);
+
+    server.delete("/v1/collections/docs").await.assert_status_ok();
+    server.get("/v1/collections/docs").await.assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_collection_validation() {
+    let server = TestServer::new(create_test_server_with_config(2, 8192, None)).unwrap();
+
+    server
+        .post("/v1/collections")
+        .json(&serde_json::json!({ "name": "docs" }))
+        .await
+        .assert_status_ok();
+
+    let response = server
+        .post("/v1/collections/missing/items")
+        .json(&serde_json::json!({ "items": [{ "id": "a", "text": "x" }] }))
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+
+    let cases = [
+        (serde_json::json!({ "items": [{ "id": "a", "vector": [1.0, 2.0] }] }), "dimension_mismatch"),
+        (serde_json::json!({ "items": [{ "id": "a" }] }), "invalid_item"),
+        (serde_json::json!({ "items": [] }), "empty_input"),
+        (
+            serde_json::json!({ "items": [{ "id": "a", "text": "x" }, { "id": "b", "text": "y" }, { "id": "c", "text": "z" }] }),
+            "batch_too_large",
+        ),
+    ];
+    for (request, code) in cases {
+        let response = server.post("/v1/collections/docs/items").json(&request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["error"]["code"], code);
+    }
+
+    // A rejected batch writes nothing
+    let response = server.get("/v1/collections/docs").await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["count"], 0);
+
+    let response = server
+        .post("/v1/collections/docs/query")
+        .json(&serde_json::json!({ "text": "x", "vector": [1.0] }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "invalid_query");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_collections_require_auth() {
+    let server = TestServer::new(create_test_server(true)).unwrap();
+
+    server.get("/v1/collections").await.assert_status(StatusCode::UNAUTHORIZED);
+    server
+        .get("/v1/collections")
+        .add_header("Authorization", "Bearer test-key")
+        .await
+        .assert_status_ok();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_hnsw_collection() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/collections")
+        .json(&serde_json::json!({
+            "name": "ann",
+            "metric": "euclidean",
+            "index": { "type": "hnsw", "m": 8, "ef_construction": 64 }
+        }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["index"], serde_json::json!({ "type": "hnsw", "m": 8, "ef_construction": 64, "ef": 64 }));
+
+    let items: Vec<serde_json::Value> = (0..100)
+        .map(|i| {
+            let vector: Vec<f32> = (0..384).map(|d| ((i * 31 + d * 7) % 101) as f32).collect();
+            serde_json::json!({ "id": format!("item-{}", i), "vector": vector })
+        })
+        .collect();
+    server
+        .post("/v1/collections/ann/items")
+        .json(&serde_json::json!({ "items": items }))
+        .await
+        .assert_status_ok();
+
+    let response = server
+        .post("/v1/collections/ann/query")
+        .json(&serde_json::json!({ "vector": items[42]["vector"], "top_k": 3, "ef": 32 }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["results"][0]["id"], "item-42");
+    assert_eq!(body["results"][0]["score"], 0.0);
+
+    let response = server
+        .post("/v1/collections/ann/selftest")
+        .json(&serde_json::json!({ "sample_size": 20, "top_k": 5 }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["sample_size"], 20);
+    assert_eq!(body["ef"], 64);
+    assert!(body["recall"].as_f64().unwrap() > 0.8);
+    assert!(body["index_latency"]["p99_us"].as_f64().unwrap() >= body["index_latency"]["p50_us"].as_f64().unwrap());
+
+    // Invalid parameters and self-tests without an index are rejected
+    let response = server
+        .post("/v1/collections")
+        .json(&serde_json::json!({ "name": "bad", "index": { "type": "hnsw", "m": 1 } }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "invalid_index");
+
+    for query in [
+        serde_json::json!({ "vector": items[0]["vector"], "top_k": 0 }),
+        serde_json::json!({ "vector": items[0]["vector"], "top_k": 1_000_000 }),
+        serde_json::json!({ "vector": items[0]["vector"], "ef": 1_000_000 }),
+    ] {
+        let response = server.post("/v1/collections/ann/query").json(&query).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["error"]["code"], "invalid_query");
+    }
+    for test in [
+        serde_json::json!({ "sample_size": 0 }),
+        serde_json::json!({ "sample_size": 1_000_000 }),
+        serde_json::json!({ "ef": 0 }),
+    ] {
+        let response = server.post("/v1/collections/ann/selftest").json(&test).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["error"]["code"], "invalid_query");
+    }
+
+    server.post("/v1/collections").json(&serde_json::json!({ "name": "flat" })).await.assert_status_ok();
+    let response = server.post("/v1/collections/flat/selftest").json(&serde_json::json!({})).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "index_not_configured");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_filtered_query() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    for (name, index) in [("flat", serde_json::json!({ "type": "flat" })), ("ann", serde_json::json!({ "type": "hnsw", "ef": 4 }))] {
+        let response = server
+            .post("/v1/collections")
+            .json(&serde_json::json!({ "name": name, "index": index, "payload_indexes": ["lang"] }))
+            .await;
+        response.assert_status_ok();
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["payload_indexes"], serde_json::json!(["lang"]));
+
+        let items: Vec<serde_json::Value> = (0..100)
+            .map(|i| {
+                let vector: Vec<f32> = (0..384).map(|d| ((i * 31 + d * 7) % 101) as f32).collect();
+                let metadata = serde_json::json!({
+                    "lang": if i % 20 == 0 { "de" } else { "en" },
+                    "published": format!("2024-01-{:02}", i % 28 + 1),
+                });
+                serde_json::json!({ "id": format!("item-{}", i), "vector": vector, "metadata": metadata })
+            })
+            .collect();
+        server
+            .post(&format!("/v1/collections/{}/items", name))
+            .json(&serde_json::json!({ "items": items }))
+            .await
+            .assert_status_ok();
+
+        // Only 5% of the items match, yet all of them are returned
+        let response = server
+            .post(&format!("/v1/collections/{}/query", name))
+            .json(&serde_json::json!({ "vector": items[1]["vector"], "top_k": 5, "filter": { "field": "lang", "eq": "de" } }))
+            .await;
+        response.assert_status_ok();
+        let body: serde_json::Value = response.json();
+        let results = body["results"].as_array().unwrap();
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(|result| result["metadata"]["lang"] == "de"));
+
+        // Date ranges on a field without a payload index
+        let filter = serde_json::json!({
+            "and": [
+                { "field": "published", "gte": "2024-01-10", "lt": "2024-01-12T00:00:00Z" },
+                { "not": { "field": "lang", "in": ["de"] } }
+            ]
+        });
+        let response = server
+            .post(&format!("/v1/collections/{}/query", name))
+            .json(&serde_json::json!({ "vector": items[1]["vector"], "top_k": 10, "filter": filter }))
+            .await;
+        let body: serde_json::Value = response.json();
+        let results = body["results"].as_array().unwrap();
+        assert_eq!(results.len(), 8);
+        assert!(results.iter().all(|result| ["2024-01-10", "2024-01-11"].contains(&result["metadata"]["published"].as_str().unwrap())));
+    }
+
+    let response = server
+        .post("/v1/collections/flat/payload_indexes")
+        .json(&serde_json::json!({ "field": "published" }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["payload_indexes"], serde_json::json!(["lang", "published"]));
+
+    for filter in [
+        serde_json::json!({ "field": "lang" }),
+        serde_json::json!({ "field": "published", "gt": "last week" }),
+    ] {
+        let response = server
+            .post("/v1/collections/flat/query")
+            .json(&serde_json::json!({ "vector": vec![0.0; 384], "filter": filter }))
+            .await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["error"]["code"], "invalid_filter");
+    }
+
+    // Unknown operators fail to deserialize
+    let response = server
+        .post("/v1/collections/flat/query")
+        .json(&serde_json::json!({ "vector": vec![0.0; 384], "filter": { "field": "lang", "like": "d%" } }))
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_hybrid_query() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server
+        .post("/v1/collections")
+        .json(&serde_json::json!({ "name": "products", "analyzer": { "type": "standard", "stopwords": ["the"] } }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["analyzer"], serde_json::json!({ "type": "standard", "stopwords": ["the"] }));
+
+    let items = serde_json::json!([
+        { "id": "mouse", "text": "Wireless mouse SKU-4471 with USB receiver", "metadata": { "stock": 3 } },
+        { "id": "cable", "text": "USB cable, two metres", "metadata": { "stock": 0 } },
+        { "id": "pad", "text": "Mouse pad for the wireless mouse", "metadata": { "stock": 12 } },
+        { "id": "lamp", "text": "Desk lamp" }
+    ]);
+    server
+        .post("/v1/collections/products/items")
+        .json(&serde_json::json!({ "items": items }))
+        .await
+        .assert_status_ok();
+
+    // Exact product codes are found lexically, without embedding the query
+    let response = server
+        .post("/v1/collections/products/query")
+        .json(&serde_json::json!({ "text": "sku 4471", "mode": "lexical" }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["results"].as_array().unwrap().len(), 1);
+    assert_eq!(body["results"][0]["id"], "mouse");
+    assert!(body["results"][0]["scores"].is_null());
+    assert_eq!(body["usage"]["total_tokens"], 0);
+
+    let response = server
+        .post("/v1/collections/products/query")
+        .json(&serde_json::json!({ "text": "the usb", "mode": "lexical", "filter": { "field": "stock", "gt": 0 } }))
+        .await;
+    let body: serde_json::Value = response.json();
+    let ids: Vec<&str> = body["results"].as_array().unwrap().iter().map(|r| r["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, vec!["mouse"]);
+
+    // Hybrid results carry both scores
+    for fusion in [serde_json::json!({ "type": "rrf" }), serde_json::json!({ "type": "weighted", "vector_weight": 0.3 })] {
+        let response = server
+            .post("/v1/collections/products/query")
+            .json(&serde_json::json!({ "text": "wireless mouse", "mode": "hybrid", "top_k": 4, "fusion": fusion }))
+            .await;
+        response.assert_status_ok();
+        let body: serde_json::Value = response.json();
+        let results = body["results"].as_array().unwrap();
+        assert_eq!(results.len(), 4);
+        assert!(body["usage"]["total_tokens"].as_u64().unwrap() > 0);
+        assert!(results.windows(2).all(|w| w[0]["score"].as_f64() >= w[1]["score"].as_f64()));
+        for result in results {
+            assert!(result["scores"]["vector"].is_number());
+            assert!(result["scores"]["lexical"].is_number());
+            assert!(result["scores"]["vector_rank"].is_u64());
+        }
+        let lamp = results.iter().find(|r| r["id"] == "lamp").unwrap();
+        assert_eq!(lamp["scores"]["lexical"], 0.0);
+        assert!(lamp["scores"]["lexical_rank"].is_null());
+    }
+
+    // The model's tokenizer can analyze text instead
+    let response = server
+        .post("/v1/collections")
+        .json(&serde_json::json!({ "name": "tokens", "analyzer": { "type": "tokenizer" } }))
+        .await;
+    response.assert_status_ok();
+    server
+        .post("/v1/collections/tokens/items")
+        .json(&serde_json::json!({ "items": items }))
+        .await
+        .assert_status_ok();
+    let response = server
+        .post("/v1/collections/tokens/query")
+        .json(&serde_json::json!({ "text": "lamp", "mode": "lexical" }))
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["results"][0]["id"], "lamp");
+
+    for (request, code) in [
+        (serde_json::json!({ "vector": vec![0.0; 384], "mode": "lexical" }), "invalid_query"),
+        (serde_json::json!({ "vector": vec![0.0; 384], "mode": "hybrid" }), "invalid_query"),
+        (
+            serde_json::json!({ "text": "mouse", "mode": "hybrid", "fusion": { "type": "weighted", "vector_weight": 2.0 } }),
+            "invalid_fusion",
+        ),
+        (serde_json::json!({ "text": "mouse", "mode": "hybrid", "fusion": { "type": "rrf", "k": -1.0 } }), "invalid_fusion"),
+    ] {
+        let response = server.post("/v1/collections/products/query").json(&request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["error"]["code"], code);
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_collections_persist_across_restarts() {
+    let dir = std::env::temp_dir().join(format!("embedding-service-data-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut config = common::create_test_config(100, 8192, None);
+    config.data_dir = Some(dir.display().to_string());
+    let start = || {
+        let app = embedding_service::create_app_with_model(config.clone(), common::mock_model::MockModel::new()).unwrap();
+        TestServer::new(app).unwrap()
+    };
+
+    let server = start();
+    server
+        .post("/v1/collections")
+        .json(&serde_json::json!({ "name": "docs", "analyzer": { "type": "tokenizer" } }))
+        .await
+        .assert_status_ok();
+    server
+        .post("/v1/collections/docs/payload_indexes")
+        .json(&serde_json::json!({ "field": "lang" }))
+        .await
+        .assert_status_ok();
+    server
+        .post("/v1/collections/docs/items")
+        .json(&serde_json::json!({ "items": [{ "id": "a", "text": "first" }, { "id": "b", "text": "second" }] }))
+        .await
+        .assert_status_ok();
+
+    let response = server.post("/v1/admin/snapshots").await;
+    response.assert_status_ok();
+    let snapshot: serde_json::Value = response.json();
+
+    server
+        .post("/v1/collections/docs/delete")
+        .json(&serde_json::json!({ "ids": ["a"] }))
+        .await
+        .assert_status_ok();
+    drop(server);
+
+    // Logged changes after the snapshot are replayed on startup
+    let server = start();
+    let body: serde_json::Value = server.get("/v1/collections/docs").await.json();
+    assert_eq!(body["count"], 1);
+    assert_eq!(body["payload_indexes"], serde_json::json!(["lang"]));
+    assert_eq!(body["analyzer"], serde_json::json!({ "type": "tokenizer" }));
+    let response = server
+        .post("/v1/collections/docs/query")
+        .json(&serde_json::json!({ "text": "second", "mode": "lexical" }))
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["results"][0]["id"], "b");
+
+    let response = server.get("/v1/admin/snapshots").await;
+    let body: serde_json::Value = response.json();
+    assert!(body["data"].as_array().unwrap().iter().any(|s| s["id"] == snapshot["id"]));
+
+    let response = server.post(&format!("/v1/admin/snapshots/{}/restore", snapshot["id"])).await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["data"][0]["count"], 2);
+    server.get("/v1/collections/docs/items/a").await.assert_status_ok();
+
+    let response = server.post("/v1/admin/snapshots/999999/restore").await;
+    response.assert_status(StatusCode::NOT_FOUND);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_snapshots_require_data_dir() {
+    let server = TestServer::new(create_test_server(false)).unwrap();
+
+    let response = server.post("/v1/admin/snapshots").await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "storage_not_configured");
+}
@@ -0,0 +1,315 @@
+mod common;
+
+use common::{create_test_app, create_test_config, create_test_state};
+use embedding_service::{
+    grpc::{
+        self,
+        pb::{embedding_service_client::EmbeddingServiceClient, EmbedRequest},
+    },
+    listen::{self, ListenSpec},
+    tls::{ReloadableTls, TlsListener, MAX_PENDING_HANDSHAKES},
+};
+use hyper_util::rt::TokioIo;
+use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, DnType, IsCa, KeyPair};
+use rustls::{
+    crypto::ring::default_provider,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName},
+    ClientConfig, RootCertStore,
+};
+use std::{path::PathBuf, sync::Arc};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_rustls::TlsConnector;
+use tonic::{
+    transport::{Channel, Endpoint, Uri},
+    Code,
+};
+
+/// A CA with server and client certificates written to a temporary directory
+struct Pki {
+    dir: PathBuf,
+    ca: CertifiedIssuer<'static, KeyPair>,
+}
+
+impl Pki {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("embedding-service-tls-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.distinguished_name.push(DnType::CommonName, "Test CA");
+        let ca = CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+
+        Self { dir, ca }
+    }
+
+    /// Issue a certificate and write it as `<name>.pem` and `<name>.key`
+    fn issue(&self, name: &str, common_name: &str) -> (PathBuf, PathBuf) {
+        let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        params.distinguished_name.push(DnType::CommonName, common_name);
+        let key = KeyPair::generate().unwrap();
+        let cert = params.signed_by(&key, &self.ca).unwrap();
+
+        let cert_path = self.dir.join(format!("{}.pem", name));
+        let key_path = self.dir.join(format!("{}.key", name));
+        std::fs::write(&cert_path, cert.pem()).unwrap();
+        std::fs::write(&key_path, key.serialize_pem()).unwrap();
+        (cert_path, key_path)
+    }
+}
+
+impl Drop for Pki {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Serve the test app over HTTPS on a free port
+async fn start_server(pki: &Pki, client_ca: bool) -> (u16, ReloadableTls) {
+    let (cert, key) = pki.issue("server", "localhost");
+
+    let mut config = create_test_config(100, 8192, Some("test-key".to_string()));
+    config.tls_cert = Some(cert.display().to_string());
+    config.tls_key = Some(key.display().to_string());
+    config.tls_client_ca = client_ca.then(|| pki.dir.join("ca.pem").display().to_string());
+    let tls = ReloadableTls::from_config(&config).unwrap().unwrap();
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let spec: ListenSpec = format!("tcp://127.0.0.1:{}", port).parse().unwrap();
+    tokio::spawn(listen::serve(spec, create_test_app(config), Some(tls.clone()), std::future::pending()));
+
+    // Wait for the listener to come up
+    while TcpStream::connect(("127.0.0.1", port)).await.is_err() {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    (port, tls)
+}
+
+fn connector(pki: &Pki, client_cert: Option<(PathBuf, PathBuf)>) -> TlsConnector {
+    let mut roots = RootCertStore::empty();
+    roots.add(pki.ca.der().clone()).unwrap();
+
+    let builder = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots);
+
+    let config = match client_cert {
+        Some((cert, key)) => builder
+            .with_client_auth_cert(
+                vec![CertificateDer::from_pem_file(cert).unwrap()],
+                PrivateKeyDer::from_pem_file(key).unwrap(),
+            )
+            .unwrap(),
+        None => builder.with_no_client_auth(),
+    };
+
+    TlsConnector::from(Arc::new(config))
+}
+
+/// Send an embeddings request and return the response status code
+async fn embed(port: u16, connector: &TlsConnector, auth_key: Option<&str>) -> u16 {
+    let tcp = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let mut stream = connector
+        .connect(ServerName::try_from("localhost").unwrap(), tcp)
+        .await
+        .unwrap();
+
+    let body = r#"{"input": "hello"}"#;
+    let authorization = auth_key
+        .map(|key| format!("Authorization: Bearer {}\r\n", key))
+        .unwrap_or_default();
+    let request = format!(
+        "POST /v1/embeddings HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        authorization,
+        body.len(),
+        body
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response[9..12].parse().unwrap()
+}
+
+#[tokio::test]
+async fn test_https_with_api_key() {
+    let pki = Pki::new("key");
+    let (port, _) = start_server(&pki, false).await;
+    let connector = connector(&pki, None);
+
+    assert_eq!(embed(port, &connector, Some("test-key")).await, 200);
+    assert_eq!(embed(port, &connector, None).await, 401);
+}
+
+#[tokio::test]
+async fn test_pending_handshakes_are_bounded() {
+    let pki = Pki::new("handshakes");
+    let (port, _) = start_server(&pki, false).await;
+
+    // Connections that never start the handshake take up every slot
+    let mut idle = Vec::with_capacity(MAX_PENDING_HANDSHAKES);
+    for _ in 0..MAX_PENDING_HANDSHAKES {
+        idle.push(TcpStream::connect(("127.0.0.1", port)).await.unwrap());
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    // so the next one is closed without waiting for the handshake timeout
+    let mut extra = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let read = tokio::time::timeout(std::time::Duration::from_secs(2), extra.read(&mut [0; 1])).await;
+    assert!(matches!(read, Ok(Ok(0) | Err(_))));
+
+    // Slots are freed once those connections go away
+    drop(idle);
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert_eq!(embed(port, &connector(&pki, None), Some("test-key")).await, 200);
+}
+
+#[tokio::test]
+async fn test_client_certificate_authenticates() {
+    let pki = Pki::new("mtls");
+    let (port, _) = start_server(&pki, true).await;
+
+    // A verified client certificate replaces the API key
+    let client = connector(&pki, Some(pki.issue("client", "indexer")));
+    assert_eq!(embed(port, &client, None).await, 200);
+
+    // Clients without a certificate still need the key
+    let anonymous = connector(&pki, None);
+    assert_eq!(embed(port, &anonymous, None).await, 401);
+    assert_eq!(embed(port, &anonymous, Some("test-key")).await, 200);
+
+    // Certificates from another CA are rejected during the handshake
+    let other = Pki::new("other");
+    let mut roots = RootCertStore::empty();
+    roots.add(pki.ca.der().clone()).unwrap();
+    let (cert, key) = other.issue("client", "intruder");
+    let config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_client_auth_cert(
+            vec![CertificateDer::from_pem_file(cert).unwrap()],
+            PrivateKeyDer::from_pem_file(key).unwrap(),
+        )
+        .unwrap();
+    let tcp = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let result = async {
+        let mut stream = TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from("localhost").unwrap(), tcp)
+            .await?;
+        // TLS 1.3 reports client certificate errors on the first read
+        stream.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n").await?;
+        let mut buf = [0u8; 1];
+        stream.read_exact(&mut buf).await
+    }
+    .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_certificate_reload() {
+    let pki = Pki::new("reload");
+    let (port, tls) = start_server(&pki, false).await;
+
+    let served_cert = || async {
+        let tcp = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let stream = connector(&pki, None)
+            .connect(ServerName::try_from("localhost").unwrap(), tcp)
+            .await
+            .unwrap();
+        stream.get_ref().1.peer_certificates().unwrap()[0].clone()
+    };
+
+    let before = served_cert().await;
+
+    // Replacing the files takes effect for new connections after a reload
+    pki.issue("server", "localhost");
+    tls.reload().unwrap();
+    let after = served_cert().await;
+    assert_ne!(before, after);
+
+    // A broken certificate is rejected and the current one stays in place
+    std::fs::write(pki.dir.join("server.pem"), "not a certificate").unwrap();
+    assert!(tls.reload().is_err());
+    assert_eq!(served_cert().await, after);
+}
+
+/// Connect a gRPC client over TLS, negotiating HTTP/2
+async fn grpc_client(pki: &Pki, port: u16, client_cert: Option<(PathBuf, PathBuf)>) -> EmbeddingServiceClient<Channel> {
+    let mut roots = RootCertStore::empty();
+    roots.add(pki.ca.der().clone()).unwrap();
+    let builder = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots);
+    let mut config = match client_cert {
+        Some((cert, key)) => builder
+            .with_client_auth_cert(
+                vec![CertificateDer::from_pem_file(cert).unwrap()],
+                PrivateKeyDer::from_pem_file(key).unwrap(),
+            )
+            .unwrap(),
+        None => builder.with_no_client_auth(),
+    };
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    let connector = TlsConnector::from(Arc::new(config));
+
+    let channel = Endpoint::from_static("http://localhost")
+        .connect_with_connector(tower::service_fn(move |_: Uri| {
+            let connector = connector.clone();
+            async move {
+                let tcp = TcpStream::connect(("127.0.0.1", port)).await?;
+                let stream = connector.connect(ServerName::try_from("localhost").unwrap(), tcp).await?;
+                Ok::<_, std::io::Error>(TokioIo::new(stream))
+            }
+        }))
+        .await
+        .unwrap();
+    EmbeddingServiceClient::new(channel)
+}
+
+#[tokio::test]
+async fn test_grpc_over_tls() {
+    let pki = Pki::new("grpc");
+    let (cert, key) = pki.issue("server", "localhost");
+
+    let mut config = create_test_config(100, 8192, Some("test-key".to_string()));
+    config.tls_cert = Some(cert.display().to_string());
+    config.tls_key = Some(key.display().to_string());
+    config.tls_client_ca = Some(pki.dir.join("ca.pem").display().to_string());
+    let tls = ReloadableTls::from_config(&config).unwrap().unwrap().for_grpc().unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let state = create_test_state(&config);
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(grpc::service(state, &config))
+            .serve_with_incoming(TlsListener::new(listener, tls).unwrap().into_incoming()),
+    );
+
+    let request = || EmbedRequest {
+        input: vec!["hello".to_string()],
+        model: None,
+    };
+
+    // A verified client certificate replaces the API key, as over HTTPS
+    let mut client = grpc_client(&pki, port, Some(pki.issue("client", "indexer"))).await;
+    let response = client.embed(request()).await.unwrap().into_inner();
+    assert_eq!(response.data.len(), 1);
+
+    let mut anonymous = grpc_client(&pki, port, None).await;
+    let status = anonymous.embed(request()).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    // Plaintext clients cannot talk to the TLS port
+    if let Ok(mut plaintext) = EmbeddingServiceClient::connect(format!("http://127.0.0.1:{}", port)).await {
+        assert!(plaintext.embed(request()).await.is_err());
+    }
+}